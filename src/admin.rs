@@ -0,0 +1,97 @@
+use warp::{Filter, Rejection};
+use log::warn;
+
+/// Environment variable holding the bearer token for admin routes.
+/// When it is unset the admin API is disabled entirely.
+pub const ADMIN_TOKEN_ENV: &str = "FORGE_ADMIN_TOKEN";
+
+/// Environment variable holding the bearer token for the routes that run commands,
+/// `/api/execute` and `/api/repl`. The admin token is accepted there too; with neither
+/// set those routes are disabled.
+pub const API_TOKEN_ENV: &str = "FORGE_API_TOKEN";
+
+#[derive(Debug)]
+pub enum AdminRejection {
+    Disabled,
+    /// Neither `FORGE_API_TOKEN` nor `FORGE_ADMIN_TOKEN` is set.
+    ApiDisabled,
+    Unauthorized,
+}
+
+impl warp::reject::Reject for AdminRejection {}
+
+impl AdminRejection {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AdminRejection::Disabled => "admin_disabled",
+            AdminRejection::ApiDisabled => "api_disabled",
+            AdminRejection::Unauthorized => "auth_failed",
+        }
+    }
+}
+
+/// Checks a presented token against `FORGE_ADMIN_TOKEN`.
+pub fn verify_token(presented: Option<&str>) -> Result<(), AdminRejection> {
+    let Some(token) = std::env::var(ADMIN_TOKEN_ENV).ok().filter(|t| !t.is_empty()) else {
+        warn!("🔒 Admin request refused: {} is not set", ADMIN_TOKEN_ENV);
+        return Err(AdminRejection::Disabled);
+    };
+    if constant_time_eq(presented.unwrap_or("").as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        warn!("🔒 Admin request refused: bad or missing token");
+        Err(AdminRejection::Unauthorized)
+    }
+}
+
+/// Checks a presented token against `FORGE_API_TOKEN` and `FORGE_ADMIN_TOKEN`.
+pub fn verify_api_token(presented: Option<&str>) -> Result<(), AdminRejection> {
+    let tokens: Vec<String> = [API_TOKEN_ENV, ADMIN_TOKEN_ENV]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        warn!("🔒 API request refused: neither {} nor {} is set", API_TOKEN_ENV, ADMIN_TOKEN_ENV);
+        return Err(AdminRejection::ApiDisabled);
+    }
+    let presented = presented.unwrap_or("").as_bytes();
+    if tokens.iter().any(|token| constant_time_eq(presented, token.as_bytes())) {
+        Ok(())
+    } else {
+        warn!("🔒 API request refused: bad or missing token");
+        Err(AdminRejection::Unauthorized)
+    }
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    header.strip_prefix("Bearer ").map(str::trim)
+}
+
+/// Filter that only passes requests carrying `Authorization: Bearer <FORGE_ADMIN_TOKEN>`.
+pub fn require_admin() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|header: Option<String>| async move {
+            verify_token(header.as_deref().and_then(bearer_token)).map_err(warp::reject::custom)
+        })
+        .untuple_one()
+}
+
+/// Filter that only passes requests carrying `Authorization: Bearer <token>` with the API
+/// or the admin token.
+pub fn require_api() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|header: Option<String>| async move {
+            verify_api_token(header.as_deref().and_then(bearer_token)).map_err(warp::reject::custom)
+        })
+        .untuple_one()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
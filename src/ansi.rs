@@ -0,0 +1,212 @@
+/// Removes ANSI escape sequences (CSI, OSC, two-byte escapes) and control characters
+/// other than newline and tab, leaving the text a reader would see.
+pub fn strip(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS, APC, PM: up to BEL or ST
+                Some(']' | 'P' | '_' | '^') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' | '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Style {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+        if let Some(fg) = &self.fg {
+            css.push(format!("color:{}", fg));
+        }
+        if let Some(bg) = &self.bg {
+            css.push(format!("background-color:{}", bg));
+        }
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.italic {
+            css.push("font-style:italic".to_string());
+        }
+        if self.underline {
+            css.push("text-decoration:underline".to_string());
+        }
+        css.join(";")
+    }
+
+    /// Applies one SGR parameter list, e.g. `1;38;5;208`.
+    fn apply(&mut self, params: &str) {
+        let codes: Vec<u16> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let mut i = 0;
+        while i < codes.len() {
+            match codes[i] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                code @ (30..=37 | 90..=97) => self.fg = Some(basic_color(code % 10, code >= 90).to_string()),
+                code @ (40..=47 | 100..=107) => self.bg = Some(basic_color(code % 10, code >= 100).to_string()),
+                39 => self.fg = None,
+                49 => self.bg = None,
+                code @ (38 | 48) => {
+                    let color = match codes.get(i + 1) {
+                        Some(5) => {
+                            i += 2;
+                            codes.get(i).map(|&n| indexed_color(n))
+                        }
+                        Some(2) => {
+                            i += 4;
+                            codes.get(i - 2..=i).map(|rgb| format!("#{:02x}{:02x}{:02x}", rgb[0] as u8, rgb[1] as u8, rgb[2] as u8))
+                        }
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+const BASIC_COLORS: [&str; 8] = ["#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5"];
+const BRIGHT_COLORS: [&str; 8] = ["#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff"];
+
+fn basic_color(index: u16, bright: bool) -> &'static str {
+    let palette = if bright { &BRIGHT_COLORS } else { &BASIC_COLORS };
+    palette[(index as usize).min(7)]
+}
+
+fn indexed_color(n: u16) -> String {
+    match n {
+        0..=7 => basic_color(n, false).to_string(),
+        8..=15 => basic_color(n - 8, true).to_string(),
+        16..=231 => {
+            let n = n - 16;
+            let level = |v: u16| if v == 0 { 0 } else { 55 + v * 40 };
+            format!("#{:02x}{:02x}{:02x}", level(n / 36), level((n / 6) % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (n.min(255) - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Converts terminal output to HTML-escaped text with SGR colors and weights as
+/// inline-styled spans. Everything else `strip` would remove is dropped.
+pub fn to_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut style = Style::default();
+    let mut open_span = false;
+    let mut plain = String::new();
+    let mut chars = text.chars().peekable();
+
+    let flush = |out: &mut String, plain: &mut String| {
+        out.push_str(&escape_html(plain));
+        plain.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            match c {
+                '\n' | '\t' => plain.push(c),
+                c if c.is_control() => {}
+                c => plain.push(c),
+            }
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut final_byte = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        final_byte = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if final_byte == Some('m') {
+                    let mut next = style.clone();
+                    next.apply(&params);
+                    if next != style {
+                        flush(&mut out, &mut plain);
+                        if open_span {
+                            out.push_str("</span>");
+                        }
+                        let css = next.css();
+                        open_span = !css.is_empty();
+                        if open_span {
+                            out.push_str(&format!("<span style=\"{}\">", css));
+                        }
+                        style = next;
+                    }
+                }
+            }
+            Some(']' | 'P' | '_' | '^') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(&mut out, &mut plain);
+    if open_span {
+        out.push_str("</span>");
+    }
+    out
+}
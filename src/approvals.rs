@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use regex::Regex;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::config::{ApprovalConfig, ConfigError};
+use crate::executor::Capture;
+use crate::policy::{CommandSpec, ExecMode, PolicyVerdict};
+use crate::redaction;
+
+/// Log target for approval decisions, kept apart so it can be routed to an audit sink.
+pub const AUDIT_TARGET: &str = "forge::audit";
+
+/// How long a settled or expired request can still be polled.
+const RETENTION_HOURS: i64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Denied,
+    Expired,
+}
+
+/// A held-back execute request and what became of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Approval {
+    pub id: String,
+    /// Redacted command line, for approvers to read.
+    pub command: String,
+    pub mode: ExecMode,
+    pub pattern: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub status: ApprovalStatus,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    /// The execute response, once approved and run.
+    pub result: Option<serde_json::Value>,
+    /// The command and argv exactly as validated; approval runs these, never a re-parse.
+    #[serde(skip)]
+    pub spec: CommandSpec,
+    #[serde(skip)]
+    pub argv: Vec<String>,
+    /// How the requester asked for the output.
+    #[serde(skip)]
+    pub capture: Capture,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ApprovalError {
+    #[error("no approval request '{0}'")]
+    NotFound(String),
+    #[error("approval request '{0}' expired")]
+    Expired(String),
+    #[error("approval request '{id}' was already {status:?}")]
+    AlreadyDecided { id: String, status: ApprovalStatus },
+}
+
+impl ApprovalError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApprovalError::NotFound(_) => "approval_not_found",
+            ApprovalError::Expired(_) => "approval_expired",
+            ApprovalError::AlreadyDecided { .. } => "approval_already_decided",
+        }
+    }
+}
+
+/// Patterns that need an approver, and the requests waiting on one. Expired requests stay
+/// pollable for an hour; decided ones for an hour past their original expiry.
+#[derive(Clone, Default)]
+pub struct Approvals {
+    ttl: Duration,
+    patterns: Arc<Vec<(String, Regex)>>,
+    requests: Arc<Mutex<HashMap<String, Approval>>>,
+}
+
+impl Approvals {
+    pub fn from_config(config: &ApprovalConfig) -> Result<Self, ConfigError> {
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|p| {
+                Regex::new(&p.regex)
+                    .map(|re| (p.name.clone(), re))
+                    .map_err(|e| ConfigError::Invalid(format!("approvals pattern '{}': {}", p.name, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            ttl: Duration::seconds(config.ttl_secs as i64),
+            patterns: Arc::new(patterns),
+            requests: Arc::default(),
+        })
+    }
+
+    /// Name of the first approval pattern the command line matches, if any.
+    pub fn matched_pattern(&self, command: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|(_, re)| re.is_match(command))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Stores a pending request for a command the policy engine already validated.
+    pub fn request(&self, spec: &CommandSpec, capture: Capture, verdict: &PolicyVerdict, requested_by: &str) -> Approval {
+        let now = Utc::now();
+        let approval = Approval {
+            id: Uuid::new_v4().to_string(),
+            command: redaction::redact(&spec.command_line()),
+            mode: verdict.mode,
+            pattern: verdict.approval_pattern().unwrap_or_default().to_string(),
+            requested_by: requested_by.to_string(),
+            requested_at: now,
+            expires_at: now + self.ttl,
+            status: ApprovalStatus::Pending,
+            decided_by: None,
+            decided_at: None,
+            result: None,
+            spec: spec.clone(),
+            argv: verdict.argv.clone(),
+            capture,
+        };
+        info!(target: AUDIT_TARGET, "📝 Approval {} requested by {} for '{}' (pattern {})", approval.id, requested_by, approval.command, approval.pattern);
+        self.lock().insert(approval.id.clone(), approval.clone());
+        approval
+    }
+
+    pub fn get(&self, id: &str) -> Result<Approval, ApprovalError> {
+        self.lock().get(id).cloned().ok_or_else(|| ApprovalError::NotFound(id.to_string()))
+    }
+
+    /// Requests still waiting for a decision, oldest first.
+    pub fn pending(&self) -> Vec<Approval> {
+        let mut pending: Vec<Approval> = self
+            .lock()
+            .values()
+            .filter(|approval| approval.status == ApprovalStatus::Pending)
+            .cloned()
+            .collect();
+        pending.sort_by_key(|approval| approval.requested_at);
+        pending
+    }
+
+    /// Approves or denies a pending request; the caller runs an approved one.
+    pub fn decide(&self, id: &str, approve: bool, approver: &str) -> Result<Approval, ApprovalError> {
+        let mut requests = self.lock();
+        let approval = requests.get_mut(id).ok_or_else(|| ApprovalError::NotFound(id.to_string()))?;
+        match approval.status {
+            ApprovalStatus::Pending => {}
+            ApprovalStatus::Expired => return Err(ApprovalError::Expired(id.to_string())),
+            status => return Err(ApprovalError::AlreadyDecided { id: id.to_string(), status }),
+        }
+        approval.status = if approve { ApprovalStatus::Approved } else { ApprovalStatus::Denied };
+        approval.decided_by = Some(approver.to_string());
+        approval.decided_at = Some(Utc::now());
+        info!(
+            target: AUDIT_TARGET,
+            "{} Approval {} {:?} by {} for {}: '{}'",
+            if approve { "✅" } else { "🚫" },
+            id,
+            approval.status,
+            approver,
+            approval.requested_by,
+            approval.command
+        );
+        Ok(approval.clone())
+    }
+
+    /// Attaches the execute response to an approved request.
+    pub fn record_result(&self, id: &str, result: serde_json::Value) {
+        if let Some(approval) = self.lock().get_mut(id) {
+            approval.result = Some(result);
+        }
+    }
+
+    /// Expires overdue requests and forgets ones past their retention.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Approval>> {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        for approval in requests.values_mut() {
+            if approval.status == ApprovalStatus::Pending && approval.expires_at <= now {
+                approval.status = ApprovalStatus::Expired;
+                info!(target: AUDIT_TARGET, "⌛ Approval {} for {} expired undecided", approval.id, approval.requested_by);
+            }
+        }
+        requests.retain(|_, approval| approval.expires_at + Duration::hours(RETENTION_HOURS) > now);
+        requests
+    }
+}
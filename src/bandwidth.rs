@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::debug;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::{BandwidthConfig, ConfigError, ForgeConfig};
+
+/// Byte budget refilled at a rate and capped at a burst. Sends may overdraw it; the debt is
+/// what the sender waits out, so bursts are smoothed rather than dropped.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: u64) -> Self {
+        Self { tokens: burst as f64, last: Instant::now() }
+    }
+
+    /// Charges `bytes` and returns how long to hold them so the rate is kept.
+    fn charge(&mut self, bytes: usize, rate: u64, burst: u64, now: Instant) -> Duration {
+        let refill = now.saturating_duration_since(self.last).as_secs_f64() * rate as f64;
+        self.tokens = (self.tokens + refill).min(burst as f64) - bytes as f64;
+        self.last = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate as f64)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: BandwidthConfig,
+    global: TokenBucket,
+}
+
+/// Output rate limits shared by every session of a server. Clones share the global bucket,
+/// and `reload` changes the rates of sessions already running.
+#[derive(Debug, Clone)]
+pub struct Bandwidth {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        Self::from_config(&BandwidthConfig::default()).unwrap()
+    }
+}
+
+impl Bandwidth {
+    pub fn from_config(config: &BandwidthConfig) -> Result<Self, ConfigError> {
+        validate(config)?;
+        let shared = Shared { config: config.clone(), global: TokenBucket::new(config.burst_bytes) };
+        Ok(Self { shared: Arc::new(Mutex::new(shared)) })
+    }
+
+    /// Re-reads the config file and applies its `[terminal.bandwidth]` limits.
+    pub fn reload(&self) -> Result<BandwidthConfig, ConfigError> {
+        let config = ForgeConfig::load()?.terminal.bandwidth;
+        self.apply(&config)?;
+        Ok(config)
+    }
+
+    pub fn apply(&self, config: &BandwidthConfig) -> Result<(), ConfigError> {
+        validate(config)?;
+        self.lock().config = config.clone();
+        Ok(())
+    }
+
+    /// A shaper for one new session.
+    pub fn session(&self) -> SessionShaper {
+        let burst = self.lock().config.burst_bytes;
+        SessionShaper {
+            bandwidth: self.clone(),
+            bucket: TokenBucket::new(burst),
+            stats: Arc::default(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn validate(config: &BandwidthConfig) -> Result<(), ConfigError> {
+    if config.global_bytes_per_sec == Some(0) || config.session_bytes_per_sec == Some(0) {
+        return Err(ConfigError::Invalid("terminal.bandwidth rates must be above zero".to_string()));
+    }
+    Ok(())
+}
+
+/// Paces the output of one session against its own rate and the global one.
+#[derive(Debug)]
+pub struct SessionShaper {
+    bandwidth: Bandwidth,
+    bucket: TokenBucket,
+    stats: Arc<ThrottleStats>,
+}
+
+impl SessionShaper {
+    /// Charges `bytes` of output and returns how long to hold it. Chunks up to
+    /// `interactive_bytes` are charged but never held.
+    pub fn charge(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let mut shared = self.bandwidth.lock();
+        let Shared { config, global } = &mut *shared;
+        let mut delay = Duration::ZERO;
+        if let Some(rate) = config.global_bytes_per_sec {
+            delay = delay.max(global.charge(bytes, rate, config.burst_bytes, now));
+        }
+        if let Some(rate) = config.session_bytes_per_sec {
+            delay = delay.max(self.bucket.charge(bytes, rate, config.burst_bytes, now));
+        }
+        if bytes <= config.interactive_bytes {
+            Duration::ZERO
+        } else {
+            delay
+        }
+    }
+
+    /// Waits until `bytes` of output may leave.
+    pub async fn throttle(&mut self, bytes: usize) {
+        let delay = self.charge(bytes);
+        if delay.is_zero() {
+            return;
+        }
+        debug!("🐢 Holding {} bytes of output for {:?}", bytes, delay);
+        self.stats.throttled.store(true, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+        self.stats.throttled.store(false, Ordering::Relaxed);
+        self.stats.delayed_messages.fetch_add(1, Ordering::Relaxed);
+        self.stats.delayed_ms.fetch_add(delay.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> Arc<ThrottleStats> {
+        self.stats.clone()
+    }
+}
+
+/// How much a session's output has been held back, readable while its writer runs.
+#[derive(Debug, Default)]
+pub struct ThrottleStats {
+    throttled: AtomicBool,
+    delayed_messages: AtomicU64,
+    delayed_ms: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ThrottleSnapshot {
+    /// Output is being held right now.
+    pub throttled: bool,
+    pub delayed_messages: u64,
+    pub delayed_ms: u64,
+}
+
+impl ThrottleStats {
+    pub fn snapshot(&self) -> ThrottleSnapshot {
+        ThrottleSnapshot {
+            throttled: self.throttled.load(Ordering::Relaxed),
+            delayed_messages: self.delayed_messages.load(Ordering::Relaxed),
+            delayed_ms: self.delayed_ms.load(Ordering::Relaxed),
+        }
+    }
+}
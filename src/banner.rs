@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::{BannerConfig, ConfigError, TerminalConfig};
+
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const DEFAULT_BANNER: &str = "🧪 Welcome to Rick's Interdimensional Rust Terminal!\nWubba Lubba Dub Dub! Type your commands below:\nSession ID: {session_id}\nConnected from: {peer_addr}\n\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    SessionId,
+    PeerAddr,
+    ServerVersion,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+/// Welcome text for new terminal connections: a template with `{session_id}`,
+/// `{peer_addr}` and `{server_version}` (`{{`/`}}` for literal braces), plus an
+/// optional MOTD file.
+pub struct Banner {
+    template: Option<Vec<Segment>>,
+    motd_file: Option<PathBuf>,
+    motd: Mutex<Option<String>>,
+}
+
+impl Banner {
+    pub fn from_config(config: &TerminalConfig) -> Result<Self, ConfigError> {
+        let template = match &config.banner {
+            BannerConfig::Enabled(false) => None,
+            BannerConfig::Enabled(true) => Some(parse(DEFAULT_BANNER)?),
+            BannerConfig::Template(template) => Some(parse(template)?),
+        };
+        let motd = config.motd_file.as_deref().map(read_motd).transpose()?;
+        Ok(Self {
+            template,
+            motd_file: config.motd_file.clone(),
+            motd: Mutex::new(motd),
+        })
+    }
+
+    /// The banner for one connection, or `None` when banners are switched off.
+    pub fn render(&self, session_id: &str, peer_addr: &str) -> Option<String> {
+        let template = self.template.as_ref()?;
+        let mut text = String::new();
+        for segment in template {
+            match segment {
+                Segment::Literal(literal) => text.push_str(literal),
+                Segment::Variable(Variable::SessionId) => text.push_str(session_id),
+                Segment::Variable(Variable::PeerAddr) => text.push_str(peer_addr),
+                Segment::Variable(Variable::ServerVersion) => text.push_str(SERVER_VERSION),
+            }
+        }
+        if let Some(motd) = self.motd.lock().unwrap().as_deref() {
+            text.push_str(motd);
+            if !motd.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        Some(text)
+    }
+
+    /// Re-reads the MOTD file; on error the previous MOTD stays in place.
+    pub fn reload_motd(&self) -> Result<(), ConfigError> {
+        if let Some(path) = &self.motd_file {
+            let motd = read_motd(path)?;
+            *self.motd.lock().unwrap() = Some(motd);
+        }
+        Ok(())
+    }
+}
+
+fn read_motd(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, ConfigError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(ConfigError::Invalid(format!("terminal.banner: unclosed '{{{}'", name))),
+                    }
+                }
+                let variable = match name.as_str() {
+                    "session_id" => Variable::SessionId,
+                    "peer_addr" => Variable::PeerAddr,
+                    "server_version" => Variable::ServerVersion,
+                    _ => return Err(ConfigError::Invalid(format!("terminal.banner: unknown variable '{{{}}}'", name))),
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Variable(variable));
+            }
+            '}' => return Err(ConfigError::Invalid("terminal.banner: unmatched '}' (use '}}')".to_string())),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
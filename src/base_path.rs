@@ -0,0 +1,121 @@
+use warp::filters::BoxedFilter;
+use warp::Filter;
+
+/// `server --base-path /terminal` and `pty-server --base-path /terminal` mount every
+/// route under the prefix a reverse proxy forwards, and 404 everything outside it.
+pub const BASE_PATH_FLAG: &str = "--base-path";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BasePathError {
+    #[error("{BASE_PATH_FLAG} needs a value, like {BASE_PATH_FLAG} /terminal")]
+    Missing,
+    #[error("base path '{0}' must start with '/' and use only letters, digits, '-', '_', '.' and '~' between slashes")]
+    Invalid(String),
+}
+
+/// A normalized path prefix: `/terminal`, no trailing slash, or empty for the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BasePath(String);
+
+impl BasePath {
+    pub fn parse(path: &str) -> Result<Self, BasePathError> {
+        let invalid = || BasePathError::Invalid(path.to_string());
+        let rest = path.strip_prefix('/').ok_or_else(invalid)?;
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        if rest.is_empty() {
+            return Ok(Self::default());
+        }
+        let segment_ok = |segment: &str| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~'))
+        };
+        if !rest.split('/').all(segment_ok) {
+            return Err(invalid());
+        }
+        Ok(Self(format!("/{}", rest)))
+    }
+
+    /// The prefix from `--base-path PATH` or `--base-path=PATH`; the root without either.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, BasePathError> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == BASE_PATH_FLAG {
+                return Self::parse(&args.next().ok_or(BasePathError::Missing)?);
+            }
+            if let Some(path) = arg.strip_prefix(BASE_PATH_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+                return Self::parse(path);
+            }
+        }
+        Ok(Self::default())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `/terminal`, or `/` for the root.
+    pub fn as_str(&self) -> &str {
+        if self.is_root() { "/" } else { &self.0 }
+    }
+
+    /// The absolute URL path for `path`, e.g. `/api/errors` becomes `/terminal/api/errors`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.0, path.trim_start_matches('/'))
+    }
+
+    /// `path` with the prefix removed, always starting with `/`; `None` outside the prefix.
+    pub fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(self.0.as_str())?;
+        match rest {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
+        }
+    }
+
+    /// Matches and consumes the prefix's segments; matches everything for the root.
+    pub fn filter(&self) -> BoxedFilter<()> {
+        self.0
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .fold(warp::any().boxed(), |filter, segment| filter.and(warp::path(segment.to_string())).boxed())
+    }
+
+    /// Rewrites a page's root-relative `src` and `href` attributes under the prefix and
+    /// adds a `<base>` plus `window.__FORGE_BASE_PATH__` for the frontend's own URLs.
+    pub fn inject(&self, html: &str) -> String {
+        let mut html = html.to_string();
+        for attribute in ["src", "href"] {
+            html = self.prefix_attribute(&html, attribute);
+        }
+        let head = format!(
+            "<base href=\"{}/\"><script>window.__FORGE_BASE_PATH__ = \"{}\";</script>",
+            self.0,
+            self.0
+        );
+        match html.find("<head>") {
+            Some(at) => html.insert_str(at + "<head>".len(), &head),
+            None => html.insert_str(0, &head),
+        }
+        html
+    }
+
+    /// Prefixes every `attribute="/..."` value, leaving protocol-relative `//host` alone.
+    fn prefix_attribute(&self, html: &str, attribute: &str) -> String {
+        let needle = format!("{}=\"/", attribute);
+        let mut prefixed = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(at) = rest.find(&needle) {
+            let (before, value) = rest.split_at(at + needle.len() - 1);
+            prefixed.push_str(before);
+            if !value.starts_with("//") {
+                prefixed.push_str(&self.0);
+            }
+            rest = value;
+        }
+        prefixed.push_str(rest);
+        prefixed
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest partial escape sequence held back between chunks.
+const MAX_PENDING: usize = 64;
+/// DEC private modes that turn on mouse reporting.
+const MOUSE_MODES: &[u32] = &[9, 1000, 1001, 1002, 1003, 1005, 1006, 1015, 1016];
+const BRACKETED_PASTE_MODE: u32 = 2004;
+/// Sequences dropped whole, up to their ST (or BEL) terminator, for clients without the
+/// capability: OSC 52 clipboard writes, iTerm2 inline images and kitty graphics. Sixel
+/// images, `ESC P <params> q`, are matched separately.
+const DROPPED_SEQUENCES: &[(&str, Capability)] = &[
+    ("\x1b]52;", Capability::Osc52),
+    ("\x1b]1337;File=", Capability::ImagePassthrough),
+    ("\x1b_G", Capability::ImagePassthrough),
+];
+
+/// Frontend features a client can declare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Truecolor,
+    Color256,
+    UnicodeWidth,
+    Mouse,
+    BracketedPaste,
+    /// OSC 52 clipboard writes.
+    Osc52,
+    /// Sixel, iTerm2 and kitty inline images.
+    ImagePassthrough,
+    /// `output=binary` frames; clients without it get base64 instead.
+    BinaryFrames,
+    /// WebSocket compression. Accepted for forward compatibility; nothing is compressed yet.
+    Compression,
+}
+
+impl Capability {
+    const ALL: [Capability; 9] = [
+        Capability::Truecolor,
+        Capability::Color256,
+        Capability::UnicodeWidth,
+        Capability::Mouse,
+        Capability::BracketedPaste,
+        Capability::Osc52,
+        Capability::ImagePassthrough,
+        Capability::BinaryFrames,
+        Capability::Compression,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|cap| serde_json::to_value(cap).is_ok_and(|value| value == name))
+    }
+}
+
+/// What the client said it supports. Clients that never declare anything get everything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Capabilities(Vec<Capability>);
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self(Capability::ALL.to_vec())
+    }
+
+    /// Unknown names are skipped so newer clients can talk to older servers.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut caps: Vec<Capability> = names.iter().filter_map(|name| Capability::parse(name.as_ref().trim())).collect();
+        caps.sort();
+        caps.dedup();
+        Self(caps)
+    }
+
+    pub fn supports(&self, cap: Capability) -> bool {
+        self.0.contains(&cap)
+    }
+
+    /// The `TERM` that does not over-promise colors to this client.
+    pub fn term(&self) -> &'static str {
+        if self.supports(Capability::Truecolor) || self.supports(Capability::Color256) {
+            "xterm-256color"
+        } else {
+            "xterm"
+        }
+    }
+
+    /// The `color` hint for `SessionEnvironment`.
+    pub fn color(&self) -> &'static str {
+        if self.supports(Capability::Truecolor) {
+            "truecolor"
+        } else if self.supports(Capability::Color256) {
+            "256"
+        } else {
+            "16"
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Removes output the client cannot handle: OSC 52 clipboard writes, inline images and
+/// the mouse and bracketed-paste mode switches. Sequences may be split across chunks.
+#[derive(Debug, Default)]
+pub struct OutputFilter {
+    caps: Capabilities,
+    pending: String,
+    /// Inside a sequence that is being dropped.
+    skipping: bool,
+}
+
+impl OutputFilter {
+    pub fn new(caps: Capabilities) -> Self {
+        Self { caps, ..Self::default() }
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.caps
+    }
+
+    pub fn set_capabilities(&mut self, caps: Capabilities) {
+        self.caps = caps;
+    }
+
+    fn passes_everything(&self) -> bool {
+        [Capability::Mouse, Capability::BracketedPaste, Capability::Osc52, Capability::ImagePassthrough]
+            .into_iter()
+            .all(|cap| self.caps.supports(cap))
+    }
+
+    pub fn filter(&mut self, chunk: &str) -> String {
+        let buf = std::mem::take(&mut self.pending) + chunk;
+        if self.passes_everything() && !self.skipping {
+            return buf;
+        }
+        let mut out = String::with_capacity(buf.len());
+        let mut rest = buf.as_str();
+
+        if self.skipping {
+            self.skipping = false;
+            match self.skip(rest) {
+                Some(after) => rest = after,
+                None => return out,
+            }
+        }
+
+        while let Some(esc) = rest.find('\x1b') {
+            out.push_str(&rest[..esc]);
+            rest = &rest[esc..];
+
+            if let Some((prefix, cap)) = DROPPED_SEQUENCES.iter().find(|(prefix, _)| rest.starts_with(prefix)) {
+                if self.caps.supports(*cap) {
+                    out.push('\x1b');
+                    rest = &rest[1..];
+                    continue;
+                }
+                match self.skip(&rest[prefix.len()..]) {
+                    Some(after) => rest = after,
+                    None => return out,
+                }
+                continue;
+            }
+            if DROPPED_SEQUENCES.iter().any(|(prefix, _)| rest.len() < prefix.len() && prefix.starts_with(rest)) {
+                self.pending = rest.to_string();
+                return out;
+            }
+
+            if let Some(body) = rest.strip_prefix("\x1bP") {
+                let params_len = body.find(|c: char| !(c.is_ascii_digit() || c == ';')).unwrap_or(body.len());
+                match body[params_len..].chars().next() {
+                    None if rest.len() <= MAX_PENDING => {
+                        self.pending = rest.to_string();
+                        return out;
+                    }
+                    Some('q') if !self.caps.supports(Capability::ImagePassthrough) => {
+                        match self.skip(&body[params_len + 1..]) {
+                            Some(after) => rest = after,
+                            None => return out,
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(body) = rest.strip_prefix("\x1b[?") {
+                let params_len = body.find(|c: char| !(c.is_ascii_digit() || c == ';')).unwrap_or(body.len());
+                match body[params_len..].chars().next() {
+                    None if rest.len() <= MAX_PENDING => {
+                        self.pending = rest.to_string();
+                        return out;
+                    }
+                    Some(final_byte @ ('h' | 'l')) => {
+                        let modes: Vec<&str> = body[..params_len]
+                            .split(';')
+                            .filter(|mode| mode.parse().map_or(true, |mode| self.mode_allowed(mode)))
+                            .collect();
+                        if !modes.is_empty() {
+                            out.push_str("\x1b[?");
+                            out.push_str(&modes.join(";"));
+                            out.push(final_byte);
+                        }
+                        rest = &body[params_len + 1..];
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            if rest == "\x1b" || rest == "\x1b[" {
+                self.pending = rest.to_string();
+                return out;
+            }
+            out.push('\x1b');
+            rest = &rest[1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// What follows the end of a dropped sequence's `payload`, or `None` when the chunk
+    /// ends first and the rest of the sequence is dropped from later chunks.
+    fn skip<'a>(&mut self, payload: &'a str) -> Option<&'a str> {
+        match osc_end(payload) {
+            Some(end) => Some(&payload[end..]),
+            // Keep a trailing ESC in case it starts the ST terminator.
+            None => {
+                self.skipping = true;
+                if payload.ends_with('\x1b') {
+                    self.pending.push('\x1b');
+                }
+                None
+            }
+        }
+    }
+
+    fn mode_allowed(&self, mode: u32) -> bool {
+        if MOUSE_MODES.contains(&mode) {
+            self.caps.supports(Capability::Mouse)
+        } else if mode == BRACKETED_PASTE_MODE {
+            self.caps.supports(Capability::BracketedPaste)
+        } else {
+            true
+        }
+    }
+}
+
+/// Byte offset just past the BEL or ST ending an OSC payload.
+fn osc_end(payload: &str) -> Option<usize> {
+    [payload.find('\x07').map(|i| i + 1), payload.find("\x1b\\").map(|i| i + 2)]
+        .into_iter()
+        .flatten()
+        .min()
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Log target for every injected failure, so they are never mistaken for real ones.
+pub const CHAOS_TARGET: &str = "forge::chaos";
+
+pub const CHAOS_FLAG: &str = "--chaos";
+/// Required next to `--chaos` in release builds.
+pub const CONFIRM_FLAG: &str = "--yes-i-know";
+
+/// What to break, globally or for one terminal session. Zero means off.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosSettings {
+    /// Holds each outgoing message back for a random time up to this many ms.
+    pub delay_output_ms: u64,
+    /// Drops every Nth outgoing message.
+    pub drop_every: u32,
+    /// Closes the connection with this code instead of sending its next message.
+    pub close_code: Option<u16>,
+    /// Sleeps this long before handling each chunk of shell output.
+    pub slow_read_ms: u64,
+    /// Share of API calls answered with a 500, in percent.
+    pub api_error_percent: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ChaosError {
+    #[error("chaos mode is off; start the server with {CHAOS_FLAG}")]
+    Disabled,
+    #[error("refusing {CHAOS_FLAG} in a release build without {CONFIRM_FLAG}")]
+    NotConfirmed,
+}
+
+impl ChaosError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChaosError::Disabled => "chaos_disabled",
+            ChaosError::NotConfirmed => "chaos_not_confirmed",
+        }
+    }
+}
+
+/// What an outgoing message should suffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFault {
+    Deliver,
+    Delay(Duration),
+    Drop,
+    Close(u16),
+}
+
+#[derive(Debug, Default)]
+struct ChaosState {
+    global: ChaosSettings,
+    sessions: HashMap<String, ChaosSettings>,
+}
+
+/// Developer-only failure injection. Off unless the server was started with `--chaos`,
+/// in which case the settings start empty and are changed through the admin API.
+#[derive(Debug, Clone, Default)]
+pub struct Chaos {
+    state: Option<Arc<RwLock<ChaosState>>>,
+}
+
+impl Chaos {
+    pub fn enabled() -> Self {
+        warn!(target: CHAOS_TARGET, "🌀 Chaos mode is ON; failures on this server may be injected on purpose");
+        Self { state: Some(Arc::default()) }
+    }
+
+    /// Reads `--chaos` (and `--yes-i-know` for release builds) from the command line.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ChaosError> {
+        let args: Vec<String> = args.into_iter().collect();
+        if !args.iter().any(|arg| arg == CHAOS_FLAG) {
+            return Ok(Self::default());
+        }
+        if !cfg!(debug_assertions) && !args.iter().any(|arg| arg == CONFIRM_FLAG) {
+            return Err(ChaosError::NotConfirmed);
+        }
+        Ok(Self::enabled())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Replaces the global settings, or one session's when `session_id` is given.
+    pub fn set(&self, session_id: Option<&str>, settings: ChaosSettings) -> Result<(), ChaosError> {
+        let state = self.state.as_ref().ok_or(ChaosError::Disabled)?;
+        warn!(target: CHAOS_TARGET, "🌀 Chaos settings for {}: {:?}", session_id.unwrap_or("all sessions"), settings);
+        let mut state = state.write().unwrap_or_else(|e| e.into_inner());
+        match session_id {
+            Some(id) if settings == ChaosSettings::default() => {
+                state.sessions.remove(id);
+            }
+            Some(id) => {
+                state.sessions.insert(id.to_string(), settings);
+            }
+            None => state.global = settings,
+        }
+        Ok(())
+    }
+
+    /// The settings in force for `session_id`: its own if it has any, else the global ones.
+    pub fn settings(&self, session_id: Option<&str>) -> ChaosSettings {
+        let Some(state) = &self.state else { return ChaosSettings::default() };
+        let state = state.read().unwrap_or_else(|e| e.into_inner());
+        session_id
+            .and_then(|id| state.sessions.get(id))
+            .unwrap_or(&state.global)
+            .clone()
+    }
+
+    /// Forgets a finished session's settings.
+    pub fn forget(&self, session_id: &str) {
+        if let Some(state) = &self.state {
+            state.write().unwrap_or_else(|e| e.into_inner()).sessions.remove(session_id);
+        }
+    }
+
+    /// Decides the fate of a session's `sent`-th outgoing message (counting from 1).
+    pub fn output_fault(&self, session_id: &str, sent: u64) -> OutputFault {
+        if !self.is_enabled() {
+            return OutputFault::Deliver;
+        }
+        let settings = self.settings(Some(session_id));
+        let fault = if let Some(code) = settings.close_code {
+            OutputFault::Close(code)
+        } else if settings.drop_every > 0 && sent.is_multiple_of(u64::from(settings.drop_every)) {
+            OutputFault::Drop
+        } else if settings.delay_output_ms > 0 {
+            OutputFault::Delay(Duration::from_millis(roll(settings.delay_output_ms + 1)))
+        } else {
+            OutputFault::Deliver
+        };
+        if fault != OutputFault::Deliver {
+            warn!(target: CHAOS_TARGET, "🌀 Session {} message {}: {:?}", session_id, sent, fault);
+        }
+        fault
+    }
+
+    /// Sleeps for the session's `slow_read_ms`, if any.
+    pub async fn slow_read(&self, session_id: &str) {
+        let delay = self.settings(Some(session_id)).slow_read_ms;
+        if delay > 0 {
+            warn!(target: CHAOS_TARGET, "🌀 Session {} read slowed by {} ms", session_id, delay);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+    }
+
+    /// Whether this API call should fail with an injected 500.
+    pub fn fail_api_call(&self, path: &str) -> bool {
+        let percent = self.settings(None).api_error_percent;
+        let fail = percent > 0 && roll(100) < u64::from(percent);
+        if fail {
+            warn!(target: CHAOS_TARGET, "🌀 Injected 500 for {}", path);
+        }
+        fail
+    }
+}
+
+/// Uniform in `0..below`; quality only matters for spreading faults around.
+fn roll(below: u64) -> u64 {
+    (Uuid::new_v4().as_u128() % u128::from(below.max(1))) as u64
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use crate::session_env::EnvironmentRequest;
+
+pub const MAX_VARS: usize = 64;
+pub const MAX_VALUE_BYTES: usize = 4096;
+/// Variables the server sets on every session itself.
+pub const RESERVED: &[&str] = &["TMPDIR"];
+
+/// Variables a client asked to have in its session's environment, like `NODE_ENV=development`.
+pub type ClientEnv = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ClientEnvError {
+    #[error("'{0}' is not NAME=value")]
+    Malformed(String),
+    #[error("'{0}' is not a valid variable name")]
+    InvalidName(String),
+    #[error("value of {0} is over {MAX_VALUE_BYTES} bytes or holds a NUL")]
+    InvalidValue(String),
+    #[error("{0} is set by the server")]
+    Reserved(String),
+    #[error("at most {MAX_VARS} variables per session")]
+    TooMany,
+}
+
+impl ClientEnvError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientEnvError::Reserved(_) => "reserved_env",
+            ClientEnvError::TooMany => "too_many_env_vars",
+            _ => "invalid_env",
+        }
+    }
+}
+
+/// Splits `NAME=value` at the first `=` and adds it, replacing an earlier value.
+pub fn insert(env: &mut ClientEnv, pair: &str) -> Result<(), ClientEnvError> {
+    let (name, value) = pair.split_once('=').ok_or_else(|| ClientEnvError::Malformed(pair.to_string()))?;
+    set(env, name, value)
+}
+
+/// Adds or replaces one variable.
+pub fn set(env: &mut ClientEnv, name: &str, value: &str) -> Result<(), ClientEnvError> {
+    check(name, value)?;
+    if env.len() >= MAX_VARS && !env.contains_key(name) {
+        return Err(ClientEnvError::TooMany);
+    }
+    env.insert(name.to_string(), value.to_string());
+    Ok(())
+}
+
+/// A POSIX name (`[A-Za-z_][A-Za-z0-9_]*`) the server does not set itself, with a value
+/// a process can be given.
+pub fn check(name: &str, value: &str) -> Result<(), ClientEnvError> {
+    let valid_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(ClientEnvError::InvalidName(name.to_string()));
+    }
+    if RESERVED.contains(&name) {
+        return Err(ClientEnvError::Reserved(name.to_string()));
+    }
+    if value.len() > MAX_VALUE_BYTES || value.contains('\0') {
+        return Err(ClientEnvError::InvalidValue(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Takes `TERM`, `LANG`, `LC_ALL` and `COLORTERM` out of `env` as the request they
+/// stand for, so they pass the `[terminal.environment]` rules like `term=` and friends.
+pub fn take_terminal(env: &mut ClientEnv) -> EnvironmentRequest {
+    EnvironmentRequest {
+        term: env.remove("TERM"),
+        lang: env.remove("LANG"),
+        lc_all: env.remove("LC_ALL"),
+        color: env.remove("COLORTERM"),
+    }
+}
+
+/// Undoes `%XX` escapes in a query value; malformed escapes are kept as they are.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        let escaped = (bytes[at] == b'%')
+            .then(|| value.get(at + 1..at + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                at += 3;
+            }
+            None => {
+                decoded.push(bytes[at]);
+                at += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
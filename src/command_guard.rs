@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::config::{ConfigError, DangerousCommandConfig, DangerousPattern};
+
+/// Patterns used when the config does not supply its own.
+const DEFAULT_PATTERNS: &[(&str, &str)] = &[
+    (
+        "recursive_rm_broad_path",
+        r"\brm\s+(?:-\S+\s+)*(?:-[a-zA-Z]*[rR][a-zA-Z]*|--recursive)\s+(?:-\S+\s+)*(?:/|/\*|~|~/|~/\*|\*|\.|\.\.|\$HOME/?)(?:\s|;|&|\||$)",
+    ),
+    ("mkfs", r"\bmkfs(?:\.\w+)?\b"),
+    ("dd_to_device", r"\bdd\b.*\bof=/dev/"),
+    ("fork_bomb", r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:"),
+];
+
+/// Outcome of checking a command line against the dangerous patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardVerdict {
+    Allowed,
+    ConfirmRequired {
+        pattern: String,
+        token: String,
+        expires_in: Duration,
+    },
+}
+
+struct PendingConfirmation {
+    command: String,
+    expires_at: Instant,
+}
+
+/// Soft guardrail for destructive commands: a matching command is held back and a
+/// single-use confirmation token issued; resubmitting the identical command with
+/// that token before it expires lets it through.
+#[derive(Clone)]
+pub struct CommandGuard {
+    enabled: bool,
+    ttl: Duration,
+    patterns: Arc<Vec<(String, Regex)>>,
+    pending: Arc<Mutex<HashMap<String, PendingConfirmation>>>,
+}
+
+impl CommandGuard {
+    pub fn from_config(config: &DangerousCommandConfig) -> Result<Self, ConfigError> {
+        let configured: Vec<DangerousPattern> = if config.patterns.is_empty() {
+            DEFAULT_PATTERNS
+                .iter()
+                .map(|(name, regex)| DangerousPattern {
+                    name: name.to_string(),
+                    regex: regex.to_string(),
+                })
+                .collect()
+        } else {
+            config.patterns.clone()
+        };
+
+        let patterns = configured
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.regex)
+                    .map(|re| (p.name.clone(), re))
+                    .map_err(|e| ConfigError::Invalid(format!("dangerous_commands pattern '{}': {}", p.name, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            enabled: config.enabled,
+            ttl: Duration::from_secs(config.confirmation_ttl_secs),
+            patterns: Arc::new(patterns),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Name of the first pattern the command line matches, if any.
+    pub fn matched_pattern(&self, command: &str) -> Option<&str> {
+        if !self.enabled {
+            return None;
+        }
+        self.patterns
+            .iter()
+            .find(|(_, re)| re.is_match(command))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Checks `command`, consuming `token` when it confirms this exact command.
+    pub fn check(&self, command: &str, token: Option<&str>) -> GuardVerdict {
+        match self.matched_pattern(command) {
+            Some(pattern) => self.confirm(command, pattern, token),
+            None => GuardVerdict::Allowed,
+        }
+    }
+
+    /// Token handling for a command already known to match `pattern`: a valid token
+    /// for this exact command is consumed, otherwise a fresh one is issued.
+    pub fn confirm(&self, command: &str, pattern: &str, token: Option<&str>) -> GuardVerdict {
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, p| p.expires_at > now);
+
+        if let Some(token) = token {
+            if pending.get(token).is_some_and(|p| p.command == command) {
+                pending.remove(token);
+                return GuardVerdict::Allowed;
+            }
+        }
+
+        let token = Uuid::new_v4().to_string();
+        pending.insert(
+            token.clone(),
+            PendingConfirmation {
+                command: command.to_string(),
+                expires_at: now + self.ttl,
+            },
+        );
+        GuardVerdict::ConfirmRequired {
+            pattern: pattern.to_string(),
+            token,
+            expires_in: self.ttl,
+        }
+    }
+}
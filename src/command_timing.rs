@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::shell_integration::{CommandPhase, OutputEvent};
+
+/// Longest command text carried in `command_finished` payloads, in characters.
+pub const MAX_COMMAND_CHARS: usize = 200;
+/// Commands kept per session for `timings` requests.
+pub const TIMING_HISTORY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinishedCommand {
+    pub command: String,
+    pub duration: Duration,
+    /// Only known from an OSC 133 `D;<code>` marker.
+    pub exit_code: Option<i32>,
+}
+
+/// One row of the timing table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandRecord {
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    /// `None` when the command's end could not be told apart from the output.
+    pub duration_ms: Option<u64>,
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandTimings {
+    /// Oldest first.
+    pub commands: Vec<CommandRecord>,
+    pub slowest: Option<CommandRecord>,
+    /// Sum of the known durations.
+    pub busy_ms: u64,
+}
+
+#[derive(Debug)]
+struct Running {
+    command: String,
+    started: Instant,
+    started_at: DateTime<Utc>,
+}
+
+impl Running {
+    fn new(command: String) -> Self {
+        Self { command, started: Instant::now(), started_at: Utc::now() }
+    }
+}
+
+/// Times commands from submission (or the `C` marker) to completion (the `D` marker,
+/// or the heuristic prompt for shells without OSC 133). Like `PromptDetector`, it
+/// stops trusting the heuristic once the session has shown a marker.
+#[derive(Debug)]
+pub struct CommandTimer {
+    /// Whether a heuristic prompt can end commands while there are no markers.
+    heuristic: bool,
+    /// Last submitted line, waiting for its `C` marker.
+    submitted: Option<Running>,
+    running: Option<Running>,
+    markers: bool,
+    history: VecDeque<CommandRecord>,
+}
+
+impl CommandTimer {
+    pub fn new(heuristic: bool) -> Self {
+        Self {
+            heuristic,
+            submitted: None,
+            running: None,
+            markers: false,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// `line` is the input line as the shell will see it.
+    pub fn submit(&mut self, line: &str) {
+        let submitted = Running::new(truncate(line.trim()));
+        if self.markers {
+            if let Some(unstarted) = self.submitted.replace(submitted) {
+                self.record_unknown(unstarted);
+            }
+        } else if !self.heuristic {
+            self.record_unknown(submitted);
+        } else if self.running.is_none() {
+            self.running = Some(submitted);
+        }
+    }
+
+    pub fn observe(&mut self, events: &[OutputEvent]) -> Vec<FinishedCommand> {
+        let mut finished = Vec::new();
+        for event in events {
+            let OutputEvent::Boundary { phase, exit_code } = event else { continue };
+            if !self.markers {
+                self.markers = true;
+                self.submitted = self.running.take();
+            }
+            match phase {
+                CommandPhase::OutputStart => {
+                    let command = self.submitted.take().map(|submitted| submitted.command).unwrap_or_default();
+                    if let Some(interrupted) = self.running.replace(Running::new(command)) {
+                        self.record_unknown(interrupted);
+                    }
+                }
+                CommandPhase::CommandEnd => {
+                    if let Some(running) = self.running.take() {
+                        finished.push(self.finish(running, *exit_code));
+                    }
+                }
+                CommandPhase::PromptStart | CommandPhase::CommandStart => {}
+            }
+        }
+        finished
+    }
+
+    /// The heuristic prompt came back; ends the running command unless markers are in charge.
+    pub fn prompt(&mut self) -> Option<FinishedCommand> {
+        if self.markers {
+            return None;
+        }
+        let running = self.running.take()?;
+        Some(self.finish(running, None))
+    }
+
+    pub fn timings(&self) -> CommandTimings {
+        let slowest = self
+            .history
+            .iter()
+            .filter(|record| record.duration_ms.is_some())
+            .max_by_key(|record| record.duration_ms)
+            .cloned();
+        CommandTimings {
+            commands: self.history.iter().cloned().collect(),
+            slowest,
+            busy_ms: self.history.iter().filter_map(|record| record.duration_ms).sum(),
+        }
+    }
+
+    /// Forgets the table; a command that is still running stays timed.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    fn finish(&mut self, running: Running, exit_code: Option<i32>) -> FinishedCommand {
+        let duration = running.started.elapsed();
+        self.push(CommandRecord {
+            command: running.command.clone(),
+            started_at: running.started_at,
+            duration_ms: Some(duration.as_millis() as u64),
+            exit_code,
+        });
+        FinishedCommand { command: running.command, duration, exit_code }
+    }
+
+    fn record_unknown(&mut self, running: Running) {
+        self.push(CommandRecord {
+            command: running.command,
+            started_at: running.started_at,
+            duration_ms: None,
+            exit_code: None,
+        });
+    }
+
+    fn push(&mut self, record: CommandRecord) {
+        if self.history.len() == TIMING_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+    }
+}
+
+fn truncate(command: &str) -> String {
+    match command.char_indices().nth(MAX_COMMAND_CHARS) {
+        Some((cut, _)) => format!("{}…", &command[..cut]),
+        None => command.to_string(),
+    }
+}
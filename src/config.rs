@@ -0,0 +1,641 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::pty::{NativePty, PtyBackend};
+use crate::session_env::ColorSupport;
+use crate::webhooks::WebhookEventKind;
+
+/// Environment variable pointing at the config file. Without it `forge.toml`
+/// in the working directory is used when present, otherwise built-in defaults.
+pub const CONFIG_PATH_ENV: &str = "FORGE_CONFIG";
+pub const DEFAULT_CONFIG_FILE: &str = "forge.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForgeConfig {
+    pub dangerous_commands: DangerousCommandConfig,
+    pub approvals: ApprovalConfig,
+    pub execute: ExecuteConfig,
+    pub repl: ReplConfig,
+    pub terminal: TerminalConfig,
+    pub redaction: RedactionConfig,
+    pub shell_env: ShellEnvConfig,
+    pub tmpdir: TmpDirConfig,
+    pub listen: ListenConfig,
+    /// `[[webhooks]]` entries; none by default.
+    pub webhooks: Vec<WebhookConfig>,
+    /// `[[templates]]` entries clients can start sessions from; none by default.
+    pub templates: Vec<TemplateConfig>,
+}
+
+impl ForgeConfig {
+    pub fn load() -> Result<Self, ConfigError> {
+        match std::env::var_os(CONFIG_PATH_ENV) {
+            Some(path) => Self::from_file(Path::new(&path)),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let raw = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&raw).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Commands that need an explicit confirmation round-trip before they run.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DangerousCommandConfig {
+    pub enabled: bool,
+    pub confirmation_ttl_secs: u64,
+    /// Replaces the built-in pattern set when non-empty.
+    pub patterns: Vec<DangerousPattern>,
+}
+
+impl Default for DangerousCommandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            confirmation_ttl_secs: 60,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Commands `/api/execute` holds until an admin approves them. Off while `patterns` is empty.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApprovalConfig {
+    /// Pending requests expire after this long.
+    pub ttl_secs: u64,
+    pub patterns: Vec<DangerousPattern>,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 900,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DangerousPattern {
+    pub name: String,
+    pub regex: String,
+}
+
+/// One-off commands behind `/api/execute`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecuteConfig {
+    /// Runs shell-mode commands as `shell -c COMMAND` (`/C` for cmd).
+    pub shell: String,
+    /// Wall-clock limit; the command and everything it started are killed after it.
+    pub timeout_secs: u64,
+    /// Kept of each of stdout and stderr; the rest is read and dropped.
+    pub max_output_bytes: usize,
+    /// Commands running at once; later ones wait for a slot in arrival order.
+    pub max_concurrent: usize,
+    /// Commands that may wait for a slot; past that they are refused with 429.
+    pub max_queued: usize,
+    /// The `Retry-After` given with those 429s.
+    pub retry_after_secs: u64,
+    /// A command still waiting for a slot after this long is answered with 202 and a job
+    /// to poll; 0 keeps the request open until it has run.
+    pub async_after_secs: u64,
+}
+
+impl Default for ExecuteConfig {
+    fn default() -> Self {
+        Self {
+            shell: DEFAULT_EXECUTE_SHELL.to_string(),
+            timeout_secs: 30,
+            max_output_bytes: 1 << 20,
+            max_concurrent: 4,
+            max_queued: 32,
+            retry_after_secs: 5,
+            async_after_secs: 10,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+const DEFAULT_EXECUTE_SHELL: &str = "sh";
+#[cfg(windows)]
+const DEFAULT_EXECUTE_SHELL: &str = "cmd.exe";
+
+/// Persistent shells behind `/api/repl`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplConfig {
+    pub shell: String,
+    pub max_repls: usize,
+    pub idle_timeout_secs: u64,
+    pub exec_timeout_secs: u64,
+    /// Sourced by every new shell before it is handed out.
+    pub init_script: Option<InitScript>,
+    /// Lets `POST /api/repl` add its own `init` script after the configured one.
+    pub allow_client_init: bool,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        Self {
+            shell: "sh".to_string(),
+            max_repls: 16,
+            idle_timeout_secs: 600,
+            exec_timeout_secs: 30,
+            init_script: None,
+            allow_client_init: false,
+        }
+    }
+}
+
+/// `init_script = { path = "..." }` or `init_script = { inline = "..." }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum InitScript {
+    Path(PathBuf),
+    Inline(String),
+}
+
+/// What the pty-server shows a client right after it connects.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    /// Started in a PTY for every session whose template names no shell or command.
+    pub shell: String,
+    /// More shells a client may pick with `?shell=`, like `zsh` or `/bin/rbash`.
+    pub allowed_shells: Vec<String>,
+    pub banner: BannerConfig,
+    /// Appended to the banner; re-read on SIGHUP.
+    pub motd_file: Option<PathBuf>,
+    pub prompt_detection: PromptDetectionConfig,
+    /// Keep a vt100 model of each session's screen for `screen_snapshot` requests.
+    /// Costs a parse of all output, so it is off unless asked for.
+    pub screen_model: bool,
+    /// Output retained per session for search, in bytes.
+    pub scrollback_bytes: usize,
+    /// Most recent output replayed to a client resuming a session, in bytes; 0 replays
+    /// nothing. Connect URLs may ask for less with `?replay_bytes=`.
+    pub replay_bytes: usize,
+    /// Sessions the pty-server holds at once, lingering ones included; unset is unlimited.
+    pub max_sessions: Option<usize>,
+    /// Terminal connections open at once from one client address; unset is unlimited.
+    pub max_connections_per_ip: Option<usize>,
+    /// Clients that may open a session alongside the one that started it, with the
+    /// `share_token` from its hello; 0 turns sharing off.
+    pub max_shared_clients: usize,
+    pub long_commands: LongCommandConfig,
+    pub environment: EnvironmentConfig,
+    pub max_lifetime: MaxLifetimeConfig,
+    pub idle_timeout: IdleTimeoutConfig,
+    pub bandwidth: BandwidthConfig,
+    pub resources: ResourceConfig,
+    pub orphaned_io: OrphanedIoConfig,
+    pub disconnect: DisconnectConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub persistence: PersistenceConfig,
+    pub observe: ObserveConfig,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            shell: NativePty::DEFAULT_SHELL.to_string(),
+            allowed_shells: Vec::new(),
+            banner: BannerConfig::default(),
+            motd_file: None,
+            prompt_detection: PromptDetectionConfig::default(),
+            screen_model: false,
+            scrollback_bytes: 1 << 20,
+            replay_bytes: 64 << 10,
+            max_sessions: None,
+            max_connections_per_ip: None,
+            max_shared_clients: 4,
+            long_commands: LongCommandConfig::default(),
+            environment: EnvironmentConfig::default(),
+            max_lifetime: MaxLifetimeConfig::default(),
+            idle_timeout: IdleTimeoutConfig::default(),
+            bandwidth: BandwidthConfig::default(),
+            resources: ResourceConfig::default(),
+            orphaned_io: OrphanedIoConfig::default(),
+            disconnect: DisconnectConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            persistence: PersistenceConfig::default(),
+            observe: ObserveConfig::default(),
+        }
+    }
+}
+
+/// `banner = "template"`, `banner = true` for the built-in greeting, or `banner = false`
+/// to send only the structured hello message.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum BannerConfig {
+    Enabled(bool),
+    Template(String),
+}
+
+impl Default for BannerConfig {
+    fn default() -> Self {
+        BannerConfig::Enabled(true)
+    }
+}
+
+/// Heuristic `prompt` events for shells that do not emit OSC 133 markers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptDetectionConfig {
+    pub enabled: bool,
+    /// Matched against the last line of output.
+    pub pattern: String,
+    /// How long output must stay quiet before a match counts as a prompt.
+    pub debounce_ms: u64,
+}
+
+impl Default for PromptDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pattern: r"[$#>] $".to_string(),
+            debounce_ms: 150,
+        }
+    }
+}
+
+/// Default `TERM`, locale and color hint for new sessions; clients may override
+/// them per session within these rules.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    pub term: String,
+    pub lang: String,
+    pub lc_all: Option<String>,
+    pub color: ColorSupport,
+    /// `TERM` values a client may ask for.
+    pub allowed_terms: Vec<String>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            term: "xterm-256color".to_string(),
+            lang: "C.UTF-8".to_string(),
+            lc_all: None,
+            color: ColorSupport::Truecolor,
+            allowed_terms: ["xterm-256color", "xterm", "screen-256color", "screen", "tmux-256color", "vt100", "dumb"]
+                .map(str::to_string)
+                .to_vec(),
+        }
+    }
+}
+
+/// What gets masked in logs, audit records and webhook payloads. Re-read on SIGHUP.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Built-in patterns for common token/password flags and `KEY=VALUE` secrets.
+    pub defaults: bool,
+    /// Extra regexes; a `(?P<secret>...)` group limits the mask to that part of the match.
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            defaults: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Where the two servers listen. Port 0 picks a free port; the bound address is
+/// printed on stdout as a plain `listening on <addr>` line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    pub api: SocketAddr,
+    pub terminal: SocketAddr,
+    /// Browser origins that may call the API and open terminal WebSockets, as
+    /// `scheme://host[:port]`. Requests without an `Origin` header are not affected.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            api: SocketAddr::from(([127, 0, 0, 1], 3001)),
+            terminal: SocketAddr::from(([127, 0, 0, 1], 3002)),
+            allowed_origins: ["http://localhost:3001", "http://127.0.0.1:3001", "http://localhost:8080", "http://127.0.0.1:8080"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// What spawned shells inherit from the server's environment. Only `PATH`, `HOME`, `USER`,
+/// `TERM` and `LANG` pass by default; everything else has to be named here.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShellEnvConfig {
+    /// Extra variable names to pass through.
+    pub allow: Vec<String>,
+    /// Globs like `CI_*` for deployments that need whole families of variables.
+    pub pass_env: Vec<String>,
+}
+
+/// A private `TMPDIR` per terminal session and REPL, removed when it ends.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TmpDirConfig {
+    /// `false` shares the system TMPDIR between sessions, as before.
+    pub enabled: bool,
+    /// Defaults to `$XDG_RUNTIME_DIR/forge`, or `forge` under the system temp dir.
+    pub root: Option<PathBuf>,
+    /// Size above which the session gets a `tmpdir_warning`.
+    pub max_bytes: Option<u64>,
+    pub check_interval_secs: u64,
+}
+
+impl Default for TmpDirConfig {
+    fn default() -> Self {
+        Self { enabled: true, root: None, max_bytes: None, check_interval_secs: 30 }
+    }
+}
+
+/// `command_finished` events for commands that ran at least `threshold_secs`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LongCommandConfig {
+    pub enabled: bool,
+    pub threshold_secs: u64,
+    /// Also send them to webhooks subscribed to `command_finished`.
+    pub webhook: bool,
+}
+
+impl Default for LongCommandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_secs: 30,
+            webhook: false,
+        }
+    }
+}
+
+/// Hard cap on how long a session may live, however busy it is. No cap when `secs` is unset.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaxLifetimeConfig {
+    pub secs: Option<u64>,
+    pub overrides: Vec<LifetimeOverrideConfig>,
+}
+
+/// Ends sessions that have had neither input nor output for `secs`. Off when `secs` is unset.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleTimeoutConfig {
+    pub secs: Option<u64>,
+    /// How often sessions are checked, so one may run up to this much past `secs`.
+    pub check_interval_secs: u64,
+}
+
+impl Default for IdleTimeoutConfig {
+    fn default() -> Self {
+        Self { secs: None, check_interval_secs: 30 }
+    }
+}
+
+/// Where sessions are saved at shutdown for their clients to resume after a restart.
+/// Off unless `path` is set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistenceConfig {
+    pub path: Option<PathBuf>,
+}
+
+/// Admins watching a session's output over `/admin/ws`. Off unless `enabled` is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObserveConfig {
+    pub enabled: bool,
+    /// Observe without the session's client being sent `observer_joined`.
+    pub covert: bool,
+}
+
+/// A different cap for connections presenting the token stored in `token_env`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LifetimeOverrideConfig {
+    pub token_env: String,
+    pub secs: u64,
+}
+
+/// Output rate limits for terminal sessions; unshaped unless a rate is set. Re-read on SIGHUP.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthConfig {
+    /// Shared by all sessions.
+    pub global_bytes_per_sec: Option<u64>,
+    pub session_bytes_per_sec: Option<u64>,
+    /// How far a quiet session may get ahead of its rate.
+    pub burst_bytes: u64,
+    /// Output chunks up to this size are never held, so keystroke echo stays prompt.
+    pub interactive_bytes: usize,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            global_bytes_per_sec: None,
+            session_bytes_per_sec: None,
+            burst_bytes: 64 * 1024,
+            interactive_bytes: 256,
+        }
+    }
+}
+
+/// CPU and memory sampling of each session's processes. Nothing is sampled unless
+/// `interval_ms` is set.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceConfig {
+    pub interval_ms: Option<u64>,
+    /// Samples kept per session.
+    pub history: usize,
+    /// Resident memory above which the session gets a `resource_warning`.
+    pub warn_rss_bytes: Option<u64>,
+    pub kill_rss_bytes: Option<u64>,
+    pub kill_cpu_percent: Option<f32>,
+    /// Consecutive samples over a kill threshold before the session is closed.
+    pub kill_after_samples: u32,
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: None,
+            history: 60,
+            warn_rss_bytes: None,
+            kill_rss_bytes: None,
+            kill_cpu_percent: None,
+            kill_after_samples: 3,
+        }
+    }
+}
+
+/// What happens when a session's shell exits but a process it left behind still holds
+/// the PTY open.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrphanedIoConfig {
+    pub mode: OrphanedIoMode,
+    /// How long `close` lets the leftovers run before killing them and the session.
+    pub grace_secs: u64,
+}
+
+impl Default for OrphanedIoConfig {
+    fn default() -> Self {
+        Self { mode: OrphanedIoMode::Close, grace_secs: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanedIoMode {
+    /// Keep streaming the leftovers' output until they close the PTY themselves.
+    KeepStreaming,
+    Close,
+}
+
+/// How a session's processes are torn down once its client goes away.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisconnectConfig {
+    /// How long the shell keeps running after the client disconnects; 0 hangs it up at once.
+    pub linger_secs: u64,
+    /// How long processes get between SIGHUP and SIGKILL.
+    pub kill_grace_secs: u64,
+}
+
+impl Default for DisconnectConfig {
+    fn default() -> Self {
+        Self { linger_secs: 0, kill_grace_secs: 2 }
+    }
+}
+
+/// Server pings that notice clients which vanished without closing their connection.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    /// Seconds between pings; 0 sends none.
+    pub interval_secs: u64,
+    /// Pings in a row a client may leave unanswered before its connection is closed.
+    pub missed_pongs: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { interval_secs: 30, missed_pongs: 3 }
+    }
+}
+
+/// A canned session setup, picked at connect time with `?template=<name>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Shell to start instead of the default one.
+    pub shell: Option<String>,
+    /// Program and arguments run directly instead of a shell, like `["psql", "app"]`.
+    #[serde(default)]
+    pub command: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Set on top of the `[shell_env]` allowlist.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub term: Option<String>,
+    pub lang: Option<String>,
+    pub lc_all: Option<String>,
+    pub color: Option<String>,
+    pub detect_links: Option<bool>,
+    /// Replaces `terminal.scrollback_bytes` for these sessions.
+    pub scrollback_bytes: Option<usize>,
+    #[serde(default)]
+    pub record: bool,
+    #[serde(default)]
+    pub policy: TemplatePolicyConfig,
+    /// Connect-time fields the client may still set: term, lang, lc_all, color, detect_links.
+    #[serde(default)]
+    pub overridable: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplatePolicyConfig {
+    /// `false` lets input through without the dangerous-command confirmation.
+    pub confirm_dangerous: bool,
+    /// Shortens `terminal.max_lifetime` for these sessions; never lengthens it.
+    pub max_lifetime_secs: Option<u64>,
+}
+
+impl Default for TemplatePolicyConfig {
+    fn default() -> Self {
+        Self {
+            confirm_dangerous: true,
+            max_lifetime_secs: None,
+        }
+    }
+}
+
+/// One HTTP endpoint that receives lifecycle events as signed JSON POSTs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Plain `http://` only; put a TLS-terminating relay in front for anything remote.
+    pub url: String,
+    /// Events to deliver; empty means all of them.
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+    /// Key for the `X-Forge-Signature` HMAC; unsigned when absent.
+    pub secret: Option<String>,
+    /// `execute_slow` only fires for executions at least this long.
+    #[serde(default = "default_slow_execute_ms")]
+    pub slow_execute_ms: u64,
+}
+
+fn default_slow_execute_ms() -> u64 {
+    10_000
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Refused because the address already has `limit` terminal connections open.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("too many connections from {ip} (limit {limit})")]
+pub struct TooManyConnections {
+    pub ip: IpAddr,
+    pub limit: usize,
+}
+
+impl TooManyConnections {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        "too_many_connections"
+    }
+}
+
+/// Open terminal connections per client address, capped at `max_per_ip`.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimiter {
+    max_per_ip: Option<usize>,
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionLimiter {
+    /// `None` counts connections without refusing any.
+    pub fn new(max_per_ip: Option<usize>) -> Self {
+        Self { max_per_ip, open: Arc::default() }
+    }
+
+    /// Counts a connection from `ip` until the returned permit is dropped.
+    pub fn acquire(&self, ip: IpAddr) -> Result<ConnectionPermit, TooManyConnections> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.get(&ip).copied().unwrap_or_default();
+        if let Some(limit) = self.max_per_ip.filter(|limit| count >= *limit) {
+            return Err(TooManyConnections { ip, limit });
+        }
+        open.insert(ip, count + 1);
+        Ok(ConnectionPermit { open: self.open.clone(), ip })
+    }
+
+    /// Connections from `ip` currently holding a permit.
+    pub fn open(&self, ip: IpAddr) -> usize {
+        self.open.lock().unwrap().get(&ip).copied().unwrap_or_default()
+    }
+}
+
+/// One counted connection; dropping it frees the slot.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+    }
+}
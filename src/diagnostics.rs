@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Per-session protocol anomaly counters, bumped inline from the message loop
+/// without taking the session lock.
+#[derive(Debug, Default)]
+pub struct ProtocolCounters {
+    malformed_messages: AtomicU64,
+    unknown_types: AtomicU64,
+    oversized_frames: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProtocolCountersSnapshot {
+    pub malformed_messages: u64,
+    pub unknown_types: u64,
+    pub oversized_frames: u64,
+}
+
+impl ProtocolCounters {
+    /// Invalid JSON, a message without a `type` or an undecodable binary frame.
+    pub fn malformed_message(&self) {
+        self.malformed_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn unknown_type(&self) {
+        self.unknown_types.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn oversized_frame(&self) {
+        self.oversized_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ProtocolCountersSnapshot {
+        ProtocolCountersSnapshot {
+            malformed_messages: self.malformed_messages.load(Ordering::Relaxed),
+            unknown_types: self.unknown_types.load(Ordering::Relaxed),
+            oversized_frames: self.oversized_frames.load(Ordering::Relaxed),
+        }
+    }
+}
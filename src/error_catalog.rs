@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::admin::AdminRejection;
+use crate::approvals::ApprovalError;
+use crate::chaos::{ChaosError, CHAOS_FLAG, CONFIRM_FLAG};
+use crate::client_env::{ClientEnvError, MAX_VALUE_BYTES, MAX_VARS};
+use crate::executor::ExecError;
+use crate::log_control::LogLevelError;
+use crate::multiplex::MuxError;
+use crate::connection_limits::TooManyConnections;
+use crate::protocol::{CloseReason, DecodeError};
+use crate::pty::PtyError;
+use crate::repl::ReplError;
+use crate::scrollback::SearchError;
+use crate::session_env::EnvironmentError;
+use crate::session_registry::RegistryError;
+use crate::session_names::{NameError, MAX_NAME_LEN};
+use crate::session_tags::{TagError, MAX_KEY_LEN, MAX_TAGS, MAX_VALUE_LEN};
+use crate::shell_policy::ShellError;
+use crate::templates::TemplateError;
+use crate::workspaces::{WorkspaceError, MAX_WORKSPACE_LEN};
+
+/// One client-visible error: its stable code and default English message, with `{name}`
+/// placeholders for the parameters sent alongside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CatalogEntry {
+    pub code: &'static str,
+    pub message: &'static str,
+}
+
+const fn entry(code: &'static str, message: &'static str) -> CatalogEntry {
+    CatalogEntry { code, message }
+}
+
+/// Every error the servers show clients, over HTTP, WebSocket messages or close frames.
+/// `GET /api/errors` serves it so the frontend can translate ahead of time.
+pub const CATALOG: &[CatalogEntry] = &[
+    // HTTP
+    entry("not_found", "🔍 Rick says: Path not found in this dimension!"),
+    entry("invalid_json", "🧪 Rick says: Invalid JSON, Morty!"),
+    entry("method_not_allowed", "🚫 Rick says: Method not allowed in this universe!"),
+    entry("internal_error", "💥 Rick says: Something went wrong in the multiverse!"),
+    entry("admin_disabled", "🔒 Rick says: The admin API is disabled in this dimension!"),
+    entry("auth_failed", "🔒 Rick says: Nice try, but you're not the admin, Morty!"),
+    entry("api_disabled", "commands are disabled until FORGE_API_TOKEN or FORGE_ADMIN_TOKEN is set"),
+    entry("origin_not_allowed", "requests from this origin are not allowed ([listen] allowed_origins)"),
+    entry("chaos_injected", "🌀 Rick says: Chaos mode ate this request on purpose!"),
+    entry("chaos_disabled", "chaos mode is off; start the server with {flag}"),
+    entry("chaos_not_confirmed", "refusing {flag} in a release build without {confirm_flag}"),
+    entry("invalid_log_directive", "invalid log directive: {error}"),
+    entry("log_reload_failed", "failed to reload log filter: {error}"),
+    // Command policy and approvals
+    entry("command_rejected", "🧪 Rick says: Can't run that, Morty! {reason}"),
+    entry("confirmation_required", "🧪 Rick says: Whoa there! Resubmit with the confirmation token if you really mean it."),
+    entry("approval_required", "🧪 Rick says: This one needs a grown-up. Waiting for an approver."),
+    entry("approval_via_execute", "🧪 Rick says: That one needs an approver. Send it through /api/execute."),
+    entry("approval_not_found", "no approval request '{id}'"),
+    entry("approval_expired", "approval request '{id}' expired"),
+    entry("approval_denied", "approval request '{id}' was denied"),
+    entry("approval_already_decided", "approval request '{id}' was already {status}"),
+    entry("execute_spawn_failed", "failed to start {program}: {error}"),
+    entry("execute_timeout", "command timed out after {timeout_secs}s and was killed"),
+    entry("execute_job_not_found", "no execute job '{id}'"),
+    entry("execute_queue_full", "{running} commands are running and {queued} more are waiting; retry in {retry_after_secs}s"),
+    // REPLs
+    entry("repl_not_found", "REPL {id} not found"),
+    entry("repl_limit_reached", "too many REPLs open (limit {limit})"),
+    entry("repl_spawn_failed", "failed to start shell: {error}"),
+    entry("repl_exited", "REPL shell exited"),
+    entry("repl_timeout", "command timed out after {timeout_secs}s; the REPL was closed"),
+    entry("repl_io_failed", "REPL I/O failed: {error}"),
+    entry("repl_init_script", "failed to read init script {path}: {error}"),
+    entry("repl_client_init_disabled", "client-supplied init scripts are disabled (repl.allow_client_init)"),
+    // Terminal connect URLs
+    entry("invalid_term", "term '{term}' is not in the allowed list"),
+    entry("invalid_locale", "'{locale}' is not a valid locale"),
+    entry("invalid_color", "color must be truecolor, 256 or 16, not '{color}'"),
+    entry("unknown_template", "no template named '{template}'"),
+    entry("not_overridable", "template '{template}' does not let clients set '{field}'"),
+    entry(
+        "invalid_tag",
+        "tag '{tag}' must be key:value with a key of 1-{max_key_len} letters, digits, '_', '-' or '.' and a value of 1-{max_value_len} letters, digits or any of _-.:/@+",
+    ),
+    entry("too_many_tags", "at most {max_tags} tags per session"),
+    entry("invalid_workspace", "workspace name '{name}' must be 1-{max_workspace_len} letters, digits, '_', '-' or '.'"),
+    entry("workspace_not_found", "workspace '{name}' not found"),
+    entry("invalid_session_name", "session name '{name}' must be 1-{max_name_len} letters, digits, spaces or any of _-.:/@+#()"),
+    entry(
+        "invalid_env",
+        "environment variable '{name}' must be NAME=value with a name of letters, digits and '_' not starting with a digit, and a value of at most {max_value_bytes} bytes",
+    ),
+    entry("reserved_env", "{name} is set by the server and cannot be changed"),
+    entry("too_many_env_vars", "at most {max_vars} environment variables per session"),
+    entry("invalid_size", "cols and rows must both be given, as numbers from 1 to 65535"),
+    entry("invalid_output", "output must be text, base64 or binary, not '{output}'"),
+    entry("invalid_resume", "session_id and resume_token must be given together"),
+    entry("invalid_replay_bytes", "replay_bytes must be a number of bytes"),
+    entry("invalid_ack_window", "ack_window must be a number of bytes above 0"),
+    // Terminal sessions
+    entry("malformed_message", "invalid JSON: {error}"),
+    entry("missing_type", "missing 'type' field"),
+    entry("unknown_type", "unknown message type '{type}'"),
+    entry("invalid_binary_frame", "invalid binary frame: {error}"),
+    entry("invalid_fields", "invalid '{type}' message: {error}"),
+    entry("screen_model_disabled", "screen model is disabled (terminal.screen_model)"),
+    entry("invalid_search_pattern", "invalid search pattern: {error}"),
+    entry("search_failed", "search failed: {error}"),
+    entry("export_failed", "export failed: {error}"),
+    entry("session_not_found", "session {id} not found"),
+    entry("session_exists", "session {id} already exists"),
+    entry("session_attached", "session {id} already has a client attached"),
+    entry("resume_rejected", "session {id} cannot be resumed with that token"),
+    entry("share_rejected", "session {id} cannot be shared with that token"),
+    entry("share_full", "session {id} already has {limit} shared clients"),
+    entry("already_attached", "this connection is already attached to session {id}"),
+    entry("missing_channel", "messages on a multiplexed connection need a numeric 'channel'"),
+    entry("channel_in_use", "channel {channel} is already open"),
+    entry("unknown_channel", "channel {channel} is not open"),
+    entry("owner_only", "only the client that started the session can send that"),
+    entry("too_many_connections", "too many connections from {ip} (limit {limit})"),
+    entry("shell_spawn_failed", "failed to start the session's shell: {error}"),
+    entry("signal_failed", "could not send {signal}: {error}"),
+    entry("job_not_found", "no job {job} in this session; ask for jobs again"),
+    entry("shell_not_allowed", "shell '{shell}' is not allowed; choose one of {allowed}"),
+    // Admin channel
+    entry("notice_empty", "notice is empty after sanitizing"),
+    entry("invalid_admin_command", "invalid admin command: {error}"),
+    entry("observe_disabled", "observing sessions is disabled (terminal.observe)"),
+    // Close reasons
+    entry("normal", "the session ended"),
+    entry("server_shutdown", "the server is shutting down"),
+    entry("protocol_error", "the connection broke the WebSocket protocol"),
+    entry("policy_violation", "the connection was refused by policy"),
+    entry("message_too_big", "a message was over the size limit"),
+    entry("server_full", "the server has no room for another session"),
+    entry("admin_disconnect", "an administrator closed the connection"),
+    entry("idle_timeout", "the session was idle for too long"),
+    entry("superseded", "another client took over the session"),
+    entry("max_lifetime", "the session reached its maximum lifetime"),
+    entry("resource_limit", "the session stayed over its resource limits"),
+    entry("chaos", "chaos mode closed the connection"),
+];
+
+pub fn lookup(code: &str) -> Option<&'static CatalogEntry> {
+    CATALOG.iter().find(|entry| entry.code == code)
+}
+
+/// The parameter names in a catalog message, in order of appearance.
+pub fn placeholders(message: &str) -> Vec<&str> {
+    message
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+/// A catalog error as sent to a client: the code, the rendered English message, and the
+/// parameters for clients that render their own translation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClientError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<&'static str, String>,
+}
+
+impl ClientError {
+    /// Codes missing from the catalog render as the bare code.
+    pub fn new(code: &'static str) -> Self {
+        let message = lookup(code).map_or(code, |entry| entry.message).to_string();
+        Self { code, message, params: BTreeMap::new() }
+    }
+
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+        let value = value.to_string();
+        self.message = self.message.replace(&format!("{{{}}}", name), &value);
+        self.params.insert(name, value);
+        self
+    }
+}
+
+impl From<&AdminRejection> for ClientError {
+    fn from(e: &AdminRejection) -> Self {
+        ClientError::new(e.code())
+    }
+}
+
+impl From<&ApprovalError> for ClientError {
+    fn from(e: &ApprovalError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            ApprovalError::NotFound(id) | ApprovalError::Expired(id) => error.with("id", id),
+            ApprovalError::AlreadyDecided { id, status } => error.with("id", id).with("status", format!("{:?}", status).to_lowercase()),
+        }
+    }
+}
+
+impl From<&ChaosError> for ClientError {
+    fn from(e: &ChaosError) -> Self {
+        let error = ClientError::new(e.code()).with("flag", CHAOS_FLAG);
+        match e {
+            ChaosError::Disabled => error,
+            ChaosError::NotConfirmed => error.with("confirm_flag", CONFIRM_FLAG),
+        }
+    }
+}
+
+impl From<&LogLevelError> for ClientError {
+    fn from(e: &LogLevelError) -> Self {
+        match e {
+            LogLevelError::InvalidDirective(error) | LogLevelError::Reload(error) => ClientError::new(e.code()).with("error", error),
+        }
+    }
+}
+
+impl From<&ExecError> for ClientError {
+    fn from(e: &ExecError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            ExecError::Spawn { program, source } => error.with("program", program).with("error", source),
+            ExecError::Timeout(timeout) => error.with("timeout_secs", timeout.as_secs()),
+            ExecError::QueueFull { running, queued, retry_after } => {
+                error.with("running", running).with("queued", queued).with("retry_after_secs", retry_after.as_secs())
+            }
+        }
+    }
+}
+
+impl From<&ReplError> for ClientError {
+    fn from(e: &ReplError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            ReplError::NotFound(id) => error.with("id", id),
+            ReplError::LimitReached(limit) => error.with("limit", limit),
+            ReplError::Spawn(source) | ReplError::Io(source) => error.with("error", source),
+            ReplError::Timeout(timeout) => error.with("timeout_secs", timeout.as_secs()),
+            ReplError::InitScript { path, source } => error.with("path", path.display()).with("error", source),
+            ReplError::Exited | ReplError::ClientInitDisabled => error,
+        }
+    }
+}
+
+impl From<&EnvironmentError> for ClientError {
+    fn from(e: &EnvironmentError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            EnvironmentError::InvalidTerm(term) => error.with("term", term),
+            EnvironmentError::InvalidLocale(locale) => error.with("locale", locale),
+            EnvironmentError::InvalidColor(color) => error.with("color", color),
+        }
+    }
+}
+
+impl From<&ClientEnvError> for ClientError {
+    fn from(e: &ClientEnvError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            ClientEnvError::Malformed(name) | ClientEnvError::InvalidName(name) | ClientEnvError::InvalidValue(name) => {
+                error.with("name", name).with("max_value_bytes", MAX_VALUE_BYTES)
+            }
+            ClientEnvError::Reserved(name) => error.with("name", name),
+            ClientEnvError::TooMany => error.with("max_vars", MAX_VARS),
+        }
+    }
+}
+
+impl From<&ShellError> for ClientError {
+    fn from(e: &ShellError) -> Self {
+        match e {
+            ShellError::NotAllowed { shell, allowed } => ClientError::new(e.code()).with("shell", shell).with("allowed", allowed),
+        }
+    }
+}
+
+impl From<&TemplateError> for ClientError {
+    fn from(e: &TemplateError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            TemplateError::Unknown(template) => error.with("template", template),
+            TemplateError::NotOverridable { template, field } => error.with("template", template).with("field", field),
+        }
+    }
+}
+
+impl From<&TagError> for ClientError {
+    fn from(e: &TagError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            TagError::Malformed(tag) | TagError::InvalidKey(tag) | TagError::InvalidValue(tag) => {
+                error.with("tag", tag).with("max_key_len", MAX_KEY_LEN).with("max_value_len", MAX_VALUE_LEN)
+            }
+            TagError::TooMany => error.with("max_tags", MAX_TAGS),
+        }
+    }
+}
+
+impl From<&NameError> for ClientError {
+    fn from(e: &NameError) -> Self {
+        ClientError::new(e.code()).with("name", &e.0).with("max_name_len", MAX_NAME_LEN)
+    }
+}
+
+impl From<&WorkspaceError> for ClientError {
+    fn from(e: &WorkspaceError) -> Self {
+        match e {
+            WorkspaceError::InvalidName(name) => ClientError::new(e.code()).with("name", name).with("max_workspace_len", MAX_WORKSPACE_LEN),
+            WorkspaceError::NotFound(name) => ClientError::new(e.code()).with("name", name),
+        }
+    }
+}
+
+impl From<&TooManyConnections> for ClientError {
+    fn from(e: &TooManyConnections) -> Self {
+        ClientError::new(e.code()).with("ip", e.ip).with("limit", e.limit)
+    }
+}
+
+impl From<&DecodeError> for ClientError {
+    fn from(e: &DecodeError) -> Self {
+        let error = ClientError::new(e.code());
+        match e {
+            DecodeError::InvalidJson(source) => error.with("error", source),
+            DecodeError::MissingType => error,
+            DecodeError::UnknownType(msg_type) => error.with("type", msg_type),
+            DecodeError::InvalidFields { msg_type, error: source } => error.with("type", msg_type).with("error", source),
+            DecodeError::InvalidBinary(source) => error.with("error", source),
+        }
+    }
+}
+
+impl From<&MuxError> for ClientError {
+    fn from(e: &MuxError) -> Self {
+        match e {
+            MuxError::Decode(e) => e.into(),
+            MuxError::MissingChannel => ClientError::new(e.code()),
+            MuxError::ChannelInUse(channel) | MuxError::UnknownChannel(channel) => ClientError::new(e.code()).with("channel", channel),
+        }
+    }
+}
+
+impl From<&SearchError> for ClientError {
+    fn from(e: &SearchError) -> Self {
+        match e {
+            SearchError::InvalidPattern(source) => ClientError::new(e.code()).with("error", source),
+        }
+    }
+}
+
+impl From<&RegistryError> for ClientError {
+    fn from(e: &RegistryError) -> Self {
+        match e {
+            RegistryError::NotFound(id) | RegistryError::AlreadyExists(id) | RegistryError::AlreadyAttached(id) => {
+                ClientError::new(e.code()).with("id", id)
+            }
+            RegistryError::Full(_) => ClientError::new(e.code()),
+        }
+    }
+}
+
+impl From<&PtyError> for ClientError {
+    fn from(e: &PtyError) -> Self {
+        match e {
+            PtyError::Signal { signal, error } => ClientError::new(e.code()).with("signal", signal).with("error", error),
+            PtyError::UnknownJob(job) => ClientError::new(e.code()).with("job", job),
+            _ => ClientError::new(e.code()).with("error", e),
+        }
+    }
+}
+
+impl From<CloseReason> for ClientError {
+    fn from(reason: CloseReason) -> Self {
+        ClientError::new(reason.reason())
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::executor::{QueuePlace, Ticket};
+use crate::policy::{CommandSpec, ExecMode};
+use crate::redaction;
+
+/// How long a finished job can still be polled.
+const RETENTION_HOURS: i64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+}
+
+/// An execute request answered with 202 while its command waited for a slot.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecJob {
+    pub id: String,
+    /// Redacted command line.
+    pub command: String,
+    pub mode: ExecMode,
+    pub status: JobStatus,
+    /// 1-based place among the commands waiting for a slot, while queued.
+    pub position: Option<usize>,
+    pub queued_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The execute response, or the error body, once finished.
+    pub result: Option<serde_json::Value>,
+    #[serde(skip)]
+    place: QueuePlace,
+}
+
+/// Execute requests that went on as jobs, by their unguessable ID. Finished jobs stay
+/// pollable for an hour.
+#[derive(Clone, Default)]
+pub struct ExecJobs {
+    jobs: Arc<Mutex<HashMap<String, ExecJob>>>,
+}
+
+impl ExecJobs {
+    /// Records a job for the command `ticket` holds a place in line for; the caller runs it.
+    pub fn submit(&self, ticket: &Ticket, spec: &CommandSpec, mode: ExecMode) -> ExecJob {
+        let place = ticket.place();
+        let job = ExecJob {
+            id: Uuid::new_v4().to_string(),
+            command: redaction::redact(&spec.command_line()),
+            mode,
+            status: JobStatus::Queued,
+            position: place.position(),
+            queued_at: Utc::now(),
+            finished_at: None,
+            result: None,
+            place,
+        };
+        self.lock().insert(job.id.clone(), job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<ExecJob> {
+        self.lock().get(id).cloned()
+    }
+
+    /// Attaches what the command's execute request would have answered.
+    pub fn finish(&self, id: &str, result: serde_json::Value) {
+        if let Some(job) = self.lock().get_mut(id) {
+            job.status = JobStatus::Finished;
+            job.position = None;
+            job.finished_at = Some(Utc::now());
+            job.result = Some(result);
+        }
+    }
+
+    /// Moves queued jobs whose ticket got a slot on to running, and forgets finished ones
+    /// past their retention.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ExecJob>> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        for job in jobs.values_mut().filter(|job| job.status == JobStatus::Queued) {
+            job.position = job.place.position();
+            if job.position.is_none() {
+                job.status = JobStatus::Running;
+            }
+        }
+        jobs.retain(|_, job| job.finished_at.is_none_or(|at| at + Duration::hours(RETENTION_HOURS) > now));
+        jobs
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::ExecuteConfig;
+use crate::policy::CommandSpec;
+use crate::shell_env::ShellEnv;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExecError {
+    #[error("failed to start {program}: {source}")]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("command timed out after {0:?} and was killed")]
+    Timeout(Duration),
+    #[error("{queued} commands already wait for one of {running} slots")]
+    QueueFull {
+        running: usize,
+        queued: usize,
+        retry_after: Duration,
+    },
+}
+
+impl ExecError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExecError::Spawn { .. } => "execute_spawn_failed",
+            ExecError::Timeout(_) => "execute_timeout",
+            ExecError::QueueFull { .. } => "execute_queue_full",
+        }
+    }
+}
+
+/// How a command's output comes back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capture {
+    /// Each stream whole, as `stdout` and `stderr`.
+    #[default]
+    Text,
+    /// One timeline of `events`, in the order the streams were read.
+    Events,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// One read's worth of output, `t_ms` after the process started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputEvent {
+    pub t_ms: u64,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+/// What a finished command printed, on each stream, and how it exited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExecOutput {
+    /// Empty under `Capture::Events`, which puts everything in `events` instead.
+    pub stdout: String,
+    pub stderr: String,
+    /// Both streams in the order they were read, under `Capture::Events`.
+    pub events: Option<Vec<OutputEvent>>,
+    /// Events dropped, oldest first, to keep `events` within `[execute] max_output_bytes`.
+    pub dropped_events: usize,
+    /// The process's exit status; 128 + the signal number when a signal ended it, as shells
+    /// report it.
+    pub exit_code: i32,
+    pub duration_ms: u64,
+    /// Set when either stream went past `[execute] max_output_bytes` and was cut there, or
+    /// when events were dropped or cut to stay within it.
+    pub truncated: bool,
+    /// How long the command waited for a slot before it started.
+    pub queue_wait_ms: u64,
+}
+
+/// Caps how many commands run at once at `[execute] max_concurrent`. The rest wait their
+/// turn first come, first served, and once `max_queued` are waiting more are refused.
+#[derive(Debug)]
+struct Pool {
+    slots: Arc<Semaphore>,
+    running: usize,
+    /// Tickets still waiting for a slot, oldest first.
+    waiting: Mutex<VecDeque<u64>>,
+    max_queued: usize,
+    retry_after: Duration,
+    next_ticket: AtomicU64,
+}
+
+/// A command's place in line for a slot, given up when dropped: a request whose caller
+/// went away leaves the queue without its command ever starting.
+#[derive(Debug)]
+pub struct Ticket {
+    pool: Arc<Pool>,
+    id: u64,
+    queued_at: Instant,
+    slot: Option<(OwnedSemaphorePermit, Duration)>,
+}
+
+impl Ticket {
+    /// 1-based place among the commands waiting for a slot; `None` once this one has it.
+    pub fn position(&self) -> Option<usize> {
+        self.pool.position(self.id)
+    }
+
+    /// A handle that keeps reporting this ticket's position after the ticket itself has
+    /// moved into the task that runs it.
+    pub fn place(&self) -> QueuePlace {
+        QueuePlace { pool: self.pool.clone(), id: self.id }
+    }
+
+    /// Waits for a slot. Cancel-safe: dropping the future keeps the ticket's place in line.
+    pub async fn ready(&mut self) {
+        if self.slot.is_some() {
+            return;
+        }
+        let permit = self.pool.slots.clone().acquire_owned().await.expect("the pool never closes");
+        self.leave_queue();
+        self.slot = Some((permit, self.queued_at.elapsed()));
+    }
+
+    fn leave_queue(&self) {
+        self.pool.waiting.lock().unwrap().retain(|&id| id != self.id);
+    }
+}
+
+impl Pool {
+    fn position(&self, ticket: u64) -> Option<usize> {
+        self.waiting.lock().unwrap().iter().position(|&id| id == ticket).map(|index| index + 1)
+    }
+}
+
+/// Where a ticket stands in line, readable by anyone holding a clone.
+#[derive(Debug, Clone)]
+pub struct QueuePlace {
+    pool: Arc<Pool>,
+    id: u64,
+}
+
+impl QueuePlace {
+    /// As `Ticket::position`; also `None` once the ticket was dropped.
+    pub fn position(&self) -> Option<usize> {
+        self.pool.position(self.id)
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.slot.is_none() {
+            debug!("🎟️ Execute ticket {} left the queue before its command started", self.id);
+            self.leave_queue();
+        }
+    }
+}
+
+/// Runs `/api/execute` commands as one-off processes: shell mode through `[execute] shell`,
+/// argv mode straight from the program and arguments with no shell in between.
+/// Clones share one pool of slots.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    config: ExecuteConfig,
+    shell_env: ShellEnv,
+    pool: Arc<Pool>,
+}
+
+impl Executor {
+    pub fn from_config(config: &ExecuteConfig) -> Self {
+        // Zero slots would queue every command forever.
+        let running = config.max_concurrent.max(1);
+        let pool = Pool {
+            slots: Arc::new(Semaphore::new(running)),
+            running,
+            waiting: Mutex::new(VecDeque::new()),
+            max_queued: config.max_queued,
+            retry_after: Duration::from_secs(config.retry_after_secs),
+            next_ticket: AtomicU64::new(1),
+        };
+        Self { config: config.clone(), shell_env: ShellEnv::default(), pool: Arc::new(pool) }
+    }
+
+    pub fn with_shell_env(self, shell_env: ShellEnv) -> Self {
+        Self { shell_env, ..self }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.timeout_secs)
+    }
+
+    /// How long an execute request waits for a slot before it goes on as a job.
+    pub fn async_after(&self) -> Option<Duration> {
+        (self.config.async_after_secs > 0).then(|| Duration::from_secs(self.config.async_after_secs))
+    }
+
+    /// A place in line for a slot, or `QueueFull` when every slot is taken and
+    /// `[execute] max_queued` commands already wait for one.
+    pub fn enqueue(&self) -> Result<Ticket, ExecError> {
+        let mut waiting = self.pool.waiting.lock().unwrap();
+        if self.pool.slots.available_permits() == 0 && waiting.len() >= self.pool.max_queued {
+            return Err(ExecError::QueueFull { running: self.pool.running, queued: waiting.len(), retry_after: self.pool.retry_after });
+        }
+        let id = self.pool.next_ticket.fetch_add(1, Ordering::Relaxed);
+        waiting.push_back(id);
+        Ok(Ticket { pool: self.pool.clone(), id, queued_at: Instant::now(), slot: None })
+    }
+
+    /// Runs `spec` once a slot is free, as `run_with` does.
+    pub async fn run(&self, spec: &CommandSpec) -> Result<ExecOutput, ExecError> {
+        self.run_with(self.enqueue()?, spec, Capture::Text).await
+    }
+
+    /// Waits for `ticket`'s slot, then runs `spec` to completion with stdin closed. Whatever
+    /// the command leaves running in the background is killed once it exits; a command
+    /// still running after the timeout is killed, together with everything it started. The
+    /// slot is free again as soon as this returns.
+    pub async fn run_with(&self, mut ticket: Ticket, spec: &CommandSpec, capture: Capture) -> Result<ExecOutput, ExecError> {
+        ticket.ready().await;
+        let queue_wait = ticket.slot.as_ref().map_or(Duration::ZERO, |(_, waited)| *waited);
+        let (program, args) = match spec {
+            CommandSpec::Shell(command) => (self.config.shell.clone(), vec![shell_flag(&self.config.shell).to_string(), command.clone()]),
+            CommandSpec::Argv { program, args } => (program.clone(), args.clone()),
+        };
+        let mut command = std::process::Command::new(&program);
+        command.args(&args);
+        #[cfg(unix)]
+        crate::process_group::new_session(&mut command);
+        let mut command = Command::from(command);
+        self.shell_env.apply(&mut command);
+        let started = Instant::now();
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| ExecError::Spawn { program: program.clone(), source })?;
+        let pid = child.id();
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let limit = self.config.max_output_bytes;
+        let finished = async move {
+            let exited = async {
+                let status = child.wait().await;
+                // Background jobs would hold the pipes open, and the request with them.
+                #[cfg(unix)]
+                if let Some(pid) = pid {
+                    crate::process_group::signal_session(pid, libc::SIGKILL);
+                }
+                status
+            };
+            let captured = async {
+                match capture {
+                    Capture::Text => {
+                        let ((stdout, stdout_truncated), (stderr, stderr_truncated)) = tokio::join!(read_capped(stdout, limit), read_capped(stderr, limit));
+                        (stdout, stderr, None, stdout_truncated || stderr_truncated)
+                    }
+                    Capture::Events => {
+                        let log = Mutex::new(EventLog::new(limit));
+                        tokio::join!(read_events(stdout, OutputStream::Stdout, started, &log), read_events(stderr, OutputStream::Stderr, started, &log));
+                        let log = log.into_inner().unwrap();
+                        let truncated = log.truncated;
+                        (Vec::new(), Vec::new(), Some(log), truncated)
+                    }
+                }
+            };
+            tokio::join!(exited, captured)
+        };
+
+        let timeout = self.timeout();
+        let Ok((status, (stdout, stderr, events, truncated))) = tokio::time::timeout(timeout, finished).await else {
+            // kill_on_drop has already taken the child; this gets what it left running.
+            #[cfg(unix)]
+            if let Some(pid) = pid {
+                crate::process_group::signal_session(pid, libc::SIGKILL);
+            }
+            warn!("⏱️ {} (pid {:?}) ran past {:?} and was killed", program, pid, timeout);
+            return Err(ExecError::Timeout(timeout));
+        };
+        let status = status.map_err(|source| ExecError::Spawn { program, source })?;
+        Ok(ExecOutput {
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            dropped_events: events.as_ref().map_or(0, |log| log.dropped),
+            events: events.map(|log| log.events.into()),
+            exit_code: exit_code(status),
+            duration_ms: started.elapsed().as_millis() as u64,
+            truncated,
+            queue_wait_ms: queue_wait.as_millis() as u64,
+        })
+    }
+}
+
+/// Reads `stream` to the end, keeping its first `limit` bytes. The rest is still read, so
+/// the command never blocks on a full pipe; the flag says whether there was any.
+async fn read_capped(stream: Option<impl AsyncRead + Unpin>, limit: usize) -> (Vec<u8>, bool) {
+    let (mut kept, mut truncated) = (Vec::new(), false);
+    let Some(mut stream) = stream else { return (kept, truncated) };
+    let mut buf = vec![0; 8192];
+    while let Ok(read @ 1..) = stream.read(&mut buf).await {
+        let room = limit.saturating_sub(kept.len());
+        kept.extend_from_slice(&buf[..read.min(room)]);
+        truncated |= read > room;
+    }
+    (kept, truncated)
+}
+
+/// Output events within `limit` bytes of data. The oldest make room for newer ones; an
+/// event bigger than the whole limit keeps only its end.
+struct EventLog {
+    events: VecDeque<OutputEvent>,
+    bytes: usize,
+    limit: usize,
+    dropped: usize,
+    truncated: bool,
+}
+
+impl EventLog {
+    fn new(limit: usize) -> Self {
+        Self { events: VecDeque::new(), bytes: 0, limit, dropped: 0, truncated: false }
+    }
+
+    fn push(&mut self, mut event: OutputEvent) {
+        if event.data.len() > self.limit {
+            let mut cut = event.data.len() - self.limit;
+            while !event.data.is_char_boundary(cut) {
+                cut += 1;
+            }
+            event.data.drain(..cut);
+            self.truncated = true;
+        }
+        if event.data.is_empty() {
+            self.dropped += 1;
+            return;
+        }
+        while self.bytes + event.data.len() > self.limit {
+            let oldest = self.events.pop_front().expect("the log holds what it counts");
+            self.bytes -= oldest.data.len();
+            self.dropped += 1;
+            self.truncated = true;
+        }
+        self.bytes += event.data.len();
+        self.events.push_back(event);
+    }
+}
+
+/// Reads `stream` to the end into `log`, one event per read. A character split between
+/// reads waits for the rest of its bytes.
+async fn read_events(stream: Option<impl AsyncRead + Unpin>, which: OutputStream, started: Instant, log: &Mutex<EventLog>) {
+    let Some(mut stream) = stream else { return };
+    let (mut buf, mut pending) = (vec![0; 8192], Vec::new());
+    let record = |bytes: &[u8]| {
+        let data = String::from_utf8_lossy(bytes).into_owned();
+        log.lock().unwrap().push(OutputEvent { t_ms: started.elapsed().as_millis() as u64, stream: which, data });
+    };
+    while let Ok(read @ 1..) = stream.read(&mut buf).await {
+        pending.extend_from_slice(&buf[..read]);
+        let complete = match std::str::from_utf8(&pending) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => pending.len(),
+        };
+        if complete > 0 {
+            record(&pending[..complete]);
+            pending.drain(..complete);
+        }
+    }
+    if !pending.is_empty() {
+        record(&pending);
+    }
+}
+
+/// How `shell` takes a command string: `/C` for cmd, `-c` for everything else.
+fn shell_flag(shell: &str) -> &'static str {
+    let name = std::path::Path::new(shell).file_stem().and_then(|stem| stem.to_str()).unwrap_or(shell);
+    if name.eq_ignore_ascii_case("cmd") {
+        "/C"
+    } else {
+        "-c"
+    }
+}
+
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.code().or_else(|| status.signal().map(|signal| 128 + signal)).unwrap_or(-1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(-1)
+}
@@ -0,0 +1,190 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rust_terminal_forge::admin;
+use rust_terminal_forge::config::ForgeConfig;
+use rust_terminal_forge::preflight::Preflight;
+use rust_terminal_forge::protocol_capture::{self, Capture};
+use rust_terminal_forge::selftest::{self, Targets};
+
+const USAGE: &str = "usage: forge selftest [--api http://HOST:PORT] [--terminal ws://HOST:PORT]
+       forge check
+       forge replay FILE [--terminal ws://HOST:PORT] [--speed N] [--baseline FILE] [--save-baseline FILE]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("selftest") => selftest_command(&args[1..]).await,
+        Some("check") if args.len() == 1 => check_command(),
+        Some("replay") if args.len() > 1 => replay_command(&args[1..]).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs the end-to-end scenarios against running servers, by default the ones
+/// `forge.toml` describes, and exits non-zero if any failed.
+async fn selftest_command(args: &[String]) -> ExitCode {
+    let config = match ForgeConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("💥 {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let mut api = format!("http://{}", reachable(config.listen.api));
+    let mut terminal = format!("ws://{}", reachable(config.listen.terminal));
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--api" => &mut api,
+            "--terminal" => &mut terminal,
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        };
+        match args.next() {
+            Some(value) => *target = value.clone(),
+            None => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    println!("🧪 Self-testing {} and {}", api, terminal);
+    let token = [admin::API_TOKEN_ENV, admin::ADMIN_TOKEN_ENV].into_iter().find_map(|name| std::env::var(name).ok().filter(|t| !t.is_empty()));
+    let results = selftest::run_all(&Targets::new(&api, &terminal).with_api_token(token)).await;
+    for result in &results {
+        println!("{}", result);
+    }
+    let failed = results.iter().filter(|result| result.failed()).count();
+    println!("{} scenarios, {} failed", results.len(), failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Checks the deployment described by `forge.toml` before the servers start, and exits
+/// non-zero if anything is wrong with it.
+fn check_command() -> ExitCode {
+    let config = match ForgeConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("FAIL  config: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let results = Preflight::default().run(&config);
+    for result in &results {
+        println!("{}", result);
+    }
+    let failed = results.iter().filter(|result| result.failed()).count();
+    println!("{} checks, {} failed", results.len(), failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Plays a `--capture-protocol-dir` capture against a running terminal server, at the
+/// original pace unless `--speed` says otherwise (0 sends everything at once), and exits
+/// non-zero if the server misbehaved or departed from `--baseline`.
+async fn replay_command(args: &[String]) -> ExitCode {
+    let config = match ForgeConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("💥 {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let path = PathBuf::from(&args[0]);
+    let mut terminal = format!("ws://{}", reachable(config.listen.terminal));
+    let mut speed = "1".to_string();
+    let mut baseline = None;
+    let mut save_baseline = None;
+
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
+        let value = match options.next() {
+            Some(value) => value.clone(),
+            None => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        };
+        match arg.as_str() {
+            "--terminal" => terminal = value,
+            "--speed" => speed = value,
+            "--baseline" => baseline = Some(PathBuf::from(value)),
+            "--save-baseline" => save_baseline = Some(PathBuf::from(value)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(speed) = speed.parse::<f64>().ok().filter(|speed| *speed >= 0.0) else {
+        eprintln!("💥 --speed must be a number, 0 or above");
+        return ExitCode::from(2);
+    };
+    let capture = match Capture::read(&path) {
+        Ok(capture) => capture,
+        Err(e) => {
+            eprintln!("💥 {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!("🎞️ Replaying {} messages from session {} against {}", capture.messages.len(), capture.header.session_id, terminal);
+    let types = match protocol_capture::replay(&terminal, &capture, speed).await {
+        Ok(types) => types,
+        Err(e) => {
+            println!("FAIL  replay: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("PASS  replay: {} messages back", types.len());
+    if let Some(path) = save_baseline {
+        if let Err(e) = std::fs::write(&path, types.join("\n") + "\n") {
+            eprintln!("💥 failed to write {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+    let Some(path) = baseline else { return ExitCode::SUCCESS };
+    let expected: Vec<String> = match std::fs::read_to_string(&path) {
+        Ok(text) => text.lines().map(str::to_string).collect(),
+        Err(e) => {
+            eprintln!("💥 failed to read {}: {}", path.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+    match protocol_capture::compare_baseline(&expected, &types) {
+        Ok(()) => {
+            println!("PASS  baseline: matches {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            println!("FAIL  baseline: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// A listen address like `0.0.0.0:3001` is reached through loopback.
+fn reachable(addr: SocketAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port())
+    } else {
+        addr
+    }
+}
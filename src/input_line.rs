@@ -0,0 +1,61 @@
+/// The line being typed into a session, rebuilt from the keystrokes on their way to the
+/// PTY so a whole command can be checked before its Enter reaches the shell. Line
+/// editing (backspace, Ctrl-U, Ctrl-W, Ctrl-C) is followed; cursor keys, history and tab
+/// completion happen inside the shell and are not.
+#[derive(Debug, Default)]
+pub struct InputLine {
+    line: String,
+    escape: Escape,
+}
+
+/// Where the tracker is inside an escape sequence, which may span several chunks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    None,
+    Started,
+    Csi,
+    Ss3,
+}
+
+impl InputLine {
+    /// Feeds `data` up to and including its first Enter (`\r` or `\n`) and returns how many
+    /// bytes that was together with the finished line; `None` when `data` holds no Enter.
+    pub fn feed(&mut self, data: &str) -> Option<(usize, String)> {
+        for (at, c) in data.char_indices() {
+            match (self.escape, c) {
+                (Escape::None, '\r' | '\n') => return Some((at + c.len_utf8(), std::mem::take(&mut self.line))),
+                (Escape::None, '\x1b') => self.escape = Escape::Started,
+                (Escape::None, '\x7f' | '\x08') => {
+                    self.line.pop();
+                }
+                (Escape::None, '\x15' | '\x03') => self.line.clear(),
+                (Escape::None, '\x17') => {
+                    let kept = self.line.trim_end().trim_end_matches(|c: char| !c.is_whitespace()).len();
+                    self.line.truncate(kept);
+                }
+                (Escape::None, c) if c.is_control() => {}
+                (Escape::None, c) => self.line.push(c),
+                (Escape::Started, '[') => self.escape = Escape::Csi,
+                (Escape::Started, 'O') => self.escape = Escape::Ss3,
+                (Escape::Csi, '\x40'..='\x7e') | (Escape::Started | Escape::Ss3, _) => self.escape = Escape::None,
+                (Escape::Csi, _) => {}
+            }
+        }
+        None
+    }
+
+    /// What has been typed since the last Enter.
+    pub fn current(&self) -> &str {
+        &self.line
+    }
+
+    /// Puts back a line `feed` returned, for when its Enter was held back.
+    pub fn restore(&mut self, line: String) {
+        self.line = line;
+    }
+
+    pub fn clear(&mut self) {
+        self.line.clear();
+    }
+}
@@ -0,0 +1,58 @@
+//! Shared backend modules for the `server` and `pty-server` binaries.
+
+pub mod admin;
+pub mod ansi;
+pub mod approvals;
+pub mod bandwidth;
+pub mod banner;
+pub mod base_path;
+pub mod capabilities;
+pub mod chaos;
+pub mod client_env;
+pub mod command_guard;
+pub mod command_timing;
+pub mod config;
+pub mod connection_limits;
+pub mod diagnostics;
+pub mod error_catalog;
+pub mod exec_jobs;
+pub mod executor;
+pub mod input_line;
+pub mod lifetime;
+pub mod links;
+pub mod log_control;
+pub mod multiplex;
+pub mod notices;
+pub mod origins;
+pub mod policy;
+pub mod ports;
+pub mod preflight;
+pub mod process_group;
+pub mod process_tree;
+pub mod protocol;
+pub mod protocol_capture;
+pub mod pty;
+pub mod redaction;
+pub mod repl;
+pub mod replay;
+pub mod resources;
+pub mod run_as;
+pub mod screen;
+pub mod scrollback;
+pub mod selftest;
+pub mod session_env;
+pub mod session_events;
+pub mod session_names;
+pub mod session_registry;
+pub mod session_store;
+pub mod session_tags;
+pub mod session_tmp;
+pub mod shell_env;
+pub mod shell_integration;
+pub mod shell_policy;
+pub mod templates;
+pub mod terminal_modes;
+pub mod transcript;
+pub mod transport;
+pub mod webhooks;
+pub mod workspaces;
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use tokio::time::Instant;
+
+use crate::admin::constant_time_eq;
+use crate::config::{IdleTimeoutConfig, MaxLifetimeConfig};
+
+/// Time remaining when `expiry_warning` messages go out.
+pub const EXPIRY_WARNINGS: [Duration; 3] = [
+    Duration::from_secs(60 * 60),
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(5 * 60),
+];
+
+/// `[terminal.max_lifetime]` with the override tokens read from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct LifetimePolicy {
+    default: Option<Duration>,
+    overrides: Vec<(String, Duration)>,
+}
+
+impl LifetimePolicy {
+    pub fn from_config(config: &MaxLifetimeConfig) -> Self {
+        let overrides = config
+            .overrides
+            .iter()
+            .filter_map(|entry| match std::env::var(&entry.token_env).ok().filter(|token| !token.is_empty()) {
+                Some(token) => Some((token, Duration::from_secs(entry.secs))),
+                None => {
+                    warn!("⏳ Ignoring max_lifetime override: {} is not set", entry.token_env);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            default: config.secs.map(Duration::from_secs),
+            overrides,
+        }
+    }
+
+    /// The cap for a connection that presented `token`; unknown tokens get the default.
+    pub fn for_token(&self, token: Option<&str>) -> Option<Duration> {
+        let Some(token) = token else { return self.default };
+        self.overrides
+            .iter()
+            .find(|(expected, _)| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+            .map_or(self.default, |(_, max)| Some(*max))
+    }
+}
+
+/// `[terminal.idle_timeout]`, for the task that sweeps idle sessions away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdlePolicy {
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl IdlePolicy {
+    /// `None` when idle sessions are left alone. Sessions are checked at least as often
+    /// as the timeout, and at most once a second.
+    pub fn from_config(config: &IdleTimeoutConfig) -> Option<Self> {
+        let secs = config.secs?;
+        Some(Self {
+            timeout: Duration::from_secs(secs),
+            interval: Duration::from_secs(config.check_interval_secs.min(secs).max(1)),
+        })
+    }
+
+    /// Whether a session last active at `last_activity` has been idle too long by `now`.
+    pub fn expired(&self, last_activity: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        (now - last_activity).to_std().is_ok_and(|idle| idle >= self.timeout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifetimeEvent {
+    Warning { remaining: Duration },
+    Expired,
+}
+
+/// Countdown for one session, fixed when the session is created.
+#[derive(Debug, Clone)]
+pub struct Lifetime {
+    expires_at: DateTime<Utc>,
+    deadline: Instant,
+    /// Warnings still to send, largest first.
+    warnings: Vec<Duration>,
+}
+
+impl Lifetime {
+    /// Warnings that would already be due for a short `max` are skipped.
+    pub fn new(max: Duration) -> Self {
+        Self {
+            expires_at: Utc::now() + chrono::Duration::from_std(max).unwrap_or(chrono::Duration::MAX),
+            deadline: Instant::now() + max,
+            warnings: EXPIRY_WARNINGS.into_iter().filter(|warning| *warning < max).collect(),
+        }
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// When the session expires.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// When `poll` next has something to report.
+    pub fn next_deadline(&self) -> Instant {
+        self.warnings.first().map_or(self.deadline, |warning| self.deadline - *warning)
+    }
+
+    /// The event due at `next_deadline`, or `None` if called early.
+    pub fn poll(&mut self) -> Option<LifetimeEvent> {
+        let now = Instant::now();
+        if now < self.next_deadline() {
+            return None;
+        }
+        if self.warnings.is_empty() {
+            return Some(LifetimeEvent::Expired);
+        }
+        self.warnings.remove(0);
+        Some(LifetimeEvent::Warning { remaining: self.deadline.saturating_duration_since(now) })
+    }
+}
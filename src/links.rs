@@ -0,0 +1,108 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::ansi;
+
+/// Most links reported for one output flush, so `cat urls.txt` cannot flood the client.
+pub const MAX_LINKS_PER_FLUSH: usize = 20;
+/// Longest unfinished token carried over to the next chunk.
+const MAX_CARRY: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Link {
+    Url {
+        text: String,
+        href: String,
+    },
+    File {
+        text: String,
+        path: String,
+        line: Option<u32>,
+        col: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkBatch {
+    pub items: Vec<Link>,
+    pub truncated: bool,
+}
+
+/// Finds http(s) URLs and relative file paths (with optional `:line:col`) in
+/// ANSI-stripped output. A token still running at the end of a chunk is held back
+/// until whitespace shows where it ends.
+#[derive(Debug, Default)]
+pub struct LinkScanner {
+    carry: String,
+}
+
+impl LinkScanner {
+    pub fn scan(&mut self, chunk: &str) -> LinkBatch {
+        let mut buf = std::mem::take(&mut self.carry) + chunk;
+        match buf.rfind(char::is_whitespace) {
+            Some(end) => {
+                let end = end + buf[end..].chars().next().map_or(1, char::len_utf8);
+                self.carry = buf.split_off(end);
+            }
+            None => {
+                self.carry = buf;
+                buf = String::new();
+            }
+        }
+        if self.carry.len() > MAX_CARRY {
+            self.carry.clear();
+        }
+
+        let mut batch = LinkBatch::default();
+        for token in ansi::strip(&buf).split_whitespace() {
+            if let Some(link) = classify(token) {
+                if batch.items.len() == MAX_LINKS_PER_FLUSH {
+                    batch.truncated = true;
+                    break;
+                }
+                batch.items.push(link);
+            }
+        }
+        batch
+    }
+}
+
+fn url_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^https?://[^\s<>\x22'`]+$").unwrap())
+}
+
+fn file_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^(?P<path>(?:\.{1,2}/)?(?:[\w@.-]+/)*[\w@-][\w@.-]*\.[A-Za-z][A-Za-z0-9]{0,9})(?::(?P<line>\d+)(?::(?P<col>\d+))?)?$").unwrap()
+    })
+}
+
+fn classify(token: &str) -> Option<Link> {
+    let token = token
+        .trim_start_matches(['(', '[', '{', '<', '"', '\''])
+        .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}', '>', '"', '\'']);
+    if url_pattern().is_match(token) {
+        return Some(Link::Url {
+            text: token.to_string(),
+            href: token.to_string(),
+        });
+    }
+    let captures = file_pattern().captures(token)?;
+    let path = &captures["path"];
+    let line = captures.name("line").and_then(|m| m.as_str().parse().ok());
+    // A bare `word.ext` is too often prose ("e.g.", "v1.rs"); ask for a directory or a line.
+    if !path.contains('/') && line.is_none() {
+        return None;
+    }
+    Some(Link::File {
+        text: token.to_string(),
+        path: path.to_string(),
+        line,
+        col: captures.name("col").and_then(|m| m.as_str().parse().ok()),
+    })
+}
@@ -0,0 +1,175 @@
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::filter::{Directive, LevelFilter};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelRequest {
+    pub level: String,
+    pub target: Option<String>,
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLevelStatus {
+    pub directives: String,
+    pub default_directives: String,
+    pub revert_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogLevelError {
+    #[error("invalid log directive: {0}")]
+    InvalidDirective(String),
+    #[error("failed to reload log filter: {0}")]
+    Reload(String),
+}
+
+impl LogLevelError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LogLevelError::InvalidDirective(_) => "invalid_log_directive",
+            LogLevelError::Reload(_) => "log_reload_failed",
+        }
+    }
+}
+
+struct FilterState {
+    directives: String,
+    generation: u64,
+    revert_at: Option<DateTime<Utc>>,
+}
+
+/// Handle to the process-wide log filter that can be adjusted while running.
+#[derive(Clone)]
+pub struct LogControl {
+    handle: FilterHandle,
+    default_directives: Arc<str>,
+    state: Arc<Mutex<FilterState>>,
+}
+
+impl LogControl {
+    /// Installs the global subscriber. `RUST_LOG` wins over `fallback` when set.
+    /// Records emitted through the `log` macros are bridged into the same filter.
+    pub fn init(fallback: &str) -> Self {
+        let default_directives = std::env::var("RUST_LOG")
+            .ok()
+            .filter(|d| EnvFilter::try_new(d).is_ok())
+            .unwrap_or_else(|| fallback.to_string());
+
+        let (filter, handle) = reload::Layer::new(EnvFilter::new(&default_directives));
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt::layer())
+            .init();
+
+        Self {
+            handle,
+            default_directives: default_directives.clone().into(),
+            state: Arc::new(Mutex::new(FilterState {
+                directives: default_directives,
+                generation: 0,
+                revert_at: None,
+            })),
+        }
+    }
+
+    pub fn status(&self) -> LogLevelStatus {
+        let state = self.state.lock().unwrap();
+        LogLevelStatus {
+            directives: state.directives.clone(),
+            default_directives: self.default_directives.to_string(),
+            revert_at: state.revert_at,
+        }
+    }
+
+    /// Applies `target=level` on top of the current directives, replacing any
+    /// existing directive for the same target. With `ttl_secs` the previous
+    /// directives are restored once the TTL elapses, unless another change
+    /// landed in the meantime.
+    pub fn apply(&self, req: &LogLevelRequest) -> Result<LogLevelStatus, LogLevelError> {
+        LevelFilter::from_str(&req.level)
+            .map_err(|e| LogLevelError::InvalidDirective(format!("'{}': {}", req.level, e)))?;
+
+        let target = req.target.as_deref().map(str::trim).filter(|t| !t.is_empty());
+        let directive = match target {
+            Some(target) => format!("{}={}", target, req.level),
+            None => req.level.clone(),
+        };
+        Directive::from_str(&directive)
+            .map_err(|e| LogLevelError::InvalidDirective(format!("'{}': {}", directive, e)))?;
+
+        let mut state = self.state.lock().unwrap();
+        let previous = state.directives.clone();
+        let mut directives: Vec<&str> = previous
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty() && directive_target(d) != target)
+            .collect();
+        directives.push(&directive);
+        let directives = directives.join(",");
+
+        self.reload(&directives)?;
+        info!("🎚️ Log filter changed: '{}' -> '{}'", previous, directives);
+
+        state.directives = directives;
+        state.generation += 1;
+        state.revert_at = None;
+
+        if let Some(ttl) = req.ttl_secs.filter(|ttl| *ttl > 0) {
+            state.revert_at = Some(Utc::now() + chrono::Duration::seconds(ttl as i64));
+            self.schedule_revert(state.generation, previous, Duration::from_secs(ttl));
+        }
+
+        Ok(LogLevelStatus {
+            directives: state.directives.clone(),
+            default_directives: self.default_directives.to_string(),
+            revert_at: state.revert_at,
+        })
+    }
+
+    fn schedule_revert(&self, generation: u64, previous: String, ttl: Duration) {
+        let control = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ttl).await;
+            let mut state = control.state.lock().unwrap();
+            if state.generation != generation {
+                return;
+            }
+            match control.reload(&previous) {
+                Ok(()) => {
+                    info!("⏪ Log filter TTL expired, reverted to '{}'", previous);
+                    state.directives = previous;
+                    state.revert_at = None;
+                }
+                Err(e) => warn!("⚠️ Failed to revert log filter: {}", e),
+            }
+        });
+    }
+
+    fn reload(&self, directives: &str) -> Result<(), LogLevelError> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| LogLevelError::InvalidDirective(e.to_string()))?;
+        self.handle
+            .reload(filter)
+            .map_err(|e| LogLevelError::Reload(e.to_string()))
+    }
+}
+
+/// Target part of a directive (`None` for a bare level such as `debug`).
+fn directive_target(directive: &str) -> Option<&str> {
+    match directive.split_once('=') {
+        Some((target, _)) => Some(target.trim()),
+        None if LevelFilter::from_str(directive).is_ok() => None,
+        None => Some(directive),
+    }
+}
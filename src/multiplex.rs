@@ -0,0 +1,96 @@
+use serde_json::{json, Value};
+
+use crate::protocol::{CloseReason, DecodeError, ServerMessage};
+
+/// Where a client connects to run several terminals over one WebSocket.
+pub const MUX_PATH: &str = "/mux";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MuxError {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error("messages on a multiplexed connection need a numeric 'channel'")]
+    MissingChannel,
+    #[error("channel {0} is already open")]
+    ChannelInUse(u32),
+    #[error("channel {0} is not open")]
+    UnknownChannel(u32),
+}
+
+impl MuxError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MuxError::Decode(e) => e.code(),
+            MuxError::MissingChannel => "missing_channel",
+            MuxError::ChannelInUse(_) => "channel_in_use",
+            MuxError::UnknownChannel(_) => "unknown_channel",
+        }
+    }
+
+    /// The channel the error is about, for tagging the error message with it.
+    pub fn channel(&self) -> Option<u32> {
+        match self {
+            MuxError::ChannelInUse(channel) | MuxError::UnknownChannel(channel) => Some(*channel),
+            MuxError::Decode(_) | MuxError::MissingChannel => None,
+        }
+    }
+}
+
+/// A client frame on a multiplexed connection. Every one names its channel, a number the
+/// client picks when it opens it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MuxFrame {
+    /// `{"type":"open_channel","channel":1,"query":"cols=120&rows=40"}` starts a session on
+    /// the channel, set up by `query` as a terminal URL's query string would set it up.
+    Open { channel: u32, query: String },
+    /// `{"type":"close_channel","channel":1}` leaves the session as if its client had
+    /// disconnected.
+    Close { channel: u32 },
+    /// Any terminal message, for the session on `channel`; `text` is the message without
+    /// its channel.
+    Message { channel: u32, text: String },
+}
+
+impl MuxFrame {
+    pub fn decode(text: &str) -> Result<Self, MuxError> {
+        let mut value: Value = serde_json::from_str(text).map_err(|e| DecodeError::InvalidJson(e.to_string()))?;
+        let channel = value.get("channel").and_then(Value::as_u64).and_then(|channel| u32::try_from(channel).ok()).ok_or(MuxError::MissingChannel)?;
+        match value["type"].as_str() {
+            Some("open_channel") => match value.get("query") {
+                None => Ok(MuxFrame::Open { channel, query: String::new() }),
+                Some(Value::String(query)) => Ok(MuxFrame::Open { channel, query: query.clone() }),
+                Some(_) => Err(MuxError::Decode(DecodeError::InvalidFields {
+                    msg_type: "open_channel".to_string(),
+                    error: "query must be a string".to_string(),
+                })),
+            },
+            Some("close_channel") => Ok(MuxFrame::Close { channel }),
+            _ => {
+                if let Some(fields) = value.as_object_mut() {
+                    fields.remove("channel");
+                }
+                Ok(MuxFrame::Message { channel, text: value.to_string() })
+            }
+        }
+    }
+}
+
+/// `msg` from the session on `channel`, tagged with it.
+pub fn encode(channel: u32, msg: &ServerMessage) -> String {
+    let mut value = msg.to_value();
+    value["channel"] = channel.into();
+    value.to_string()
+}
+
+/// Tells the client the session on `channel` let it go, with the close code a connection
+/// of its own would have been closed with.
+pub fn channel_closed(channel: u32, reason: Option<CloseReason>) -> String {
+    json!({
+        "type": "channel_closed",
+        "channel": channel,
+        "code": reason.map(CloseReason::code),
+        "reason": reason.map(CloseReason::reason)
+    })
+    .to_string()
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Longest notice text accepted after sanitizing, in characters.
+pub const MAX_NOTICE_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoticeLevel {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Server-wide message pushed to every attached terminal connection as
+/// `{"type":"notice","level":...,"message":...}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notice {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pub level: NoticeLevel,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub countdown_secs: Option<u64>,
+}
+
+impl Notice {
+    /// Builds a notice from untrusted text, stripping markup and control characters.
+    /// Returns `None` when nothing printable is left.
+    pub fn new(level: NoticeLevel, message: &str, countdown_secs: Option<u64>) -> Option<Self> {
+        let message = sanitize(message);
+        if message.is_empty() {
+            return None;
+        }
+        Some(Self {
+            kind: "notice",
+            level,
+            message,
+            countdown_secs,
+        })
+    }
+}
+
+/// Drops anything that looks like an HTML tag and all control characters,
+/// collapses the remaining whitespace, and caps the result at `MAX_NOTICE_LEN`.
+fn sanitize(raw: &str) -> String {
+    let mut text = String::with_capacity(raw.len());
+    let mut in_tag = false;
+    for c in raw.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {}
+            c if c.is_control() => text.push(' '),
+            c => text.push(c),
+        }
+    }
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_NOTICE_LEN)
+        .collect()
+}
+
+/// Fan-out of notices to terminal connections.
+#[derive(Clone)]
+pub struct NoticeBus {
+    tx: broadcast::Sender<Notice>,
+}
+
+impl NoticeBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    /// Sends to every subscribed connection and returns how many received it.
+    pub fn broadcast(&self, notice: Notice) -> usize {
+        self.tx.send(notice).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notice> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for NoticeBus {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
@@ -0,0 +1,49 @@
+/// Browser origins allowed to call the API and open terminal WebSockets, from
+/// `[listen] allowed_origins`. Requests without an `Origin` header come from scripts and
+/// other non-browser clients; the API token covers those instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedOrigins(Vec<String>);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum OriginError {
+    #[error("allowed origin '{0}' must look like scheme://host[:port]")]
+    Invalid(String),
+}
+
+impl AllowedOrigins {
+    pub fn from_config(origins: &[String]) -> Result<Self, OriginError> {
+        origins
+            .iter()
+            .map(|origin| normalize(origin).ok_or_else(|| OriginError::Invalid(origin.clone())))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Whether a request with this `Origin` header may go ahead.
+    pub fn allows(&self, origin: Option<&str>) -> bool {
+        match origin {
+            None => true,
+            Some(origin) => normalize(origin).is_some_and(|origin| self.0.contains(&origin)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// `origin` lowercased, if it is a `scheme://host[:port]` with nothing after it.
+fn normalize(origin: &str) -> Option<String> {
+    let origin = origin.trim().to_ascii_lowercase();
+    let (scheme, authority) = origin.split_once("://")?;
+    let scheme_ok = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !authority.ends_with(']') => (host, Some(port)),
+        _ => (authority, None),
+    };
+    let bracketed = host.starts_with('[') && host.ends_with(']');
+    let host_ok = !host.is_empty() && !host.contains(['/', '?', '#', '@', ' ']) && (bracketed || !host.contains(['[', ']', ':']));
+    let port_ok = port.is_none_or(|port| port.parse::<u16>().is_ok());
+    (scheme_ok && host_ok && port_ok).then_some(origin)
+}
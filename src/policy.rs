@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+use crate::approvals::Approvals;
+use crate::command_guard::CommandGuard;
+
+/// The `command` field of an execute request: either a shell string or an
+/// explicit program plus argument vector that never goes through a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum CommandSpec {
+    Shell(String),
+    Argv {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecMode {
+    Shell,
+    Argv,
+}
+
+impl CommandSpec {
+    pub fn mode(&self) -> ExecMode {
+        match self {
+            CommandSpec::Shell(_) => ExecMode::Shell,
+            CommandSpec::Argv { .. } => ExecMode::Argv,
+        }
+    }
+
+    /// Single-line rendering used for logging, pattern matching and confirmation
+    /// tokens. Argv entries are shell-quoted so each stays one word.
+    pub fn command_line(&self) -> String {
+        match self {
+            CommandSpec::Shell(command) => command.trim().to_string(),
+            CommandSpec::Argv { program, args } => {
+                shell_words::join(std::iter::once(program).chain(args))
+            }
+        }
+    }
+}
+
+/// A rule that fired while evaluating a command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchedRule {
+    pub kind: &'static str,
+    pub name: String,
+}
+
+/// What the execute pipeline would do with a command, without running it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyVerdict {
+    pub allowed: bool,
+    pub requires_confirmation: bool,
+    /// Held for an approver instead; supersedes confirmation.
+    pub requires_approval: bool,
+    pub mode: ExecMode,
+    pub matched_rules: Vec<MatchedRule>,
+    pub argv: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl PolicyVerdict {
+    fn rejected(mode: ExecMode, matched_rules: Vec<MatchedRule>, reason: String) -> Self {
+        Self {
+            allowed: false,
+            requires_confirmation: false,
+            requires_approval: false,
+            mode,
+            matched_rules,
+            argv: Vec::new(),
+            reason: Some(reason),
+        }
+    }
+
+    /// Name of the dangerous pattern that needs confirming, if any.
+    pub fn confirmation_pattern(&self) -> Option<&str> {
+        self.matched_rules
+            .iter()
+            .find(|rule| rule.kind == "dangerous_pattern")
+            .map(|rule| rule.name.as_str())
+    }
+
+    /// Name of the approval pattern that holds the command for an approver, if any.
+    pub fn approval_pattern(&self) -> Option<&str> {
+        self.matched_rules
+            .iter()
+            .find(|rule| rule.kind == "approval_pattern")
+            .map(|rule| rule.name.as_str())
+    }
+}
+
+/// Single policy pipeline shared by `/api/execute` and `/api/execute/validate`:
+/// resolves the argv (word-splitting shell strings the way the shell will, taking
+/// argv requests verbatim) and runs the dangerous-pattern check. Side-effect free,
+/// so confirmation tokens are handled by the caller.
+pub fn evaluate(guard: &CommandGuard, spec: &CommandSpec) -> PolicyVerdict {
+    evaluate_with_approvals(guard, &Approvals::default(), spec)
+}
+
+/// `evaluate`, plus the `[approvals]` patterns that hold a command for an approver.
+pub fn evaluate_with_approvals(guard: &CommandGuard, approvals: &Approvals, spec: &CommandSpec) -> PolicyVerdict {
+    let mode = spec.mode();
+
+    let argv = match spec {
+        CommandSpec::Shell(command) => match shell_words::split(command.trim()) {
+            Ok(argv) => argv,
+            Err(e) => {
+                let rule = MatchedRule {
+                    kind: "parse_error",
+                    name: e.to_string(),
+                };
+                return PolicyVerdict::rejected(mode, vec![rule], format!("could not parse command: {}", e));
+            }
+        },
+        CommandSpec::Argv { program, args } => {
+            if program.trim().is_empty() {
+                return PolicyVerdict::rejected(mode, Vec::new(), "program is empty".to_string());
+            }
+            std::iter::once(program).chain(args).cloned().collect()
+        }
+    };
+    if argv.is_empty() {
+        return PolicyVerdict::rejected(mode, Vec::new(), "command is empty".to_string());
+    }
+    if argv.iter().any(|arg| arg.contains('\0')) {
+        return PolicyVerdict::rejected(mode, Vec::new(), "arguments may not contain NUL bytes".to_string());
+    }
+
+    let command_line = spec.command_line();
+    let dangerous = guard.matched_pattern(&command_line).map(|name| MatchedRule {
+        kind: "dangerous_pattern",
+        name: name.to_string(),
+    });
+    let approval = approvals.matched_pattern(&command_line).map(|name| MatchedRule {
+        kind: "approval_pattern",
+        name: name.to_string(),
+    });
+    let requires_approval = approval.is_some();
+    let requires_confirmation = dangerous.is_some() && !requires_approval;
+    let matched_rules: Vec<MatchedRule> = dangerous.into_iter().chain(approval).collect();
+
+    PolicyVerdict {
+        allowed: true,
+        requires_confirmation,
+        requires_approval,
+        mode,
+        matched_rules,
+        argv,
+        reason: None,
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::ansi;
+#[cfg(target_os = "linux")]
+use crate::process_tree;
+
+/// Longest unfinished line carried over to the next chunk.
+const MAX_CARRY: usize = 2048;
+
+/// Dev servers recognised by name in the line announcing the port (or the few before it).
+const HINTS: &[(&str, &str)] = &[
+    ("vite", "vite"),
+    ("next", "next"),
+    ("webpack", "webpack"),
+    ("astro", "astro"),
+    ("nuxt", "nuxt"),
+    ("angular", "angular"),
+    ("puma", "rails"),
+    ("rails", "rails"),
+    ("uvicorn", "uvicorn"),
+    ("django", "django"),
+    ("flask", "flask"),
+    ("jupyter", "jupyter"),
+    ("http.server", "python"),
+    ("trunk", "trunk"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    Opened { port: u16, pid: Option<u32>, hint: Option<String> },
+    Closed { port: u16, pid: Option<u32> },
+}
+
+/// Spots "listening on :5173"-style announcements in ANSI-stripped output. Each port is
+/// reported once per session; output alone never says when a server stops.
+#[derive(Debug, Default)]
+pub struct PortAnnouncementScanner {
+    carry: String,
+    /// Recent complete lines, for hints printed above the address (vite's banner).
+    recent: Vec<String>,
+    announced: BTreeSet<u16>,
+}
+
+impl PortAnnouncementScanner {
+    pub fn scan(&mut self, chunk: &str) -> Vec<PortEvent> {
+        let mut buf = std::mem::take(&mut self.carry) + chunk;
+        match buf.rfind('\n') {
+            Some(end) => self.carry = buf.split_off(end + 1),
+            None => {
+                self.carry = buf;
+                buf = String::new();
+            }
+        }
+        if self.carry.len() > MAX_CARRY {
+            self.carry.clear();
+        }
+
+        let mut events = Vec::new();
+        for line in ansi::strip(&buf).lines() {
+            if let Some(port) = announced_port(line) {
+                if self.announced.insert(port) {
+                    let hint = std::iter::once(line).chain(self.recent.iter().rev().map(String::as_str)).find_map(hint_for);
+                    events.push(PortEvent::Opened { port, pid: None, hint: hint.map(str::to_string) });
+                }
+            }
+            self.recent.push(line.to_string());
+            if self.recent.len() > 5 {
+                self.recent.remove(0);
+            }
+        }
+        events
+    }
+
+    /// Marks a port as open already, e.g. after the proc scan found it first.
+    pub fn note(&mut self, port: u16) {
+        self.announced.insert(port);
+    }
+}
+
+fn announcement_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // "Listening on http://localhost:3000", "Server running at 0.0.0.0:8000"
+            r"(?i)\b(?:listening|running|started|serving|available|ready)\b.*?(?:on|at)\b.*?(?:localhost|127\.0\.0\.1|0\.0\.0\.0|\[::1?\]|::):(\d{2,5})\b",
+            // vite/astro: "➜  Local:   http://localhost:5173/"
+            r"(?i)\blocal:\s+https?://[^\s/:]+:(\d{2,5})\b",
+            // "listening on port 8080"
+            r"(?i)\blistening on port (\d{2,5})\b",
+        ]
+        .iter()
+        .map(|pattern| Regex::new(pattern).unwrap())
+        .collect()
+    })
+}
+
+fn announced_port(line: &str) -> Option<u16> {
+    announcement_patterns()
+        .iter()
+        .find_map(|pattern| pattern.captures(line))
+        .and_then(|captures| captures[1].parse().ok())
+        .filter(|port| *port > 0)
+}
+
+fn hint_for(line: &str) -> Option<&'static str> {
+    let line = line.to_ascii_lowercase();
+    HINTS.iter().find(|(needle, _)| line.contains(needle)).map(|(_, hint)| *hint)
+}
+
+/// Diffs the TCP ports that `root` and its descendants listen on, via `/proc`. Only
+/// sockets owned by those processes count, so other local services never show up.
+#[derive(Debug)]
+pub struct ProcPortScanner {
+    root: u32,
+    open: BTreeMap<u16, u32>,
+}
+
+impl ProcPortScanner {
+    pub fn new(root: u32) -> Self {
+        Self { root, open: BTreeMap::new() }
+    }
+
+    /// Whether this platform can be scanned at all; elsewhere only announcements work.
+    pub fn supported() -> bool {
+        cfg!(target_os = "linux") && std::path::Path::new("/proc/net/tcp").exists()
+    }
+
+    /// Ports opened and closed since the previous poll.
+    pub fn poll(&mut self) -> Vec<PortEvent> {
+        let now = listening_ports(self.root);
+        let mut events: Vec<PortEvent> = self
+            .open
+            .iter()
+            .filter(|(port, _)| !now.contains_key(port))
+            .map(|(&port, &pid)| PortEvent::Closed { port, pid: Some(pid) })
+            .collect();
+        events.extend(
+            now.iter()
+                .filter(|(port, _)| !self.open.contains_key(port))
+                .map(|(&port, &pid)| PortEvent::Opened { port, pid: Some(pid), hint: process_hint(pid) }),
+        );
+        self.open = now;
+        events
+    }
+}
+
+/// Listening TCP ports owned by `root` or its descendants, with the owning pid.
+#[cfg(target_os = "linux")]
+pub fn listening_ports(root: u32) -> BTreeMap<u16, u32> {
+    let listening = listening_inodes();
+    if listening.is_empty() {
+        return BTreeMap::new();
+    }
+    let mut ports = BTreeMap::new();
+    for pid in process_tree::descendants(root) {
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else { continue };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else { continue };
+            let inode = target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|rest| rest.strip_suffix(']'))
+                .and_then(|inode| inode.parse::<u64>().ok());
+            if let Some(port) = inode.and_then(|inode| listening.get(&inode)) {
+                ports.entry(*port).or_insert(pid);
+            }
+        }
+    }
+    ports
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn listening_ports(_root: u32) -> BTreeMap<u16, u32> {
+    BTreeMap::new()
+}
+
+/// Socket inode to port for every TCP socket in LISTEN state.
+#[cfg(target_os = "linux")]
+fn listening_inodes() -> BTreeMap<u64, u16> {
+    const TCP_LISTEN: &str = "0A";
+    let mut inodes = BTreeMap::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(contents) = std::fs::read_to_string(table) else { continue };
+        for row in contents.lines().skip(1) {
+            let fields: Vec<&str> = row.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                continue;
+            }
+            let port = fields[1].rsplit(':').next().and_then(|hex| u16::from_str_radix(hex, 16).ok());
+            if let (Some(port), Ok(inode)) = (port, fields[9].parse::<u64>()) {
+                inodes.insert(inode, port);
+            }
+        }
+    }
+    inodes
+}
+
+/// A hint from the listening process's command line.
+fn process_hint(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let cmdline = String::from_utf8_lossy(&cmdline).replace('\0', " ");
+    hint_for(&cmdline).map(str::to_string)
+}
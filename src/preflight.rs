@@ -0,0 +1,239 @@
+use std::fmt;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::approvals::Approvals;
+use crate::bandwidth::Bandwidth;
+use crate::banner::Banner;
+use crate::command_guard::CommandGuard;
+use crate::config::ForgeConfig;
+use crate::redaction::Redactor;
+use crate::resources::ResourceLimits;
+use crate::session_env::EnvironmentPolicy;
+use crate::session_tmp::TmpDirs;
+use crate::shell_integration::PromptDetector;
+use crate::templates::Templates;
+
+/// Where `server` serves the frontend from, relative to its working directory.
+pub const STATIC_DIR: &str = "dist";
+
+/// How long the PTY check waits for its trivial command to exit.
+const PTY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Passed(String),
+    Failed(String),
+    /// The deployment has nothing to check yet; the reason says what is missing.
+    Skipped(&'static str),
+}
+
+/// One named check of the deployment. Each runs on its own, so features can add theirs
+/// with `Preflight::register`.
+#[derive(Debug, Clone, Copy)]
+pub struct Check {
+    pub name: &'static str,
+    pub run: fn(&ForgeConfig) -> CheckOutcome,
+}
+
+pub const CONFIG: Check = Check { name: "config", run: config_sections };
+pub const STATIC_FILES: Check = Check { name: "static_files", run: static_files };
+pub const SHELLS: Check = Check { name: "shells", run: shells };
+pub const TEMPLATE_DIRS: Check = Check { name: "template_dirs", run: template_dirs };
+pub const LISTEN: Check = Check { name: "listen", run: listen };
+pub const PTY: Check = Check { name: "pty", run: pty };
+pub const WORKSPACE: Check = Check { name: "workspace", run: |_| CheckOutcome::Skipped("there is no workspace root yet") };
+pub const TLS: Check = Check { name: "tls", run: |_| CheckOutcome::Skipped("the servers do not terminate TLS") };
+pub const SQLITE: Check = Check { name: "sqlite", run: |_| CheckOutcome::Skipped("nothing is stored in SQLite yet") };
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub outcome: CheckOutcome,
+}
+
+impl CheckResult {
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, CheckOutcome::Failed(_))
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            CheckOutcome::Passed(detail) => write!(f, "PASS  {}: {}", self.name, detail),
+            CheckOutcome::Failed(why) => write!(f, "FAIL  {}: {}", self.name, why),
+            CheckOutcome::Skipped(why) => write!(f, "SKIP  {}: {}", self.name, why),
+        }
+    }
+}
+
+/// The checks `forge check` runs, in order.
+#[derive(Debug, Clone)]
+pub struct Preflight {
+    checks: Vec<Check>,
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Self::new(&[CONFIG, STATIC_FILES, SHELLS, TEMPLATE_DIRS, LISTEN, PTY, WORKSPACE, TLS, SQLITE])
+    }
+}
+
+impl Preflight {
+    pub fn new(checks: &[Check]) -> Self {
+        Self { checks: checks.to_vec() }
+    }
+
+    pub fn register(&mut self, check: Check) {
+        self.checks.push(check);
+    }
+
+    pub fn run(&self, config: &ForgeConfig) -> Vec<CheckResult> {
+        self.checks
+            .iter()
+            .map(|check| CheckResult { name: check.name, outcome: (check.run)(config) })
+            .collect()
+    }
+
+    /// Runs the checks and logs each one, failures as warnings; for server startup.
+    pub fn log(&self, config: &ForgeConfig) {
+        for result in self.run(config) {
+            match result.outcome {
+                CheckOutcome::Failed(_) => warn!("🩺 Startup check {} (`forge check` runs them all)", result),
+                _ => info!("🩺 Startup check {}", result),
+            }
+        }
+    }
+}
+
+/// Every section a server validates when it starts, beyond parsing. Webhooks are left out,
+/// since building them starts their delivery tasks.
+fn config_sections(config: &ForgeConfig) -> CheckOutcome {
+    let validated = CommandGuard::from_config(&config.dangerous_commands)
+        .map(drop)
+        .and_then(|()| Approvals::from_config(&config.approvals).map(drop))
+        .and_then(|()| Templates::from_config(&config.templates).map(drop))
+        .and_then(|()| Redactor::from_config(&config.redaction).map(drop))
+        .and_then(|()| Banner::from_config(&config.terminal).map(drop))
+        .and_then(|()| PromptDetector::from_config(&config.terminal.prompt_detection).map(drop))
+        .and_then(|()| EnvironmentPolicy::from_config(&config.terminal.environment).map(drop))
+        .and_then(|()| Bandwidth::from_config(&config.terminal.bandwidth).map(drop))
+        .and_then(|()| ResourceLimits::from_config(&config.terminal.resources).map(drop))
+        .and_then(|()| TmpDirs::from_config(&config.tmpdir).map(drop));
+    match validated {
+        Ok(()) => CheckOutcome::Passed(format!("{} templates, {} webhooks", config.templates.len(), config.webhooks.len())),
+        Err(e) => CheckOutcome::Failed(e.to_string()),
+    }
+}
+
+fn static_files(_config: &ForgeConfig) -> CheckOutcome {
+    let index = Path::new(STATIC_DIR).join("index.html");
+    if index.is_file() {
+        CheckOutcome::Passed(format!("{} found", index.display()))
+    } else {
+        CheckOutcome::Failed(format!("{} is missing; build the frontend first", index.display()))
+    }
+}
+
+/// The REPL and session shells plus every shell or program a template launches.
+fn shells(config: &ForgeConfig) -> CheckOutcome {
+    let mut programs = vec![config.repl.shell.as_str()];
+    for shell in std::iter::once(&config.terminal.shell).chain(&config.terminal.allowed_shells) {
+        if !programs.contains(&shell.as_str()) {
+            programs.push(shell.as_str());
+        }
+    }
+    for template in &config.templates {
+        let program = template.shell.as_deref().or(template.command.first().map(String::as_str));
+        if let Some(program) = program.filter(|program| !programs.contains(program)) {
+            programs.push(program);
+        }
+    }
+
+    let mut found = Vec::new();
+    for program in &programs {
+        let Some(path) = resolve(program) else {
+            return CheckOutcome::Failed(format!("'{}' is not on PATH", program));
+        };
+        // Shells without a --version flag still prove they run by answering at all.
+        let ran = Command::new(&path)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if let Err(e) = ran {
+            return CheckOutcome::Failed(format!("'{}' does not run: {}", path.display(), e));
+        }
+        found.push(path.display().to_string());
+    }
+    CheckOutcome::Passed(found.join(", "))
+}
+
+/// `program` as given when it contains a slash, otherwise the first match on `PATH`.
+fn resolve(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Path::new(program).is_file().then(|| PathBuf::from(program));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+fn template_dirs(config: &ForgeConfig) -> CheckOutcome {
+    let dirs: Vec<(&str, &PathBuf)> = config
+        .templates
+        .iter()
+        .filter_map(|template| Some((template.name.as_str(), template.cwd.as_ref()?)))
+        .collect();
+    if dirs.is_empty() {
+        return CheckOutcome::Skipped("no template sets a cwd");
+    }
+    match dirs.iter().find(|(_, dir)| !dir.is_dir()) {
+        Some((name, dir)) => CheckOutcome::Failed(format!("template '{}': {} is not a directory", name, dir.display())),
+        None => CheckOutcome::Passed(format!("{} directories exist", dirs.len())),
+    }
+}
+
+/// Binds and releases both listen addresses.
+fn listen(config: &ForgeConfig) -> CheckOutcome {
+    let addrs: [(&str, SocketAddr); 2] = [("api", config.listen.api), ("terminal", config.listen.terminal)];
+    for (name, addr) in addrs {
+        if let Err(e) = TcpListener::bind(addr) {
+            return CheckOutcome::Failed(format!("{} address {}: {}", name, addr, e));
+        }
+    }
+    CheckOutcome::Passed(format!("{} and {} are free", config.listen.api, config.listen.terminal))
+}
+
+/// Opens a PTY and runs `true` in it.
+fn pty(_config: &ForgeConfig) -> CheckOutcome {
+    use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+    let pair = match native_pty_system().openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 }) {
+        Ok(pair) => pair,
+        Err(e) => return CheckOutcome::Failed(format!("cannot open a PTY: {}", e)),
+    };
+    let mut child = match pair.slave.spawn_command(CommandBuilder::new("true")) {
+        Ok(child) => child,
+        Err(e) => return CheckOutcome::Failed(format!("cannot spawn in a PTY: {}", e)),
+    };
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return CheckOutcome::Passed("spawned `true` in a PTY".to_string()),
+            Ok(Some(status)) => return CheckOutcome::Failed(format!("`true` exited with {:?}", status)),
+            Ok(None) if started.elapsed() > PTY_TIMEOUT => {
+                let _ = child.kill();
+                return CheckOutcome::Failed(format!("`true` did not exit within {:?}", PTY_TIMEOUT));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => return CheckOutcome::Failed(e.to_string()),
+        }
+    }
+}
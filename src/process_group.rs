@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{DisconnectConfig, OrphanedIoConfig, OrphanedIoMode};
+use crate::process_tree;
+
+/// Makes the child lead a new session and process group, so everything it starts can be
+/// found and signalled together after it exits. portable-pty already does this for PTYs.
+#[cfg(unix)]
+pub fn new_session(command: &mut std::process::Command) -> &mut std::process::Command {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: setsid is async-signal-safe and touches no state shared with the parent.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Sends `signal` to every live process in the session led by `sid`, background jobs
+/// and reparented leftovers included, and returns how many were signalled.
+#[cfg(unix)]
+pub fn signal_session(sid: u32, signal: i32) -> usize {
+    process_tree::session_members(sid)
+        .into_iter()
+        .filter(|pid| i32::try_from(*pid).is_ok_and(|pid| unsafe { libc::kill(pid, signal) } == 0))
+        .count()
+}
+
+/// Makes this process adopt whatever its descendants orphan, instead of init, so processes
+/// a shell leaves behind are reaped here once they are killed.
+#[cfg(target_os = "linux")]
+pub fn become_subreaper() -> std::io::Result<()> {
+    // SAFETY: PR_SET_CHILD_SUBREAPER only flips a flag on the calling process.
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn become_subreaper() -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "only Linux has child subreapers"))
+}
+
+/// Waits on this process's exited orphans, so killed leftovers do not stay zombies, and
+/// returns how many were reaped. Shells are left to their PTYs.
+#[cfg(unix)]
+pub fn reap_orphans() -> usize {
+    process_tree::zombie_children(std::process::id())
+        .into_iter()
+        .filter(|pid| i32::try_from(*pid).is_ok_and(|pid| unsafe { libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG) } == pid))
+        .count()
+}
+
+/// Processes still running in a session whose shell has exited; any means the session's
+/// I/O is orphaned rather than finished.
+pub fn leftovers(sid: u32) -> Vec<u32> {
+    process_tree::session_members(sid)
+}
+
+/// `[terminal.orphaned_io]`: what a session does once it is orphaned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanedIoPolicy {
+    KeepStreaming,
+    CloseAfter(Duration),
+}
+
+impl OrphanedIoPolicy {
+    pub fn from_config(config: &OrphanedIoConfig) -> Self {
+        match config.mode {
+            OrphanedIoMode::KeepStreaming => OrphanedIoPolicy::KeepStreaming,
+            OrphanedIoMode::Close => OrphanedIoPolicy::CloseAfter(Duration::from_secs(config.grace_secs)),
+        }
+    }
+
+    /// When a session orphaned at `since` gets its leftovers killed and is closed; `None`
+    /// while it keeps streaming.
+    pub fn close_at(&self, since: Instant) -> Option<Instant> {
+        match self {
+            OrphanedIoPolicy::KeepStreaming => None,
+            OrphanedIoPolicy::CloseAfter(grace) => Some(since + *grace),
+        }
+    }
+}
+
+/// `[terminal.disconnect]`: how long a session outlives its client, and how long its
+/// processes get to exit once it is hung up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisconnectPolicy {
+    pub linger: Duration,
+    pub kill_grace: Duration,
+}
+
+impl DisconnectPolicy {
+    pub fn from_config(config: &DisconnectConfig) -> Self {
+        Self { linger: Duration::from_secs(config.linger_secs), kill_grace: Duration::from_secs(config.kill_grace_secs) }
+    }
+
+    /// When a session whose client left at `since` gets hung up; `None` to do it at once.
+    pub fn hang_up_at(&self, since: Instant) -> Option<Instant> {
+        (!self.linger.is_zero()).then(|| since + self.linger)
+    }
+}
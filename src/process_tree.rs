@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// How long a sample is reused, so clients polling `ps` cannot keep the server scanning `/proc`.
+pub const CACHE_TTL: Duration = Duration::from_secs(1);
+
+/// `USER_HZ`, the unit of the CPU times in `/proc/*/stat`; 100 on every Linux ABI in use.
+const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+/// One process at or below a session's shell.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    /// Process group, which the shell's job control makes one per pipeline or job.
+    pub pgid: u32,
+    pub command: String,
+    /// The `/proc` state letter: R, S, D, Z, T...
+    pub state: String,
+    /// Since the previous sample; 0 on the first one.
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    /// Leads the terminal's foreground process group, i.e. what is "currently running".
+    pub foreground: bool,
+}
+
+/// The process tree below one root pid, sampled from `/proc` at most once per `CACHE_TTL`.
+#[derive(Debug)]
+pub struct ProcessSampler {
+    root: u32,
+    cached: Option<(Instant, Vec<ProcessInfo>)>,
+    /// CPU ticks per pid at the previous scan, for `cpu_percent`.
+    ticks: HashMap<u32, u64>,
+}
+
+impl ProcessSampler {
+    pub fn new(root: u32) -> Self {
+        Self { root, cached: None, ticks: HashMap::new() }
+    }
+
+    pub fn sample(&mut self) -> Vec<ProcessInfo> {
+        let now = Instant::now();
+        let previous = match &self.cached {
+            Some((at, processes)) if now.duration_since(*at) < CACHE_TTL => return processes.clone(),
+            Some((at, _)) => Some(*at),
+            None => None,
+        };
+        let elapsed = previous.map(|at| now.duration_since(at).as_secs_f64());
+
+        let mut ticks = HashMap::new();
+        let mut processes = Vec::new();
+        for pid in descendants(self.root) {
+            let Some(stat) = read_stat(pid) else { continue };
+            let cpu_percent = match (elapsed, self.ticks.get(&pid)) {
+                (Some(elapsed), Some(before)) if elapsed > 0.0 => {
+                    (stat.ticks.saturating_sub(*before) as f64 / CLOCK_TICKS_PER_SEC / elapsed * 100.0) as f32
+                }
+                _ => 0.0,
+            };
+            ticks.insert(pid, stat.ticks);
+            processes.push(ProcessInfo {
+                pid,
+                ppid: stat.ppid,
+                pgid: stat.pgid,
+                command: command_line(pid).unwrap_or(stat.comm),
+                state: stat.state,
+                cpu_percent,
+                rss_bytes: rss_bytes(pid).unwrap_or(0),
+                foreground: stat.tpgid == Some(pid),
+            });
+        }
+        self.ticks = ticks;
+        self.cached = Some((now, processes.clone()));
+        processes
+    }
+}
+
+/// One pipeline or background job below a session's shell: the processes sharing a
+/// process group, as the shell's job control sees them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Job {
+    pub pgid: u32,
+    /// The group leader's command line, or the first member's once the leader is gone.
+    pub command: String,
+    pub foreground: bool,
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Groups `processes` into jobs by process group, leaving out `shell`'s own group.
+pub fn jobs(processes: &[ProcessInfo], shell: u32) -> Vec<Job> {
+    let mut jobs: Vec<Job> = Vec::new();
+    for process in processes.iter().filter(|process| process.pgid != shell) {
+        let job = match jobs.iter().position(|job| job.pgid == process.pgid) {
+            Some(at) => &mut jobs[at],
+            None => {
+                jobs.push(Job {
+                    pgid: process.pgid,
+                    command: process.command.clone(),
+                    foreground: false,
+                    cpu_percent: 0.0,
+                    rss_bytes: 0,
+                    processes: Vec::new(),
+                });
+                jobs.last_mut().unwrap()
+            }
+        };
+        if process.pid == process.pgid {
+            job.command = process.command.clone();
+        }
+        job.foreground |= process.foreground;
+        job.cpu_percent += process.cpu_percent;
+        job.rss_bytes += process.rss_bytes;
+        job.processes.push(process.clone());
+    }
+    jobs.sort_by_key(|job| job.pgid);
+    jobs
+}
+
+/// `root` and every process below it, from the parent pids in `/proc/*/stat`.
+#[cfg(target_os = "linux")]
+pub fn descendants(root: u32) -> Vec<u32> {
+    let mut parents: Vec<(u32, u32)> = Vec::new();
+    for entry in std::fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        if let Some(stat) = read_stat(pid) {
+            parents.push((pid, stat.ppid));
+        }
+    }
+    if !parents.iter().any(|(pid, _)| *pid == root) {
+        return Vec::new();
+    }
+    let mut found = vec![root];
+    let mut i = 0;
+    while i < found.len() {
+        let parent = found[i];
+        found.extend(parents.iter().filter(|(_, ppid)| *ppid == parent).map(|(pid, _)| *pid));
+        i += 1;
+    }
+    found
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn descendants(_root: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+/// Live processes in session `sid`, zombies left out. Unlike `descendants` this still
+/// finds what a shell started after the shell itself is gone and they were reparented.
+#[cfg(target_os = "linux")]
+pub fn session_members(sid: u32) -> Vec<u32> {
+    std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()))
+        .filter(|pid| read_stat(*pid).is_some_and(|stat| stat.sid == sid && stat.state != "Z"))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn session_members(_sid: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+/// Exited children of `ppid` nobody has waited on yet, except session leaders: those are
+/// shells, each waited on by its own PTY.
+#[cfg(target_os = "linux")]
+pub fn zombie_children(ppid: u32) -> Vec<u32> {
+    std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()))
+        .filter(|pid| read_stat(*pid).is_some_and(|stat| stat.ppid == ppid && stat.state == "Z" && stat.sid != *pid))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn zombie_children(_ppid: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+/// Where `pid` is working, from `/proc/<pid>/cwd`.
+#[cfg(target_os = "linux")]
+pub fn cwd(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cwd(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
+struct Stat {
+    comm: String,
+    state: String,
+    ppid: u32,
+    pgid: u32,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    sid: u32,
+    /// Foreground process group of the controlling terminal; `None` without one.
+    tpgid: Option<u32>,
+    /// utime + stime.
+    ticks: u64,
+}
+
+fn read_stat(pid: u32) -> Option<Stat> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses; fields resume after the last ')'.
+    let (head, rest) = stat.rsplit_once(')')?;
+    let comm = head.split_once('(').map(|(_, comm)| comm.to_string()).unwrap_or_default();
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |i: usize| fields.get(i).copied();
+    Some(Stat {
+        comm,
+        state: field(0)?.to_string(),
+        ppid: field(1)?.parse().ok()?,
+        pgid: field(2)?.parse().ok()?,
+        sid: field(3)?.parse().ok()?,
+        tpgid: field(5).and_then(|tpgid| tpgid.parse::<i64>().ok()).and_then(|tpgid| u32::try_from(tpgid).ok()),
+        ticks: field(11)?.parse::<u64>().ok()? + field(12)?.parse::<u64>().ok()?,
+    })
+}
+
+fn command_line(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let cmdline = String::from_utf8_lossy(&cmdline).replace('\0', " ").trim_end().to_string();
+    (!cmdline.is_empty()).then_some(cmdline)
+}
+
+fn rss_bytes(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kb = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?.trim().trim_end_matches("kB").trim();
+    kb.parse::<u64>().ok().map(|kb| kb * 1024)
+}
@@ -0,0 +1,826 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use uuid::Uuid;
+
+use crate::bandwidth::ThrottleSnapshot;
+use crate::capabilities::Capabilities;
+use crate::command_timing::CommandTimings;
+use crate::diagnostics::ProtocolCountersSnapshot;
+use crate::error_catalog::ClientError;
+use crate::links::LinkBatch;
+use crate::notices::Notice;
+use crate::ports::PortEvent;
+use crate::process_tree::{Job, ProcessInfo};
+use crate::pty::PtySignal;
+use crate::resources::ResourceUsage;
+use crate::screen::{ScreenSnapshot, TerminalSize};
+use crate::scrollback::{ScrollbackPage, SearchResults};
+use crate::session_env::SessionEnvironment;
+use crate::session_registry::SessionState;
+use crate::shell_integration::{CommandPhase, OutputEvent};
+use crate::terminal_modes::TerminalModes;
+use crate::transcript::TranscriptFormat;
+
+/// Message types a terminal client may send.
+const CLIENT_MESSAGE_TYPES: &[&str] = &[
+    "input",
+    "confirm",
+    "resize",
+    "diagnostics",
+    "screen_snapshot",
+    "search",
+    "export",
+    "timings",
+    "clear_scrollback",
+    "capabilities",
+    "mouse",
+    "ps",
+    "signal",
+    "setenv",
+    "jobs",
+    "rename",
+    "session_info",
+    "detach",
+    "attach",
+    "fetch_scrollback",
+    "ack",
+];
+
+/// A decoded message from a terminal client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Input {
+        data: String,
+    },
+    /// Answer to `confirm_required`; `proceed: false` drops the held-back input.
+    Confirm {
+        #[serde(default)]
+        token: String,
+        #[serde(default)]
+        proceed: bool,
+    },
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    Diagnostics,
+    ScreenSnapshot,
+    /// Searches the session's scrollback, literally unless `regex` is set.
+    Search {
+        query: String,
+        #[serde(default)]
+        regex: bool,
+        max_results: Option<usize>,
+    },
+    /// Lines of the retained scrollback, e.g. `{"type":"fetch_scrollback","from":1,"count":100}`;
+    /// without `from`, the newest `count`.
+    FetchScrollback {
+        #[serde(default)]
+        from: Option<u64>,
+        #[serde(default)]
+        count: Option<usize>,
+    },
+    /// Transcript of the retained scrollback as `txt` or `html`.
+    Export {
+        format: TranscriptFormat,
+    },
+    /// The session's recent commands and how long they took.
+    Timings,
+    /// Forgets the retained scrollback and the timing table.
+    ClearScrollback,
+    /// Replaces the capabilities declared at connect; unknown names are ignored.
+    Capabilities {
+        capabilities: Vec<String>,
+    },
+    /// An encoded mouse report, passed to the shell without the guard or any rewriting.
+    Mouse {
+        data: String,
+    },
+    /// The processes the session is running.
+    Ps,
+    /// Signals the foreground process group, e.g. `{"type":"signal","signal":"SIGINT"}`,
+    /// or with `job` set to a pgid from `jobs`, that job.
+    Signal {
+        signal: PtySignal,
+        #[serde(default)]
+        job: Option<u32>,
+    },
+    /// The session's processes grouped into jobs, without the shell itself.
+    Jobs,
+    /// Sets the session's variables, e.g. `{"type":"setenv","env":{"NODE_ENV":"test"}}`;
+    /// `null` removes one.
+    #[serde(rename = "setenv")]
+    SetEnv {
+        env: BTreeMap<String, Option<String>>,
+    },
+    /// Names the session for tab bars, e.g. `{"type":"rename","name":"logs"}`; `null`
+    /// clears the name.
+    Rename {
+        name: Option<String>,
+    },
+    /// What the session is and has done so far, for a details panel to poll.
+    SessionInfo,
+    /// Leaves the session running with no client until one attaches to it again.
+    Detach,
+    /// Switches this connection to another session, given its resume token, leaving the
+    /// current one detached.
+    Attach {
+        session_id: String,
+        #[serde(default)]
+        resume_token: String,
+    },
+    /// The client has processed output up to `seq`, on connections with `?ack_window=`.
+    Ack {
+        seq: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("invalid JSON: {0}")]
+    InvalidJson(String),
+    #[error("missing 'type' field")]
+    MissingType,
+    #[error("unknown message type '{0}'")]
+    UnknownType(String),
+    #[error("invalid '{msg_type}' message: {error}")]
+    InvalidFields { msg_type: String, error: String },
+    #[error("invalid binary frame: {0}")]
+    InvalidBinary(String),
+}
+
+impl DecodeError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DecodeError::InvalidJson(_) => "malformed_message",
+            DecodeError::MissingType => "missing_type",
+            DecodeError::UnknownType(_) => "unknown_type",
+            DecodeError::InvalidFields { .. } => "invalid_fields",
+            DecodeError::InvalidBinary(_) => "invalid_binary_frame",
+        }
+    }
+}
+
+impl ClientMessage {
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| DecodeError::InvalidJson(e.to_string()))?;
+        Self::decode_value(value)
+    }
+
+    fn decode_value(value: serde_json::Value) -> Result<Self, DecodeError> {
+        let msg_type = value["type"].as_str().ok_or(DecodeError::MissingType)?.to_string();
+        if !CLIENT_MESSAGE_TYPES.contains(&msg_type.as_str()) {
+            return Err(DecodeError::UnknownType(msg_type));
+        }
+        serde_json::from_value(value).map_err(|e| DecodeError::InvalidFields {
+            msg_type,
+            error: e.to_string(),
+        })
+    }
+
+    /// A binary frame: keystrokes for the shell on `TERMINAL_CHANNEL`, as UTF-8, or any
+    /// message as MessagePack on `MESSAGE_CHANNEL`.
+    pub fn decode_binary(frame: &[u8]) -> Result<Self, DecodeError> {
+        match frame.split_first() {
+            Some((&TERMINAL_CHANNEL, data)) => String::from_utf8(data.to_vec())
+                .map(|data| ClientMessage::Input { data })
+                .map_err(|e| DecodeError::InvalidBinary(e.to_string())),
+            Some((&MESSAGE_CHANNEL, data)) => {
+                let value: serde_json::Value = rmp_serde::from_slice(data).map_err(|e| DecodeError::InvalidBinary(format!("invalid MessagePack: {}", e)))?;
+                Self::decode_value(value)
+            }
+            Some((channel, _)) => Err(DecodeError::InvalidBinary(format!("unknown channel {}", channel))),
+            None => Err(DecodeError::InvalidBinary("empty frame".to_string())),
+        }
+    }
+}
+
+/// First byte of every binary frame, naming what the rest of it carries: PTY output from
+/// the server to `output=binary` clients, and keystrokes from any client to the server.
+pub const TERMINAL_CHANNEL: u8 = 0;
+
+/// Binary-frame channel for protocol messages encoded as MessagePack, in both directions.
+pub const MESSAGE_CHANNEL: u8 = 1;
+
+/// How protocol messages travel, picked by the client with the `Sec-WebSocket-Protocol`
+/// handshake header. Anything but `forge.msgpack` gets JSON text frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl WireFormat {
+    pub const JSON_SUBPROTOCOL: &'static str = "forge.json";
+    pub const MSGPACK_SUBPROTOCOL: &'static str = "forge.msgpack";
+
+    /// The first format named in a comma-separated `Sec-WebSocket-Protocol` offer, with
+    /// the subprotocol to answer with; `None` when the offer names neither.
+    pub fn negotiate(offered: &str) -> Option<(Self, &'static str)> {
+        offered.split(',').find_map(|protocol| match protocol.trim() {
+            Self::JSON_SUBPROTOCOL => Some((WireFormat::Json, Self::JSON_SUBPROTOCOL)),
+            Self::MSGPACK_SUBPROTOCOL => Some((WireFormat::MessagePack, Self::MSGPACK_SUBPROTOCOL)),
+            _ => None,
+        })
+    }
+}
+
+/// `data` behind its channel byte, as a binary frame carries it.
+pub fn binary_frame(channel: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + 1);
+    frame.push(channel);
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// A message from the server to a terminal client.
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    /// First message on every terminal connection, before any banner.
+    Hello {
+        session_id: String,
+        server_version: String,
+        /// So the client can configure its emulator to match the shell.
+        environment: SessionEnvironment,
+        capabilities: Capabilities,
+        modes: TerminalModes,
+        /// The `[[templates]]` entry the session was started from.
+        template: Option<String>,
+        /// Size the session started at, from the connect URL or the 80x24 default.
+        size: TerminalSize,
+        /// Where the shell started; `cwd` messages follow it from there.
+        cwd: Option<String>,
+        /// How output reaches this client, from the connect URL's `output=`.
+        output: OutputEncoding,
+        /// Reconnecting with `session_id` and this token while the session lingers gets
+        /// the same shell back; `None` when `[terminal.disconnect]` does not linger.
+        resume_token: Option<String>,
+        /// Connecting with `session_id` and this token opens the same terminal alongside
+        /// this client; `None` when `[terminal] max_shared_clients` is 0.
+        share_token: Option<String>,
+        /// Who this client is among a shared session's participants; `None` for the
+        /// session's own client, which is always `p1`.
+        participant: Option<Participant>,
+    },
+    Output {
+        data: String,
+        /// Output bytes sent to this client so far, this message's included; only on
+        /// connections with `?ack_window=`.
+        seq: Option<u64>,
+    },
+    /// Output exactly as the PTY produced it, for clients that asked for `base64` or
+    /// `binary` output; a binary WebSocket frame in `binary` mode.
+    OutputBytes {
+        data: Vec<u8>,
+        encoding: OutputEncoding,
+        /// As for `Output`. Binary frames have no room for it, so those clients count.
+        seq: Option<u64>,
+    },
+    ConfirmRequired {
+        pattern: String,
+        token: String,
+        expires_in_secs: u64,
+    },
+    /// The prompt detector thinks the shell is waiting for input.
+    Prompt,
+    /// A command that ran longer than `[terminal.long_commands] threshold_secs` finished.
+    CommandFinished {
+        command: String,
+        duration_ms: u64,
+        exit_code: Option<i32>,
+    },
+    /// The application turned a mouse tracking mode on or off.
+    MouseMode {
+        mode: u16,
+        enabled: bool,
+    },
+    /// An OSC 133 marker seen in the output stream.
+    CommandBoundary {
+        phase: CommandPhase,
+        exit_code: Option<i32>,
+    },
+    Diagnostics {
+        session_id: String,
+        counters: ProtocolCountersSnapshot,
+        throttle: ThrottleSnapshot,
+        /// Latest `[terminal.resources]` sample; `None` until one is taken.
+        resources: Option<ResourceUsage>,
+    },
+    ScreenSnapshot(ScreenSnapshot),
+    Timings(CommandTimings),
+    /// Reply to `ps`: the session's shell and everything below it. Empty for sessions
+    /// without a child process.
+    Processes(Vec<ProcessInfo>),
+    /// Reply to `jobs`.
+    Jobs(Vec<Job>),
+    /// Reply to `capabilities`. Only output filtering and the output encoding follow the
+    /// new set; the shell's environment was fixed when it started.
+    Capabilities {
+        capabilities: Capabilities,
+        environment: SessionEnvironment,
+        output: OutputEncoding,
+    },
+    /// The shell moved to another directory.
+    Cwd {
+        path: String,
+        source: CwdSource,
+    },
+    /// Reply to `setenv`. The shell keeps the environment it was started with.
+    Env {
+        environment: SessionEnvironment,
+        env: BTreeMap<String, String>,
+    },
+    /// Reply to `rename`.
+    Renamed {
+        name: Option<String>,
+    },
+    SessionInfo(SessionInfo),
+    /// Reply to `detach`, with what to attach to the session again with.
+    Detached {
+        session_id: String,
+        resume_token: String,
+    },
+    /// Links found in the output that was just sent.
+    Links(LinkBatch),
+    /// A dev server in the session started listening; `pid` only when found through `/proc`.
+    PortOpened {
+        port: u16,
+        pid: Option<u32>,
+        hint: Option<String>,
+    },
+    PortClosed {
+        port: u16,
+        pid: Option<u32>,
+    },
+    SearchResults {
+        query: String,
+        results: SearchResults,
+    },
+    /// Reply to `fetch_scrollback`.
+    Scrollback(ScrollbackPage),
+    Export {
+        format: TranscriptFormat,
+        filename: String,
+        content: String,
+    },
+    /// Sent 60, 15 and 5 minutes before the session hits its maximum lifetime.
+    ExpiryWarning {
+        remaining_secs: u64,
+        expires_at: DateTime<Utc>,
+    },
+    /// The session's processes crossed `[terminal.resources] warn_rss_bytes`.
+    ResourceWarning {
+        rss_bytes: u64,
+        limit_bytes: u64,
+    },
+    /// The session's `TMPDIR` grew past `[tmpdir] max_bytes`.
+    TmpdirWarning {
+        bytes: u64,
+        limit_bytes: u64,
+    },
+    /// Last message before the server ends the session itself, with the shell's exit
+    /// status when it was the shell exiting that ended it.
+    Exit {
+        reason: CloseReason,
+        code: Option<u32>,
+    },
+    /// Sent just before a close the client may retry after, and only then.
+    ReconnectHint {
+        retry_after_ms: u64,
+        /// Whether reconnecting with `resume_token` gets the same session back.
+        resumable: bool,
+        resume_token: Option<String>,
+    },
+    /// A request the server could not serve; the connection stays open.
+    Error(ClientError),
+    Notice(Notice),
+    /// An admin started watching this session's output; not sent for covert observers.
+    ObserverJoined {
+        who: String,
+    },
+    /// Someone else is in the session: sent to the others when a shared client joins, and
+    /// to a joining client for everyone already there.
+    PeerJoined(Participant),
+    PeerLeft {
+        participant: String,
+    },
+    /// A shared client typed `bytes` since the last summary; at most one per participant
+    /// every half second.
+    InputAttribution {
+        participant: String,
+        bytes: usize,
+    },
+}
+
+impl ServerMessage {
+    /// The JSON text frame sent over the wire.
+    pub fn encode(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    /// The binary frame sent to clients that negotiated `WireFormat::MessagePack`: the
+    /// same fields as `encode`, behind `MESSAGE_CHANNEL`. Output bytes go as MessagePack
+    /// binary rather than base64.
+    pub fn encode_msgpack(&self) -> Vec<u8> {
+        let packed = match self {
+            ServerMessage::OutputBytes { data, seq, .. } => rmp_serde::to_vec_named(&PackedOutput { kind: "output", encoding: "binary", data: PackedBytes(data), seq: *seq }),
+            msg => rmp_serde::to_vec_named(&msg.to_value()),
+        };
+        binary_frame(MESSAGE_CHANNEL, &packed.unwrap_or_default())
+    }
+
+    /// The message as a JSON value, before it is framed.
+    pub fn to_value(&self) -> serde_json::Value {
+        match self {
+            ServerMessage::Hello { session_id, server_version, environment, capabilities, modes, template, size, cwd, output, resume_token, share_token, participant } => json!({
+                "type": "hello",
+                "session_id": session_id,
+                "server_version": server_version,
+                "environment": environment,
+                "capabilities": capabilities,
+                "modes": modes,
+                "template": template,
+                "size": size,
+                "cwd": cwd,
+                "output": output,
+                "resume_token": resume_token,
+                "share_token": share_token,
+                "participant": participant
+            }),
+            ServerMessage::Output { data, seq } => sequenced(json!({ "type": "output", "data": data }), *seq),
+            // Binary frames are the transport's business; everywhere else they travel as base64.
+            ServerMessage::OutputBytes { data, seq, .. } => sequenced(json!({
+                "type": "output",
+                "encoding": "base64",
+                "data": base64::engine::general_purpose::STANDARD.encode(data)
+            }), *seq),
+            ServerMessage::ConfirmRequired { pattern, token, expires_in_secs } => json!({
+                "type": "confirm_required",
+                "pattern": pattern,
+                "token": token,
+                "expires_in_secs": expires_in_secs
+            }),
+            ServerMessage::Prompt => json!({ "type": "prompt" }),
+            ServerMessage::CommandFinished { command, duration_ms, exit_code } => json!({
+                "type": "command_finished",
+                "duration_ms": duration_ms,
+                "exit_code": exit_code,
+                "command": command
+            }),
+            ServerMessage::MouseMode { mode, enabled } => json!({ "type": "mouse_mode", "enabled": enabled, "mode": mode }),
+            ServerMessage::CommandBoundary { phase, exit_code } => json!({
+                "type": "command_boundary",
+                "phase": phase.as_str(),
+                "exit_code": exit_code
+            }),
+            ServerMessage::Diagnostics { session_id, counters, throttle, resources } => json!({
+                "type": "diagnostics",
+                "session_id": session_id,
+                "counters": counters,
+                "throttle": throttle,
+                "resources": resources
+            }),
+            ServerMessage::ScreenSnapshot(snapshot) => json!({
+                "type": "screen_snapshot",
+                "lines": snapshot.lines,
+                "cursor": snapshot.cursor,
+                "rows": snapshot.rows,
+                "cols": snapshot.cols
+            }),
+            ServerMessage::Processes(processes) => json!({ "type": "ps", "processes": processes }),
+            ServerMessage::Jobs(jobs) => json!({ "type": "jobs", "jobs": jobs }),
+            ServerMessage::Timings(timings) => json!({
+                "type": "timings",
+                "commands": timings.commands,
+                "slowest": timings.slowest,
+                "busy_ms": timings.busy_ms
+            }),
+            ServerMessage::Capabilities { capabilities, environment, output } => json!({
+                "type": "capabilities",
+                "capabilities": capabilities,
+                "environment": environment,
+                "output": output,
+                "environment_updated": false,
+                "note": "TERM, COLORTERM and locale were set when the shell started and stay as they were; only the output sent to this client changed"
+            }),
+            ServerMessage::Cwd { path, source } => json!({ "type": "cwd", "path": path, "source": source }),
+            ServerMessage::Env { environment, env } => json!({
+                "type": "env",
+                "environment": environment,
+                "env": env,
+                "shell_updated": false,
+                "note": "the running shell keeps the environment it started with; these are the values the session reports"
+            }),
+            ServerMessage::Renamed { name } => json!({ "type": "renamed", "name": name }),
+            ServerMessage::Detached { session_id, resume_token } => json!({
+                "type": "detached",
+                "session_id": session_id,
+                "resume_token": resume_token
+            }),
+            ServerMessage::SessionInfo(info) => json!({
+                "type": "session_info",
+                "id": info.id,
+                "name": info.name,
+                "created_at": info.created_at,
+                "last_activity": info.last_activity,
+                "client_ip": info.client_ip,
+                "size": info.size,
+                "shell": info.shell,
+                "template": info.template,
+                "cwd": info.cwd,
+                "bytes_in": info.bytes_in,
+                "bytes_out": info.bytes_out,
+                "state": info.state,
+                "shared_clients": info.shared_clients,
+                "expires_at": info.expires_at
+            }),
+            ServerMessage::Links(batch) => json!({
+                "type": "links",
+                "items": batch.items,
+                "truncated": batch.truncated
+            }),
+            ServerMessage::PortOpened { port, pid, hint } => json!({
+                "type": "port_opened",
+                "port": port,
+                "pid": pid,
+                "hint": hint
+            }),
+            ServerMessage::PortClosed { port, pid } => json!({ "type": "port_closed", "port": port, "pid": pid }),
+            ServerMessage::SearchResults { query, results } => json!({
+                "type": "search_results",
+                "query": query,
+                "matches": results.matches,
+                "truncated": results.truncated
+            }),
+            ServerMessage::Scrollback(page) => json!({
+                "type": "scrollback",
+                "from": page.from,
+                "lines": page.lines,
+                "first_line": page.first_line,
+                "last_line": page.last_line
+            }),
+            ServerMessage::Export { format, filename, content } => json!({
+                "type": "export",
+                "format": format.as_str(),
+                "filename": filename,
+                "content": content
+            }),
+            ServerMessage::ExpiryWarning { remaining_secs, expires_at } => json!({
+                "type": "expiry_warning",
+                "remaining_secs": remaining_secs,
+                "expires_at": expires_at
+            }),
+            ServerMessage::ResourceWarning { rss_bytes, limit_bytes } => json!({
+                "type": "resource_warning",
+                "rss_bytes": rss_bytes,
+                "limit_bytes": limit_bytes
+            }),
+            ServerMessage::TmpdirWarning { bytes, limit_bytes } => json!({
+                "type": "tmpdir_warning",
+                "bytes": bytes,
+                "limit_bytes": limit_bytes
+            }),
+            ServerMessage::Exit { reason, code } => json!({
+                "type": "exit",
+                "reason": reason.reason(),
+                "code": code,
+                "message": ClientError::from(*reason).message
+            }),
+            ServerMessage::ReconnectHint { retry_after_ms, resumable, resume_token } => json!({
+                "type": "reconnect_hint",
+                "retry_after_ms": retry_after_ms,
+                "resumable": resumable,
+                "resume_token": resume_token
+            }),
+            ServerMessage::Error(error) => json!({
+                "type": "error",
+                "code": error.code,
+                "message": error.message,
+                "params": error.params
+            }),
+            ServerMessage::Notice(notice) => serde_json::to_value(notice).unwrap_or_default(),
+            ServerMessage::ObserverJoined { who } => json!({ "type": "observer_joined", "who": who }),
+            ServerMessage::PeerJoined(participant) => json!({ "type": "peer_joined", "participant": participant.id, "color": participant.color }),
+            ServerMessage::PeerLeft { participant } => json!({ "type": "peer_left", "participant": participant }),
+            ServerMessage::InputAttribution { participant, bytes } => json!({ "type": "input_attribution", "participant": participant, "bytes": bytes }),
+        }
+    }
+}
+
+/// `output` with its `seq`, left out for clients that do not ack.
+fn sequenced(mut output: serde_json::Value, seq: Option<u64>) -> serde_json::Value {
+    if let Some(seq) = seq {
+        output["seq"] = seq.into();
+    }
+    output
+}
+
+#[derive(Serialize)]
+struct PackedOutput<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    encoding: &'static str,
+    data: PackedBytes<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+}
+
+/// Serializes as MessagePack binary instead of an array of numbers.
+struct PackedBytes<'a>(&'a [u8]);
+
+impl Serialize for PackedBytes<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// One of the clients in a shared session, as `p1`, `p2`, ... in join order, with a color
+/// for UIs to mark its input with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Participant {
+    pub id: String,
+    pub color: String,
+}
+
+/// How a client wants PTY output: `text` is decoded UTF-8 with invalid bytes replaced,
+/// the others carry the bytes untouched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputEncoding {
+    #[default]
+    Text,
+    Base64,
+    Binary,
+}
+
+impl OutputEncoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "base64" => Some(Self::Base64),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// How a session's working directory was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CwdSource {
+    /// The shell reported it with OSC 7.
+    Osc7,
+    /// Read from `/proc` for shells that do not report it.
+    Proc,
+}
+
+/// Reply to `session_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Last input to or output from the shell.
+    pub last_activity: DateTime<Utc>,
+    /// The attached client's address, without its port.
+    pub client_ip: Option<String>,
+    pub size: TerminalSize,
+    /// The program running in the PTY.
+    pub shell: String,
+    pub template: Option<String>,
+    pub cwd: Option<String>,
+    /// Bytes written to the PTY and read from it since the session started.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub state: SessionState,
+    pub shared_clients: usize,
+    /// From `[terminal.max_lifetime]`.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Why the server is closing a WebSocket. Every close path goes through this
+/// mapping so clients can tell the cases apart by code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// 1000: the client or the session ended normally.
+    Normal,
+    /// 1001: the server is going away.
+    ServerShutdown,
+    /// 1002: the peer broke the WebSocket protocol.
+    ProtocolError,
+    /// 1008: authentication or origin checks failed.
+    PolicyViolation,
+    /// 1009: a message exceeded the configured size limit.
+    MessageTooBig,
+    /// 1013: no capacity for another session.
+    ServerFull,
+    /// 4000: an administrator closed the connection.
+    AdminDisconnect,
+    /// 4001: the session was idle for too long.
+    IdleTimeout,
+    /// 4002: another client took over the session.
+    Superseded,
+    /// 4003: the session reached `[terminal.max_lifetime]`.
+    MaxLifetime,
+    /// 4004: the session's processes stayed over a `[terminal.resources]` kill threshold.
+    ResourceLimit,
+    /// Any code, sent by `--chaos` mode to see how clients cope.
+    Chaos(u16),
+}
+
+impl CloseReason {
+    pub fn code(self) -> u16 {
+        match self {
+            CloseReason::Normal => 1000,
+            CloseReason::ServerShutdown => 1001,
+            CloseReason::ProtocolError => 1002,
+            CloseReason::PolicyViolation => 1008,
+            CloseReason::MessageTooBig => 1009,
+            CloseReason::ServerFull => 1013,
+            CloseReason::AdminDisconnect => 4000,
+            CloseReason::IdleTimeout => 4001,
+            CloseReason::Superseded => 4002,
+            CloseReason::MaxLifetime => 4003,
+            CloseReason::ResourceLimit => 4004,
+            CloseReason::Chaos(code) => code,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            CloseReason::Normal => "normal",
+            CloseReason::ServerShutdown => "server_shutdown",
+            CloseReason::ProtocolError => "protocol_error",
+            CloseReason::PolicyViolation => "policy_violation",
+            CloseReason::MessageTooBig => "message_too_big",
+            CloseReason::ServerFull => "server_full",
+            CloseReason::AdminDisconnect => "admin_disconnect",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::Superseded => "superseded",
+            CloseReason::MaxLifetime => "max_lifetime",
+            CloseReason::ResourceLimit => "resource_limit",
+            CloseReason::Chaos(_) => "chaos",
+        }
+    }
+
+    /// How long a client should wait before reconnecting, or `None` for closes it should
+    /// not retry (auth, policy, admin). Staggered so a restart is not met by every client
+    /// at once.
+    pub fn retry_after(self) -> Option<Duration> {
+        let (base, spread) = match self {
+            CloseReason::ServerShutdown => (1_000, 5_000),
+            CloseReason::ServerFull => (5_000, 10_000),
+            CloseReason::IdleTimeout => (0, 0),
+            _ => return None,
+        };
+        let jitter = (Uuid::new_v4().as_u128() % (spread + 1)) as u64;
+        Some(Duration::from_millis(base + jitter))
+    }
+
+    pub fn frame(self) -> CloseFrame<'static> {
+        CloseFrame {
+            code: CloseCode::from(self.code()),
+            reason: self.reason().into(),
+        }
+    }
+
+    /// Close reason for a socket error, or `None` when the connection is already
+    /// gone and there is nobody left to send a close frame to.
+    pub fn for_error(error: &WsError) -> Option<Self> {
+        match error {
+            WsError::Capacity(_) => Some(CloseReason::MessageTooBig),
+            WsError::Protocol(_) | WsError::Utf8 => Some(CloseReason::ProtocolError),
+            _ => None,
+        }
+    }
+}
+
+impl From<PortEvent> for ServerMessage {
+    fn from(event: PortEvent) -> Self {
+        match event {
+            PortEvent::Opened { port, pid, hint } => ServerMessage::PortOpened { port, pid, hint },
+            PortEvent::Closed { port, pid } => ServerMessage::PortClosed { port, pid },
+        }
+    }
+}
+
+impl From<OutputEvent> for ServerMessage {
+    fn from(event: OutputEvent) -> Self {
+        match event {
+            OutputEvent::Text(data) => ServerMessage::Output { data, seq: None },
+            OutputEvent::Boundary { phase, exit_code } => ServerMessage::CommandBoundary { phase, exit_code },
+            OutputEvent::WorkingDirectory(path) => ServerMessage::Cwd { path, source: CwdSource::Osc7 },
+        }
+    }
+}
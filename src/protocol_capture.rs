@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::protocol::ClientMessage;
+use crate::redaction;
+use crate::selftest::TerminalClient;
+
+/// Bumped whenever the line format changes; `Capture::read` refuses other versions.
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// `pty-server --capture-protocol-dir DIR` writes one `<session_id>.jsonl` per session.
+pub const CAPTURE_FLAG: &str = "--capture-protocol-dir";
+
+/// How long a replay keeps listening after the last message.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// First line of a capture file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub version: u32,
+    pub session_id: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Every following line: one inbound message and when it arrived.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedMessage {
+    /// Since the capture started.
+    pub at_ms: u64,
+    pub message: Value,
+}
+
+impl CapturedMessage {
+    /// When to send this message in a replay at `speed` times the original pace; a speed of
+    /// 0 sends everything back to back.
+    pub fn due(&self, speed: f64) -> Duration {
+        if speed <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.at_ms as f64 / 1000.0 / speed)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("failed to read capture {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("capture {path} line {line}: {error}")]
+    Parse { path: PathBuf, line: usize, error: String },
+    #[error("capture {path} is version {version}; this build reads version {CAPTURE_VERSION}")]
+    UnsupportedVersion { path: PathBuf, version: u32 },
+}
+
+/// A capture file read back for replay.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub header: CaptureHeader,
+    pub messages: Vec<CapturedMessage>,
+}
+
+impl Capture {
+    pub fn read(path: &Path) -> Result<Self, CaptureError> {
+        let file = File::open(path).map_err(|source| CaptureError::Read { path: path.to_path_buf(), source })?;
+        let mut lines = BufReader::new(file).lines().enumerate();
+        let parse_error = |line: usize, error: String| CaptureError::Parse { path: path.to_path_buf(), line: line + 1, error };
+
+        let (_, header) = lines.next().ok_or_else(|| parse_error(0, "empty file".to_string()))?;
+        let header = header.map_err(|source| CaptureError::Read { path: path.to_path_buf(), source })?;
+        // Check the version on its own first, so a newer header gets a clearer error.
+        let version = serde_json::from_str::<Value>(&header).ok().and_then(|header| header["version"].as_u64());
+        if let Some(version) = version.filter(|version| *version != CAPTURE_VERSION as u64) {
+            return Err(CaptureError::UnsupportedVersion { path: path.to_path_buf(), version: version as u32 });
+        }
+        let header: CaptureHeader = serde_json::from_str(&header).map_err(|e| parse_error(0, e.to_string()))?;
+
+        let mut messages = Vec::new();
+        for (i, line) in lines {
+            let line = line.map_err(|source| CaptureError::Read { path: path.to_path_buf(), source })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            messages.push(serde_json::from_str(&line).map_err(|e| parse_error(i, e.to_string()))?);
+        }
+        Ok(Self { header, messages })
+    }
+}
+
+/// Appends one session's inbound messages to its capture file, secrets redacted.
+#[derive(Debug)]
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(dir: &Path, session_id: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut writer = Self {
+            file: BufWriter::new(File::create(dir.join(format!("{}.jsonl", session_id)))?),
+            started: Instant::now(),
+        };
+        let header = CaptureHeader { version: CAPTURE_VERSION, session_id: session_id.to_string(), started_at: Utc::now() };
+        writer.write_line(&header)?;
+        Ok(writer)
+    }
+
+    pub fn record(&mut self, message: &ClientMessage) -> std::io::Result<()> {
+        let mut message = serde_json::to_value(message)?;
+        redact_strings(&mut message);
+        let at_ms = self.started.elapsed().as_millis() as u64;
+        self.write_line(&CapturedMessage { at_ms, message })
+    }
+
+    /// Flushed per line, so a capture survives the crash it was meant to catch.
+    fn write_line<L: Serialize>(&mut self, line: &L) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.file, line)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(text) => *text = redaction::redact(text),
+        Value::Array(items) => items.iter_mut().for_each(redact_strings),
+        Value::Object(fields) => fields.values_mut().for_each(redact_strings),
+        _ => {}
+    }
+}
+
+/// The capture directory from `--capture-protocol-dir DIR` or `--capture-protocol-dir=DIR`.
+pub fn dir_from_args<I: IntoIterator<Item = String>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == CAPTURE_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(dir) = arg.strip_prefix(CAPTURE_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(PathBuf::from(dir));
+        }
+    }
+    None
+}
+
+/// The `type` of an encoded server message.
+pub fn message_type(msg: &Value) -> String {
+    msg["type"].as_str().unwrap_or("untyped").to_string()
+}
+
+/// Plays `capture` against the terminal server at `url` and returns the types of the
+/// messages it sent back, hello first. Fails if the server drops the connection or closes
+/// it with anything but a normal close.
+pub async fn replay(url: &str, capture: &Capture, speed: f64) -> Result<Vec<String>, String> {
+    let (mut client, hello) = TerminalClient::connect(url).await?;
+    let mut types = vec![message_type(&hello)];
+    let started = tokio::time::Instant::now();
+    for captured in &capture.messages {
+        let due = started + captured.due(speed);
+        while let Some(msg) = client.recv_until(due).await? {
+            types.push(message_type(&msg));
+        }
+        client.send(captured.message.clone()).await?;
+    }
+    let settled = tokio::time::Instant::now() + SETTLE_TIME;
+    while let Some(msg) = client.recv_until(settled).await? {
+        types.push(message_type(&msg));
+    }
+    match client.close().await? {
+        (1000, _) => Ok(types),
+        (code, reason) => Err(format!("server closed with {} {}", code, reason)),
+    }
+}
+
+/// Where `actual` first departs from a recorded baseline of message types. `output` is
+/// left out on both sides: a real shell prints in different chunks and at different
+/// moments on every run, so only the rest of the conversation can be held to a baseline.
+pub fn compare_baseline(baseline: &[String], actual: &[String]) -> Result<(), String> {
+    let without_output = |types: &[String]| types.iter().filter(|t| *t != "output").cloned().collect::<Vec<_>>();
+    let (baseline, actual) = (without_output(baseline), without_output(actual));
+    match baseline.iter().zip(&actual).position(|(expected, got)| expected != got) {
+        Some(i) => Err(format!("message {} is '{}', the baseline has '{}'", i + 1, actual[i], baseline[i])),
+        None if baseline.len() != actual.len() => {
+            Err(format!("{} messages, the baseline has {}", actual.len(), baseline.len()))
+        }
+        None => Ok(()),
+    }
+}
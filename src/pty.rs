@@ -0,0 +1,363 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc as std_mpsc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::screen::TerminalSize;
+
+/// Output chunks buffered between the PTY and the session loop; a slow client makes the
+/// reader wait instead of the server holding everything the shell prints.
+const OUTPUT_BUFFER: usize = 64;
+const READ_CHUNK: usize = 8192;
+/// How long a hung-up session has to exit before what is left of it gets SIGKILL.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// What to start in a new PTY. The environment is exactly `env`; nothing is inherited.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    pub size: TerminalSize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PtyEvent {
+    /// Bytes as read; a multi-byte character may be split across chunks.
+    Output(Vec<u8>),
+    /// The child exited; processes it left behind may still hold the PTY open.
+    Exited { code: u32 },
+    /// Nothing holds the PTY open any more; no output follows.
+    Closed,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PtyError {
+    #[error("cannot open a PTY: {0}")]
+    Open(String),
+    #[error("cannot start '{program}': {error}")]
+    Spawn { program: String, error: String },
+    #[error("cannot send {signal} to the foreground process group: {error}")]
+    Signal { signal: PtySignal, error: String },
+    #[error("no job {0} in this session")]
+    UnknownJob(u32),
+}
+
+impl PtyError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PtyError::Open(_) | PtyError::Spawn { .. } => "shell_spawn_failed",
+            PtyError::Signal { .. } => "signal_failed",
+            PtyError::UnknownJob(_) => "job_not_found",
+        }
+    }
+}
+
+/// Signals a client may send to whatever runs in the foreground of its terminal, the
+/// equivalents of Ctrl-C and Ctrl-Z plus the ones no key produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PtySignal {
+    Sigint,
+    Sigtstp,
+    Sigcont,
+    Sighup,
+    Sigterm,
+    Sigkill,
+}
+
+impl PtySignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PtySignal::Sigint => "SIGINT",
+            PtySignal::Sigtstp => "SIGTSTP",
+            PtySignal::Sigcont => "SIGCONT",
+            PtySignal::Sighup => "SIGHUP",
+            PtySignal::Sigterm => "SIGTERM",
+            PtySignal::Sigkill => "SIGKILL",
+        }
+    }
+
+    #[cfg(unix)]
+    fn number(&self) -> i32 {
+        match self {
+            PtySignal::Sigint => libc::SIGINT,
+            PtySignal::Sigtstp => libc::SIGTSTP,
+            PtySignal::Sigcont => libc::SIGCONT,
+            PtySignal::Sighup => libc::SIGHUP,
+            PtySignal::Sigterm => libc::SIGTERM,
+            PtySignal::Sigkill => libc::SIGKILL,
+        }
+    }
+}
+
+impl std::fmt::Display for PtySignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A child process on its own PTY. Dropping it hangs up the child's whole session.
+pub struct Pty {
+    master: Box<dyn MasterPty + Send>,
+    input: std_mpsc::Sender<Vec<u8>>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    pid: Option<u32>,
+    /// How long hanging up waits between SIGHUP and SIGKILL.
+    #[cfg_attr(windows, allow(dead_code))]
+    kill_grace: Duration,
+}
+
+impl Pty {
+    /// Starts `spec` and returns the PTY with its stream of output and exit events.
+    pub fn spawn(spec: &SpawnSpec) -> Result<(Self, mpsc::Receiver<PtyEvent>), PtyError> {
+        let pair = native_pty_system().openpty(pty_size(spec.size)).map_err(|e| PtyError::Open(e.to_string()))?;
+        let mut command = CommandBuilder::new(&spec.program);
+        command.args(&spec.args);
+        command.env_clear();
+        for (name, value) in &spec.env {
+            command.env(name, value);
+        }
+        if let Some(cwd) = &spec.cwd {
+            command.cwd(cwd);
+        }
+        let spawn_error = |e: &dyn std::fmt::Display| PtyError::Spawn { program: spec.program.clone(), error: e.to_string() };
+        let mut child = pair.slave.spawn_command(command).map_err(|e| spawn_error(&e))?;
+        // Only the child may hold the slave side, so the reader sees EOF once it is gone.
+        drop(pair.slave);
+        let reader = pair.master.try_clone_reader().map_err(|e| spawn_error(&e))?;
+        let writer = pair.master.take_writer().map_err(|e| spawn_error(&e))?;
+
+        let pid = child.process_id();
+        let killer = child.clone_killer();
+        let (events, events_rx) = mpsc::channel(OUTPUT_BUFFER);
+        let exited = events.clone();
+        std::thread::spawn(move || {
+            let code = child.wait().map(|status| status.exit_code()).unwrap_or(1);
+            let _ = exited.blocking_send(PtyEvent::Exited { code });
+        });
+        std::thread::spawn(move || read_output(reader, events));
+        let (input, input_rx) = std_mpsc::channel();
+        std::thread::spawn(move || write_input(writer, input_rx));
+        debug!("🐚 Started {} in a PTY (pid {:?})", spec.program, pid);
+        Ok((Self { master: pair.master, input, killer, pid, kill_grace: KILL_GRACE }, events_rx))
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn set_kill_grace(&mut self, kill_grace: Duration) {
+        self.kill_grace = kill_grace;
+    }
+
+    /// Queues `data` for the child; writes happen on their own thread, so a child that
+    /// stops reading never blocks the caller.
+    pub fn write(&self, data: &[u8]) {
+        if !data.is_empty() {
+            let _ = self.input.send(data.to_vec());
+        }
+    }
+
+    pub fn resize(&self, size: TerminalSize) {
+        if let Err(e) = self.master.resize(pty_size(size)) {
+            warn!("📐 Could not resize PTY of pid {:?}: {}", self.pid, e);
+        }
+    }
+
+    /// Sends `signal` to the terminal's foreground process group, the running command
+    /// rather than the shell waiting on it, and returns that group's id.
+    pub fn signal_foreground(&self, signal: PtySignal) -> Result<u32, PtyError> {
+        NativePty::signal_foreground(self, signal)
+    }
+
+    /// Kills everything in the child's session, leftovers of an exited child included,
+    /// and returns how many processes that was.
+    pub fn kill_session(&mut self) -> usize {
+        NativePty::kill_session(self)
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        NativePty::hang_up(self);
+    }
+}
+
+/// Sends `signal` to process group `pgid`, such as one of a session's jobs, and returns
+/// the group again.
+pub fn signal_group(pgid: u32, signal: PtySignal) -> Result<u32, PtyError> {
+    NativePty::signal_group(pgid, signal)
+}
+
+/// What differs between platforms once a child runs in a PTY: how it is signalled and how
+/// what it leaves behind is cleaned up. Spawning, I/O and resizing go through portable-pty
+/// everywhere, which opens a ConPTY on Windows.
+pub trait PtyBackend {
+    /// Started for sessions when `[terminal] shell` is not set.
+    const DEFAULT_SHELL: &'static str;
+
+    fn signal_foreground(pty: &Pty, signal: PtySignal) -> Result<u32, PtyError>;
+    fn signal_group(pgid: u32, signal: PtySignal) -> Result<u32, PtyError>;
+    /// Kills the child and everything it started, and returns how many processes that was.
+    fn kill_session(pty: &mut Pty) -> usize;
+    /// Tells the child its terminal is gone, and makes sure it exits.
+    fn hang_up(pty: &mut Pty);
+}
+
+/// The backend this build of the server runs shells with.
+#[cfg(unix)]
+pub type NativePty = UnixPty;
+#[cfg(windows)]
+pub type NativePty = ConPty;
+
+/// A unix PTY: signals go to process groups, and the child leads a session that is
+/// hung up and then killed as a whole.
+#[cfg(unix)]
+pub struct UnixPty;
+
+#[cfg(unix)]
+impl PtyBackend for UnixPty {
+    const DEFAULT_SHELL: &'static str = "bash";
+
+    fn signal_foreground(pty: &Pty, signal: PtySignal) -> Result<u32, PtyError> {
+        let group = pty.master.process_group_leader().filter(|group| *group > 0);
+        let group = group.ok_or_else(|| PtyError::Signal { signal, error: "no foreground process group".to_string() })?;
+        Self::signal_group(group as u32, signal)
+    }
+
+    fn signal_group(pgid: u32, signal: PtySignal) -> Result<u32, PtyError> {
+        let failed = |error: String| PtyError::Signal { signal, error };
+        let group = i32::try_from(pgid).ok().filter(|group| *group > 0).ok_or_else(|| failed(format!("no process group {}", pgid)))?;
+        // SAFETY: killpg only takes plain integers.
+        if unsafe { libc::killpg(group, signal.number()) } == -1 {
+            return Err(failed(std::io::Error::last_os_error().to_string()));
+        }
+        Ok(pgid)
+    }
+
+    fn kill_session(pty: &mut Pty) -> usize {
+        match pty.pid {
+            Some(pid) => crate::process_group::signal_session(pid, libc::SIGKILL),
+            None => usize::from(pty.killer.kill().is_ok()),
+        }
+    }
+
+    fn hang_up(pty: &mut Pty) {
+        let Some(pid) = pty.pid else {
+            let _ = pty.killer.kill();
+            return;
+        };
+        crate::process_group::signal_session(pid, libc::SIGHUP);
+        let kill_grace = pty.kill_grace;
+        std::thread::spawn(move || {
+            std::thread::sleep(kill_grace);
+            crate::process_group::signal_session(pid, libc::SIGKILL);
+        });
+    }
+}
+
+/// A Windows pseudo console. There are no process groups or job control: Ctrl-C is typed
+/// into the console, which raises it in every attached process, and the rest terminate
+/// the shell, taking the console's other processes with it.
+#[cfg(windows)]
+pub struct ConPty;
+
+#[cfg(windows)]
+impl PtyBackend for ConPty {
+    const DEFAULT_SHELL: &'static str = "powershell.exe";
+
+    fn signal_foreground(pty: &Pty, signal: PtySignal) -> Result<u32, PtyError> {
+        let pid = pty.pid.ok_or_else(|| PtyError::Signal { signal, error: "the console has no process".to_string() })?;
+        match signal {
+            PtySignal::Sigint => pty.write(b"\x03"),
+            PtySignal::Sighup | PtySignal::Sigterm | PtySignal::Sigkill => {
+                pty.killer.clone_killer().kill().map_err(|e| PtyError::Signal { signal, error: e.to_string() })?
+            }
+            PtySignal::Sigtstp | PtySignal::Sigcont => {
+                return Err(PtyError::Signal { signal, error: "Windows consoles have no job control".to_string() })
+            }
+        }
+        Ok(pid)
+    }
+
+    fn signal_group(pgid: u32, _signal: PtySignal) -> Result<u32, PtyError> {
+        Err(PtyError::UnknownJob(pgid))
+    }
+
+    fn kill_session(pty: &mut Pty) -> usize {
+        usize::from(pty.killer.kill().is_ok())
+    }
+
+    fn hang_up(pty: &mut Pty) {
+        let _ = pty.killer.kill();
+    }
+}
+
+fn pty_size(size: TerminalSize) -> PtySize {
+    PtySize { rows: size.rows, cols: size.cols, pixel_width: 0, pixel_height: 0 }
+}
+
+fn read_output(mut reader: Box<dyn Read + Send>, events: mpsc::Sender<PtyEvent>) {
+    let mut buf = [0u8; READ_CHUNK];
+    loop {
+        // Linux reports EIO rather than EOF once the last slave descriptor closes.
+        let n = match reader.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        if events.blocking_send(PtyEvent::Output(buf[..n].to_vec())).is_err() {
+            return;
+        }
+    }
+    let _ = events.blocking_send(PtyEvent::Closed);
+}
+
+fn write_input(mut writer: Box<dyn Write + Send>, input: std_mpsc::Receiver<Vec<u8>>) {
+    for data in input {
+        if writer.write_all(&data).and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Turns a byte stream into text without splitting characters across chunk boundaries;
+/// invalid bytes become U+FFFD.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let complete = self.pending.len() - incomplete_tail(&self.pending);
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+        text
+    }
+}
+
+/// Length of a multi-byte character cut off at the end of `bytes`.
+fn incomplete_tail(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if needed > back { back } else { 0 };
+    }
+    0
+}
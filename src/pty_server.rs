@@ -1,53 +1,1060 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
+use std::time::{Duration, Instant};
 use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_tungstenite::{accept_hdr_async_with_config, WebSocketStream};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{self, StatusCode},
+    protocol::WebSocketConfig,
+    Message,
+};
 use uuid::Uuid;
-use serde_json::json;
 use log::{info, error, warn, debug};
+use rust_terminal_forge::admin::{self, AdminRejection};
+use rust_terminal_forge::approvals::AUDIT_TARGET;
+use rust_terminal_forge::bandwidth::{Bandwidth, ThrottleStats};
+use rust_terminal_forge::banner::{Banner, SERVER_VERSION};
+use rust_terminal_forge::base_path::BasePath;
+use rust_terminal_forge::capabilities::{Capabilities, Capability, OutputFilter};
+use rust_terminal_forge::chaos::Chaos;
+use rust_terminal_forge::client_env::{self, ClientEnv, ClientEnvError};
+use rust_terminal_forge::command_guard::{CommandGuard, GuardVerdict};
+use rust_terminal_forge::command_timing::{CommandTimer, FinishedCommand};
+use rust_terminal_forge::config::{ConfigError, ForgeConfig, ObserveConfig, TerminalConfig};
+use rust_terminal_forge::connection_limits::{ConnectionLimiter, ConnectionPermit};
+use rust_terminal_forge::diagnostics::ProtocolCounters;
+use rust_terminal_forge::error_catalog::ClientError;
+use rust_terminal_forge::input_line::InputLine;
+use rust_terminal_forge::multiplex::{self, MuxError, MuxFrame, MUX_PATH};
+use rust_terminal_forge::lifetime::{IdlePolicy, Lifetime, LifetimeEvent, LifetimePolicy};
+use rust_terminal_forge::links::LinkScanner;
+use rust_terminal_forge::notices::{Notice, NoticeBus, NoticeLevel};
+use rust_terminal_forge::origins::AllowedOrigins;
+use rust_terminal_forge::ports::PortAnnouncementScanner;
+use rust_terminal_forge::process_group::{self, DisconnectPolicy, OrphanedIoPolicy};
+use rust_terminal_forge::process_tree::{self, ProcessInfo, ProcessSampler};
+use rust_terminal_forge::preflight::{self, Preflight};
+use rust_terminal_forge::protocol::{ClientMessage, CloseReason, CwdSource, DecodeError, OutputEncoding, Participant, ServerMessage, SessionInfo, WireFormat};
+use rust_terminal_forge::protocol_capture::{self, CaptureWriter};
+use rust_terminal_forge::pty::{self, Pty, PtyError, PtyEvent, SpawnSpec, Utf8Decoder};
+use rust_terminal_forge::redaction::{self, Redactor};
+use rust_terminal_forge::replay::ReplayBuffer;
+use rust_terminal_forge::resources::{ResourceEvent, ResourceLimits, ResourceMonitor, ResourceUsage};
+use rust_terminal_forge::run_as::{self, RunAs};
+use rust_terminal_forge::session_env::{EnvironmentError, EnvironmentPolicy, EnvironmentRequest, SessionEnvironment};
+use rust_terminal_forge::session_events::{self, AdminSessions, EventBus, Observation, SessionEventKind};
+use rust_terminal_forge::screen::{ScreenModel, TerminalSize};
+use rust_terminal_forge::scrollback::{self, Scrollback};
+use rust_terminal_forge::session_registry::{KillSignal, RegistryError, SessionMetadata, SessionRegistry, SessionState};
+use rust_terminal_forge::session_names::{self, NameError};
+use rust_terminal_forge::session_store::{RestoredSessions, SavedSession, SessionStore};
+use rust_terminal_forge::session_tags::{self, SessionTags, TagError};
+use rust_terminal_forge::session_tmp::{self, SessionTmpDir, TmpDirs};
+use rust_terminal_forge::shell_env::ShellEnv;
+use rust_terminal_forge::shell_policy::{ShellError, ShellPolicy};
+use rust_terminal_forge::shell_integration::{OutputEvent, PromptDetector, ShellIntegrationParser};
+use rust_terminal_forge::templates::{Launch, TemplateError, Templates};
+use rust_terminal_forge::terminal_modes::{ModeTracker, MOUSE_MODES};
+use rust_terminal_forge::transcript::{self, TranscriptHeader};
+use rust_terminal_forge::transport::{memory_pair, ClientFrame, Connection, Heartbeat, Inbound, ServerFrame, CHANNEL_CAPACITY, Transport, TransportError, TransportWriter, WsTransport};
+use rust_terminal_forge::webhooks::{WebhookEvent, Webhooks};
+use rust_terminal_forge::workspaces::{self, WorkspaceError, Workspaces};
+
+const ADMIN_WS_PATH: &str = "/admin/ws";
+const SESSIONS_PATH: &str = "/sessions";
+const WORKSPACES_PATH: &str = "/workspaces";
+const DISCONNECT_SUFFIX: &str = "/disconnect";
+/// How long `DELETE /sessions/{id}` and `/workspaces/{name}` wait for sessions to leave
+/// the registry.
+const KILL_WAIT: Duration = Duration::from_secs(5);
+/// How much of a new connection is peeked at for its request line before it is handed
+/// to the WebSocket handshake regardless.
+const REQUEST_LINE_LIMIT: usize = 8192;
+/// How long a new connection gets to send its request line and finish the WebSocket
+/// handshake before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Output a shared client may fall behind by before it misses some.
+const MIRROR_CAPACITY: usize = 256;
+/// The session's own client, among the participants of a shared session.
+const OWNER_PARTICIPANT: &str = "p1";
+/// Handed out in join order, `p1` first, and reused past the end.
+const PARTICIPANT_COLORS: [&str; 8] = ["#4e9a06", "#3465a4", "#c4a000", "#75507b", "#06989a", "#cc0000", "#f57900", "#ad7fa8"];
+/// How often a shared client's typing is summarized for the other participants.
+const ATTRIBUTION_INTERVAL: Duration = Duration::from_millis(500);
+const THROUGHPUT_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// How often shells that do not send OSC 7 have their working directory read from `/proc`.
+const CWD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SHUTDOWN_NOTICE_GRACE: Duration = Duration::from_millis(500);
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+type Sessions = SessionRegistry<Arc<Mutex<TerminalSession>>>;
+
+/// Shared handles every connection task gets a clone of.
+#[derive(Clone)]
+struct ServerState {
+    sessions: Sessions,
+    events: EventBus,
+    notices: NoticeBus,
+    guard: CommandGuard,
+    banner: Arc<Banner>,
+    defaults: SessionDefaults,
+    webhooks: Webhooks,
+    /// Off unless started with `--chaos`.
+    chaos: Chaos,
+    bandwidth: Bandwidth,
+    /// Terminal connections open per client address, against `max_connections_per_ip`.
+    connections: ConnectionLimiter,
+    /// Sessions saved before the last restart that their clients have yet to resume.
+    restored: RestoredSessions,
+    /// Created over HTTP or by the first session opened in one.
+    workspaces: Workspaces,
+    /// Set by `--capture-protocol-dir`; every session records its inbound messages there.
+    capture_dir: Option<Arc<PathBuf>>,
+    /// Set by `--base-path`; handshakes outside it get a 404.
+    base_path: BasePath,
+    /// `[listen] allowed_origins`; handshakes from other browser pages get a 403.
+    origins: AllowedOrigins,
+    /// Flips to `true` once the server starts shutting down.
+    shutdown: watch::Receiver<bool>,
+}
+
+impl ServerState {
+    fn new(
+        guard: CommandGuard,
+        banner: Banner,
+        defaults: SessionDefaults,
+        webhooks: Webhooks,
+        chaos: Chaos,
+        bandwidth: Bandwidth,
+        shutdown: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            sessions: SessionRegistry::with_limit(defaults.max_sessions),
+            connections: ConnectionLimiter::new(defaults.max_connections_per_ip),
+            restored: RestoredSessions::default(),
+            workspaces: Workspaces::new(),
+            events: EventBus::default(),
+            notices: NoticeBus::default(),
+            guard,
+            banner: Arc::new(banner),
+            defaults,
+            webhooks,
+            chaos,
+            bandwidth,
+            capture_dir: None,
+            base_path: BasePath::default(),
+            origins: AllowedOrigins::default(),
+            shutdown,
+        }
+    }
+}
+
+/// Per-session settings from `[terminal]`, applied to every new session.
+#[derive(Clone)]
+struct SessionDefaults {
+    shells: ShellPolicy,
+    shell_env: ShellEnv,
+    /// Cloned into each session; `None` when prompt detection is off.
+    prompt: Option<PromptDetector>,
+    screen_model: bool,
+    scrollback_bytes: usize,
+    replay_bytes: usize,
+    max_sessions: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    max_shared_clients: usize,
+    /// Sessions are saved at shutdown, so every one gets a resume token.
+    persist: bool,
+    /// `None` when long-command notifications are off.
+    long_command_threshold: Option<Duration>,
+    long_command_webhook: bool,
+    environment: EnvironmentPolicy,
+    lifetime: LifetimePolicy,
+    /// `None` when idle sessions are left alone.
+    idle: Option<IdlePolicy>,
+    /// `None` when resource sampling is off.
+    resources: Option<ResourceLimits>,
+    /// `None` when sessions share the system TMPDIR.
+    tmpdirs: Option<TmpDirs>,
+    templates: Templates,
+    orphaned_io: OrphanedIoPolicy,
+    disconnect: DisconnectPolicy,
+    /// `None` when connections are not pinged.
+    heartbeat: Option<Heartbeat>,
+    /// `None` when shells run as the server's own user.
+    run_as: Option<RunAs>,
+    observe: ObserveConfig,
+}
+
+impl SessionDefaults {
+    fn from_config(config: &TerminalConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            shells: ShellPolicy::from_config(config),
+            shell_env: ShellEnv::default(),
+            prompt: PromptDetector::from_config(&config.prompt_detection)?,
+            screen_model: config.screen_model,
+            scrollback_bytes: config.scrollback_bytes,
+            replay_bytes: config.replay_bytes,
+            max_sessions: config.max_sessions,
+            max_connections_per_ip: config.max_connections_per_ip,
+            max_shared_clients: config.max_shared_clients,
+            persist: config.persistence.path.is_some(),
+            long_command_threshold: config
+                .long_commands
+                .enabled
+                .then(|| Duration::from_secs(config.long_commands.threshold_secs)),
+            long_command_webhook: config.long_commands.webhook,
+            environment: EnvironmentPolicy::from_config(&config.environment)?,
+            lifetime: LifetimePolicy::from_config(&config.max_lifetime),
+            idle: IdlePolicy::from_config(&config.idle_timeout),
+            resources: ResourceLimits::from_config(&config.resources)?,
+            tmpdirs: None,
+            templates: Templates::default(),
+            orphaned_io: OrphanedIoPolicy::from_config(&config.orphaned_io),
+            disconnect: DisconnectPolicy::from_config(&config.disconnect),
+            heartbeat: Heartbeat::from_config(&config.heartbeat),
+            run_as: None,
+            observe: config.observe,
+        })
+    }
+
+    fn with_shell_env(self, shell_env: ShellEnv) -> Self {
+        Self { shell_env, ..self }
+    }
+
+    fn with_templates(self, templates: Templates) -> Self {
+        Self { templates, ..self }
+    }
+
+    fn with_tmpdirs(self, tmpdirs: Option<TmpDirs>) -> Self {
+        Self { tmpdirs, ..self }
+    }
+
+    fn with_run_as(self, run_as: Option<RunAs>) -> Self {
+        Self { run_as, ..self }
+    }
 
-type Sessions = Arc<Mutex<HashMap<String, Arc<Mutex<TerminalSession>>>>>;
+    /// What a session with `options` starts: its template's shell or command, or the
+    /// default shell, with the `[shell_env]` allowlist, the session environment, the
+    /// template's variables, the client's and the session's `TMPDIR`, in that order of
+    /// precedence.
+    fn spawn_spec(&self, options: &SessionOptions, tmpdir: Option<&SessionTmpDir>) -> SpawnSpec {
+        let (program, args) = match &options.launch {
+            Launch::Shell(shell) => (shell.clone(), Vec::new()),
+            Launch::Command(argv) if !argv.is_empty() => (argv[0].clone(), argv[1..].to_vec()),
+            Launch::DefaultShell | Launch::Command(_) => (self.shells.default_shell().to_string(), Vec::new()),
+        };
+        let mut env = self.shell_env.vars();
+        env.extend(options.environment.vars().into_iter().map(|(name, value)| (name.to_string(), value)));
+        env.extend(options.env.iter().map(|(name, value)| (name.clone(), value.clone())));
+        env.extend(options.client_env.iter().map(|(name, value)| (name.clone(), value.clone())));
+        if let Some((name, path)) = tmpdir.map(SessionTmpDir::env) {
+            env.push((name.to_string(), path.display().to_string()));
+        }
+        SpawnSpec { program, args, cwd: options.cwd.clone(), env, size: options.size }
+    }
+}
 
 struct TerminalSession {
     id: String,
+    /// The program running in the PTY, for transcripts.
+    shell: String,
+    template: Option<String>,
+    /// Issued when `[terminal.disconnect]` lingers or sessions are saved at shutdown, for
+    /// the client to reconnect with.
+    resume_token: Option<String>,
+    /// Where a client presenting `resume_token` is handed to the session's task.
+    reattach: Option<mpsc::Sender<Reattach>>,
+    /// Where an admin's disconnect is handed to the session's task, to detach its client.
+    disconnect: Option<mpsc::Sender<()>>,
+    /// Issued when sharing is on, for more clients to open the session with.
+    share_token: Option<String>,
+    max_shared_clients: usize,
+    /// Clients that joined with the share token, in join order; the session's own client
+    /// is `p1` and not listed.
+    participants: Vec<Participant>,
+    /// Number in the next participant ID; never reused within a session.
+    next_participant: usize,
+    /// Output and the session's end, for shared clients.
+    mirror: broadcast::Sender<ServerMessage>,
+    pty: Pty,
+    /// What is typed on the current line, for the dangerous-command guard.
+    input_line: InputLine,
     active: bool,
+    /// Last input to or output from the shell.
+    last_activity: chrono::DateTime<chrono::Utc>,
+    bytes_in: u64,
+    bytes_out: u64,
+    sampled_bytes_in: u64,
+    sampled_bytes_out: u64,
+    /// Confirmation token and the input held back by the dangerous-command guard, from
+    /// the held line's Enter on.
+    pending_confirmation: Option<(String, String)>,
+    counters: Arc<ProtocolCounters>,
+    shell_integration: ShellIntegrationParser,
+    prompt: Option<PromptDetector>,
+    screen: Option<ScreenModel>,
+    scrollback: Scrollback,
+    /// What this client was sent, for the one that resumes the session.
+    replay: ReplayBuffer,
+    links: Option<LinkScanner>,
+    ports: Option<PortAnnouncementScanner>,
+    /// Rooted at the session's child process; sessions without one report no processes.
+    processes: Option<Arc<Mutex<ProcessSampler>>>,
+    resources: Option<ResourceMonitor>,
+    /// Exported as `TMPDIR` to the session's child; removed when the session is dropped.
+    tmpdir: Option<SessionTmpDir>,
+    /// Warnings from the background samplers and news of the session's other participants,
+    /// for the session loop to send on.
+    warnings: Option<mpsc::Sender<ServerMessage>>,
+    output_filter: OutputFilter,
+    modes: ModeTracker,
+    commands: CommandTimer,
+    long_command_threshold: Option<Duration>,
+    long_command_webhook: bool,
+    confirm_dangerous: bool,
+    /// The PTY's size; the screen model, when there is one, is kept at the same size.
+    size: TerminalSize,
+    /// What the shell was started with, updated by `setenv` for clients to read back.
+    environment: SessionEnvironment,
+    env: ClientEnv,
+    /// The shell's working directory, from OSC 7 once the shell sends it and from `/proc`
+    /// until then.
+    cwd: Option<String>,
+    cwd_from_shell: bool,
+    /// PTY output is decoded here; the raw bytes go on untouched unless `output` is `Text`.
+    decoder: Utf8Decoder,
+    output: OutputEncoding,
 }
 
 impl TerminalSession {
-    fn new() -> Self {
-        let id = Uuid::new_v4().to_string();
-        Self {
+    /// Starts the session's shell in a new PTY; its output and exit arrive on the receiver.
+    fn new(defaults: &SessionDefaults, options: &SessionOptions) -> Result<(Self, mpsc::Receiver<PtyEvent>), PtyError> {
+        let id = options.restored.as_ref().map_or_else(|| Uuid::new_v4().to_string(), |saved| saved.id.clone());
+        let tmpdir = defaults.tmpdirs.as_ref().and_then(|tmpdirs| match tmpdirs.create(&id) {
+            Ok(tmpdir) => Some(tmpdir),
+            Err(e) => {
+                warn!("🗂️ Session {} falls back to the system TMPDIR: {}", id, e);
+                None
+            }
+        });
+        let spec = defaults.spawn_spec(options, tmpdir.as_ref());
+        let (mut pty, pty_events) = match &defaults.run_as {
+            Some(run_as) => {
+                if let Some(Err(e)) = tmpdir.as_ref().map(|tmpdir| run_as.give(tmpdir.path())) {
+                    warn!("🗂️ Session {} TMPDIR stays the server's: {}", id, e);
+                }
+                Pty::spawn(&run_as.wrap(&spec))?
+            }
+            None => Pty::spawn(&spec)?,
+        };
+        pty.set_kill_grace(defaults.disconnect.kill_grace);
+        let processes = pty.pid().map(|pid| Arc::new(Mutex::new(ProcessSampler::new(pid))));
+        let cwd = pty.pid().and_then(process_tree::cwd).or_else(|| spec.cwd.clone()).map(|path| path.display().to_string());
+        let resume_token = match &options.restored {
+            Some(saved) => Some(saved.resume_token.clone()),
+            None => (defaults.persist || !defaults.disconnect.linger.is_zero()).then(|| Uuid::new_v4().to_string()),
+        };
+        let mut session = Self {
             id,
+            shell: spec.program,
+            template: options.template.clone(),
+            resume_token,
+            reattach: None,
+            disconnect: None,
+            share_token: (defaults.max_shared_clients > 0).then(|| Uuid::new_v4().to_string()),
+            max_shared_clients: defaults.max_shared_clients,
+            participants: Vec::new(),
+            next_participant: 2,
+            mirror: broadcast::channel(MIRROR_CAPACITY).0,
+            pty,
+            input_line: InputLine::default(),
             active: true,
+            last_activity: chrono::Utc::now(),
+            bytes_in: 0,
+            bytes_out: 0,
+            sampled_bytes_in: 0,
+            sampled_bytes_out: 0,
+            pending_confirmation: None,
+            counters: Arc::default(),
+            shell_integration: ShellIntegrationParser::default(),
+            prompt: defaults.prompt.clone(),
+            screen: defaults.screen_model.then(|| ScreenModel::new(options.size.rows, options.size.cols)),
+            scrollback: Scrollback::new(options.scrollback_bytes.unwrap_or(defaults.scrollback_bytes)),
+            replay: ReplayBuffer::new(options.replay_bytes.map_or(defaults.replay_bytes, |bytes| bytes.min(defaults.replay_bytes))),
+            links: options.detect_links.then(LinkScanner::default),
+            ports: options.detect_ports.then(PortAnnouncementScanner::default),
+            processes,
+            resources: defaults.resources.clone().map(ResourceMonitor::new),
+            tmpdir,
+            warnings: None,
+            output_filter: OutputFilter::new(options.capabilities.clone()),
+            modes: ModeTracker::default(),
+            commands: CommandTimer::new(defaults.prompt.is_some()),
+            long_command_threshold: defaults.long_command_threshold,
+            long_command_webhook: defaults.long_command_webhook,
+            confirm_dangerous: !options.skip_confirmation,
+            size: options.size,
+            environment: options.environment.clone(),
+            env: options.client_env.clone(),
+            cwd,
+            cwd_from_shell: false,
+            decoder: Utf8Decoder::default(),
+            output: options.output,
+        };
+        if let Some(saved) = &options.restored {
+            session.scrollback.push(&saved.scrollback);
+            session.replay.push(saved.scrollback.as_bytes());
+        }
+        Ok((session, pty_events))
+    }
+
+    /// What is kept of the session across a restart; sessions without a resume token could
+    /// not be claimed again, so they are not kept.
+    fn saved(&self, metadata: SessionMetadata) -> Option<SavedSession> {
+        Some(SavedSession {
+            id: self.id.clone(),
+            name: metadata.name,
+            workspace: metadata.workspace,
+            created_at: metadata.created_at,
+            cwd: self.cwd.clone(),
+            env: self.env_vars(),
+            scrollback: self.scrollback.contents().to_string(),
+            resume_token: self.resume_token.clone()?,
+        })
+    }
+
+    /// What a client is greeted with on attaching, and again on resuming the session.
+    fn hello(&self) -> ServerMessage {
+        ServerMessage::Hello {
+            session_id: self.id.clone(),
+            server_version: SERVER_VERSION.to_string(),
+            environment: self.environment.clone(),
+            capabilities: self.output_filter.capabilities().clone(),
+            modes: self.modes.modes(),
+            template: self.template.clone(),
+            size: self.size,
+            cwd: self.cwd.clone(),
+            output: self.output_encoding(),
+            resume_token: self.resume_token.clone(),
+            share_token: self.share_token.clone(),
+            participant: None,
+        }
+    }
+
+    /// Output from here on for a client presenting `token`, if it is this session's share
+    /// token and there is room for another shared client, and who that client is. Everyone
+    /// already in the session hears it joined.
+    fn share_with(&mut self, token: &str) -> Result<(broadcast::Receiver<ServerMessage>, Participant), ClientError> {
+        let expected = self.share_token.as_deref().unwrap_or_default();
+        if expected.is_empty() || !admin::constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            return Err(ClientError::new("share_rejected").with("id", &self.id));
+        }
+        if self.participants.len() >= self.max_shared_clients {
+            return Err(ClientError::new("share_full").with("id", &self.id).with("limit", self.max_shared_clients));
+        }
+        let participant = numbered_participant(self.next_participant);
+        self.next_participant += 1;
+        self.announce(ServerMessage::PeerJoined(participant.clone()));
+        self.participants.push(participant.clone());
+        Ok((self.mirror.subscribe(), participant))
+    }
+
+    /// Everyone in the session but `participant`, for a client that just joined.
+    fn peers_of(&self, participant: &str) -> Vec<Participant> {
+        std::iter::once(numbered_participant(1)).chain(self.participants.iter().cloned()).filter(|peer| peer.id != participant).collect()
+    }
+
+    fn leave(&mut self, participant: &str) {
+        self.participants.retain(|peer| peer.id != participant);
+        self.announce(ServerMessage::PeerLeft { participant: participant.to_string() });
+    }
+
+    /// Tells every participant of a shared session: the session's own client through its
+    /// task, the rest through the mirror.
+    fn announce(&self, msg: ServerMessage) {
+        if let Some(events) = &self.warnings {
+            let _ = events.try_send(msg.clone());
+        }
+        let _ = self.mirror.send(msg);
+    }
+
+    /// Where to send a client presenting `token`, if it is this session's resume token.
+    /// The token a detaching client comes back with, issued now if the session had none.
+    fn detach_token(&mut self) -> String {
+        self.resume_token.get_or_insert_with(|| Uuid::new_v4().to_string()).clone()
+    }
+
+    fn resume_with(&self, token: &str) -> Option<mpsc::Sender<Reattach>> {
+        let expected = self.resume_token.as_deref()?;
+        admin::constant_time_eq(token.as_bytes(), expected.as_bytes()).then(|| self.reattach.clone()).flatten()
+    }
+
+    /// Decodes a chunk from the PTY for `record_output`. Clients that asked for base64 or
+    /// binary output get the chunk as read instead of its decoded, capability-filtered text.
+    fn pty_output(&mut self, bytes: Vec<u8>) -> Vec<ServerMessage> {
+        self.bytes_out += bytes.len() as u64;
+        self.last_activity = chrono::Utc::now();
+        let text = self.decoder.decode(&bytes);
+        let mut replies = if text.is_empty() { Vec::new() } else { self.record_output(&text) };
+        if self.output != OutputEncoding::Text {
+            self.replay.push(&bytes);
+            replies.retain(|reply| !matches!(reply, ServerMessage::Output { .. }));
+            replies.insert(0, ServerMessage::OutputBytes { data: bytes, encoding: self.output_encoding(), seq: None });
+        }
+        if self.mirror.receiver_count() > 0 {
+            for reply in replies.iter().filter(|reply| matches!(reply, ServerMessage::Output { .. } | ServerMessage::OutputBytes { .. })) {
+                let _ = self.mirror.send(reply.clone());
+            }
+        }
+        replies
+    }
+
+    /// The encoding output goes out in: what the client asked for, except that binary
+    /// frames fall back to base64 while it does not declare `binary_frames`.
+    fn output_encoding(&self) -> OutputEncoding {
+        match self.output {
+            OutputEncoding::Binary if !self.output_filter.capabilities().supports(Capability::BinaryFrames) => OutputEncoding::Base64,
+            encoding => encoding,
+        }
+    }
+
+    /// Recent output for a client resuming the session, in its output encoding.
+    fn missed_output(&self) -> Option<ServerMessage> {
+        if self.replay.is_empty() {
+            return None;
+        }
+        let data = self.replay.contents();
+        Some(match self.output_encoding() {
+            OutputEncoding::Text => ServerMessage::Output { data: String::from_utf8_lossy(&data).into_owned(), seq: None },
+            encoding => ServerMessage::OutputBytes { data, encoding, seq: None },
+        })
+    }
+
+    /// `msg` as this client wants output: server-made text like the banner goes out as
+    /// bytes to base64 and binary clients too.
+    fn encode_output(&self, msg: ServerMessage) -> ServerMessage {
+        match msg {
+            ServerMessage::Output { data, seq } if self.output != OutputEncoding::Text => {
+                ServerMessage::OutputBytes { data: data.into_bytes(), encoding: self.output_encoding(), seq }
+            }
+            msg => msg,
+        }
+    }
+
+    /// Runs output through the per-session trackers and returns the messages for the client.
+    fn record_output(&mut self, output: &str) -> Vec<ServerMessage> {
+        // Modes follow the raw stream; the filter may hide them from this client.
+        let mode_changes = self.modes.feed(output);
+        let output = &self.output_filter.filter(output);
+        self.scrollback.push(output);
+        if self.output == OutputEncoding::Text {
+            self.replay.push(output.as_bytes());
+        }
+        if let Some(screen) = self.screen.as_mut() {
+            screen.feed(output);
+        }
+        let events = self.shell_integration.feed(output);
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.observe(&events);
+        }
+        let finished = self.commands.observe(&events);
+        let mut messages: Vec<ServerMessage> = Vec::new();
+        if self.output_filter.capabilities().supports(Capability::Mouse) {
+            messages.extend(
+                mode_changes
+                    .into_iter()
+                    .filter(|change| MOUSE_MODES.contains(&change.mode))
+                    .map(|change| ServerMessage::MouseMode { mode: change.mode, enabled: change.enabled }),
+            );
+        }
+        for event in events {
+            match event {
+                OutputEvent::WorkingDirectory(path) => {
+                    self.cwd_from_shell = true;
+                    if self.cwd.as_ref() != Some(&path) {
+                        self.cwd = Some(path.clone());
+                        messages.push(ServerMessage::from(OutputEvent::WorkingDirectory(path)));
+                    }
+                }
+                event => messages.push(ServerMessage::from(event)),
+            }
+        }
+        messages.extend(finished.into_iter().filter_map(|command| self.long_command(command)));
+        if let Some(batch) = self.links.as_mut().map(|links| links.scan(output)) {
+            if !batch.items.is_empty() {
+                messages.push(ServerMessage::Links(batch));
+            }
+        }
+        if let Some(ports) = self.ports.as_mut() {
+            messages.extend(ports.scan(output).into_iter().map(ServerMessage::from));
+        }
+        messages
+    }
+
+    /// Checks `/proc` for a new working directory, unless the shell reports its own.
+    fn poll_cwd(&mut self) -> Option<ServerMessage> {
+        if self.cwd_from_shell {
+            return None;
+        }
+        let path = process_tree::cwd(self.pty.pid()?)?.display().to_string();
+        if self.cwd.as_ref() == Some(&path) {
+            return None;
+        }
+        self.cwd = Some(path.clone());
+        Some(ServerMessage::Cwd { path, source: CwdSource::Proc })
+    }
+
+    /// Called when the prompt detector's deadline passes; empty when it was a false alarm.
+    fn prompt_fired(&mut self) -> Vec<ServerMessage> {
+        if !self.prompt.as_mut().is_some_and(PromptDetector::fire) {
+            return Vec::new();
+        }
+        let finished = self.commands.prompt().and_then(|command| self.long_command(command));
+        std::iter::once(ServerMessage::Prompt).chain(finished).collect()
+    }
+
+    fn long_command(&self, command: FinishedCommand) -> Option<ServerMessage> {
+        let threshold = self.long_command_threshold?;
+        (command.duration >= threshold).then_some(ServerMessage::CommandFinished {
+            command: command.command,
+            duration_ms: command.duration.as_millis() as u64,
+            exit_code: command.exit_code,
+        })
+    }
+
+    /// Sends client keystrokes to the shell. Each line they complete goes through the
+    /// dangerous-command guard first; one that needs confirming reaches the shell without
+    /// its Enter, and everything from the Enter on waits in `pending_confirmation`.
+    fn send_input(&mut self, guard: &CommandGuard, webhooks: &Webhooks, data: &str, mut token: Option<&str>, participant: &str) -> Option<ServerMessage> {
+        self.active = true;
+        let mut rest = data;
+        while let Some((end, line)) = self.input_line.feed(rest) {
+            let command = line.trim().to_string();
+            let verdict = if self.confirm_dangerous { guard.check(&command, token) } else { GuardVerdict::Allowed };
+            if let GuardVerdict::ConfirmRequired { pattern, token, expires_in } = verdict {
+                warn!("☢️ Session {} input matched dangerous pattern '{}', confirmation required", self.id, pattern);
+                // Enter is always a single byte.
+                let enter = end - 1;
+                self.forward_raw(&rest[..enter]);
+                self.pending_confirmation = Some((token.clone(), rest[enter..].to_string()));
+                self.input_line.restore(line);
+                return Some(ServerMessage::ConfirmRequired { pattern, token, expires_in_secs: expires_in.as_secs() });
+            }
+            if let (Some(_), Some(pattern)) = (token, guard.matched_pattern(&command)) {
+                webhooks.emit(WebhookEvent::DangerousConfirmed {
+                    session_id: Some(self.id.clone()),
+                    pattern: pattern.to_string(),
+                    command: command.clone(),
+                });
+            }
+            if !command.is_empty() {
+                info!("⚙️ Session {} runs '{}'", self.id, redaction::redact(&command));
+                if !self.participants.is_empty() {
+                    info!(target: AUDIT_TARGET, "⌨️ Session {} line from {}: '{}'", self.id, participant, redaction::redact(&command));
+                }
+                self.commands.submit(&command);
+            }
+            self.forward_raw(&rest[..end]);
+            rest = &rest[end..];
+            token = None;
         }
+        self.forward_raw(rest);
+        None
+    }
+
+    /// Drops the line held back for confirmation from the shell's input as well.
+    fn decline(&mut self) {
+        self.input_line.clear();
+        // Ctrl-U: the terminal's kill-line character, in cooked mode and readline alike.
+        self.forward_raw("\x15");
     }
 
-    fn process_input(&mut self, input: &str) -> String {
-        info!("⚙️ Processing input in session {}: '{}'", self.id, input.trim());
-        
-        // Mark session as active when processing input
+    /// Input that goes to the shell as-is, like mouse reports.
+    fn forward_raw(&mut self, data: &str) {
         self.active = true;
-        
-        // For now, just echo back with Rick's style
-        let response = format!(
-            "🧪 Rick's Rust Terminal processed: {}\n\
-            Wubba Lubba Dub Dub!\n\
-            📊 Session: {} (Active: {})\n\
-            ⏰ Processed at: {}\n",
-            input.trim(),
-            self.id,
-            self.active,
-            chrono::Utc::now().format("%H:%M:%S UTC")
+        self.last_activity = chrono::Utc::now();
+        self.bytes_in += data.len() as u64;
+        self.pty.write(data.as_bytes());
+    }
+
+    fn resize(&mut self, size: TerminalSize) {
+        self.size = size;
+        self.pty.resize(size);
+        if let Some(screen) = self.screen.as_mut() {
+            screen.resize(size.rows, size.cols);
+        }
+    }
+
+    /// Applies a `setenv`: terminal variables pass the environment policy and the rest
+    /// the checks `env=` gets at connect. Nothing changes unless every one passes.
+    fn set_env(&mut self, policy: &EnvironmentPolicy, changes: BTreeMap<String, Option<String>>) -> Result<(), ClientError> {
+        let mut env = self.env.clone();
+        for (name, value) in changes {
+            match value {
+                Some(value) => client_env::set(&mut env, &name, &value).map_err(|e| ClientError::from(&e))?,
+                None => {
+                    env.remove(&name);
+                }
+            }
+        }
+        let request = client_env::take_terminal(&mut env);
+        self.environment = policy.update(&self.environment, &request).map_err(|e| ClientError::from(&e))?;
+        self.env = env;
+        Ok(())
+    }
+
+    /// The session's variables on top of the `[shell_env]` allowlist, leaving out the
+    /// template's, which come from the server's config.
+    fn env_vars(&self) -> BTreeMap<String, String> {
+        let terminal = self.environment.vars().into_iter().map(|(name, value)| (name.to_string(), value));
+        terminal.chain(self.env.clone()).collect()
+    }
+
+    /// Bytes in/out since the previous sample, or `None` when the session was idle.
+    fn take_throughput_sample(&mut self) -> Option<(u64, u64)> {
+        let delta = (
+            self.bytes_in - self.sampled_bytes_in,
+            self.bytes_out - self.sampled_bytes_out,
         );
-        
-        info!("✅ Generated response for session {} ({} chars)", self.id, response.len());
-        response
+        self.sampled_bytes_in = self.bytes_in;
+        self.sampled_bytes_out = self.bytes_out;
+        (delta != (0, 0)).then_some(delta)
+    }
+}
+
+/// A client that presented the session's resume token, on its way to the session's task.
+struct Reattach {
+    conn: Connection,
+    throttle: Arc<ThrottleStats>,
+    peer_addr: String,
+}
+
+/// A transport whose connection counts against its address's `max_connections_per_ip`
+/// until the writer task stops, however many sessions it is handed between.
+struct Counted<T> {
+    transport: T,
+    permit: ConnectionPermit,
+}
+
+struct CountedWriter<W> {
+    writer: W,
+    _permit: ConnectionPermit,
+}
+
+impl<T: Transport> Transport for Counted<T> {
+    type Reader = T::Reader;
+    type Writer = CountedWriter<T::Writer>;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (reader, writer) = self.transport.split();
+        (reader, CountedWriter { writer, _permit: self.permit })
+    }
+
+    fn heartbeat(&self) -> Option<Heartbeat> {
+        self.transport.heartbeat()
+    }
+}
+
+#[async_trait::async_trait]
+impl<W: TransportWriter> TransportWriter for CountedWriter<W> {
+    async fn send(&mut self, msg: &ServerMessage) -> Result<(), TransportError> {
+        self.writer.send(msg).await
+    }
+
+    async fn ping(&mut self) -> Result<(), TransportError> {
+        self.writer.ping().await
+    }
+
+    async fn close(&mut self, reason: Option<CloseReason>) {
+        self.writer.close(reason).await
+    }
+}
+
+/// How a lingering session stopped waiting for its client.
+enum Lingered {
+    Exited(u32),
+    Reattached(Reattach),
+    HungUp,
+}
+
+/// Which endpoint a WebSocket handshake asked for.
+#[allow(clippy::large_enum_variant)]
+enum Route {
+    Terminal(SessionOptions),
+    /// Sessions on channels of one connection, capped at the lifetime for the presented token.
+    Mux(Option<Duration>),
+    Admin,
+}
+
+/// Why a terminal WebSocket URL was refused.
+#[derive(Debug, thiserror::Error)]
+enum SessionRequestError {
+    #[error(transparent)]
+    Environment(#[from] EnvironmentError),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Tag(#[from] TagError),
+    #[error(transparent)]
+    Name(#[from] NameError),
+    #[error(transparent)]
+    Workspace(#[from] WorkspaceError),
+    #[error(transparent)]
+    Shell(#[from] ShellError),
+    #[error(transparent)]
+    Env(#[from] ClientEnvError),
+    #[error("cols and rows must both be given, as numbers from 1 to 65535")]
+    InvalidSize,
+    #[error("output must be text, base64 or binary, not '{0}'")]
+    InvalidOutput(String),
+    #[error("session_id must come with exactly one of resume_token and share_token")]
+    InvalidResume,
+    #[error("replay_bytes must be a number of bytes")]
+    InvalidReplayBytes,
+    #[error("ack_window must be a number of bytes above 0")]
+    InvalidAckWindow,
+}
+
+impl SessionRequestError {
+    fn code(&self) -> &'static str {
+        match self {
+            SessionRequestError::Environment(e) => e.code(),
+            SessionRequestError::Template(e) => e.code(),
+            SessionRequestError::Tag(e) => e.code(),
+            SessionRequestError::Name(e) => e.code(),
+            SessionRequestError::Workspace(e) => e.code(),
+            SessionRequestError::Shell(e) => e.code(),
+            SessionRequestError::Env(e) => e.code(),
+            SessionRequestError::InvalidSize => "invalid_size",
+            SessionRequestError::InvalidOutput(_) => "invalid_output",
+            SessionRequestError::InvalidResume => "invalid_resume",
+            SessionRequestError::InvalidReplayBytes => "invalid_replay_bytes",
+            SessionRequestError::InvalidAckWindow => "invalid_ack_window",
+        }
+    }
+}
+
+impl From<&SessionRequestError> for ClientError {
+    fn from(e: &SessionRequestError) -> Self {
+        match e {
+            SessionRequestError::Environment(e) => e.into(),
+            SessionRequestError::Template(e) => e.into(),
+            SessionRequestError::Tag(e) => e.into(),
+            SessionRequestError::Name(e) => e.into(),
+            SessionRequestError::Workspace(e) => e.into(),
+            SessionRequestError::Shell(e) => e.into(),
+            SessionRequestError::Env(e) => e.into(),
+            SessionRequestError::InvalidSize => ClientError::new(e.code()),
+            SessionRequestError::InvalidOutput(output) => ClientError::new(e.code()).with("output", output),
+            SessionRequestError::InvalidResume | SessionRequestError::InvalidReplayBytes | SessionRequestError::InvalidAckWindow => ClientError::new(e.code()),
+        }
+    }
+}
+
+/// Per-connection settings from the terminal WebSocket URL, e.g.
+/// `/?detect_links=true&detect_ports=true&term=xterm&lang=en_US.UTF-8&color=256&cols=120&rows=40`.
+/// `tag=purpose:build` (repeatable, `:` may arrive as `%3A`) labels the session.
+/// `name=build` (percent-encoded as needed) is what tab bars call it.
+/// `workspace=project-A` opens the session in that workspace, creating it if need be.
+/// `shell=zsh` runs another shell from `[terminal] allowed_shells`.
+/// `env=NODE_ENV=development` (repeatable, percent-encoded as needed) sets a variable;
+/// `TERM`, `LANG`, `LC_ALL` and `COLORTERM` count as `term`, `lang`, `lc_all` and `color`,
+/// which win when both are given.
+/// `capabilities=truecolor,mouse,...` picks `term` and `color` unless those are given too,
+/// and holds `output=binary` back to base64 unless it includes `binary_frames`.
+/// `replay_bytes=4096` keeps less recent output than `[terminal] replay_bytes` for replay.
+/// `ack_window=262144` stamps output with `seq` and stops reading the PTY while more than
+/// that many bytes of it are unacked.
+/// `session_id=<id>&resume_token=<token>` resumes a session from its `hello` instead of
+/// starting one, and `session_id=<id>&share_token=<token>` opens it alongside its client;
+/// everything else in the URL, apart from `ack_window` when resuming, is then ignored.
+/// `template=<name>` starts from a `[[templates]]` entry; the other fields are then only
+/// accepted where its `overridable` list allows.
+#[derive(Debug, Clone, Default)]
+struct SessionOptions {
+    detect_links: bool,
+    /// Announce dev servers that print the port they listen on.
+    detect_ports: bool,
+    environment: SessionEnvironment,
+    capabilities: Capabilities,
+    /// From `[terminal.max_lifetime]` and the presented token, shortened by the template.
+    max_lifetime: Option<Duration>,
+    template: Option<String>,
+    launch: Launch,
+    /// The template's working directory and extra variables for the shell.
+    cwd: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+    /// Variables from the connect URL's `env=`, terminal ones moved to `environment`.
+    client_env: ClientEnv,
+    scrollback_bytes: Option<usize>,
+    /// Less than `[terminal] replay_bytes` to keep for a resuming client.
+    replay_bytes: Option<usize>,
+    /// The template turned off dangerous-command confirmation.
+    skip_confirmation: bool,
+    /// Initial size, so the first screen is not drawn at 80x24 and then resized.
+    size: TerminalSize,
+    tags: SessionTags,
+    name: Option<String>,
+    workspace: Option<String>,
+    output: OutputEncoding,
+    /// Output bytes the client may leave unacked before the session stops reading its PTY;
+    /// `None` when the client does not ack.
+    ack_window: Option<u64>,
+    /// Session ID and resume token of the session to take over.
+    resume: Option<(String, String)>,
+    /// Session ID and share token of the session to open alongside its client.
+    join: Option<(String, String)>,
+    /// Saved before the last restart; the new session takes its place.
+    restored: Option<SavedSession>,
+}
+
+impl SessionOptions {
+    /// A fresh shell for a session saved before the last restart, in its old working
+    /// directory if that is still there.
+    fn restoring(saved: SavedSession) -> Self {
+        Self {
+            cwd: saved.cwd.as_ref().map(PathBuf::from).filter(|cwd| cwd.is_dir()),
+            env: saved.env.clone(),
+            name: saved.name.clone(),
+            workspace: saved.workspace.clone(),
+            restored: Some(saved),
+            ..Self::default()
+        }
+    }
+
+    /// Keeps `max_lifetime` within `cap`, the limit for the token the client presented.
+    fn cap_lifetime(&mut self, cap: Option<Duration>) {
+        self.max_lifetime = match (cap, self.max_lifetime) {
+            (Some(cap), Some(template)) => Some(cap.min(template)),
+            (cap, template) => cap.or(template),
+        };
+    }
+
+    fn from_query(query: Option<&str>, defaults: &SessionDefaults) -> Result<Self, SessionRequestError> {
+        let (policy, templates) = (&defaults.environment, &defaults.templates);
+        let mut options = Self::default();
+        let mut detect_links = None;
+        let mut client = EnvironmentRequest::default();
+        let mut capabilities = None;
+        let mut template = None;
+        let mut shell = None;
+        let (mut cols, mut rows) = (None, None);
+        let (mut resume_id, mut resume_token, mut share_token) = (None, None, None);
+        for pair in query.unwrap_or_default().split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, "true"));
+            match key {
+                "detect_links" => detect_links = Some(matches!(value, "true" | "1")),
+                "detect_ports" => options.detect_ports = matches!(value, "true" | "1"),
+                "term" => client.term = Some(value.to_string()),
+                "lang" => client.lang = Some(value.to_string()),
+                "lc_all" => client.lc_all = Some(value.to_string()),
+                "color" => client.color = Some(value.to_string()),
+                "capabilities" => {
+                    let names: Vec<&str> = value.split(',').filter(|name| !name.is_empty()).collect();
+                    capabilities = Some(Capabilities::from_names(&names));
+                }
+                "template" => template = Some(templates.get(value)?),
+                "shell" => shell = Some(defaults.shells.resolve(value)?),
+                "env" => client_env::insert(&mut options.client_env, &client_env::percent_decode(value))?,
+                "name" => options.name = Some(session_names::parse(&client_env::percent_decode(value))?),
+                "workspace" => options.workspace = Some(workspaces::parse(value)?),
+                "tag" => session_tags::insert(&mut options.tags, &value.replace("%3A", ":").replace("%3a", ":"))?,
+                "cols" => cols = Some(value.parse::<u16>().map_err(|_| SessionRequestError::InvalidSize)?),
+                "rows" => rows = Some(value.parse::<u16>().map_err(|_| SessionRequestError::InvalidSize)?),
+                "output" => {
+                    options.output = OutputEncoding::parse(value).ok_or_else(|| SessionRequestError::InvalidOutput(value.to_string()))?
+                }
+                "replay_bytes" => options.replay_bytes = Some(value.parse().map_err(|_| SessionRequestError::InvalidReplayBytes)?),
+                "ack_window" => {
+                    let window = value.parse().ok().filter(|window| *window > 0);
+                    options.ack_window = Some(window.ok_or(SessionRequestError::InvalidAckWindow)?);
+                }
+                "session_id" => resume_id = Some(value.to_string()),
+                "resume_token" => resume_token = Some(value.to_string()),
+                "share_token" => share_token = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        match (resume_id, resume_token, share_token) {
+            (None, None, None) => {}
+            (Some(id), Some(token), None) => options.resume = Some((id, token)),
+            (Some(id), None, Some(token)) => options.join = Some((id, token)),
+            _ => return Err(SessionRequestError::InvalidResume),
+        }
+
+        options.size = match (cols, rows) {
+            (None, None) => TerminalSize::default(),
+            (Some(cols), Some(rows)) => TerminalSize::new(cols, rows).ok_or(SessionRequestError::InvalidSize)?,
+            _ => return Err(SessionRequestError::InvalidSize),
+        };
+
+        let terminal = client_env::take_terminal(&mut options.client_env);
+        client.term = client.term.or(terminal.term);
+        client.lang = client.lang.or(terminal.lang);
+        client.lc_all = client.lc_all.or(terminal.lc_all);
+        client.color = client.color.or(terminal.color);
+
+        let mut request = EnvironmentRequest::default();
+        if let Some(template) = template {
+            let given = [
+                ("term", client.term.is_some()),
+                ("lang", client.lang.is_some()),
+                ("lc_all", client.lc_all.is_some()),
+                ("color", client.color.is_some()),
+                ("detect_links", detect_links.is_some()),
+                ("shell", shell.is_some()),
+                ("env", !options.client_env.is_empty()),
+            ];
+            for (field, _) in given.iter().filter(|(_, given)| *given) {
+                template.check_override(field)?;
+            }
+            request = EnvironmentRequest {
+                term: template.term().map(str::to_string),
+                lang: template.lang().map(str::to_string),
+                lc_all: template.lc_all().map(str::to_string),
+                color: template.color().map(str::to_string),
+            };
+            options.detect_links = template.detect_links().unwrap_or_default();
+            options.template = Some(template.name().to_string());
+            options.launch = template.launch();
+            options.cwd = template.cwd().cloned();
+            options.env = template.env().clone();
+            options.scrollback_bytes = template.scrollback_bytes();
+            options.skip_confirmation = !template.confirm_dangerous();
+            options.max_lifetime = template.max_lifetime();
+        }
+        request.term = client.term.or(request.term);
+        request.lang = client.lang.or(request.lang);
+        request.lc_all = client.lc_all.or(request.lc_all);
+        request.color = client.color.or(request.color);
+        options.detect_links = detect_links.unwrap_or(options.detect_links);
+        if let Some(shell) = shell {
+            options.launch = Launch::Shell(shell);
+        }
+
+        if let Some(caps) = &capabilities {
+            if request.term.is_none() && policy.allows_term(caps.term()) {
+                request.term = Some(caps.term().to_string());
+            }
+            request.color.get_or_insert_with(|| caps.color().to_string());
+        }
+        options.environment = policy.resolve(&request)?;
+        options.capabilities = capabilities.unwrap_or_default();
+        Ok(options)
     }
 }
 
 #[tokio::main]
 async fn main() {
+    if let Some(e) = run_as::exec_if_requested(std::env::args_os()) {
+        eprintln!("💥 {}", e);
+        std::process::exit(126);
+    }
     env_logger::Builder::from_default_env()
         .filter_level(log::LevelFilter::Debug)
         .init();
@@ -55,170 +1062,4150 @@ async fn main() {
     info!("🚀 Rick's Interdimensional PTY Terminal Server Starting...");
     info!("🐛 MAXIMUM LOGGING enabled for WebSocket debugging!");
     
-    let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+    let chaos = Chaos::from_args(std::env::args()).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let config = ForgeConfig::load().unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let guard = CommandGuard::from_config(&config.dangerous_commands).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+
+    let banner = Banner::from_config(&config.terminal).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+
+    let templates = Templates::from_config(&config.templates).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let tmpdirs = TmpDirs::from_config(&config.tmpdir).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let shell_env = ShellEnv::from_config(&config.shell_env);
+    shell_env.self_check();
+    let defaults = SessionDefaults::from_config(&config.terminal).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    }).with_templates(templates).with_tmpdirs(tmpdirs).with_shell_env(shell_env);
+
+    let webhooks = Webhooks::from_config(&config.webhooks).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let redactor = Redactor::from_config(&config.redaction).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    redaction::install(redactor);
+    Preflight::new(&[preflight::SHELLS, preflight::TEMPLATE_DIRS, preflight::PTY]).log(&config);
+    let bandwidth = Bandwidth::from_config(&config.terminal.bandwidth).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+
+    let run_as = RunAs::from_args(std::env::args()).and_then(|run_as| {
+        run_as.as_ref().map(RunAs::check_privileges).transpose()?;
+        Ok(run_as)
+    });
+    let run_as = run_as.unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    match &run_as {
+        Some(run_as) => info!("🔒 Shells run as {} (uid {}, gid {})", run_as.user, run_as.uid, run_as.gid),
+        None => warn!("🔓 Shells run as the server's own user; pass {} before exposing it beyond localhost", run_as::RUN_AS_FLAG),
+    }
+    let defaults = defaults.with_run_as(run_as);
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut state = ServerState::new(guard, banner, defaults, webhooks, chaos, bandwidth, shutdown_rx);
+    if let Some(dir) = protocol_capture::dir_from_args(std::env::args()) {
+        warn!("🎞️ Capturing every session's inbound messages to {}", dir.display());
+        state.capture_dir = Some(Arc::new(dir));
+    }
+    state.base_path = BasePath::from_args(std::env::args()).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    state.origins = AllowedOrigins::from_config(&config.listen.allowed_origins).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let store = config.terminal.persistence.path.clone().map(SessionStore::new);
+    match store.as_ref().map(SessionStore::take) {
+        Some(Ok(saved)) => {
+            info!("♻️ {} sessions from before the restart wait for their clients", saved.len());
+            state.restored = RestoredSessions::new(saved);
+        }
+        Some(Err(e)) => warn!("♻️ Starting without the saved sessions: {}", e),
+        None => debug!("♻️ Sessions end with the server"),
+    }
+    tokio::spawn(sample_throughput(state.sessions.clone(), state.events.clone()));
+    match &state.defaults.resources {
+        Some(limits) => {
+            info!("🌡️ Sampling session resources every {:?}", limits.interval);
+            tokio::spawn(sample_resources(state.sessions.clone(), limits.interval));
+        }
+        None => debug!("🌡️ Resource sampling is off"),
+    }
+    match state.defaults.idle {
+        Some(policy) => {
+            info!("💤 Ending sessions idle for {:?}", policy.timeout);
+            tokio::spawn(sweep_idle(state.sessions.clone(), policy));
+        }
+        None => debug!("💤 Idle sessions stay until they end"),
+    }
+    match &state.defaults.tmpdirs {
+        Some(tmpdirs) => {
+            info!("🗂️ Sessions get their own TMPDIR under {}", tmpdirs.root().display());
+            if let Some(interval) = tmpdirs.check_interval {
+                tokio::spawn(check_tmpdirs(state.sessions.clone(), interval));
+            }
+        }
+        None => info!("🗂️ Sessions share the system TMPDIR"),
+    }
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(state.banner.clone(), state.bandwidth.clone()));
+    #[cfg(target_os = "linux")]
+    match process_group::become_subreaper() {
+        Ok(()) => {
+            info!("🧟 Adopting and reaping whatever sessions leave behind");
+            tokio::spawn(reap_orphans());
+        }
+        Err(e) => warn!("🧟 Processes sessions leave behind go to init: {}", e),
+    }
     
-    let listener = TcpListener::bind("127.0.0.1:3002").await
-        .expect("Failed to bind to port 3002");
+    let listener = TcpListener::bind(config.listen.terminal).await.unwrap_or_else(|e| {
+        error!("💥 Failed to bind {}: {}", config.listen.terminal, e);
+        std::process::exit(1);
+    });
+    let addr = listener.local_addr().unwrap_or(config.listen.terminal);
+    // Plain line among the logs, for scripts and the e2e harness started with port 0.
+    println!("listening on {}", addr);
     
-    info!("🌟 Rick's PTY Terminal Server running on port 3002");
-    info!("📊 Session management available at /sessions");
-    info!("💊 Health check at /health");
+    info!("🌟 Rick's PTY Terminal Server running on {}", addr);
+    info!("🖥️ Terminal sessions at {}", state.base_path.as_str());
+    info!("📊 Session management available at {}", state.base_path.url("/sessions"));
+    info!("💊 Health check at {}", state.base_path.url("/health"));
+    info!("🛰️ Admin session monitor at {}", state.base_path.url(ADMIN_WS_PATH));
     info!("🔥 WUBBA LUBBA DUB DUB - Real terminal is ONLINE!");
     info!("👂 Listening for WebSocket connections...");
     
-    while let Ok((stream, addr)) = listener.accept().await {
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("❌ Failed to accept connection: {}", e);
+                    break;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("🛑 Shutdown requested");
+                break;
+            }
+        };
+
         info!("🔌 NEW CONNECTION from: {} (IP: {})", addr, addr.ip());
-        info!("📈 Active sessions before new connection: {}", sessions.lock().unwrap().len());
-        let sessions = sessions.clone();
+        info!("📈 Active sessions before new connection: {}", state.sessions.count().await);
+        let state = state.clone();
         tokio::spawn(async move {
             info!("🚀 Spawning connection handler for {}", addr);
-            handle_connection(stream, sessions).await;
+            handle_connection(stream, state).await;
             info!("🔚 Connection handler for {} completed", addr);
         });
     }
+
+    if let Some(notice) = Notice::new(NoticeLevel::Warning, "Server is shutting down", None) {
+        let delivered = state.notices.broadcast(notice);
+        info!("📢 Shutdown notice sent to {} connections", delivered);
+    }
+    if let Some(store) = &store {
+        let mut saved = saved_sessions(&state.sessions).await;
+        saved.extend(state.restored.unclaimed());
+        match store.save(saved) {
+            Ok(()) => info!("♻️ Sessions saved to {}", store.path().display()),
+            Err(e) => error!("❌ {}", e),
+        }
+    }
+    let _ = shutdown_tx.send(true);
+    tokio::time::sleep(SHUTDOWN_NOTICE_GRACE).await;
 }
 
-async fn handle_connection(stream: TcpStream, sessions: Sessions) {
-    let peer_addr = stream.peer_addr().unwrap_or_else(|_| "unknown".parse().unwrap());
-    info!("🔄 Starting WebSocket handshake for {}", peer_addr);
-    
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => {
-            info!("✅ WebSocket handshake successful for {}", peer_addr);
-            ws
-        },
+/// Every live session as it stands, for the store to bring back after a restart.
+async fn saved_sessions(sessions: &Sessions) -> Vec<SavedSession> {
+    let mut saved = Vec::new();
+    for metadata in sessions.list().await {
+        let Ok(session) = sessions.get(&metadata.id).await else { continue };
+        saved.extend(session.lock().unwrap().saved(metadata));
+    }
+    saved
+}
+
+/// Re-reads the MOTD file, the redaction rules and the bandwidth limits whenever the
+/// process receives SIGHUP.
+#[cfg(unix)]
+async fn reload_on_sighup(banner: Arc<Banner>, bandwidth: Bandwidth) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
         Err(e) => {
-            error!("❌ WebSocket connection failed for {}: {}", peer_addr, e);
+            warn!("⚠️ SIGHUP reload unavailable: {}", e);
             return;
         }
     };
+    while hangups.recv().await.is_some() {
+        match banner.reload_motd() {
+            Ok(()) => info!("🔄 SIGHUP: MOTD reloaded"),
+            Err(e) => warn!("⚠️ SIGHUP: keeping previous MOTD, {}", e),
+        }
+        match redaction::reload() {
+            Ok(count) => info!("🔄 SIGHUP: {} redaction patterns loaded", count),
+            Err(e) => warn!("⚠️ SIGHUP: keeping previous redaction patterns, {}", e),
+        }
+        match bandwidth.reload() {
+            Ok(limits) => info!(
+                "🔄 SIGHUP: bandwidth limits now {:?} B/s global, {:?} B/s per session",
+                limits.global_bytes_per_sec, limits.session_bytes_per_sec
+            ),
+            Err(e) => warn!("⚠️ SIGHUP: keeping previous bandwidth limits, {}", e),
+        }
+    }
+}
 
-    info!("🎉 WebSocket connection established for {}", peer_addr);
-    
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-    
-    // Create a new terminal session
-    let session_id = Uuid::new_v4().to_string();
-    info!("🆕 Creating new terminal session: {}", session_id);
-    let terminal_session = TerminalSession::new();
-    
-    let session = Arc::new(Mutex::new(terminal_session));
-    sessions.lock().unwrap().insert(session_id.clone(), session.clone());
-    info!("📝 Session {} registered in session manager", session_id);
-    info!("📊 Total active sessions: {}", sessions.lock().unwrap().len());
-    
-    // Send initial welcome message
-    let welcome_msg = json!({
-        "type": "output",
-        "data": format!("🧪 Welcome to Rick's Interdimensional Rust Terminal!\nWubba Lubba Dub Dub! Type your commands below:\nSession ID: {}\nConnected from: {}\n\n$ ", session_id, peer_addr)
-    });
-    
-    info!("📤 Sending welcome message to session {}", session_id);
-    if let Err(e) = ws_sender.send(Message::Text(welcome_msg.to_string())).await {
-        error!("❌ Failed to send welcome message to {}: {}", session_id, e);
-        return;
+/// Waits on the processes this server adopted from its sessions whenever one of them exits.
+#[cfg(target_os = "linux")]
+async fn reap_orphans() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut exits = match signal(SignalKind::child()) {
+        Ok(exits) => exits,
+        Err(e) => {
+            warn!("🧟 Not reaping orphaned processes: {}", e);
+            return;
+        }
+    };
+    while exits.recv().await.is_some() {
+        let reaped = process_group::reap_orphans();
+        if reaped > 0 {
+            debug!("🧟 Reaped {} processes left behind by sessions", reaped);
+        }
     }
-    info!("✅ Welcome message sent successfully to {}", session_id);
-    
-    // Handle incoming WebSocket messages
-    info!("👂 Starting message loop for session {}", session_id);
-    while let Some(msg) = ws_receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                info!("📨 Received text message from {}: {} chars", session_id, text.len());
-                debug!("📋 Message content: {}", text);
-                
-                match serde_json::from_str::<serde_json::Value>(&text) {
-                    Ok(json_msg) => {
-                        info!("✅ JSON parsed successfully for session {}", session_id);
-                        debug!("📄 Parsed JSON: {:?}", json_msg);
-                        
-                        if let Some(msg_type) = json_msg["type"].as_str() {
-                            info!("🏷️ Message type: '{}' from session {}", msg_type, session_id);
-                            match msg_type {
-                                "input" => {
-                                    if let Some(data) = json_msg["data"].as_str() {
-                                        info!("⌨️ Processing input from {}: '{}'", session_id, data);
-                                        
-                                        let response = if let Ok(mut session_guard) = session.lock() {
-                                            info!("🔓 Session lock acquired for {}", session_id);
-                                            let result = session_guard.process_input(data);
-                                            info!("⚙️ Input processed, response length: {}", result.len());
-                                            result
-                                        } else {
-                                            error!("❌ Failed to acquire session lock for {}", session_id);
-                                            "Session error!".to_string()
-                                        };
-                                        
-                                        // Send the response back
-                                        let response_msg = json!({
-                                            "type": "output",
-                                            "data": format!("{}$ ", response)
-                                        });
-                                        
-                                        info!("📤 Sending response to session {}", session_id);
-                                        if let Err(e) = ws_sender.send(Message::Text(response_msg.to_string())).await {
-                                            error!("❌ Failed to send response to {}: {}", session_id, e);
-                                            break;
-                                        }
-                                        info!("✅ Response sent successfully to {}", session_id);
-                                    } else {
-                                        warn!("⚠️ No 'data' field in input message from {}", session_id);
-                                    }
-                                }
-                                "resize" => {
-                                    if let (Some(cols), Some(rows)) = (
-                                        json_msg["cols"].as_u64(),
-                                        json_msg["rows"].as_u64()
-                                    ) {
-                                        info!("📐 Terminal resize request from {}: {}x{}", session_id, cols, rows);
-                                        // Terminal resize acknowledged
-                                    } else {
-                                        warn!("⚠️ Invalid resize message from {}: missing cols/rows", session_id);
-                                    }
-                                }
-                                _ => {
-                                    warn!("❓ Unknown message type '{}' from session {}", msg_type, session_id);
-                                }
-                            }
-                        } else {
-                            warn!("⚠️ Message from {} missing 'type' field", session_id);
-                        }
-                    }
-                    Err(e) => {
-                        error!("❌ Failed to parse JSON message from {}: {}", session_id, e);
-                        error!("📋 Raw message: {}", text);
-                    }
+}
+
+/// Periodically publishes per-session byte counters for admin monitors.
+async fn sample_throughput(sessions: Sessions, events: EventBus) {
+    let mut ticker = tokio::time::interval(THROUGHPUT_SAMPLE_INTERVAL);
+    let mut last_tick = Instant::now();
+    loop {
+        ticker.tick().await;
+        let interval_ms = last_tick.elapsed().as_millis() as u64;
+        last_tick = Instant::now();
+        if !events.has_subscribers() {
+            continue;
+        }
+
+        for session in sessions.all().await {
+            let Ok(mut session) = session.lock() else { continue };
+            if let Some((bytes_in, bytes_out)) = session.take_throughput_sample() {
+                events.publish(&session.id, SessionEventKind::Throughput { bytes_in, bytes_out, interval_ms });
+            }
+        }
+    }
+}
+
+/// Periodically totals each session's process tree against `[terminal.resources]`, warning
+/// sessions over the memory threshold and killing those that stay over a kill threshold.
+async fn sample_resources(sessions: Sessions, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        for session in sessions.all().await {
+            let Some(sampler) = session.lock().ok().and_then(|session| session.processes.clone()) else { continue };
+            let processes = tokio::task::spawn_blocking(move || sampler.lock().unwrap().sample()).await.unwrap_or_default();
+            let usage = ResourceUsage::total(&processes);
+
+            let (id, event) = {
+                let Ok(mut session) = session.lock() else { continue };
+                let event = session.resources.as_mut().and_then(|monitor| monitor.record(usage));
+                if let (Some(ResourceEvent::Warning { rss_bytes, limit_bytes }), Some(warnings)) = (event, &session.warnings) {
+                    let _ = warnings.try_send(ServerMessage::ResourceWarning { rss_bytes, limit_bytes });
+                }
+                (session.id.clone(), event)
+            };
+            match event {
+                Some(ResourceEvent::LimitExceeded) => {
+                    warn!("🔥 Session {} stayed over its resource limit ({:.0}% CPU, {} bytes)", id, usage.cpu_percent, usage.rss_bytes);
+                    let _ = sessions.kill(&id, CloseReason::ResourceLimit).await;
+                }
+                Some(ResourceEvent::Warning { .. }) => info!("🌡️ Session {} is using {} bytes of memory", id, usage.rss_bytes),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Periodically ends the sessions that have had neither input nor output for the policy's
+/// timeout; dropping them hangs up their processes.
+async fn sweep_idle(sessions: Sessions, policy: IdlePolicy) {
+    let mut ticker = tokio::time::interval(policy.interval);
+    loop {
+        ticker.tick().await;
+        let now = chrono::Utc::now();
+        for session in sessions.all().await {
+            let id = match session.lock() {
+                Ok(session) if policy.expired(session.last_activity, now) => session.id.clone(),
+                _ => continue,
+            };
+            info!("💤 Session {} was idle for {:?}", id, policy.timeout);
+            let _ = sessions.kill(&id, CloseReason::IdleTimeout).await;
+        }
+    }
+}
+
+/// Periodically measures each session's `TMPDIR` against `[tmpdir] max_bytes`, warning
+/// sessions each time they grow past it.
+async fn check_tmpdirs(sessions: Sessions, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        for session in sessions.all().await {
+            let Some(path) = session.lock().ok().and_then(|session| session.tmpdir.as_ref().map(|tmpdir| tmpdir.path().to_path_buf()))
+            else {
+                continue;
+            };
+            let bytes = tokio::task::spawn_blocking(move || session_tmp::dir_size(&path)).await.unwrap_or(0);
+
+            let Ok(mut session) = session.lock() else { continue };
+            let Some(limit_bytes) = session.tmpdir.as_mut().and_then(|tmpdir| tmpdir.record_size(bytes)) else { continue };
+            warn!("🗂️ Session {} TMPDIR holds {} bytes (cap {})", session.id, bytes, limit_bytes);
+            if let Some(warnings) = &session.warnings {
+                let _ = warnings.try_send(ServerMessage::TmpdirWarning { bytes, limit_bytes });
+            }
+        }
+    }
+}
+
+/// The session's process tree, at most `process_tree::CACHE_TTL` old; empty for sessions
+/// without a child process.
+async fn sample_processes(session: &Arc<Mutex<TerminalSession>>) -> Vec<ProcessInfo> {
+    let Some(sampler) = session.lock().unwrap().processes.clone() else { return Vec::new() };
+    tokio::task::spawn_blocking(move || sampler.lock().unwrap().sample()).await.unwrap_or_default()
+}
+
+/// Token from `Authorization: Bearer` or a `token` query parameter for browsers, which
+/// arrives percent-encoded.
+fn presented_token<B>(req: &http::Request<B>) -> Option<String> {
+    req.headers()
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(admin::bearer_token)
+        .map(str::to_string)
+        .or_else(|| {
+            req.uri()
+                .query()
+                .and_then(|q| q.split('&').find_map(|pair| pair.strip_prefix("token=")))
+                .map(client_env::percent_decode)
+        })
+}
+
+/// Routes the handshake by path; `/admin/ws` additionally requires the admin token.
+/// Terminal connections with invalid environment settings get a 400 naming the problem;
+/// their token, if any, only selects a `[terminal.max_lifetime]` override.
+#[allow(clippy::result_large_err)]
+fn route_handshake(req: &Request, route: &mut Route, state: &ServerState, peer_addr: &str) -> Result<(), ErrorResponse> {
+    let Some(path) = state.base_path.strip(req.uri().path()) else {
+        warn!("🪧 Rejecting handshake from {} for {} outside {}", peer_addr, req.uri().path(), state.base_path.as_str());
+        let error = ClientError::new("not_found");
+        let body = serde_json::json!({ "error": error.code, "message": error.message }).to_string();
+        let mut response = ErrorResponse::new(Some(body));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Err(response);
+    };
+    // Browsers let any page open a WebSocket anywhere; only the Origin header tells them apart.
+    let origin = req.headers().get(http::header::ORIGIN).map(|value| value.to_str().unwrap_or_default());
+    if !state.origins.allows(origin) {
+        warn!("🌐 Rejecting handshake from {} sent by page {:?}", peer_addr, origin.unwrap_or_default());
+        let error = ClientError::new("origin_not_allowed");
+        let body = serde_json::json!({ "error": error.code, "message": error.message }).to_string();
+        let mut response = ErrorResponse::new(Some(body));
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        return Err(response);
+    }
+    if path == MUX_PATH {
+        *route = Route::Mux(state.defaults.lifetime.for_token(presented_token(req).as_deref()));
+        return Ok(());
+    }
+    if path != ADMIN_WS_PATH {
+        return match SessionOptions::from_query(req.uri().query(), &state.defaults) {
+            Ok(mut options) => {
+                options.cap_lifetime(state.defaults.lifetime.for_token(presented_token(req).as_deref()));
+                *route = Route::Terminal(options);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("⚠️ Rejecting terminal session from {}: {}", peer_addr, e);
+                let error = ClientError::from(&e);
+                let body = serde_json::json!({ "error": error.code, "message": error.message, "params": error.params }).to_string();
+                let mut response = ErrorResponse::new(Some(body));
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                Err(response)
+            }
+        };
+    }
+
+    match admin::verify_token(presented_token(req).as_deref()) {
+        Ok(()) => {
+            *route = Route::Admin;
+            Ok(())
+        }
+        Err(rejection) => {
+            state.webhooks.emit(WebhookEvent::AuthFailed {
+                endpoint: ADMIN_WS_PATH.to_string(),
+                peer_addr: Some(peer_addr.to_string()),
+            });
+            let status = match rejection {
+                AdminRejection::Disabled | AdminRejection::ApiDisabled => StatusCode::FORBIDDEN,
+                AdminRejection::Unauthorized => StatusCode::UNAUTHORIZED,
+            };
+            let error = ClientError::from(&rejection);
+            let body = serde_json::json!({ "error": error.code, "message": error.message }).to_string();
+            let mut response = ErrorResponse::new(Some(body));
+            *response.status_mut() = status;
+            Err(response)
+        }
+    }
+}
+
+/// Path of the request line waiting on `stream`, without consuming it. `None` when the
+/// connection closes first or sends no line break within `REQUEST_LINE_LIMIT` bytes.
+async fn peek_request_path(stream: &TcpStream) -> Option<String> {
+    let mut buf = vec![0; REQUEST_LINE_LIMIT];
+    loop {
+        let read = stream.peek(&mut buf).await.ok().filter(|&read| read > 0)?;
+        if let Some(end) = buf[..read].windows(2).position(|pair| pair == b"\r\n") {
+            let target = std::str::from_utf8(&buf[..end]).ok()?.split(' ').nth(1)?;
+            return Some(target.split('?').next().unwrap_or(target).to_string());
+        }
+        if read == buf.len() {
+            return None;
+        }
+        // Peeking again returns at once with the same bytes until more arrive.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// One entry of `GET /sessions`.
+#[derive(Debug, serde::Serialize)]
+struct SessionSummary {
+    id: String,
+    name: Option<String>,
+    workspace: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_activity: chrono::DateTime<chrono::Utc>,
+    attached: bool,
+    /// The attached client's address.
+    peer_addr: Option<String>,
+    /// Cleared once the shell exits.
+    active: bool,
+    state: SessionState,
+    /// Clients that joined with the session's share token.
+    shared_clients: usize,
+}
+
+/// `p{number}`, colored by join order.
+fn numbered_participant(number: usize) -> Participant {
+    Participant { id: format!("p{}", number), color: PARTICIPANT_COLORS[(number - 1) % PARTICIPANT_COLORS.len()].to_string() }
+}
+
+/// The registry's view of a session with what its task counts.
+fn session_info(metadata: SessionMetadata, session: &TerminalSession) -> SessionInfo {
+    let client_ip = metadata.peer_addr.map(|addr| addr.parse::<std::net::SocketAddr>().map_or(addr, |addr| addr.ip().to_string()));
+    SessionInfo {
+        id: metadata.id,
+        name: metadata.name,
+        created_at: metadata.created_at,
+        last_activity: session.last_activity,
+        client_ip,
+        size: session.size,
+        shell: session.shell.clone(),
+        template: session.template.clone(),
+        cwd: session.cwd.clone().or(metadata.cwd),
+        bytes_in: session.bytes_in,
+        bytes_out: session.bytes_out,
+        state: metadata.state,
+        shared_clients: session.participants.len(),
+        expires_at: metadata.expires_at,
+    }
+}
+
+/// Every registered session, oldest first.
+async fn session_summaries(sessions: &Sessions) -> Vec<SessionSummary> {
+    let mut summaries = Vec::new();
+    for metadata in sessions.list().await {
+        let Ok(session) = sessions.get(&metadata.id).await else { continue };
+        let (last_activity, active, shared_clients) = {
+            let session = session.lock().unwrap();
+            (session.last_activity, session.active, session.participants.len())
+        };
+        summaries.push(SessionSummary {
+            id: metadata.id,
+            name: metadata.name,
+            workspace: metadata.workspace,
+            created_at: metadata.created_at,
+            last_activity,
+            attached: metadata.attached,
+            peer_addr: metadata.peer_addr,
+            active,
+            state: metadata.state,
+            shared_clients,
+        });
+    }
+    summaries.sort_by_key(|summary| summary.created_at);
+    summaries
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::new(hyper::Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert("content-type", http::HeaderValue::from_static("application/json"));
+    response
+}
+
+/// The plain-HTTP endpoints the pty-server answers on its WebSocket port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HttpTarget<'a> {
+    /// `/sessions`
+    Sessions,
+    /// `/sessions/{id}`
+    Session(&'a str),
+    /// `/sessions/{id}/disconnect`
+    Disconnect(&'a str),
+    /// `/workspaces`
+    Workspaces,
+    /// `/workspaces/{name}`
+    Workspace(&'a str),
+}
+
+impl<'a> HttpTarget<'a> {
+    /// `path` with the base path already stripped.
+    fn parse(path: &'a str) -> Option<Self> {
+        match path {
+            SESSIONS_PATH => return Some(HttpTarget::Sessions),
+            WORKSPACES_PATH => return Some(HttpTarget::Workspaces),
+            _ => {}
+        }
+        let (rest, target): (_, fn(&'a str) -> Self) = match path.strip_prefix(SESSIONS_PATH) {
+            Some(rest) => match rest.strip_suffix(DISCONNECT_SUFFIX) {
+                Some(rest) => (rest, HttpTarget::Disconnect),
+                None => (rest, HttpTarget::Session),
+            },
+            None => (path.strip_prefix(WORKSPACES_PATH)?, HttpTarget::Workspace),
+        };
+        let id = rest.strip_prefix('/')?;
+        (!id.is_empty() && !id.contains('/')).then(|| target(id))
+    }
+
+    fn allows(self, method: &http::Method) -> bool {
+        match self {
+            HttpTarget::Sessions | HttpTarget::Workspaces => method == http::Method::GET,
+            HttpTarget::Session(_) => method == http::Method::DELETE,
+            HttpTarget::Disconnect(_) => method == http::Method::POST,
+            HttpTarget::Workspace(_) => [http::Method::GET, http::Method::PUT, http::Method::DELETE].contains(method),
+        }
+    }
+}
+
+/// `GET /sessions`, `DELETE /sessions/{id}`, `POST /sessions/{id}/disconnect` and the
+/// `/workspaces` endpoints for holders of the admin token; everything else on a plain HTTP
+/// connection is a 404 or 405.
+async fn http_response(req: hyper::Request<hyper::Body>, state: &ServerState, peer_addr: &str) -> hyper::Response<hyper::Body> {
+    let path = state.base_path.strip(req.uri().path()).unwrap_or_default();
+    let Some(target) = HttpTarget::parse(path) else {
+        let error = ClientError::new("not_found");
+        return json_response(StatusCode::NOT_FOUND, serde_json::json!({ "error": error.code, "message": error.message }));
+    };
+    if !target.allows(req.method()) {
+        let error = ClientError::new("method_not_allowed");
+        return json_response(StatusCode::METHOD_NOT_ALLOWED, serde_json::json!({ "error": error.code, "message": error.message }));
+    }
+    if let Err(rejection) = admin::verify_token(presented_token(&req).as_deref()) {
+        state.webhooks.emit(WebhookEvent::AuthFailed {
+            endpoint: path.to_string(),
+            peer_addr: Some(peer_addr.to_string()),
+        });
+        let status = match rejection {
+            AdminRejection::Disabled | AdminRejection::ApiDisabled => StatusCode::FORBIDDEN,
+            AdminRejection::Unauthorized => StatusCode::UNAUTHORIZED,
+        };
+        let error = ClientError::from(&rejection);
+        return json_response(status, serde_json::json!({ "error": error.code, "message": error.message }));
+    }
+    match target {
+        HttpTarget::Sessions => {
+            let sessions = session_summaries(&state.sessions).await;
+            json_response(StatusCode::OK, serde_json::json!({ "count": sessions.len(), "sessions": sessions }))
+        }
+        HttpTarget::Session(id) => kill_session(&state.sessions, id, peer_addr).await,
+        HttpTarget::Disconnect(id) => disconnect_response(&state.sessions, id, peer_addr).await,
+        HttpTarget::Workspaces => {
+            let summaries = session_summaries(&state.sessions).await;
+            let workspaces: Vec<_> = state
+                .workspaces
+                .list()
+                .into_iter()
+                .map(|workspace| {
+                    let count = summaries.iter().filter(|summary| summary.workspace.as_ref() == Some(&workspace.name)).count();
+                    serde_json::json!({ "name": workspace.name, "created_at": workspace.created_at, "sessions": count })
+                })
+                .collect();
+            json_response(StatusCode::OK, serde_json::json!({ "count": workspaces.len(), "workspaces": workspaces }))
+        }
+        HttpTarget::Workspace(name) => workspace_response(req.method(), state, name, peer_addr).await,
+    }
+}
+
+/// `PUT /workspaces/{name}` creates the workspace, `GET` lists its sessions and `DELETE`
+/// closes every one of them, as `DELETE /sessions/{id}` would, and forgets the workspace.
+async fn workspace_response(method: &http::Method, state: &ServerState, name: &str, peer_addr: &str) -> hyper::Response<hyper::Body> {
+    let error_response = |status, error: &WorkspaceError| {
+        let error = ClientError::from(error);
+        json_response(status, serde_json::json!({ "error": error.code, "message": error.message }))
+    };
+    if *method == http::Method::PUT {
+        return match workspaces::parse(name) {
+            Ok(name) => {
+                let (workspace, created) = state.workspaces.create(&name);
+                if created {
+                    info!("🗂️ {} created workspace {}", peer_addr, name);
+                }
+                let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+                json_response(status, serde_json::json!(workspace))
+            }
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e),
+        };
+    }
+    let workspace = match state.workspaces.get(name) {
+        Ok(workspace) => workspace,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, &e),
+    };
+    let members: Vec<_> = session_summaries(&state.sessions).await.into_iter().filter(|summary| summary.workspace.as_deref() == Some(name)).collect();
+    if *method == http::Method::GET {
+        return json_response(
+            StatusCode::OK,
+            serde_json::json!({ "name": workspace.name, "created_at": workspace.created_at, "count": members.len(), "sessions": members }),
+        );
+    }
+    let mut closed = Vec::new();
+    for member in members {
+        if state.sessions.kill(&member.id, CloseReason::AdminDisconnect).await.is_ok() {
+            closed.push(member.id);
+        }
+    }
+    let _ = state.workspaces.remove(name);
+    warn!("🔪 {} closed workspace {} and its {} sessions over HTTP", peer_addr, name, closed.len());
+    let removed = removed_within(&state.sessions, &closed, KILL_WAIT).await;
+    let status = if removed { StatusCode::OK } else { StatusCode::ACCEPTED };
+    json_response(status, serde_json::json!({ "name": name, "closed": closed, "removed": removed }))
+}
+
+/// Whether every one of `ids` has left the registry within `wait`.
+async fn removed_within(sessions: &Sessions, ids: &[String], wait: Duration) -> bool {
+    tokio::time::timeout(wait, async {
+        for id in ids {
+            while sessions.get_metadata(id).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    })
+    .await
+    .is_ok()
+}
+
+/// `DELETE /sessions/{id}`: closes the session's client with `admin_disconnect` and hangs
+/// up its processes. Answers once the session has left the registry, or with a 202 if it
+/// is still winding down after `KILL_WAIT`.
+async fn kill_session(sessions: &Sessions, id: &str, peer_addr: &str) -> hyper::Response<hyper::Body> {
+    if let Err(e) = sessions.kill(id, CloseReason::AdminDisconnect).await {
+        let error = ClientError::from(&e);
+        return json_response(StatusCode::NOT_FOUND, serde_json::json!({ "error": error.code, "message": error.message }));
+    }
+    warn!("🔪 {} killed session {} over HTTP", peer_addr, id);
+    let removed = removed_within(sessions, &[id.to_string()], KILL_WAIT).await;
+    let status = if removed { StatusCode::OK } else { StatusCode::ACCEPTED };
+    json_response(status, serde_json::json!({ "id": id, "removed": removed }))
+}
+
+/// Closes the client attached to session `id` with `admin_disconnect`, leaving the session
+/// detached for its user to attach again; `Ok(false)`, with nothing done, when no client
+/// was attached. Either way the request goes to the audit log.
+async fn disconnect_client(sessions: &Sessions, id: &str, admin: &str) -> Result<bool, RegistryError> {
+    let attached = sessions.get_metadata(id).await?.attached;
+    let signal = match attached {
+        true => sessions.get(id).await?.lock().unwrap().disconnect.clone(),
+        false => None,
+    };
+    let disconnected = signal.is_some_and(|signal| signal.try_send(()).is_ok());
+    info!(target: AUDIT_TARGET, "⏏️ {} disconnected the client of session {} (attached: {})", admin, id, disconnected);
+    Ok(disconnected)
+}
+
+/// `POST /sessions/{id}/disconnect`
+async fn disconnect_response(sessions: &Sessions, id: &str, peer_addr: &str) -> hyper::Response<hyper::Body> {
+    match disconnect_client(sessions, id, &format!("{} over HTTP", peer_addr)).await {
+        Ok(disconnected) => json_response(StatusCode::OK, serde_json::json!({ "id": id, "disconnected": disconnected })),
+        Err(e) => {
+            let error = ClientError::from(&e);
+            json_response(StatusCode::NOT_FOUND, serde_json::json!({ "error": error.code, "message": error.message }))
+        }
+    }
+}
+
+/// The server's sessions as the admin channel sees them.
+struct AdminHandle {
+    sessions: Sessions,
+    observe: ObserveConfig,
+}
+
+#[async_trait::async_trait]
+impl AdminSessions for AdminHandle {
+    async fn disconnect(&self, session_id: &str, admin: &str) -> Result<bool, ClientError> {
+        disconnect_client(&self.sessions, session_id, admin).await.map_err(|e| ClientError::from(&e))
+    }
+
+    async fn observe(&self, session_id: &str, replay_lines: usize, admin: &str) -> Result<Observation, ClientError> {
+        if !self.observe.enabled {
+            return Err(ClientError::new("observe_disabled"));
+        }
+        let session = self.sessions.get(session_id).await.map_err(|e| ClientError::from(&e))?;
+        let announced = !self.observe.covert;
+        let observation = {
+            let session = session.lock().unwrap();
+            let mut replay = session.scrollback.fetch(None, replay_lines);
+            if replay_lines == 0 {
+                replay.lines.clear();
+            }
+            // Taken together under the lock, so no output falls between the two.
+            let output = session.mirror.subscribe();
+            if announced {
+                if let Some(warnings) = &session.warnings {
+                    let _ = warnings.try_send(ServerMessage::ObserverJoined { who: "admin".to_string() });
                 }
             }
-            Ok(Message::Close(frame)) => {
-                info!("🔚 WebSocket connection closed by {} - Frame: {:?}", session_id, frame);
+            Observation { replay, output, announced }
+        };
+        info!(target: AUDIT_TARGET, "👁️ {} observing session {} ({})", admin, session_id, if announced { "announced" } else { "covert" });
+        Ok(observation)
+    }
+}
+
+/// Serves a connection that asked for `/sessions` over plain HTTP instead of a WebSocket.
+async fn serve_http(stream: TcpStream, state: ServerState) {
+    let peer_addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".to_string());
+    let service = hyper::service::service_fn(|req| {
+        let (state, peer_addr) = (state.clone(), peer_addr.clone());
+        async move { Ok::<_, std::convert::Infallible>(http_response(req, &state, &peer_addr).await) }
+    });
+    if let Err(e) = hyper::server::conn::Http::new().http1_only(true).serve_connection(stream, service).await {
+        debug!("📊 HTTP connection from {} ended: {}", peer_addr, e);
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: ServerState) {
+    let Ok(peer_addr) = stream.peer_addr() else { return };
+    // Counted before anything is read, so connections that never finish a request line
+    // still use up their address's share. Addresses over it go straight to the handshake,
+    // to be refused once it is done.
+    let permit = state.connections.acquire(peer_addr.ip());
+    if permit.is_ok() {
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, peek_request_path(&stream)).await {
+            Err(_) => {
+                warn!("⏱️ Dropping {}: no request line within {:?}", peer_addr, HANDSHAKE_TIMEOUT);
+                return;
+            }
+            Ok(Some(path)) if state.base_path.strip(&path).and_then(HttpTarget::parse).is_some() => {
+                drop(permit);
+                return serve_http(stream, state).await;
+            }
+            Ok(_) => {}
+        }
+    }
+    info!("🔄 Starting WebSocket handshake for {}", peer_addr);
+    
+    let mut route = Route::Terminal(SessionOptions::default());
+    let mut format = WireFormat::Json;
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    };
+    #[allow(clippy::result_large_err)]
+    let handshake = accept_hdr_async_with_config(stream, |req: &Request, mut response: Response| {
+        route_handshake(req, &mut route, &state, &peer_addr.to_string())?;
+        let offered = req.headers().get(http::header::SEC_WEBSOCKET_PROTOCOL).and_then(|value| value.to_str().ok());
+        if let (Route::Terminal(_), Some((negotiated, subprotocol))) = (&route, offered.and_then(WireFormat::negotiate)) {
+            format = negotiated;
+            response.headers_mut().insert(http::header::SEC_WEBSOCKET_PROTOCOL, http::HeaderValue::from_static(subprotocol));
+        }
+        Ok(response)
+    }, Some(ws_config));
+    let ws_stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(ws)) => {
+            info!("✅ WebSocket handshake successful for {}", peer_addr);
+            ws
+        },
+        Ok(Err(e)) => {
+            error!("❌ WebSocket connection failed for {}: {}", peer_addr, e);
+            return;
+        }
+        Err(_) => {
+            warn!("⏱️ Dropping {}: handshake not finished within {:?}", peer_addr, HANDSHAKE_TIMEOUT);
+            return;
+        }
+    };
+
+    info!("🎉 WebSocket connection established for {}", peer_addr);
+
+    match route {
+        Route::Admin => {
+            drop(permit);
+            let sessions = Arc::new(AdminHandle { sessions: state.sessions, observe: state.defaults.observe });
+            session_events::run_admin_channel(ws_stream, state.events, state.notices, state.chaos, sessions, state.shutdown, peer_addr.to_string()).await
+        }
+        Route::Mux(lifetime_cap) => {
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(e) => {
+                    warn!("🚦 Refusing {}: {}", peer_addr, e);
+                    return refuse(Connection::spawn(WsTransport::new(ws_stream, peer_addr.to_string())), ClientError::from(&e)).await;
+                }
+            };
+            run_mux(ws_stream, peer_addr.to_string(), lifetime_cap, permit, state).await
+        }
+        Route::Terminal(options) => {
+            let transport = WsTransport::new(ws_stream, peer_addr.to_string()).with_format(format).with_heartbeat(state.defaults.heartbeat);
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(e) => {
+                    warn!("🚦 Refusing {}: {}", peer_addr, e);
+                    return refuse(Connection::spawn(transport), ClientError::from(&e)).await;
+                }
+            };
+            handle_terminal(Counted { transport, permit }, peer_addr.to_string(), options, state).await
+        }
+    }
+}
+
+/// Sends `data` to the session's shell through the dangerous-command guard, and on to
+/// the client any confirmation it asks for. Returns `false` when the client is gone.
+async fn submit_input(
+    session: &Arc<Mutex<TerminalSession>>,
+    guard: &CommandGuard,
+    webhooks: &Webhooks,
+    data: &str,
+    token: Option<&str>,
+    participant: &str,
+    conn: &Connection,
+) -> bool {
+    let reply = session.lock().unwrap().send_input(guard, webhooks, data, token, participant);
+    match reply {
+        Some(reply) => conn.send(reply).await.is_ok(),
+        None => true,
+    }
+}
+
+/// Turns a client away from a full server with `error`, a hint to retry later and a
+/// `server_full` close.
+async fn refuse(conn: Connection, error: ClientError) {
+    let _ = conn.send(ServerMessage::Error(error)).await;
+    if let Some(hint) = reconnect_hint(CloseReason::ServerFull, None) {
+        let _ = conn.send(hint).await;
+    }
+    conn.shutdown(Some(CloseReason::ServerFull)).await;
+}
+
+/// Runs a session for every channel the client opens on a `/mux` connection. Each session
+/// sees its channel as a connection of its own; this only tags and routes their frames.
+async fn run_mux<S>(ws: WebSocketStream<S>, peer_addr: String, lifetime_cap: Option<Duration>, _permit: ConnectionPermit, state: ServerState)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sink, mut stream) = ws.split();
+    let (frames, mut frames_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = frames_rx.recv().await {
+            if sink.send(frame).await.is_err() {
                 break;
             }
-            Ok(Message::Binary(data)) => {
-                info!("📦 Binary message received from {} ({} bytes)", session_id, data.len());
-                warn!("⚠️ Binary messages not supported, ignoring");
+        }
+        sink
+    });
+    let mut channels: HashMap<u32, mpsc::UnboundedSender<ClientFrame>> = HashMap::new();
+    let (closed, mut closed_rx) = mpsc::unbounded_channel();
+    let mut shutdown = state.shutdown.clone();
+    let close_reason = loop {
+        let frame = tokio::select! {
+            // Freed channels first, so a client reopening one it saw close finds it free.
+            biased;
+            Some(channel) = closed_rx.recv() => {
+                channels.remove(&channel);
+                continue;
             }
-            Ok(Message::Ping(data)) => {
-                info!("🏓 Ping received from {} ({} bytes)", session_id, data.len());
+            _ = shutdown.changed() => break Some(CloseReason::ServerShutdown),
+            frame = stream.next() => frame,
+        };
+        let text = match frame {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => break None,
+            Some(Err(e)) => {
+                error!("❌ WebSocket error for mux connection {}: {}", peer_addr, e);
+                break CloseReason::for_error(&e);
             }
-            Ok(Message::Pong(data)) => {
-                info!("🏓 Pong received from {} ({} bytes)", session_id, data.len());
+            Some(Ok(_)) => continue,
+        };
+        let failed = match MuxFrame::decode(&text) {
+            Ok(MuxFrame::Open { channel, .. }) if channels.contains_key(&channel) => Some((Some(channel), ClientError::from(&MuxError::ChannelInUse(channel)))),
+            Ok(MuxFrame::Open { channel, query }) => match SessionOptions::from_query(Some(&query), &state.defaults) {
+                Ok(mut options) => {
+                    info!("🔀 {} opens channel {}", peer_addr, channel);
+                    options.cap_lifetime(lifetime_cap);
+                    let (transport, peer) = memory_pair();
+                    channels.insert(channel, peer.tx);
+                    tokio::spawn(handle_terminal(transport, peer_addr.clone(), options, state.clone()));
+                    tokio::spawn(forward_channel(channel, peer.rx, frames.clone(), closed.clone()));
+                    None
+                }
+                Err(e) => Some((Some(channel), ClientError::from(&e))),
+            },
+            Ok(MuxFrame::Close { channel }) => match channels.get(&channel) {
+                Some(tx) => {
+                    let _ = tx.send(ClientFrame::Close);
+                    None
+                }
+                None => Some((Some(channel), ClientError::from(&MuxError::UnknownChannel(channel)))),
+            },
+            Ok(MuxFrame::Message { channel, text }) => match channels.get(&channel) {
+                Some(tx) => {
+                    let _ = tx.send(ClientFrame::Text(text));
+                    None
+                }
+                None => Some((Some(channel), ClientError::from(&MuxError::UnknownChannel(channel)))),
+            },
+            Err(e) => Some((e.channel(), ClientError::from(&e))),
+        };
+        if let Some((channel, error)) = failed {
+            warn!("⚠️ Bad frame on mux connection {}: {}", peer_addr, error.message);
+            let error = ServerMessage::Error(error);
+            let text = match channel {
+                Some(channel) => multiplex::encode(channel, &error),
+                None => error.encode(),
+            };
+            if frames.send(Message::Text(text)).await.is_err() {
+                break None;
             }
-            Ok(Message::Frame(_)) => {
-                // Raw frame messages - typically handled internally by the WebSocket library
-                debug!("🔧 Raw frame message received from {}", session_id);
+        }
+    };
+
+    // Sessions hear about a shutdown themselves; otherwise their client is gone.
+    if close_reason != Some(CloseReason::ServerShutdown) {
+        for tx in channels.values() {
+            let _ = tx.send(ClientFrame::Close);
+        }
+    }
+    // The writer finishes once every channel's session has let its channel go.
+    drop((frames, closed));
+    let Ok(mut sink) = writer.await else { return };
+    drop(channels);
+    if let Some(reason) = close_reason {
+        let _ = sink.send(Message::Close(Some(reason.frame()))).await;
+    }
+    let _ = sink.close().await;
+}
+
+/// Relays what the session on `channel` sends, tagged with the channel, and reports the
+/// channel free once the session lets it go.
+async fn forward_channel(channel: u32, mut rx: mpsc::UnboundedReceiver<ServerFrame>, frames: mpsc::Sender<Message>, closed: mpsc::UnboundedSender<u32>) {
+    let mut reason = None;
+    while let Some(frame) = rx.recv().await {
+        match frame {
+            ServerFrame::Message(msg) => {
+                if frames.send(Message::Text(multiplex::encode(channel, &msg))).await.is_err() {
+                    break;
+                }
             }
-            Err(e) => {
-                error!("❌ WebSocket error for {}: {}", session_id, e);
+            ServerFrame::Ping => {}
+            ServerFrame::Close(close) => {
+                reason = close;
                 break;
             }
         }
     }
+    let _ = closed.send(channel);
+    let _ = frames.send(Message::Text(multiplex::channel_closed(channel, reason))).await;
+}
+
+/// Releases or drops the input held back for confirmation `token`. Returns `false` when
+/// the client is gone.
+async fn answer_confirmation(
+    session: &Arc<Mutex<TerminalSession>>,
+    guard: &CommandGuard,
+    webhooks: &Webhooks,
+    token: &str,
+    proceed: bool,
+    participant: &str,
+    conn: &Connection,
+) -> bool {
+    let (session_id, pending) = {
+        let mut session = session.lock().unwrap();
+        (session.id.clone(), session.pending_confirmation.take())
+    };
+    match pending {
+        Some((expected, data)) if expected == token => {
+            if proceed {
+                return submit_input(session, guard, webhooks, &data, Some(token), participant, conn).await;
+            }
+            info!("🙅 Session {} declined dangerous command", session_id);
+            session.lock().unwrap().decline();
+        }
+        _ => warn!("⚠️ Confirmation from {} does not match a pending command", session_id),
+    }
+    true
+}
+
+/// The `reconnect_hint` sent before closing for `reason`, if clients should retry at all.
+/// `saved_token` is the session's resume token when `[terminal.persistence]` saves it at
+/// shutdown; a client reconnecting with it after a restart gets the session back. Any
+/// other close ends the session, so a reconnect starts a new one.
+fn reconnect_hint(reason: CloseReason, saved_token: Option<&str>) -> Option<ServerMessage> {
+    let resume_token = saved_token.filter(|_| reason == CloseReason::ServerShutdown).map(str::to_string);
+    reason.retry_after().map(|delay| ServerMessage::ReconnectHint {
+        retry_after_ms: delay.as_millis() as u64,
+        resumable: resume_token.is_some(),
+        resume_token,
+    })
+}
+
+/// Mirrors `command_finished` messages to webhooks subscribed to them.
+fn forward_finished_commands(webhooks: &Webhooks, session_id: &str, messages: &[ServerMessage]) {
+    for msg in messages {
+        if let ServerMessage::CommandFinished { command, duration_ms, exit_code } = msg {
+            info!("⏱️ Session {} finished a long command after {} ms", session_id, duration_ms);
+            webhooks.emit(WebhookEvent::CommandFinished {
+                session_id: session_id.to_string(),
+                command: command.clone(),
+                duration_ms: *duration_ms,
+                exit_code: *exit_code,
+            });
+        }
+    }
+}
+
+/// Drives one terminal session over `transport` until the client leaves or the server
+/// shuts down. Socket reads and writes run in their own tasks, so server-initiated
+/// messages go out even while the client is silent.
+async fn handle_terminal<T: Transport>(transport: T, peer_addr: String, options: SessionOptions, state: ServerState) {
+    let mut options = options;
+    if let Some((session_id, token)) = options.resume.take() {
+        let Some(saved) = state.restored.claim(&session_id, &token) else {
+            return resume_session(transport, peer_addr, session_id, token, options.ack_window, state).await;
+        };
+        info!("♻️ {} restores session {} from before the restart", peer_addr, session_id);
+        options = SessionOptions { ack_window: options.ack_window, ..SessionOptions::restoring(saved) };
+    }
+    if let Some((session_id, token)) = options.join {
+        return join_session(transport, peer_addr, session_id, token, state).await;
+    }
+    let ServerState { sessions, events, notices, guard, banner, defaults, webhooks, chaos, bandwidth, capture_dir, base_path: _, origins: _, connections: _, restored: _, workspaces, mut shutdown } = state;
+    let mut notices = notices.subscribe();
     
-    // Clean up
-    info!("🧹 Cleaning up session {}", session_id);
-    sessions.lock().unwrap().remove(&session_id);
-    let remaining_sessions = sessions.lock().unwrap().len();
-    info!("✅ Terminal session {} ended. Remaining sessions: {}", session_id, remaining_sessions);
-}
\ No newline at end of file
+    // Hold a slot before starting a shell, so a full server never forks one to throw away
+    let reservation = match sessions.reserve().await {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            warn!("🚦 Refusing {}: {}", peer_addr, e);
+            return refuse(Connection::spawn_with_chaos(transport, chaos, String::new()), ClientError::from(&e)).await;
+        }
+    };
+    // Create a new terminal session and start its shell
+    let (mut terminal_session, mut pty_events) = match TerminalSession::new(&defaults, &options) {
+        Ok(started) => started,
+        Err(e) => {
+            error!("💥 No shell for {}: {}", peer_addr, e);
+            let conn = Connection::spawn_with_chaos(transport, chaos, String::new());
+            let _ = conn.send(ServerMessage::Error(ClientError::from(&e))).await;
+            conn.shutdown(None).await;
+            return;
+        }
+    };
+    let session_id = terminal_session.id.clone();
+    let pid = terminal_session.pty.pid();
+    let shaper = bandwidth.session();
+    let mut throttle = shaper.stats();
+    let mut conn = Connection::spawn_shaped(transport, chaos.clone(), session_id.clone(), Some(shaper)).with_ack_window(options.ack_window);
+    let mut peer_addr = peer_addr;
+    let counters = terminal_session.counters.clone();
+    // Room for a burst of peers joining; warnings and summaries are fine to lose.
+    let (warnings, mut warnings_rx) = mpsc::channel(16);
+    terminal_session.warnings = Some(warnings);
+    let (reattach, mut reattach_rx) = mpsc::channel(1);
+    terminal_session.reattach = Some(reattach);
+    let (disconnect, mut disconnect_rx) = mpsc::channel(1);
+    terminal_session.disconnect = Some(disconnect);
+    info!("🆕 Creating new terminal session: {} ({} as pid {:?})", session_id, terminal_session.shell, pid);
+    if let Some(template) = &options.template {
+        info!("🧬 Session {} from template '{}': {:?}", session_id, template, options.launch);
+    }
+    
+    let session = Arc::new(Mutex::new(terminal_session));
+    let mut lifetime = options.max_lifetime.map(Lifetime::new);
+    let expires_at = lifetime.as_ref().map(Lifetime::expires_at);
+    let (env_vars, cwd) = {
+        let session = session.lock().unwrap();
+        (session.env_vars(), session.cwd.clone())
+    };
+    let metadata = SessionMetadata {
+        name: options.name.clone(),
+        workspace: options.workspace.clone(),
+        attached: true,
+        peer_addr: Some(peer_addr.clone()),
+        expires_at,
+        tags: options.tags.clone(),
+        size: options.size,
+        env: env_vars,
+        cwd,
+        ..SessionMetadata::new(session_id.clone())
+    };
+    let mut kill = match sessions.create_reserved(reservation, metadata, session.clone()).await {
+        Ok(kill) => kill,
+        Err(e) => {
+            error!("❌ Failed to register session {}: {}", session_id, e);
+            return refuse(conn, ClientError::from(&e)).await;
+        }
+    };
+    info!("📝 Session {} registered in session manager", session_id);
+    if let Some(workspace) = &options.workspace {
+        if workspaces.create(workspace).1 {
+            info!("🗂️ Workspace {} created for session {}", workspace, session_id);
+        }
+    }
+    info!("📊 Total active sessions: {}", sessions.count().await);
+    events.publish(&session_id, SessionEventKind::Created { peer_addr: peer_addr.clone() });
+    events.publish(&session_id, SessionEventKind::Attached { peer_addr: peer_addr.clone() });
+    if !options.tags.is_empty() {
+        info!("🏷️ Session {} tagged {:?}", session_id, options.tags);
+    }
+    webhooks.emit(WebhookEvent::SessionCreated {
+        session_id: session_id.clone(),
+        peer_addr: peer_addr.clone(),
+        tags: options.tags.clone(),
+    });
+    
+    // Send the hello message and, unless switched off, the welcome banner
+    let mut welcome = vec![session.lock().unwrap().hello()];
+    if options.restored.is_some() {
+        welcome.extend(session.lock().unwrap().missed_output());
+    }
+    if let Some(text) = banner.render(&session_id, &peer_addr) {
+        let mut session = session.lock().unwrap();
+        let replies = session.record_output(&text);
+        welcome.extend(replies.into_iter().map(|reply| session.encode_output(reply)));
+    }
+    
+    info!("📤 Sending welcome message to session {}", session_id);
+    let mut welcome_sent = Ok(());
+    for msg in welcome {
+        welcome_sent = conn.send(msg).await;
+        if welcome_sent.is_err() {
+            break;
+        }
+    }
+    if let Err(e) = welcome_sent {
+        error!("❌ Failed to send welcome message to {}: {}", session_id, e);
+        let _ = sessions.remove(&session_id).await;
+        conn.shutdown(None).await;
+        return;
+    }
+    info!("✅ Welcome message sent successfully to {}", session_id);
+    
+    let mut capture = capture_dir.and_then(|dir| match CaptureWriter::create(&dir, &session_id) {
+        Ok(capture) => Some(capture),
+        Err(e) => {
+            warn!("🎞️ Not capturing session {}: {}", session_id, e);
+            None
+        }
+    });
+
+    // Handle incoming client messages
+    info!("👂 Starting message loop for session {}", session_id);
+    let mut close_reason = None;
+    // Whether the client went away on its own, rather than being closed by the server.
+    let mut client_left = false;
+    // Whether the client detached, leaving the session to wait for another; with a session
+    // to switch to if it is attaching to that one instead.
+    let mut detached = false;
+    let mut switch_to: Option<(String, mpsc::Sender<Reattach>)> = None;
+    // The shell's exit code once it exits, and whether the PTY has closed; the session
+    // ends once both are in.
+    let mut exit_code = None;
+    let mut pty_closed = false;
+    let mut orphaned_deadline: Option<tokio::time::Instant> = None;
+    let mut cwd_poll = tokio::time::interval_at(tokio::time::Instant::now() + CWD_POLL_INTERVAL, CWD_POLL_INTERVAL);
+    cwd_poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        loop {
+            let prompt_deadline = session
+                .lock()
+                .unwrap()
+                .prompt
+                .as_ref()
+                .and_then(PromptDetector::deadline)
+                .map(tokio::time::Instant::from_std);
+            let lifetime_deadline = lifetime.as_ref().map(Lifetime::next_deadline);
+            let inbound = tokio::select! {
+                inbound = conn.recv() => match inbound {
+                    Some(inbound) => inbound,
+                    None => {
+                        info!("🔚 Connection tasks for session {} stopped", session_id);
+                        client_left = true;
+                        break;
+                    }
+                },
+                Some(reattach) = reattach_rx.recv() => {
+                    info!("🔁 Session {} taken over by {}", session_id, reattach.peer_addr);
+                    std::mem::replace(&mut conn, reattach.conn).shutdown(Some(CloseReason::Superseded)).await;
+                    (throttle, peer_addr) = (reattach.throttle, reattach.peer_addr);
+                    let _ = sessions.detach(&session_id).await;
+                    events.publish(&session_id, SessionEventKind::Detached);
+                    reattached(&sessions, &events, &session, &peer_addr, &conn).await;
+                    continue;
+                }
+                Some(()) = disconnect_rx.recv() => {
+                    let resume_token = session.lock().unwrap().detach_token();
+                    info!("⏏️ Session {} detached from {} by an admin", session_id, peer_addr);
+                    let _ = conn.send(ServerMessage::Detached { session_id: session_id.clone(), resume_token }).await;
+                    (detached, close_reason) = (true, Some(CloseReason::AdminDisconnect));
+                    break;
+                }
+                notice = notices.recv() => {
+                    let Ok(notice) = notice else { continue };
+                    info!("📢 Delivering {:?} notice to session {}", notice.level, session_id);
+                    if let Err(e) = conn.send(ServerMessage::Notice(notice)).await {
+                        error!("❌ Failed to send notice to {}: {}", session_id, e);
+                        break;
+                    }
+                    continue;
+                }
+                // A client behind on its acks leaves output in the PTY, which holds the shell up.
+                Some(event) = pty_events.recv(), if conn.window_open() => {
+                    match event {
+                        PtyEvent::Output(bytes) => {
+                            chaos.slow_read(&session_id).await;
+                            let replies = {
+                                let mut session_guard = session.lock().unwrap();
+                                let replies = session_guard.pty_output(bytes);
+                                if session_guard.long_command_webhook {
+                                    forward_finished_commands(&webhooks, &session_id, &replies);
+                                }
+                                replies
+                            };
+                            if let Some(ServerMessage::Cwd { path, .. }) = replies.iter().rev().find(|reply| matches!(reply, ServerMessage::Cwd { .. })) {
+                                let cwd = Some(path.clone());
+                                let _ = sessions.update(&session_id, move |metadata| metadata.cwd = cwd).await;
+                            }
+                            let mut sent = Ok(());
+                            for reply in replies {
+                                sent = conn.send(reply).await;
+                                if sent.is_err() {
+                                    break;
+                                }
+                            }
+                            if let Err(e) = sent {
+                                error!("❌ Failed to send output to {}: {}", session_id, e);
+                                break;
+                            }
+                        }
+                        PtyEvent::Exited { code } => {
+                            exit_code = Some(code);
+                            session.lock().unwrap().active = false;
+                            let _ = sessions.update(&session_id, move |metadata| metadata.exit_code = Some(code)).await;
+                            let leftovers = pid.map(process_group::leftovers).unwrap_or_default();
+                            if !pty_closed && !leftovers.is_empty() {
+                                warn!("👻 Shell of session {} exited with {} but left {:?} running", session_id, code, leftovers);
+                                let _ = sessions.update(&session_id, |metadata| metadata.state = SessionState::OrphanedIo).await;
+                                orphaned_deadline = defaults.orphaned_io.close_at(Instant::now()).map(tokio::time::Instant::from_std);
+                            }
+                        }
+                        PtyEvent::Closed => pty_closed = true,
+                    }
+                    if let (Some(code), true) = (exit_code, pty_closed) {
+                        info!("🏁 Shell of session {} exited with {}", session_id, code);
+                        let _ = conn.send(ServerMessage::Exit { reason: CloseReason::Normal, code: Some(code) }).await;
+                        close_reason = Some(CloseReason::Normal);
+                        break;
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep_until(orphaned_deadline.unwrap_or_else(tokio::time::Instant::now)), if orphaned_deadline.is_some() => {
+                    let killed = session.lock().unwrap().pty.kill_session();
+                    info!("🪓 Killed {} processes left behind in session {}", killed, session_id);
+                    let _ = conn.send(ServerMessage::Exit { reason: CloseReason::Normal, code: exit_code }).await;
+                    close_reason = Some(CloseReason::Normal);
+                    break;
+                }
+                _ = cwd_poll.tick() => {
+                    let Some(moved) = session.lock().unwrap().poll_cwd() else { continue };
+                    if let ServerMessage::Cwd { path, .. } = &moved {
+                        debug!("📂 Session {} is now in {}", session_id, path);
+                        let cwd = Some(path.clone());
+                        let _ = sessions.update(&session_id, move |metadata| metadata.cwd = cwd).await;
+                    }
+                    if let Err(e) = conn.send(moved).await {
+                        error!("❌ Failed to send working directory to {}: {}", session_id, e);
+                        break;
+                    }
+                    continue;
+                }
+                Some(warning) = warnings_rx.recv() => {
+                    if let Err(e) = conn.send(warning).await {
+                        error!("❌ Failed to send warning to {}: {}", session_id, e);
+                        break;
+                    }
+                    continue;
+                }
+                _ = shutdown.changed() => {
+                    info!("🛑 Closing session {} for server shutdown", session_id);
+                    close_reason = Some(CloseReason::ServerShutdown);
+                    break;
+                }
+                reason = &mut kill => {
+                    close_reason = reason.ok();
+                    if close_reason == Some(CloseReason::AdminDisconnect) {
+                        webhooks.emit(WebhookEvent::AdminKill { session_id: session_id.clone() });
+                    }
+                    if let Some(reason @ (CloseReason::ResourceLimit | CloseReason::IdleTimeout)) = close_reason {
+                        let _ = conn.send(ServerMessage::Exit { reason, code: None }).await;
+                    }
+                    break;
+                }
+                _ = tokio::time::sleep_until(lifetime_deadline.unwrap_or_else(tokio::time::Instant::now)), if lifetime_deadline.is_some() => {
+                    let Some(event) = lifetime.as_mut().and_then(Lifetime::poll) else { continue };
+                    let LifetimeEvent::Warning { remaining } = event else {
+                        info!("⌛ Session {} reached its maximum lifetime", session_id);
+                        let _ = conn.send(ServerMessage::Exit { reason: CloseReason::MaxLifetime, code: None }).await;
+                        close_reason = Some(CloseReason::MaxLifetime);
+                        break;
+                    };
+                    info!("⏳ Session {} expires in {}s", session_id, remaining.as_secs());
+                    let warning = ServerMessage::ExpiryWarning {
+                        remaining_secs: remaining.as_secs(),
+                        expires_at: expires_at.unwrap_or_else(chrono::Utc::now),
+                    };
+                    if let Err(e) = conn.send(warning).await {
+                        error!("❌ Failed to send expiry warning to {}: {}", session_id, e);
+                        break;
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep_until(prompt_deadline.unwrap_or_else(tokio::time::Instant::now)), if prompt_deadline.is_some() => {
+                    let replies = {
+                        let mut session_guard = session.lock().unwrap();
+                        let replies = session_guard.prompt_fired();
+                        if session_guard.long_command_webhook {
+                            forward_finished_commands(&webhooks, &session_id, &replies);
+                        }
+                        replies
+                    };
+                    if !replies.is_empty() {
+                        debug!("💲 Prompt detected in session {}", session_id);
+                    }
+                    let mut sent = Ok(());
+                    for reply in replies {
+                        sent = conn.send(reply).await;
+                        if sent.is_err() {
+                            break;
+                        }
+                    }
+                    if let Err(e) = sent {
+                        error!("❌ Failed to send prompt event to {}: {}", session_id, e);
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            if let (Some(writer), Inbound::Message(msg)) = (capture.as_mut(), &inbound) {
+                if let Err(e) = writer.record(msg) {
+                    warn!("🎞️ Stopped capturing session {}: {}", session_id, e);
+                    capture = None;
+                }
+            }
+
+            match inbound {
+                Inbound::Message(ClientMessage::Input { data }) => {
+                    debug!("⌨️ Input for {}: {} bytes", session_id, data.len());
+                    if !submit_input(&session, &guard, &webhooks, &data, None, OWNER_PARTICIPANT, &conn).await {
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Mouse { data }) => {
+                    session.lock().unwrap().forward_raw(&data);
+                }
+                Inbound::Message(ClientMessage::Confirm { token, proceed }) => {
+                    if !answer_confirmation(&session, &guard, &webhooks, &token, proceed, OWNER_PARTICIPANT, &conn).await {
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Resize { cols, rows }) => {
+                    info!("📐 Terminal resize request from {}: {}x{}", session_id, cols, rows);
+                    match TerminalSize::new(cols, rows) {
+                        Some(size) => {
+                            session.lock().unwrap().resize(size);
+                            let _ = sessions.update(&session_id, move |metadata| metadata.size = size).await;
+                        }
+                        None => warn!("📐 Ignoring {}x{} resize from {}", cols, rows, session_id),
+                    }
+                }
+                Inbound::Message(ClientMessage::Diagnostics) => {
+                    let diagnostics_msg = ServerMessage::Diagnostics {
+                        session_id: session_id.clone(),
+                        counters: counters.snapshot(),
+                        throttle: throttle.snapshot(),
+                        resources: session.lock().unwrap().resources.as_ref().and_then(ResourceMonitor::current),
+                    };
+                    if let Err(e) = conn.send(diagnostics_msg).await {
+                        error!("❌ Failed to send diagnostics to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::ScreenSnapshot) => {
+                    let snapshot = session.lock().unwrap().screen.as_ref().map(ScreenModel::snapshot);
+                    let reply = match snapshot {
+                        Some(snapshot) => ServerMessage::ScreenSnapshot(snapshot),
+                        None => ServerMessage::Error(ClientError::new("screen_model_disabled")),
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to send screen snapshot to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Search { query, regex, max_results }) => {
+                    let reply = match scrollback::compile_query(&query, regex) {
+                        Ok(pattern) => {
+                            let (output, dropped_lines) = {
+                                let session_guard = session.lock().unwrap();
+                                (session_guard.scrollback.contents().to_string(), session_guard.scrollback.dropped_lines())
+                            };
+                            let max_results = max_results.unwrap_or(scrollback::DEFAULT_SEARCH_RESULTS);
+                            let search = tokio::task::spawn_blocking(move || scrollback::search(&output, dropped_lines, &pattern, max_results));
+                            match search.await {
+                                Ok(results) => {
+                                    info!("🔎 Session {} search for '{}': {} matches", session_id, redaction::redact(&query), results.matches.len());
+                                    ServerMessage::SearchResults { query, results }
+                                }
+                                Err(e) => ServerMessage::Error(ClientError::new("search_failed").with("error", e)),
+                            }
+                        }
+                        Err(e) => ServerMessage::Error(ClientError::from(&e)),
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to send search results to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::FetchScrollback { from, count }) => {
+                    let page = session.lock().unwrap().scrollback.fetch(from, count.unwrap_or(scrollback::DEFAULT_FETCH_LINES));
+                    if let Err(e) = conn.send(ServerMessage::Scrollback(page)).await {
+                        error!("❌ Failed to send scrollback to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Export { format }) => {
+                    let (output, dropped_lines, shell) = {
+                        let session_guard = session.lock().unwrap();
+                        let scrollback = &session_guard.scrollback;
+                        (scrollback.contents().to_string(), scrollback.dropped_lines(), session_guard.shell.clone())
+                    };
+                    let reply = match sessions.get_metadata(&session_id).await {
+                        Ok(metadata) => {
+                            let header = TranscriptHeader {
+                                session_id: session_id.clone(),
+                                created_at: metadata.created_at,
+                                exported_at: chrono::Utc::now(),
+                                shell: Some(shell),
+                                dropped_lines,
+                            };
+                            let filename = header.filename(format);
+                            match tokio::task::spawn_blocking(move || transcript::render(format, &header, &output)).await {
+                                Ok(content) => {
+                                    info!("📜 Exported {} transcript of session {} ({} bytes)", format.as_str(), session_id, content.len());
+                                    ServerMessage::Export { format, filename, content }
+                                }
+                                Err(e) => ServerMessage::Error(ClientError::new("export_failed").with("error", e)),
+                            }
+                        }
+                        Err(e) => ServerMessage::Error(ClientError::from(&e)),
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to send transcript to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Timings) => {
+                    let timings = session.lock().unwrap().commands.timings();
+                    if let Err(e) = conn.send(ServerMessage::Timings(timings)).await {
+                        error!("❌ Failed to send timings to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Ps) => {
+                    let processes = sample_processes(&session).await;
+                    debug!("🌳 Session {} is running {} processes", session_id, processes.len());
+                    if let Err(e) = conn.send(ServerMessage::Processes(processes)).await {
+                        error!("❌ Failed to send process list to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Jobs) => {
+                    let jobs = process_tree::jobs(&sample_processes(&session).await, pid.unwrap_or_default());
+                    debug!("🌳 Session {} has {} jobs", session_id, jobs.len());
+                    if let Err(e) = conn.send(ServerMessage::Jobs(jobs)).await {
+                        error!("❌ Failed to send job list to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Signal { signal, job }) => {
+                    let sent = match job {
+                        None => session.lock().unwrap().pty.signal_foreground(signal),
+                        Some(job) => {
+                            let jobs = process_tree::jobs(&sample_processes(&session).await, pid.unwrap_or_default());
+                            if jobs.iter().any(|known| known.pgid == job) {
+                                pty::signal_group(job, signal)
+                            } else {
+                                Err(PtyError::UnknownJob(job))
+                            }
+                        }
+                    };
+                    match sent {
+                        Ok(group) => info!("📡 Sent {} to process group {} of session {}", signal, group, session_id),
+                        Err(e) => {
+                            warn!("📡 Session {}: {}", session_id, e);
+                            if let Err(e) = conn.send(ServerMessage::Error(ClientError::from(&e))).await {
+                                error!("❌ Failed to report signal error to {}: {}", session_id, e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Inbound::Message(ClientMessage::ClearScrollback) => {
+                    info!("🧽 Clearing scrollback of session {}", session_id);
+                    let mut session_guard = session.lock().unwrap();
+                    session_guard.scrollback.clear();
+                    session_guard.commands.clear();
+                }
+                Inbound::Message(ClientMessage::Capabilities { capabilities }) => {
+                    let capabilities = Capabilities::from_names(&capabilities);
+                    info!("🎛️ Session {} now declares capabilities {:?}", session_id, capabilities);
+                    let (environment, output) = {
+                        let mut session_guard = session.lock().unwrap();
+                        session_guard.output_filter.set_capabilities(capabilities.clone());
+                        (session_guard.environment.clone(), session_guard.output_encoding())
+                    };
+                    let reply = ServerMessage::Capabilities { capabilities, environment, output };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to acknowledge capabilities for {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Rename { name }) => {
+                    let reply = match name.as_deref().map(session_names::parse).transpose() {
+                        Ok(name) => match sessions.update(&session_id, move |metadata| metadata.name = name).await {
+                            Ok(metadata) => {
+                                info!("🏷️ Session {} is now called {:?}", session_id, metadata.name);
+                                ServerMessage::Renamed { name: metadata.name }
+                            }
+                            Err(e) => ServerMessage::Error(ClientError::from(&e)),
+                        },
+                        Err(e) => {
+                            warn!("🏷️ Session {} refused rename: {}", session_id, e);
+                            ServerMessage::Error(ClientError::from(&e))
+                        }
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to acknowledge rename for {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Ack { seq }) => conn.ack(seq),
+                Inbound::Message(ClientMessage::SessionInfo) => {
+                    let reply = match sessions.get_metadata(&session_id).await {
+                        Ok(metadata) => ServerMessage::SessionInfo(session_info(metadata, &session.lock().unwrap())),
+                        Err(e) => ServerMessage::Error(ClientError::from(&e)),
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to send session info to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Message(ClientMessage::Detach) => {
+                    let resume_token = session.lock().unwrap().detach_token();
+                    info!("⏏️ Client {} detached from session {}", peer_addr, session_id);
+                    let _ = conn.send(ServerMessage::Detached { session_id: session_id.clone(), resume_token }).await;
+                    (detached, close_reason) = (true, Some(CloseReason::Normal));
+                    break;
+                }
+                Inbound::Message(ClientMessage::Attach { session_id: target_id, resume_token }) => {
+                    let target = match sessions.get(&target_id).await {
+                        Ok(_) if target_id == session_id => Err(ClientError::new("already_attached").with("id", &target_id)),
+                        Ok(target) => target.lock().unwrap().resume_with(&resume_token).ok_or_else(|| ClientError::new("resume_rejected").with("id", &target_id)),
+                        Err(e) => Err(ClientError::from(&e)),
+                    };
+                    match target {
+                        Ok(target) => {
+                            switch_to = Some((target_id, target));
+                            detached = true;
+                            break;
+                        }
+                        Err(error) => {
+                            warn!("🔁 {} could not switch from session {} to {}: {}", peer_addr, session_id, target_id, error.message);
+                            if let Err(e) = conn.send(ServerMessage::Error(error)).await {
+                                error!("❌ Failed to refuse attach for {}: {}", session_id, e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Inbound::Message(ClientMessage::SetEnv { env }) => {
+                    let applied = {
+                        let mut session_guard = session.lock().unwrap();
+                        session_guard
+                            .set_env(&defaults.environment, env)
+                            .map(|()| (session_guard.environment.clone(), session_guard.env.clone(), session_guard.env_vars()))
+                    };
+                    let reply = match applied {
+                        Ok((environment, env, vars)) => {
+                            info!("🧬 Session {} now reports {} variables", session_id, vars.len());
+                            match sessions.update(&session_id, move |metadata| metadata.env = vars).await {
+                                Ok(_) => ServerMessage::Env { environment, env },
+                                Err(e) => ServerMessage::Error(ClientError::from(&e)),
+                            }
+                        }
+                        Err(error) => {
+                            warn!("🧬 Session {} refused setenv: {}", session_id, error.message);
+                            ServerMessage::Error(error)
+                        }
+                    };
+                    if let Err(e) = conn.send(reply).await {
+                        error!("❌ Failed to acknowledge setenv for {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Invalid(e) => {
+                    warn!("⚠️ Bad message from {}: {}", session_id, e);
+                    match e {
+                        DecodeError::UnknownType(_) => counters.unknown_type(),
+                        _ => counters.malformed_message(),
+                    }
+                    events.publish(&session_id, SessionEventKind::ProtocolError { error: e.to_string() });
+                    if let Err(e) = conn.send(ServerMessage::Error(ClientError::from(&e))).await {
+                        error!("❌ Failed to report bad message to {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                Inbound::Closed => {
+                    info!("🔚 Client of session {} disconnected", session_id);
+                    client_left = true;
+                    break;
+                }
+                Inbound::Failed { error, close } => {
+                    error!("❌ WebSocket error for {}: {}", session_id, error);
+                    if close == Some(CloseReason::MessageTooBig) {
+                        counters.oversized_frame();
+                    }
+                    client_left = close.is_none();
+                    close_reason = close;
+                    break;
+                }
+            }
+        }
+
+        // Sends fail once the writer stops, as it does when a dead client stops taking
+        // output; that client left as much as one that closed its connection.
+        client_left |= close_reason.is_none() && conn.writer_stopped();
+        match switch_to.take() {
+            Some((target_id, target)) => {
+                info!("🔁 {} switches from session {} to {}", peer_addr, session_id, target_id);
+                let reattach = Reattach { conn, throttle: throttle.clone(), peer_addr: peer_addr.clone() };
+                if let Err(mpsc::error::SendError(reattach)) = target.send(reattach).await {
+                    let _ = reattach.conn.send(ServerMessage::Error(ClientError::from(&RegistryError::NotFound(target_id)))).await;
+                    reattach.conn.shutdown(None).await;
+                }
+            }
+            None => {
+                if let Some(reason) = close_reason {
+                    info!("👋 Closing {} with {} ({})", session_id, reason.code(), reason.reason());
+                    let saved_token = defaults.persist.then(|| session.lock().unwrap().resume_token.clone()).flatten();
+                    if let Some(hint) = reconnect_hint(reason, saved_token.as_deref()) {
+                        let _ = conn.send(hint).await;
+                    }
+                }
+                conn.shutdown(close_reason).await;
+            }
+        }
+
+        // A detached session waits for a client however long it takes; one whose client
+        // left lingers for `[terminal.disconnect]` linger_secs, if at all.
+        let waiting = match (exit_code, detached, client_left) {
+            (None, true, _) => Some((SessionState::Detached, None)),
+            (None, false, true) => defaults
+                .disconnect
+                .hang_up_at(Instant::now())
+                .map(|deadline| (SessionState::Lingering, Some(tokio::time::Instant::from_std(deadline)))),
+            _ => None,
+        };
+        if let Some((waiting_state, deadline)) = waiting {
+            // Waiting for a client does not stretch `[terminal.max_lifetime]`.
+            let deadline = deadline.into_iter().chain(lifetime.as_ref().map(Lifetime::deadline)).min();
+            match waiting_state {
+                SessionState::Detached => info!("⏸️ Session {} waits detached for a client", session_id),
+                _ => info!("⏸️ Session {} lingers for {:?} after its client left", session_id, defaults.disconnect.linger),
+            }
+            let _ = sessions.detach(&session_id).await;
+            let _ = sessions.update(&session_id, move |metadata| metadata.state = waiting_state).await;
+            match linger(&session, &mut pty_events, deadline, &mut shutdown, &mut kill, &mut reattach_rx).await {
+                Lingered::Reattached(reattach) => {
+                    info!("🔁 Session {} resumed by {}", session_id, reattach.peer_addr);
+                    let _ = sessions.update(&session_id, |metadata| metadata.state = SessionState::Running).await;
+                    (conn, throttle, peer_addr) = (reattach.conn, reattach.throttle, reattach.peer_addr);
+                    (close_reason, client_left, detached) = (None, false, false);
+                    // A disconnect that raced the client leaving is not meant for this one.
+                    while disconnect_rx.try_recv().is_ok() {}
+                    reattached(&sessions, &events, &session, &peer_addr, &conn).await;
+                    continue;
+                }
+                Lingered::Exited(code) => exit_code = Some(code),
+                Lingered::HungUp => {}
+            }
+        }
+        break;
+    }
+
+    // Clean up; dropping the session hangs up whatever is still running in it
+    info!("🧹 Cleaning up session {}", session_id);
+    let end = ServerMessage::Exit { reason: close_reason.unwrap_or(CloseReason::Normal), code: exit_code };
+    let _ = session.lock().unwrap().mirror.send(end);
+    let _ = sessions.detach(&session_id).await;
+    // Tags as they were at the end; they may have changed since creation.
+    let tags = sessions.remove(&session_id).await.map(|metadata| metadata.tags).unwrap_or_default();
+    let remaining_sessions = sessions.count().await;
+    events.publish(&session_id, SessionEventKind::Detached);
+    events.publish(&session_id, SessionEventKind::Exited);
+    chaos.forget(&session_id);
+    webhooks.emit(WebhookEvent::SessionEnded {
+        session_id: session_id.clone(),
+        reason: close_reason.unwrap_or(CloseReason::Normal).reason().to_string(),
+        exit_code,
+        tags,
+    });
+    info!("✅ Terminal session {} ended. Remaining sessions: {}. Protocol counters: {:?}", session_id, remaining_sessions, counters.snapshot());
+}
+/// Keeps a session whose client left running until `deadline`, if any, the shell exits, the
+/// server shuts down, an admin kills it or a client resumes it, recording its output to
+/// scrollback meanwhile.
+async fn linger(
+    session: &Arc<Mutex<TerminalSession>>,
+    pty_events: &mut mpsc::Receiver<PtyEvent>,
+    deadline: Option<tokio::time::Instant>,
+    shutdown: &mut watch::Receiver<bool>,
+    kill: &mut KillSignal,
+    reattach: &mut mpsc::Receiver<Reattach>,
+) -> Lingered {
+    loop {
+        tokio::select! {
+            event = pty_events.recv() => match event {
+                Some(PtyEvent::Output(bytes)) => {
+                    session.lock().unwrap().pty_output(bytes);
+                }
+                Some(PtyEvent::Exited { code }) => return Lingered::Exited(code),
+                Some(PtyEvent::Closed) => {}
+                None => return Lingered::HungUp,
+            },
+            Some(reattach) = reattach.recv() => return Lingered::Reattached(reattach),
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => return Lingered::HungUp,
+            _ = shutdown.changed() => return Lingered::HungUp,
+            _ = &mut *kill => return Lingered::HungUp,
+        }
+    }
+}
+
+/// Registers the client a session was just handed as attached and greets it with `hello`
+/// and the output it may have missed.
+async fn reattached(sessions: &Sessions, events: &EventBus, session: &Arc<Mutex<TerminalSession>>, peer_addr: &str, conn: &Connection) {
+    let (session_id, hello, replay) = {
+        let session = session.lock().unwrap();
+        (session.id.clone(), session.hello(), session.missed_output())
+    };
+    if let Err(e) = sessions.attach(&session_id, peer_addr.to_string()).await {
+        warn!("🔁 Session {} resumed without registering {}: {}", session_id, peer_addr, e);
+    }
+    events.publish(&session_id, SessionEventKind::Attached { peer_addr: peer_addr.to_string() });
+    for msg in std::iter::once(hello).chain(replay) {
+        if let Err(e) = conn.send(msg).await {
+            error!("❌ Failed to greet {} resuming session {}: {}", peer_addr, session_id, e);
+            return;
+        }
+    }
+}
+
+/// Hands a client that presented a resume token to that session's task, which takes it
+/// over from the lingering wait or from the client attached before.
+async fn resume_session<T: Transport>(transport: T, peer_addr: String, session_id: String, token: String, ack_window: Option<u64>, state: ServerState) {
+    let shaper = state.bandwidth.session();
+    let throttle = shaper.stats();
+    let conn = Connection::spawn_shaped(transport, state.chaos.clone(), session_id.clone(), Some(shaper)).with_ack_window(ack_window);
+    let target = match state.sessions.get(&session_id).await {
+        Ok(session) => session.lock().unwrap().resume_with(&token).ok_or_else(|| ClientError::new("resume_rejected").with("id", &session_id)),
+        Err(e) => Err(ClientError::from(&e)),
+    };
+    let (conn, error) = match target {
+        Ok(target) => match target.send(Reattach { conn, throttle, peer_addr: peer_addr.clone() }).await {
+            Ok(()) => return,
+            Err(mpsc::error::SendError(reattach)) => (reattach.conn, ClientError::from(&RegistryError::NotFound(session_id.clone()))),
+        },
+        Err(error) => (conn, error),
+    };
+    warn!("🔁 {} could not resume session {}: {}", peer_addr, session_id, error.message);
+    let reason = rejected_token(&error);
+    let _ = conn.send(ServerMessage::Error(error)).await;
+    conn.shutdown(reason).await;
+}
+
+/// `policy_violation` for a client turned away over the token it presented, so it stops
+/// retrying with it; other failures close without a reason.
+fn rejected_token(error: &ClientError) -> Option<CloseReason> {
+    matches!(error.code, "resume_rejected" | "share_rejected").then_some(CloseReason::PolicyViolation)
+}
+
+/// Opens a running session for a client presenting its share token. The client gets the
+/// session's hello and recent output, then its output as it comes, and may type into it;
+/// it leaves when it disconnects or the session ends.
+async fn join_session<T: Transport>(transport: T, peer_addr: String, session_id: String, token: String, state: ServerState) {
+    let mut conn = Connection::spawn_with_chaos(transport, state.chaos.clone(), session_id.clone());
+    let joined = match state.sessions.get(&session_id).await {
+        Ok(session) => {
+            let mut guard = session.lock().unwrap();
+            guard.share_with(&token).map(|(mirror, participant)| {
+                let mut hello = guard.hello();
+                if let ServerMessage::Hello { resume_token, participant: joined, .. } = &mut hello {
+                    // Resuming would supersede the session's own client.
+                    *resume_token = None;
+                    *joined = Some(participant.clone());
+                }
+                let peers = guard.peers_of(&participant.id).into_iter().map(ServerMessage::PeerJoined);
+                let greeting: Vec<ServerMessage> = std::iter::once(hello).chain(peers).chain(guard.missed_output()).collect();
+                (Arc::downgrade(&session), mirror, participant, greeting)
+            })
+        }
+        Err(e) => Err(ClientError::from(&e)),
+    };
+    let (shared, mut mirror, participant, greeting) = match joined {
+        Ok(joined) => joined,
+        Err(error) => {
+            warn!("👥 {} could not open session {}: {}", peer_addr, session_id, error.message);
+            let reason = rejected_token(&error);
+            let _ = conn.send(ServerMessage::Error(error)).await;
+            conn.shutdown(reason).await;
+            return;
+        }
+    };
+    info!("👥 {} opened session {} alongside its client as {}", peer_addr, session_id, participant.id);
+
+    let mut shutdown = state.shutdown.clone();
+    let mut close_reason = None;
+    // Bytes typed since the last `input_attribution`, and when the next may go out.
+    let (mut typed, mut next_attribution) = (0, tokio::time::Instant::now());
+    // A client gone already is noticed by `recv` below.
+    for msg in greeting {
+        let _ = conn.send(msg).await;
+    }
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(next_attribution), if typed > 0 => {
+                let Some(session) = shared.upgrade() else { break };
+                let bytes = std::mem::take(&mut typed);
+                session.lock().unwrap().announce(ServerMessage::InputAttribution { participant: participant.id.clone(), bytes });
+                next_attribution = tokio::time::Instant::now() + ATTRIBUTION_INTERVAL;
+            }
+            msg = mirror.recv() => match msg {
+                Ok(ServerMessage::Exit { reason, code }) => {
+                    let _ = conn.send(ServerMessage::Exit { reason, code }).await;
+                    close_reason = Some(reason);
+                    break;
+                }
+                Ok(msg) => {
+                    if conn.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("🐢 {} missed {} messages of session {}", peer_addr, missed, session_id);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            inbound = conn.recv() => {
+                let Some(session) = shared.upgrade() else { break };
+                let open = match inbound {
+                    Some(Inbound::Message(ClientMessage::Input { data })) => {
+                        typed += data.len();
+                        submit_input(&session, &state.guard, &state.webhooks, &data, None, &participant.id, &conn).await
+                    }
+                    Some(Inbound::Message(ClientMessage::Mouse { data })) => {
+                        session.lock().unwrap().forward_raw(&data);
+                        true
+                    }
+                    Some(Inbound::Message(ClientMessage::Confirm { token, proceed })) => {
+                        answer_confirmation(&session, &state.guard, &state.webhooks, &token, proceed, &participant.id, &conn).await
+                    }
+                    Some(Inbound::Message(_)) => conn.send(ServerMessage::Error(ClientError::new("owner_only"))).await.is_ok(),
+                    Some(Inbound::Invalid(e)) => {
+                        warn!("⚠️ Bad message from {} in session {}: {}", peer_addr, session_id, e);
+                        conn.send(ServerMessage::Error(ClientError::from(&e))).await.is_ok()
+                    }
+                    Some(Inbound::Failed { error, close }) => {
+                        error!("❌ WebSocket error for {} in session {}: {}", peer_addr, session_id, error);
+                        close_reason = close;
+                        false
+                    }
+                    Some(Inbound::Closed) | None => false,
+                };
+                if !open {
+                    break;
+                }
+            }
+            _ = shutdown.changed() => {
+                close_reason = Some(CloseReason::ServerShutdown);
+                break;
+            }
+        }
+    }
+    if let Some(session) = shared.upgrade() {
+        session.lock().unwrap().leave(&participant.id);
+    }
+    info!("👥 {} left session {}", peer_addr, session_id);
+    conn.shutdown(close_reason).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use futures_util::{SinkExt, StreamExt};
+    use rust_terminal_forge::config::{BannerConfig, DangerousCommandConfig, LongCommandConfig, PersistenceConfig, PromptDetectionConfig, TmpDirConfig};
+    #[cfg(unix)]
+    use rust_terminal_forge::config::DisconnectConfig;
+    #[cfg(target_os = "linux")]
+    use rust_terminal_forge::config::{OrphanedIoConfig, OrphanedIoMode, ResourceConfig};
+    use rust_terminal_forge::chaos::ChaosSettings;
+    use rust_terminal_forge::protocol::{MESSAGE_CHANNEL, TERMINAL_CHANNEL};
+    use rust_terminal_forge::protocol_capture::{Capture, CAPTURE_VERSION};
+    use rust_terminal_forge::session_env::ColorSupport;
+    use rust_terminal_forge::transport::{memory_pair, ClientFrame, MemoryPeer, ServerFrame};
+    use serde_json::json;
+    use tokio::task::JoinHandle;
+    use tokio_tungstenite::tungstenite::protocol::frame::{coding::{Data, OpCode}, Frame};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Tests run plain `sh` with a fixed prompt, so echo and prompts are the same for
+    /// whoever runs them.
+    const TEST_SHELL: &str = "sh";
+    const TEST_PROMPT: &str = "forge$ ";
+
+    /// Prompt detection is off so timing-dependent `prompt` events stay out of unrelated tests.
+    fn test_state() -> (ServerState, watch::Sender<bool>) {
+        state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        })
+    }
+
+    fn state_with_terminal(terminal: TerminalConfig) -> (ServerState, watch::Sender<bool>) {
+        let terminal = TerminalConfig { shell: TEST_SHELL.to_string(), ..terminal };
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let banner = Banner::from_config(&terminal).unwrap();
+        let defaults = SessionDefaults::from_config(&terminal).unwrap();
+        (ServerState::new(guard, banner, defaults, Webhooks::default(), Chaos::default(), Bandwidth::default(), shutdown_rx), shutdown_tx)
+    }
+
+    /// Drives a full terminal session in-process over a `MemoryTransport`.
+    struct TestClient {
+        peer: MemoryPeer,
+        session: JoinHandle<()>,
+        /// Messages other than output that arrived while `output_until` was waiting.
+        backlog: VecDeque<ServerMessage>,
+    }
+
+    impl TestClient {
+        /// Starts a session and consumes its hello message, welcome banner and first prompt.
+        async fn attach(state: &ServerState) -> Self {
+            let mut client = Self::attach_raw(state);
+            assert!(matches!(client.message().await, ServerMessage::Hello { .. }));
+            assert!(client.output().await.contains("Welcome"));
+            client.output_until(TEST_PROMPT).await;
+            client
+        }
+
+        fn attach_raw(state: &ServerState) -> Self {
+            Self::attach_with(state, SessionOptions::default())
+        }
+
+        fn attach_with(state: &ServerState, mut options: SessionOptions) -> Self {
+            options.env.entry("PS1".to_string()).or_insert_with(|| TEST_PROMPT.to_string());
+            let (transport, peer) = memory_pair();
+            let session = tokio::spawn(handle_terminal(transport, "memory".to_string(), options, state.clone()));
+            Self { peer, session, backlog: VecDeque::new() }
+        }
+
+        fn send_raw(&self, text: &str) {
+            self.peer.tx.send(ClientFrame::Text(text.to_string())).unwrap();
+        }
+
+        fn send(&self, msg: serde_json::Value) {
+            self.send_raw(&msg.to_string());
+        }
+
+        fn input(&self, data: &str) {
+            self.send(json!({ "type": "input", "data": data }));
+        }
+
+        fn resize(&self, cols: u16, rows: u16) {
+            self.send(json!({ "type": "resize", "cols": cols, "rows": rows }));
+        }
+
+        async fn recv(&mut self) -> ServerFrame {
+            tokio::time::timeout(Duration::from_secs(5), self.peer.rx.recv())
+                .await
+                .expect("timed out waiting for the server")
+                .expect("session ended without closing")
+        }
+
+        async fn message(&mut self) -> ServerMessage {
+            match self.recv().await {
+                ServerFrame::Message(msg) => msg,
+                frame => panic!("expected a message, got {:?}", frame),
+            }
+        }
+
+        async fn output(&mut self) -> String {
+            match self.message().await {
+                ServerMessage::Output { data, .. } => data,
+                msg => panic!("expected output, got {:?}", msg),
+            }
+        }
+
+        /// Collects output until it contains `needle`, keeping other messages for `event`.
+        async fn output_until(&mut self, needle: &str) -> String {
+            let mut output = String::new();
+            while !output.contains(needle) {
+                match self.message().await {
+                    ServerMessage::Output { data, .. } => output.push_str(&data),
+                    msg => self.backlog.push_back(msg),
+                }
+            }
+            output
+        }
+
+        /// The next message that is not output, starting with those `output_until` set aside.
+        async fn event(&mut self) -> ServerMessage {
+            if let Some(msg) = self.backlog.pop_front() {
+                return msg;
+            }
+            loop {
+                match self.message().await {
+                    ServerMessage::Output { .. } | ServerMessage::OutputBytes { .. } => {}
+                    msg => return msg,
+                }
+            }
+        }
+
+        /// The next news of the session's other participants, skipping other events.
+        async fn peer_event(&mut self) -> ServerMessage {
+            loop {
+                let msg = self.event().await;
+                if matches!(msg, ServerMessage::PeerJoined(_) | ServerMessage::PeerLeft { .. } | ServerMessage::InputAttribution { .. }) {
+                    return msg;
+                }
+            }
+        }
+
+        /// Sends `messages` and a close, and returns the type of everything the server sent
+        /// back; the session panicking fails the test.
+        async fn play(mut self, messages: &[serde_json::Value]) -> Vec<String> {
+            for msg in messages {
+                self.send(msg.clone());
+            }
+            self.peer.tx.send(ClientFrame::Close).unwrap();
+            let mut types = Vec::new();
+            while let ServerFrame::Message(msg) = self.recv().await {
+                types.push(protocol_capture::message_type(&serde_json::from_str(&msg.encode()).unwrap()));
+            }
+            self.session.await.unwrap();
+            types
+        }
+
+        /// Closes from the client side and waits for the session to finish, passing over
+        /// whatever output was still queued ahead of the close.
+        async fn detach(mut self) -> Option<CloseReason> {
+            self.peer.tx.send(ClientFrame::Close).unwrap();
+            let reason = loop {
+                if let ServerFrame::Close(reason) = self.recv().await {
+                    break reason;
+                }
+            };
+            self.session.await.unwrap();
+            reason
+        }
+    }
+
+    async fn start_server() -> (std::net::SocketAddr, watch::Sender<bool>) {
+        let (state, shutdown_tx) = test_state();
+        (listen(state).await, shutdown_tx)
+    }
+
+    async fn listen(state: ServerState) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+        });
+        addr
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> Client {
+        let (mut client, _) = connect_async(format!("ws://{}/", addr)).await.unwrap();
+        // Hello message and welcome banner
+        for _ in 0..2 {
+            assert!(matches!(client.next().await, Some(Ok(Message::Text(_)))));
+        }
+        client
+    }
+
+    /// Reads until the server's close frame and returns its code and reason.
+    async fn admin_channel(addr: std::net::SocketAddr) -> Client {
+        connect_async(format!("ws://{}/admin/ws?token=sessions-secret", addr)).await.unwrap().0
+    }
+
+    /// The next admin channel message of type `kind`, past the session events around it.
+    async fn admin_message(admin: &mut Client, kind: &str) -> serde_json::Value {
+        loop {
+            let Some(Ok(Message::Text(text))) = admin.next().await else { panic!("expected {}", kind) };
+            let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if msg["type"] == kind {
+                return msg;
+            }
+        }
+    }
+
+    async fn close_frame(client: &mut Client) -> (u16, String) {
+        while let Some(msg) = client.next().await {
+            if let Ok(Message::Close(Some(frame))) = msg {
+                return (frame.code.into(), frame.reason.into_owned());
+            }
+        }
+        panic!("connection ended without a close frame");
+    }
+
+    #[tokio::test]
+    async fn client_close_is_acknowledged_as_normal() {
+        let (addr, _shutdown) = start_server().await;
+        let mut client = connect(addr).await;
+        client.send(Message::Close(Some(CloseReason::Normal.frame()))).await.unwrap();
+        assert_eq!(close_frame(&mut client).await, (1000, "normal".to_string()));
+    }
+
+    #[tokio::test]
+    async fn oversized_message_closes_with_1009() {
+        let (addr, _shutdown) = start_server().await;
+        let mut client = connect(addr).await;
+        // Only the header of a masked text frame over the limit: the server rejects it before
+        // the payload, and with nothing left unread it closes without resetting the connection.
+        let mut header = vec![0x81, 0x80 | 127];
+        header.extend_from_slice(&(MAX_MESSAGE_SIZE as u64 + 1).to_be_bytes());
+        header.extend_from_slice(&[1, 2, 3, 4]);
+        let MaybeTlsStream::Plain(stream) = client.get_mut() else { panic!("expected a plain TCP stream") };
+        tokio::io::AsyncWriteExt::write_all(stream, &header).await.unwrap();
+        assert_eq!(close_frame(&mut client).await, (1009, "message_too_big".to_string()));
+    }
+
+    #[tokio::test]
+    async fn invalid_environment_is_rejected_at_handshake() {
+        let (addr, _shutdown) = start_server().await;
+        let err = connect_async(format!("ws://{}/?term=vt52", addr)).await.unwrap_err();
+        let tokio_tungstenite::tungstenite::Error::Http(response) = err else { panic!("expected an HTTP error, got {}", err) };
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(response.body().as_deref().unwrap()).unwrap();
+        assert_eq!(body["error"], "invalid_term");
+    }
+
+    #[tokio::test]
+    async fn wrong_share_tokens_close_with_policy_violation() {
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let mut owner = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id, .. } = owner.message().await else { panic!("expected hello") };
+
+        let (mut stranger, _) = connect_async(format!("ws://{}/?session_id={}&share_token=guess", addr, session_id)).await.unwrap();
+        let Some(Ok(Message::Text(text))) = stranger.next().await else { panic!("expected an error message") };
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap()["code"], "share_rejected");
+        assert_eq!(close_frame(&mut stranger).await, (1008, "policy_violation".to_string()));
+    }
+
+    #[tokio::test]
+    async fn handshakes_from_other_origins_are_refused() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let (mut state, _shutdown) = test_state();
+        state.origins = AllowedOrigins::from_config(&["http://localhost:8080".to_string()]).unwrap();
+        let addr = listen(state).await;
+        let from = |origin: &'static str| {
+            let mut request = format!("ws://{}/", addr).into_client_request().unwrap();
+            request.headers_mut().insert(http::header::ORIGIN, http::HeaderValue::from_static(origin));
+            request
+        };
+
+        let err = connect_async(from("https://evil.example")).await.unwrap_err();
+        let tokio_tungstenite::tungstenite::Error::Http(response) = err else { panic!("expected an HTTP error, got {}", err) };
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(response.body().as_deref().unwrap()).unwrap();
+        assert_eq!(body["error"], "origin_not_allowed");
+
+        connect_async(from("http://LOCALHOST:8080")).await.unwrap();
+        connect_async(format!("ws://{}/", addr)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn full_servers_refuse_new_sessions() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig { max_sessions: Some(1), ..Default::default() });
+        let _first = TestClient::attach(&state).await;
+        let mut second = TestClient::attach_raw(&state);
+        let ServerMessage::Error(error) = second.message().await else { panic!("expected error") };
+        assert_eq!(error.code, "server_full");
+        assert!(matches!(second.message().await, ServerMessage::ReconnectHint { .. }));
+        assert!(matches!(second.recv().await, ServerFrame::Close(Some(CloseReason::ServerFull))));
+        assert_eq!(state.sessions.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn connections_per_address_are_capped() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig { max_connections_per_ip: Some(1), ..Default::default() });
+        let addr = listen(state.clone()).await;
+        let mut first = connect(addr).await;
+
+        let (mut second, _) = connect_async(format!("ws://{}/", addr)).await.unwrap();
+        let Some(Ok(Message::Text(text))) = second.next().await else { panic!("expected an error message") };
+        let error: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!((&error["type"], &error["code"]), (&json!("error"), &json!("too_many_connections")));
+        assert_eq!(close_frame(&mut second).await, (1013, "server_full".to_string()));
+
+        first.send(Message::Close(Some(CloseReason::Normal.frame()))).await.unwrap();
+        close_frame(&mut first).await;
+        let ip = addr.ip();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.connections.open(ip) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the first connection's slot was never freed");
+        let mut third = connect(addr).await;
+        third.send(Message::Close(Some(CloseReason::Normal.frame()))).await.unwrap();
+        close_frame(&mut third).await;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.connections.open(ip) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the third connection's slot was never freed");
+
+        // A connection counts from the moment it is accepted, before it sends anything.
+        let idle = TcpStream::connect(addr).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.connections.open(ip) != 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the idle connection was never counted");
+        let (mut refused, _) = connect_async(format!("ws://{}/", addr)).await.unwrap();
+        assert_eq!(close_frame(&mut refused).await, (1013, "server_full".to_string()));
+        drop(idle);
+    }
+
+    #[tokio::test]
+    async fn sessions_endpoint_lists_sessions_for_the_admin() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let get = |authorization: &'static str| async move {
+            let request = hyper::Request::get(format!("http://{}/sessions", addr))
+                .header("authorization", authorization)
+                .body(hyper::Body::empty())
+                .unwrap();
+            let response = hyper::Client::new().request(request).await.unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+        };
+
+        let (status, body) = get("Bearer wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "auth_failed");
+
+        let _client = TestClient::attach(&state).await;
+        let (status, body) = get("Bearer sessions-secret").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["count"], 1);
+        let listed = &body["sessions"][0];
+        assert_eq!(listed["id"], state.sessions.list().await[0].id.as_str());
+        assert_eq!(listed["attached"], true);
+        assert_eq!(listed["peer_addr"], "memory");
+        assert_eq!(listed["active"], true);
+        let time = |field: &str| chrono::DateTime::parse_from_rfc3339(listed[field].as_str().unwrap()).unwrap();
+        assert!(time("created_at") <= time("last_activity"));
+
+        // Browsers pass the token in the query string, percent-encoded.
+        let response = hyper::Client::new().get(format!("http://{}/sessions?token=sessions%2Dsecret", addr).parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // WebSocket handshakes on the same port are unaffected.
+        connect(addr).await;
+    }
+
+    #[tokio::test]
+    async fn admins_kill_sessions_with_delete() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let request = |method: &'static str, id: &str, authorization: &'static str| {
+            let request = hyper::Request::builder()
+                .method(method)
+                .uri(format!("http://{}/sessions/{}", addr, id))
+                .header("authorization", authorization)
+                .body(hyper::Body::empty())
+                .unwrap();
+            async move {
+                let response = hyper::Client::new().request(request).await.unwrap();
+                let status = response.status();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let mut client = TestClient::attach(&state).await;
+        let id = state.sessions.list().await[0].id.clone();
+        assert_eq!(request("DELETE", &id, "Bearer wrong").await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(request("GET", &id, "Bearer sessions-secret").await.0, StatusCode::METHOD_NOT_ALLOWED);
+        let (status, body) = request("DELETE", "no-such-session", "Bearer sessions-secret").await;
+        assert_eq!((status, &body["error"]), (StatusCode::NOT_FOUND, &json!("session_not_found")));
+        assert_eq!(state.sessions.count().await, 1);
+
+        let (status, body) = request("DELETE", &id, "Bearer sessions-secret").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "id": id, "removed": true }));
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::AdminDisconnect))));
+        assert_eq!(state.sessions.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn admins_disconnect_clients_but_keep_their_sessions() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let disconnect = |id: &str| {
+            let request = hyper::Request::post(format!("http://{}/sessions/{}/disconnect", addr, id))
+                .header("authorization", "Bearer sessions-secret")
+                .body(hyper::Body::empty())
+                .unwrap();
+            async move {
+                let response = hyper::Client::new().request(request).await.unwrap();
+                let status = response.status();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+        let (status, body) = disconnect("no-such-session").await;
+        assert_eq!((status, &body["error"]), (StatusCode::NOT_FOUND, &json!("session_not_found")));
+
+        let (status, body) = disconnect(&id).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "id": id, "disconnected": true })));
+        let ServerMessage::Detached { session_id, .. } = client.event().await else { panic!("expected detached") };
+        assert_eq!(session_id, id);
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::AdminDisconnect))));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.sessions.get_metadata(&id).await.unwrap().state != SessionState::Detached {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the session never went detached");
+        assert!(!state.sessions.get_metadata(&id).await.unwrap().attached);
+
+        // With nobody attached there is nothing to do.
+        let (status, body) = disconnect(&id).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "id": id, "disconnected": false })));
+        assert_eq!(state.sessions.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn the_admin_channel_disconnects_clients() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+
+        let mut admin = admin_channel(addr).await;
+        admin.send(Message::Text(json!({ "disconnect": { "session_id": id } }).to_string())).await.unwrap();
+        assert_eq!(admin_message(&mut admin, "disconnected").await, json!({ "type": "disconnected", "session_id": id, "was_attached": true }));
+        let ServerMessage::Detached { session_id, .. } = client.event().await else { panic!("expected detached") };
+        assert_eq!(session_id, id);
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::AdminDisconnect))));
+        assert_eq!(state.sessions.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn admins_observe_sessions_read_only() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = state_with_terminal(TerminalConfig { observe: ObserveConfig { enabled: true, covert: false }, ..Default::default() });
+        let addr = listen(state.clone()).await;
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+        client.input("echo before-observe\r");
+        client.output_until("before-observe\r\n").await;
+
+        let mut admin = admin_channel(addr).await;
+        admin.send(Message::Text(json!({ "observe": { "session_id": id, "replay_lines": 50 } }).to_string())).await.unwrap();
+        let observing = admin_message(&mut admin, "observing").await;
+        assert_eq!((&observing["session_id"], &observing["announced"]), (&json!(id), &json!(true)));
+        assert!(observing["lines"].as_array().unwrap().iter().any(|line| line == "before-observe\r"));
+        assert!(matches!(client.event().await, ServerMessage::ObserverJoined { who } if who == "admin"));
+
+        client.input("echo live-output\r");
+        client.output_until("live-output\r\n").await;
+        let mut seen = String::new();
+        while !seen.contains("live-output\r\n") {
+            let output = admin_message(&mut admin, "output").await;
+            assert_eq!(output["session_id"], id.as_str());
+            seen.push_str(output["data"].as_str().unwrap());
+        }
+
+        // The observer going away leaves the session as it was.
+        admin.send(Message::Text(json!({ "unobserve": {} }).to_string())).await.unwrap();
+        assert_eq!(admin_message(&mut admin, "unobserved").await["session_id"], id.as_str());
+        admin.close(None).await.unwrap();
+        client.input("echo still-open\r");
+        client.output_until("still-open\r\n").await;
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().state, SessionState::Running);
+    }
+
+    #[tokio::test]
+    async fn observing_is_opt_in_and_may_be_covert() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+        let mut admin = admin_channel(addr).await;
+        admin.send(Message::Text(json!({ "observe": { "session_id": id } }).to_string())).await.unwrap();
+        assert_eq!(admin_message(&mut admin, "error").await["code"], "observe_disabled");
+
+        let (state, _shutdown) = state_with_terminal(TerminalConfig { observe: ObserveConfig { enabled: true, covert: true }, ..Default::default() });
+        let addr = listen(state.clone()).await;
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+        let mut admin = admin_channel(addr).await;
+        admin.send(Message::Text(json!({ "observe": { "session_id": id } }).to_string())).await.unwrap();
+        assert_eq!(admin_message(&mut admin, "observing").await["announced"], false);
+        client.send(json!({ "type": "session_info" }));
+        assert!(matches!(client.event().await, ServerMessage::SessionInfo(_)));
+    }
+
+    #[tokio::test]
+    async fn workspaces_group_sessions_to_list_and_close_together() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "sessions-secret");
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let request = |method: &'static str, path: &str| {
+            let request = hyper::Request::builder()
+                .method(method)
+                .uri(format!("http://{}{}", addr, path))
+                .header("authorization", "Bearer sessions-secret")
+                .body(hyper::Body::empty())
+                .unwrap();
+            async move {
+                let response = hyper::Client::new().request(request).await.unwrap();
+                let status = response.status();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let (status, body) = request("PUT", "/workspaces/project-b").await;
+        assert_eq!((status, &body["name"]), (StatusCode::CREATED, &json!("project-b")));
+        assert_eq!(request("PUT", "/workspaces/project-b").await.0, StatusCode::OK);
+        let (status, body) = request("PUT", "/workspaces/a%20b").await;
+        assert_eq!((status, &body["error"]), (StatusCode::BAD_REQUEST, &json!("invalid_workspace")));
+        let (status, body) = request("GET", "/workspaces/project-c").await;
+        assert_eq!((status, &body["error"]), (StatusCode::NOT_FOUND, &json!("workspace_not_found")));
+        assert_eq!(request("POST", "/workspaces").await.0, StatusCode::METHOD_NOT_ALLOWED);
+
+        let defaults = session_defaults(Templates::default());
+        assert_eq!(SessionOptions::from_query(Some("workspace=a/b"), &defaults).unwrap_err().code(), "invalid_workspace");
+        let options = SessionOptions::from_query(Some("workspace=project-a"), &defaults).unwrap();
+        let mut panes = [TestClient::attach_with(&state, options.clone()), TestClient::attach_with(&state, options)];
+        let mut ids = Vec::new();
+        for pane in &mut panes {
+            let ServerMessage::Hello { session_id, .. } = pane.message().await else { panic!("expected hello") };
+            ids.push(session_id);
+        }
+        let mut outside = TestClient::attach(&state).await;
+
+        let (status, body) = request("GET", "/workspaces").await;
+        assert_eq!(status, StatusCode::OK);
+        let counts: Vec<_> = body["workspaces"].as_array().unwrap().iter().map(|w| (w["name"].clone(), w["sessions"].clone())).collect();
+        assert_eq!(counts, [(json!("project-a"), json!(2)), (json!("project-b"), json!(0))]);
+        let (_, body) = request("GET", "/workspaces/project-a").await;
+        let mut listed: Vec<_> = body["sessions"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap().to_string()).collect();
+        listed.sort();
+        ids.sort();
+        assert_eq!((&body["count"], &listed), (&json!(2), &ids));
+
+        let (status, body) = request("DELETE", "/workspaces/project-a").await;
+        assert_eq!((status, &body["removed"]), (StatusCode::OK, &json!(true)));
+        for pane in &mut panes {
+            let close = loop {
+                if let ServerFrame::Close(reason) = pane.recv().await {
+                    break reason;
+                }
+            };
+            assert_eq!(close, Some(CloseReason::AdminDisconnect));
+        }
+        assert_eq!(state.sessions.count().await, 1);
+        assert_eq!(request("GET", "/workspaces/project-a").await.0, StatusCode::NOT_FOUND);
+        outside.input("echo still-open\r");
+        outside.output_until("still-open\r\n").await;
+    }
+
+    #[tokio::test]
+    async fn hello_echoes_the_session_environment() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("term=xterm&lang=en_US.UTF-8&color=16"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { environment, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(
+            serde_json::to_value(&environment).unwrap(),
+            json!({ "term": "xterm", "lang": "en_US.UTF-8", "lc_all": null, "color": "16" })
+        );
+    }
+
+    #[tokio::test]
+    async fn the_shell_runs_with_the_term_and_locale_the_client_asked_for() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("term=dumb&lc_all=C"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        client.output_until(TEST_PROMPT).await;
+        client.input("echo \"env=$TERM:$LC_ALL:${COLORTERM-none}\"\n");
+        assert!(client.output_until("env=dumb:C:none").await.contains("env=dumb:C:none"));
+    }
+
+    fn session_defaults(templates: Templates) -> SessionDefaults {
+        SessionDefaults::from_config(&TerminalConfig::default()).unwrap().with_templates(templates)
+    }
+
+    fn rust_dev_templates() -> Templates {
+        let config: ForgeConfig = toml::from_str(r#"
+            [[templates]]
+            name = "rust-dev"
+            command = ["cat"]
+            lang = "en_US.UTF-8"
+            color = "16"
+            overridable = ["term"]
+            policy = { confirm_dangerous = false }
+        "#).unwrap();
+        Templates::from_config(&config.templates).unwrap()
+    }
+
+    #[tokio::test]
+    async fn templates_set_session_defaults_and_limit_overrides() {
+        let (state, _shutdown) = test_state();
+        let defaults = session_defaults(rust_dev_templates());
+        let code = |query| SessionOptions::from_query(Some(query), &defaults).unwrap_err().code();
+        assert_eq!(code("template=db-console"), "unknown_template");
+        assert_eq!(code("template=rust-dev&lang=C.UTF-8"), "not_overridable");
+
+        let options = SessionOptions::from_query(Some("template=rust-dev&term=xterm"), &defaults).unwrap();
+        assert_eq!(options.launch, Launch::Command(vec!["cat".to_string()]));
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { environment, template, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(template.as_deref(), Some("rust-dev"));
+        assert_eq!((environment.term.as_str(), environment.lang.as_str(), environment.color), ("xterm", "en_US.UTF-8", ColorSupport::Ansi16));
+        client.output().await;
+
+        // The template switched dangerous-command confirmation off, so the line reaches
+        // `cat`, which prints it back after the terminal's echo.
+        client.input("rm -rf / --no-preserve-root\n");
+        client.output_until("--no-preserve-root\r\nrm -rf /").await;
+        assert!(client.backlog.is_empty());
+    }
+
+    #[tokio::test]
+    async fn template_shells_start_in_their_cwd_with_their_env() {
+        let (state, _shutdown) = test_state();
+        let cwd = std::env::temp_dir().canonicalize().unwrap();
+        let config: ForgeConfig = toml::from_str(&format!(r#"
+            [[templates]]
+            name = "scratch"
+            shell = "sh"
+            cwd = "{}"
+            env = {{ FORGE_GREETING = "from-template" }}
+        "#, cwd.display())).unwrap();
+        let templates = Templates::from_config(&config.templates).unwrap();
+        let options = SessionOptions::from_query(Some("template=scratch"), &session_defaults(templates)).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        client.message().await;
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        client.input("echo \"$FORGE_GREETING:$(pwd):$TERM\"\n");
+        client.output_until(&format!("from-template:{}:xterm-256color", cwd.display())).await;
+    }
+
+    #[tokio::test]
+    async fn clients_pick_a_shell_from_the_allowed_list() {
+        let terminal = TerminalConfig { allowed_shells: vec!["dash".to_string()], ..TerminalConfig::default() };
+        let (state, _shutdown) = state_with_terminal(terminal);
+        let defaults = state.defaults.clone().with_templates(rust_dev_templates());
+        let error = SessionOptions::from_query(Some("shell=zsh"), &defaults).unwrap_err();
+        assert_eq!(ClientError::from(&error).message, format!("shell 'zsh' is not allowed; choose one of {}, dash", TEST_SHELL));
+        let code = |query| SessionOptions::from_query(Some(query), &defaults).unwrap_err().code();
+        assert_eq!(code("template=rust-dev&shell=dash"), "not_overridable");
+
+        let options = SessionOptions::from_query(Some("shell=dash"), &defaults).unwrap();
+        assert_eq!(options.launch, Launch::Shell("dash".to_string()));
+        let mut client = TestClient::attach_with(&state, options);
+        client.output_until(TEST_PROMPT).await;
+        client.input("echo \"shell=$0\"\n");
+        client.output_until("shell=dash").await;
+    }
+
+    #[tokio::test]
+    async fn connect_url_variables_reach_the_shell() {
+        let (state, _shutdown) = test_state();
+        let defaults = state.defaults.clone().with_templates(rust_dev_templates());
+        let code = |query| SessionOptions::from_query(Some(query), &defaults).unwrap_err().code();
+        assert_eq!(code("env=TMPDIR=/tmp"), "reserved_env");
+        assert_eq!(code("env=1X=y"), "invalid_env");
+        assert_eq!(code("env=TERM=xterm-kitty"), "invalid_term");
+        assert_eq!(code("template=rust-dev&env=FOO=bar"), "not_overridable");
+
+        let options = SessionOptions::from_query(Some("env=GREETING%3Dhello%20there&env=LANG=de_DE.UTF-8"), &defaults).unwrap();
+        assert_eq!(options.environment.lang, "de_DE.UTF-8");
+        let mut client = TestClient::attach_with(&state, options);
+        client.output_until(TEST_PROMPT).await;
+        client.input("echo \"[$GREETING] [$LANG]\"\n");
+        client.output_until("[hello there] [de_DE.UTF-8]").await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        assert_eq!(metadata.env.get("GREETING").map(String::as_str), Some("hello there"));
+        assert_eq!(metadata.env.get("TERM").map(String::as_str), Some("xterm-256color"));
+    }
+
+    #[tokio::test]
+    async fn setenv_updates_what_the_session_reports() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        let id = state.sessions.list().await[0].id.clone();
+
+        client.send(json!({ "type": "setenv", "env": { "NODE_ENV": "test", "COLORTERM": "256" } }));
+        let ServerMessage::Env { environment, env } = client.event().await else { panic!("expected env") };
+        assert_eq!(environment.color, ColorSupport::Ansi256);
+        assert_eq!(env, BTreeMap::from([("NODE_ENV".to_string(), "test".to_string())]));
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().env.get("NODE_ENV").map(String::as_str), Some("test"));
+
+        client.send(json!({ "type": "setenv", "env": { "NODE_ENV": null, "TMPDIR": "/tmp" } }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "reserved_env");
+        client.send(json!({ "type": "setenv", "env": { "NODE_ENV": null } }));
+        let ServerMessage::Env { env, .. } = client.event().await else { panic!("expected env") };
+        assert!(env.is_empty());
+    }
+
+    #[tokio::test]
+    async fn working_directory_follows_the_shell() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { cwd, .. } = client.message().await else { panic!("expected hello") };
+        assert!(cwd.is_some());
+        client.output_until(TEST_PROMPT).await;
+        let id = state.sessions.list().await[0].id.clone();
+
+        client.input("cd /\n");
+        let ServerMessage::Cwd { path, source } = client.event().await else { panic!("expected cwd") };
+        assert_eq!((path.as_str(), source), ("/", CwdSource::Proc));
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().cwd.as_deref(), Some("/"));
+
+        client.input("printf '\\033]7;file://devbox/srv/my%%20app\\007'\n");
+        let ServerMessage::Cwd { path, source } = client.event().await else { panic!("expected cwd") };
+        assert_eq!((path.as_str(), source), ("/srv/my app", CwdSource::Osc7));
+    }
+
+    #[tokio::test]
+    async fn base64_clients_get_the_bytes_the_shell_printed() {
+        let (state, _shutdown) = test_state();
+        let defaults = session_defaults(Templates::default());
+        assert_eq!(SessionOptions::from_query(Some("output=utf16"), &defaults).unwrap_err().code(), "invalid_output");
+        let mut client = TestClient::attach_with(&state, SessionOptions::from_query(Some("output=base64"), &defaults).unwrap());
+        let ServerMessage::Hello { output, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(output, OutputEncoding::Base64);
+
+        client.input("printf 'raw:\\377\\376:end\\n'\n");
+        let mut bytes = Vec::new();
+        while !bytes.windows(10).any(|window| window == b"raw:\xff\xfe:end") {
+            match client.message().await {
+                ServerMessage::OutputBytes { data, encoding, .. } => {
+                    assert_eq!(encoding, OutputEncoding::Base64);
+                    bytes.extend(data);
+                }
+                ServerMessage::Output { data, .. } => panic!("text output for a base64 client: {:?}", data),
+                _ => {}
+            }
+        }
+        let encoded = ServerMessage::OutputBytes { data: vec![0xff, b'o'], encoding: OutputEncoding::Binary, seq: None }.encode();
+        let encoded: serde_json::Value = serde_json::from_str(&encoded).unwrap();
+        assert_eq!((encoded["encoding"].as_str(), encoded["data"].as_str()), (Some("base64"), Some("/28=")));
+    }
+
+    #[tokio::test]
+    async fn binary_frames_carry_terminal_io_behind_a_channel_byte() {
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let (mut client, _) = connect_async(format!("ws://{}/?output=binary", addr)).await.unwrap();
+        let mut input = vec![TERMINAL_CHANNEL];
+        input.extend_from_slice(b"PS1=; echo bin-$((40+2))\r");
+        client.send(Message::Binary(input)).await.unwrap();
+        loop {
+            let Some(Ok(frame)) = client.next().await else { panic!("expected output") };
+            let Message::Binary(frame) = frame else { continue };
+            assert_eq!(frame[0], TERMINAL_CHANNEL);
+            if frame.windows(8).any(|window| window == b"bin-42\r\n") {
+                break;
+            }
+        }
+
+        let mut plain = TestClient::attach(&state).await;
+        plain.peer.tx.send(ClientFrame::Binary(b"\x07echo".to_vec())).unwrap();
+        plain.peer.tx.send(ClientFrame::Binary(vec![TERMINAL_CHANNEL, 0xff])).unwrap();
+        plain.peer.tx.send(ClientFrame::Binary([&[TERMINAL_CHANNEL][..], b"echo typed-$((6*7))\r"].concat())).unwrap();
+        plain.output_until("typed-42\r\n").await;
+        for _ in 0..2 {
+            let ServerMessage::Error(error) = plain.event().await else { panic!("expected an error") };
+            assert_eq!(error.code, "invalid_binary_frame");
+        }
+        plain.send(json!({ "type": "diagnostics" }));
+        let ServerMessage::Diagnostics { counters, .. } = plain.event().await else { panic!("expected diagnostics") };
+        assert_eq!(counters.malformed_messages, 2);
+    }
+
+    #[tokio::test]
+    async fn msgpack_subprotocol_carries_messages_as_binary_frames() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let mut request = format!("ws://{}/", addr).into_client_request().unwrap();
+        request.headers_mut().insert(http::header::SEC_WEBSOCKET_PROTOCOL, http::HeaderValue::from_static("v2.example, forge.msgpack"));
+        let (mut client, response) = connect_async(request).await.unwrap();
+        assert_eq!(response.headers()[http::header::SEC_WEBSOCKET_PROTOCOL], WireFormat::MSGPACK_SUBPROTOCOL);
+
+        let Some(Ok(Message::Binary(hello))) = client.next().await else { panic!("expected a binary frame") };
+        assert_eq!(hello[0], MESSAGE_CHANNEL);
+        assert_eq!(rmp_serde::from_slice::<serde_json::Value>(&hello[1..]).unwrap()["type"], "hello");
+
+        let input = rmp_serde::to_vec_named(&json!({ "type": "input", "data": "PS1=; echo mp-$((40+2))\r" })).unwrap();
+        client.send(Message::Binary([&[MESSAGE_CHANNEL][..], &input].concat())).await.unwrap();
+        let mut output = String::new();
+        while !output.contains("mp-42\r\n") {
+            let Some(Ok(Message::Binary(frame))) = client.next().await else { panic!("expected a binary frame") };
+            let message: serde_json::Value = rmp_serde::from_slice(&frame[1..]).unwrap();
+            if message["type"] == "output" {
+                output.push_str(message["data"].as_str().unwrap());
+            }
+        }
+
+        let (_, response) = connect_async(format!("ws://{}/", addr)).await.unwrap();
+        assert!(response.headers().get(http::header::SEC_WEBSOCKET_PROTOCOL).is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn mux_connections_carry_a_session_per_channel() {
+        let (state, _shutdown) = test_state();
+        let addr = listen(state.clone()).await;
+        let (mut client, _) = connect_async(format!("ws://{}{}", addr, MUX_PATH)).await.unwrap();
+        async fn next(client: &mut Client) -> serde_json::Value {
+            let frame = tokio::time::timeout(Duration::from_secs(5), client.next()).await.expect("no frame within 5s");
+            let Some(Ok(Message::Text(text))) = frame else { panic!("expected a text frame, got {:?}", frame) };
+            serde_json::from_str(&text).unwrap()
+        }
+        async fn send(client: &mut Client, msg: serde_json::Value) {
+            client.send(Message::Text(msg.to_string())).await.unwrap();
+        }
+
+        send(&mut client, json!({ "type": "open_channel", "channel": 1 })).await;
+        send(&mut client, json!({ "type": "open_channel", "channel": 2, "query": "name=second" })).await;
+        let mut sessions = HashMap::new();
+        while sessions.len() < 2 {
+            let msg = next(&mut client).await;
+            if msg["type"] == "hello" {
+                sessions.insert(msg["channel"].as_u64().unwrap(), msg["session_id"].as_str().unwrap().to_string());
+            }
+        }
+        assert_ne!(sessions[&1], sessions[&2]);
+        assert_eq!(state.sessions.get_metadata(&sessions[&2]).await.unwrap().name.as_deref(), Some("second"));
+
+        send(&mut client, json!({ "type": "open_channel", "channel": 1 })).await;
+        send(&mut client, json!({ "type": "input", "channel": 9, "data": "ls\r" })).await;
+        send(&mut client, json!({ "type": "input", "data": "ls\r" })).await;
+        let mut errors = Vec::new();
+        while errors.len() < 3 {
+            let msg = next(&mut client).await;
+            if msg["type"] == "error" {
+                errors.push((msg["code"].as_str().unwrap().to_string(), msg["channel"].as_u64()));
+            }
+        }
+        assert_eq!(
+            errors,
+            [("channel_in_use".to_string(), Some(1)), ("unknown_channel".to_string(), Some(9)), ("missing_channel".to_string(), None)]
+        );
+
+        send(&mut client, json!({ "type": "input", "channel": 1, "data": "echo one-$((0+1))\r" })).await;
+        send(&mut client, json!({ "type": "input", "channel": 2, "data": "echo two-$((1+1))\r" })).await;
+        let mut output = [String::new(), String::new()];
+        while !output[0].contains("one-1\r\n") || !output[1].contains("two-2\r\n") {
+            let msg = next(&mut client).await;
+            if msg["type"] == "output" {
+                output[msg["channel"].as_u64().unwrap() as usize - 1].push_str(msg["data"].as_str().unwrap());
+            }
+        }
+        assert!(!output[0].contains("two-2"), "channel 1 saw channel 2's output: {:?}", output[0]);
+
+        send(&mut client, json!({ "type": "close_channel", "channel": 1 })).await;
+        loop {
+            let msg = next(&mut client).await;
+            if msg["type"] == "channel_closed" {
+                assert_eq!(msg["channel"], 1);
+                break;
+            }
+        }
+        send(&mut client, json!({ "type": "input", "channel": 1, "data": "ls\r" })).await;
+        loop {
+            let msg = next(&mut client).await;
+            if msg["type"] == "error" {
+                assert_eq!((msg["code"].as_str(), msg["channel"].as_u64()), (Some("unknown_channel"), Some(1)));
+                break;
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn clients_behind_on_acks_pause_their_output() {
+        let (state, _shutdown) = test_state();
+        for query in ["ack_window=0", "ack_window=lots"] {
+            assert_eq!(SessionOptions::from_query(Some(query), &state.defaults).unwrap_err().code(), "invalid_ack_window");
+        }
+        let mut client = TestClient::attach_with(&state, SessionOptions { ack_window: Some(4096), ..Default::default() });
+        assert!(matches!(client.message().await, ServerMessage::Hello { .. }));
+        let mut output = String::new();
+        let mut sent = 0;
+        while !output.ends_with(TEST_PROMPT) {
+            if let ServerMessage::Output { data, seq } = client.message().await {
+                sent += data.len() as u64;
+                assert_eq!(seq, Some(sent));
+                output.push_str(&data);
+            }
+        }
+        client.send(json!({ "type": "ack", "seq": sent }));
+        let acked = sent;
+
+        client.input("head -c 200000 /dev/zero | tr '\\0' x; echo flood-done\r");
+        while let Ok(frame) = tokio::time::timeout(Duration::from_millis(300), client.peer.rx.recv()).await {
+            if let Some(ServerFrame::Message(ServerMessage::Output { seq: Some(seq), .. })) = frame {
+                sent = seq;
+            }
+        }
+        assert!(sent - acked < 50_000, "{} bytes sent past the last ack", sent - acked);
+
+        let mut output = String::new();
+        while !output.contains("flood-done\r\n") {
+            client.send(json!({ "type": "ack", "seq": sent }));
+            if let ServerMessage::Output { data, seq: Some(seq) } = client.message().await {
+                output.push_str(&data);
+                sent = seq;
+            }
+        }
+        assert!(sent - acked > 200_000);
+    }
+
+    #[tokio::test]
+    async fn sessions_start_at_the_size_in_the_connect_url() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig { screen_model: true, ..TerminalConfig::default() });
+        let defaults = session_defaults(Templates::default());
+        for query in ["cols=120", "cols=0&rows=40", "cols=wide&rows=40", "cols=120&rows=70000"] {
+            let error = SessionOptions::from_query(Some(query), &defaults).unwrap_err();
+            assert_eq!(error.code(), "invalid_size", "{}", query);
+        }
+
+        let options = SessionOptions::from_query(Some("cols=120&rows=40"), &defaults).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { size, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!((size.cols, size.rows), (120, 40));
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+        client.input("stty size\n");
+        client.output_until("40 120").await;
+        client.send(json!({ "type": "screen_snapshot" }));
+        let ServerMessage::ScreenSnapshot(snapshot) = client.event().await else { panic!("expected screen_snapshot") };
+        assert_eq!((snapshot.cols, snapshot.rows), (120, 40));
+
+        let mut plain = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { size, .. } = plain.message().await else { panic!("expected hello") };
+        assert_eq!(size, TerminalSize::default());
+    }
+
+    #[tokio::test]
+    async fn connect_url_tags_land_in_the_registry() {
+        let (state, _shutdown) = test_state();
+        let defaults = session_defaults(Templates::default());
+        assert_eq!(SessionOptions::from_query(Some("tag=nope"), &defaults).unwrap_err().code(), "invalid_tag");
+
+        let options = SessionOptions::from_query(Some("tag=purpose%3Abuild&tag=team:ci"), &defaults).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { session_id, .. } = client.message().await else { panic!("expected hello") };
+        let metadata = state.sessions.get_metadata(&session_id).await.unwrap();
+        assert_eq!(serde_json::to_value(&metadata.tags).unwrap(), json!({ "purpose": "build", "team": "ci" }));
+        assert_eq!(state.sessions.list_tagged(vec![("team".to_string(), "ci".to_string())]).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_named_at_connect_and_renamed() {
+        let (state, _shutdown) = test_state();
+        let defaults = session_defaults(Templates::default());
+        assert_eq!(SessionOptions::from_query(Some("name=%1B%5B31m"), &defaults).unwrap_err().code(), "invalid_session_name");
+
+        let options = SessionOptions::from_query(Some("name=build%20logs"), &defaults).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { session_id, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(state.sessions.get_metadata(&session_id).await.unwrap().name.as_deref(), Some("build logs"));
+
+        client.send(json!({ "type": "rename", "name": " logs " }));
+        let ServerMessage::Renamed { name } = client.event().await else { panic!("expected renamed") };
+        assert_eq!(name.as_deref(), Some("logs"));
+        assert_eq!(session_summaries(&state.sessions).await[0].name.as_deref(), Some("logs"));
+
+        client.send(json!({ "type": "rename", "name": "" }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "invalid_session_name");
+        client.send(json!({ "type": "rename", "name": null }));
+        assert!(matches!(client.event().await, ServerMessage::Renamed { name: None }));
+        assert_eq!(state.sessions.get_metadata(&session_id).await.unwrap().name, None);
+    }
+
+    #[tokio::test]
+    async fn session_info_describes_the_session() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("name=build"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { session_id, .. } = client.message().await else { panic!("expected hello") };
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+        client.resize(100, 30);
+        client.input("echo hi\r");
+        client.output_until("hi").await;
+
+        client.send(json!({ "type": "session_info" }));
+        let ServerMessage::SessionInfo(info) = client.event().await else { panic!("expected session info") };
+        assert_eq!((info.id.as_str(), info.name.as_deref()), (session_id.as_str(), Some("build")));
+        assert_eq!((info.size.cols, info.size.rows), (100, 30));
+        assert_eq!(info.bytes_in, "echo hi\r".len() as u64);
+        assert!(info.bytes_out > 0);
+        assert!(info.created_at <= info.last_activity);
+        assert_eq!((info.state, info.shared_clients), (SessionState::Running, 0));
+
+        let addr = listen(state.clone()).await;
+        let mut remote = connect(addr).await;
+        remote.send(Message::Text(json!({ "type": "session_info" }).to_string())).await.unwrap();
+        let info = loop {
+            let Some(Ok(Message::Text(text))) = remote.next().await else { panic!("expected session info") };
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if message["type"] == "session_info" {
+                break message;
+            }
+        };
+        assert_eq!(info["client_ip"], json!("127.0.0.1"));
+        assert_eq!(info["shell"], json!("sh"));
+    }
+
+    #[tokio::test]
+    async fn fetch_scrollback_pages_older_output() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("for i in 1 2 3; do echo line-$i; done\r");
+        client.output_until(&format!("line-3\r\n{}", TEST_PROMPT)).await;
+
+        client.send(json!({ "type": "fetch_scrollback", "count": 4 }));
+        let ServerMessage::Scrollback(newest) = client.event().await else { panic!("expected scrollback") };
+        assert_eq!(newest.lines, ["line-1\r", "line-2\r", "line-3\r", TEST_PROMPT]);
+        client.send(json!({ "type": "fetch_scrollback", "from": 1, "count": 1 }));
+        let ServerMessage::Scrollback(oldest) = client.event().await else { panic!("expected scrollback") };
+        assert_eq!((oldest.from, oldest.first_line, oldest.last_line), (1, 1, newest.last_line));
+        assert!(oldest.lines[0].contains("Welcome"), "the first line is the banner: {:?}", oldest.lines);
+    }
+
+    #[tokio::test]
+    async fn declared_capabilities_pick_the_environment_and_filter_output() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("capabilities=color256,unicode_width"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { environment, capabilities, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!((environment.term.as_str(), environment.color), ("xterm-256color", ColorSupport::Ansi256));
+        assert_eq!(serde_json::to_value(&capabilities).unwrap(), json!(["color256", "unicode_width"]));
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        client.input("printf '\\033[?1000;25h\\033[?2004h'\n");
+        let output = client.output_until("\x1b[?25h").await;
+        assert!(!output.contains("\x1b[?1000") && !output.contains("\x1b[?2004h"), "{:?}", output);
+
+        client.send(json!({ "type": "capabilities", "capabilities": ["mouse", "bracketed_paste", "hologram"] }));
+        let ServerMessage::Capabilities { capabilities, environment, output } = client.event().await else { panic!("expected capabilities") };
+        assert_eq!(serde_json::to_value(&capabilities).unwrap(), json!(["mouse", "bracketed_paste"]));
+        assert_eq!((environment.color, output), (ColorSupport::Ansi256, OutputEncoding::Text));
+        client.input("printf '\\033[?1000;25h\\033[?2004h'\n");
+        client.output_until("\x1b[?1000;25h\x1b[?2004h").await;
+    }
+
+    #[tokio::test]
+    async fn binary_output_waits_for_clients_to_declare_binary_frames() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("output=binary&capabilities=truecolor"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { output, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(output, OutputEncoding::Base64);
+        assert!(matches!(client.message().await, ServerMessage::OutputBytes { encoding: OutputEncoding::Base64, .. }));
+
+        client.send(json!({ "type": "capabilities", "capabilities": ["truecolor", "binary_frames"] }));
+        let ServerMessage::Capabilities { output, .. } = client.event().await else { panic!("expected capabilities") };
+        assert_eq!(output, OutputEncoding::Binary);
+        client.input("echo bin-$((40+2))\n");
+        loop {
+            let ServerMessage::OutputBytes { data, encoding, .. } = client.message().await else { continue };
+            assert_eq!(encoding, OutputEncoding::Binary);
+            if data.windows(8).any(|window| window == b"bin-42\r\n") {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn mouse_modes_are_reported_and_mouse_input_is_accepted() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { modes, .. } = client.message().await else { panic!("expected hello") };
+        assert!(modes.mouse.is_empty());
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        client.input("printf '\\033[?1000;1006h'\n");
+        assert!(matches!(client.event().await, ServerMessage::MouseMode { mode: 1000, enabled: true }));
+        assert!(matches!(client.event().await, ServerMessage::MouseMode { mode: 1006, enabled: true }));
+
+        // Mouse reports go to the PTY as typed; Ctrl-U clears them off the shell's line.
+        client.send(json!({ "type": "mouse", "data": "\x1b[<0;10;5M" }));
+        client.input("\x15printf '\\033c'\n");
+        assert!(matches!(client.event().await, ServerMessage::MouseMode { mode: 1000, enabled: false }));
+        assert!(matches!(client.event().await, ServerMessage::MouseMode { mode: 1006, enabled: false }));
+    }
+
+    #[tokio::test]
+    async fn clients_without_mouse_support_get_no_mouse_mode_events() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("capabilities=truecolor"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        client.message().await;
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+        client.input("printf '\\033[?1000h'; echo done\n");
+        let output = client.output_until("\ndone").await;
+        assert!(!output.contains("\x1b[?1000h"));
+        client.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(client.event().await, ServerMessage::Diagnostics { .. }));
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_closes_with_protocol_error() {
+        let (addr, _shutdown) = start_server().await;
+        let mut client = connect(addr).await;
+        let frame = Frame::message(vec![0xff, 0xfe, 0xfd], OpCode::Data(Data::Text), true);
+        client.send(Message::Frame(frame)).await.unwrap();
+        assert_eq!(close_frame(&mut client).await, (1002, "protocol_error".to_string()));
+    }
+
+    #[tokio::test]
+    async fn diagnostics_reports_protocol_anomalies() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        for msg in ["not json", r#"{"data":"no type"}"#, r#"{"type":"bogus"}"#, r#"{"type":"resize"}"#, r#"{"type":"diagnostics"}"#] {
+            client.send_raw(msg);
+        }
+
+        let mut codes = Vec::new();
+        for _ in 0..4 {
+            let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+            codes.push(error.code);
+        }
+        assert_eq!(codes, ["malformed_message", "missing_type", "unknown_type", "invalid_fields"]);
+        let ServerMessage::Diagnostics { counters, .. } = client.event().await else { panic!("no diagnostics reply") };
+        assert_eq!(serde_json::to_value(counters).unwrap(), json!({ "malformed_messages": 3, "unknown_types": 1, "oversized_frames": 0 }));
+    }
+
+    #[tokio::test]
+    async fn session_lifecycle_in_process() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        assert_eq!(state.sessions.count().await, 1);
+
+        client.resize(120, 40);
+        client.input("stty size\n");
+        client.output_until("40 120").await;
+        assert_eq!(client.detach().await, None);
+        assert_eq!(state.sessions.count().await, 0);
+
+        let mut again = TestClient::attach(&state).await;
+        again.input("echo $((6 * 7))\n");
+        again.output_until("42").await;
+        assert_eq!(state.sessions.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn resizes_reach_the_pty_and_the_session_metadata() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        let id = metadata.id.clone();
+        assert_eq!(metadata.size, TerminalSize::default());
+
+        client.resize(0, 40);
+        client.resize(132, 43);
+        client.input("stty size\n");
+        client.output_until("43 132").await;
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().size, TerminalSize { cols: 132, rows: 43 });
+    }
+
+    #[tokio::test]
+    async fn sessions_end_when_their_shell_exits() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("exit 3\n");
+        assert!(matches!(client.event().await, ServerMessage::Exit { reason: CloseReason::Normal, code: Some(3) }));
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::Normal))));
+        client.session.await.unwrap();
+        assert_eq!(state.sessions.count().await, 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn orphaned_sessions_close_after_their_grace_period() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            orphaned_io: OrphanedIoConfig { mode: OrphanedIoMode::Close, grace_secs: 1 },
+            ..Default::default()
+        });
+        let mut client = TestClient::attach(&state).await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        let id = metadata.id.clone();
+        client.input("sleep 1000 & exit\n");
+
+        let orphaned = async {
+            while state.sessions.get_metadata(&id).await.unwrap().state != SessionState::OrphanedIo {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), orphaned).await.expect("session never reported orphaned_io");
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().exit_code, Some(0));
+        assert!(matches!(client.event().await, ServerMessage::Exit { reason: CloseReason::Normal, code: Some(0) }));
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::Normal))));
+        client.session.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    fn lingering_state() -> (ServerState, watch::Sender<bool>) {
+        state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            disconnect: DisconnectConfig { linger_secs: 30, kill_grace_secs: 1 },
+            ..Default::default()
+        })
+    }
+
+    #[cfg(unix)]
+    /// Attaches a client and returns it with the session ID and resume token from its hello.
+    async fn attach_resumable(state: &ServerState) -> (TestClient, String, String) {
+        let mut client = TestClient::attach_raw(state);
+        let ServerMessage::Hello { session_id, resume_token, .. } = client.message().await else { panic!("expected hello") };
+        client.output_until(TEST_PROMPT).await;
+        (client, session_id, resume_token.expect("lingering sessions issue a resume token"))
+    }
+
+    #[cfg(unix)]
+    fn resume(state: &ServerState, session_id: &str, token: &str) -> TestClient {
+        let options = SessionOptions { resume: Some((session_id.to_string(), token.to_string())), ..Default::default() };
+        TestClient::attach_with(state, options)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn clients_that_stop_answering_pings_leave_their_session_lingering() {
+        let (state, _shutdown) = lingering_state();
+        let (transport, peer) = memory_pair();
+        let transport = transport.with_heartbeat(Some(Heartbeat { interval: Duration::from_millis(50), missed: 2 }));
+        let session = tokio::spawn(handle_terminal(transport, "memory".to_string(), SessionOptions::default(), state.clone()));
+        let mut client = TestClient { peer, session, backlog: VecDeque::new() };
+        let ServerMessage::Hello { session_id, .. } = client.message().await else { panic!("expected hello") };
+
+        let mut answered = 0;
+        while answered < 4 {
+            if let ServerFrame::Ping = client.recv().await {
+                client.peer.tx.send(ClientFrame::Pong).unwrap();
+                answered += 1;
+            }
+        }
+        assert_eq!(state.sessions.get_metadata(&session_id).await.unwrap().state, SessionState::Running);
+
+        // The connection is dropped without a close frame, which a dead client would miss.
+        let mut unanswered = 0;
+        let dropped = async {
+            while let Some(frame) = client.peer.rx.recv().await {
+                match frame {
+                    ServerFrame::Ping => unanswered += 1,
+                    ServerFrame::Message(_) => {}
+                    ServerFrame::Close(reason) => panic!("expected the connection dropped, got close {:?}", reason),
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), dropped).await.expect("connection never dropped");
+        assert_eq!(unanswered, 2);
+        let lingering = async {
+            while state.sessions.get_metadata(&session_id).await.unwrap().state != SessionState::Lingering {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), lingering).await.expect("session never reported lingering");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn clients_resume_a_lingering_session_with_its_token() {
+        let (state, _shutdown) = lingering_state();
+        let (mut client, id, token) = attach_resumable(&state).await;
+        client.input("FORGE_RESUMED=still-here\r");
+        client.output_until(TEST_PROMPT).await;
+        client.input("echo printed-while-away\r");
+        client.output_until(TEST_PROMPT).await;
+        client.peer.tx.send(ClientFrame::Close).unwrap();
+        assert!(matches!(client.recv().await, ServerFrame::Close(None)));
+        let lingering = async {
+            while state.sessions.get_metadata(&id).await.unwrap().state != SessionState::Lingering {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), lingering).await.expect("session never reported lingering");
+
+        let mut resumed = resume(&state, &id, &token);
+        let ServerMessage::Hello { session_id, .. } = resumed.message().await else { panic!("expected hello") };
+        assert_eq!(session_id, id);
+        let replayed = resumed.output_until("printed-while-away\r\n").await;
+        assert!(replayed.contains("Welcome"), "the replay should start with the banner: {:?}", replayed);
+        resumed.input("echo $FORGE_RESUMED\r");
+        resumed.output_until("still-here\r\n").await;
+        let metadata = state.sessions.get_metadata(&id).await.unwrap();
+        assert_eq!((metadata.attached, metadata.state), (true, SessionState::Running));
+        assert_eq!(state.sessions.count().await, 1);
+    }
+
+    #[cfg(unix)]
+    /// Detaches `client` and returns the token its session hands back.
+    async fn detach(state: &ServerState, client: &mut TestClient, id: &str) -> String {
+        client.send(json!({ "type": "detach" }));
+        let ServerMessage::Detached { session_id, resume_token } = client.event().await else { panic!("expected detached") };
+        assert_eq!(session_id, id);
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::Normal))));
+        let detached = async {
+            while state.sessions.get_metadata(id).await.unwrap().state != SessionState::Detached {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), detached).await.expect("session never reported detached");
+        resume_token
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn detached_sessions_keep_running_until_a_client_attaches() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, resume_token, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(resume_token, None, "sessions that do not linger issue no token up front");
+        client.output_until(TEST_PROMPT).await;
+        client.input("FORGE_DETACHED=kept\r");
+        client.output_until(TEST_PROMPT).await;
+        let token = detach(&state, &mut client, &id).await;
+        assert!(!state.sessions.get_metadata(&id).await.unwrap().attached);
+
+        let mut attached = resume(&state, &id, &token);
+        let ServerMessage::Hello { session_id, .. } = attached.message().await else { panic!("expected hello") };
+        assert_eq!(session_id, id);
+        attached.input("echo $FORGE_DETACHED\r");
+        attached.output_until("kept\r\n").await;
+        assert_eq!(state.sessions.get_metadata(&id).await.unwrap().state, SessionState::Running);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn attach_switches_the_connection_to_another_session() {
+        let (state, _shutdown) = test_state();
+        let mut other = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: other_id, .. } = other.message().await else { panic!("expected hello") };
+        other.output_until(TEST_PROMPT).await;
+        other.input("FORGE_OTHER=over-here\r");
+        other.output_until(TEST_PROMPT).await;
+        let token = detach(&state, &mut other, &other_id).await;
+
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, .. } = client.message().await else { panic!("expected hello") };
+        client.output_until(TEST_PROMPT).await;
+        client.send(json!({ "type": "attach", "session_id": other_id, "resume_token": "wrong" }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "resume_rejected");
+        client.send(json!({ "type": "attach", "session_id": id, "resume_token": "" }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "already_attached");
+
+        client.send(json!({ "type": "attach", "session_id": other_id, "resume_token": token }));
+        let ServerMessage::Hello { session_id, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(session_id, other_id);
+        client.input("echo $FORGE_OTHER\r");
+        client.output_until("over-here\r\n").await;
+        let left_behind = async {
+            while state.sessions.get_metadata(&id).await.unwrap().state != SessionState::Detached {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), left_behind).await.expect("the session switched from never reported detached");
+        assert!(!state.sessions.get_metadata(&id).await.unwrap().attached);
+        assert_eq!(state.sessions.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn sessions_saved_before_a_restart_are_restored_for_their_token() {
+        let (mut state, _shutdown) = state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            persistence: PersistenceConfig { path: Some(PathBuf::from("unused")) },
+            ..Default::default()
+        });
+        let mut before = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, resume_token, .. } = before.message().await else { panic!("expected hello") };
+        assert!(resume_token.is_some(), "saved sessions need a token to come back with");
+        before.output_until(TEST_PROMPT).await;
+        before.input("echo from-before-$((6*7))\r");
+        before.output_until("from-before-42\r\n").await;
+        let [saved] = &saved_sessions(&state.sessions).await[..] else { panic!("expected one saved session") };
+        assert_eq!((&saved.id, &saved.resume_token), (&id, resume_token.as_ref().unwrap()));
+        assert!(saved.scrollback.contains("from-before-42"));
+
+        let cwd = std::env::temp_dir().canonicalize().unwrap();
+        // The old session is still live here, unlike after a real restart, so it comes back
+        // under another ID.
+        let saved = SavedSession {
+            id: "restored-1".to_string(),
+            cwd: Some(cwd.display().to_string()),
+            env: BTreeMap::from([
+                ("FORGE_RESTORED".to_string(), "yes".to_string()),
+                ("PS1".to_string(), TEST_PROMPT.to_string()),
+            ]),
+            ..saved.clone()
+        };
+        state.restored = RestoredSessions::new(vec![saved.clone()]);
+        let options = SessionOptions { resume: Some((saved.id.clone(), saved.resume_token.clone())), ..Default::default() };
+        let mut after = TestClient::attach_with(&state, options);
+        let ServerMessage::Hello { session_id, .. } = after.message().await else { panic!("expected hello") };
+        assert_eq!(session_id, "restored-1");
+        after.output_until("from-before-42\r\n").await;
+        after.output_until(TEST_PROMPT).await;
+        after.input("echo $FORGE_RESTORED $PWD\r");
+        after.output_until(&format!("yes {}\r\n", cwd.display())).await;
+        assert!(state.restored.is_empty());
+        assert_eq!(state.sessions.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn shared_clients_see_the_output_and_type_into_the_session() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            max_shared_clients: 1,
+            ..Default::default()
+        });
+        let mut owner = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, share_token, .. } = owner.message().await else { panic!("expected hello") };
+        let token = share_token.expect("sharing is on");
+        owner.output_until(TEST_PROMPT).await;
+        let join = |token: &str| {
+            let options = SessionOptions { join: Some((id.clone(), token.to_string())), ..Default::default() };
+            TestClient::attach_with(&state, options)
+        };
+
+        let mut stranger = join("guess");
+        let ServerMessage::Error(error) = stranger.message().await else { panic!("expected error") };
+        assert_eq!(error.code, "share_rejected");
+
+        let mut guest = join(&token);
+        let ServerMessage::Hello { session_id, resume_token, .. } = guest.message().await else { panic!("expected hello") };
+        assert_eq!((session_id.as_str(), resume_token), (id.as_str(), None));
+        assert!(guest.output_until(TEST_PROMPT).await.contains("Welcome"), "guests start with a replay");
+        let mut crowd = join(&token);
+        let ServerMessage::Error(error) = crowd.message().await else { panic!("expected error") };
+        assert_eq!(error.code, "share_full");
+
+        guest.input("echo from-$((6*7))\r");
+        guest.output_until("from-42\r\n").await;
+        owner.output_until("from-42\r\n").await;
+        guest.resize(100, 30);
+        let error = loop {
+            if let ServerMessage::Error(error) = guest.event().await {
+                break error;
+            }
+        };
+        assert_eq!(error.code, "owner_only");
+
+        owner.input("exit\r");
+        let ServerMessage::Exit { reason, code } = guest.event().await else { panic!("expected exit") };
+        assert_eq!((reason, code), (CloseReason::Normal, Some(0)));
+        assert!(matches!(guest.recv().await, ServerFrame::Close(Some(CloseReason::Normal))));
+        guest.session.await.unwrap();
+        owner.session.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shared_sessions_name_their_participants_and_attribute_input() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            max_shared_clients: 2,
+            ..Default::default()
+        });
+        let mut owner = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id: id, share_token, participant, .. } = owner.message().await else { panic!("expected hello") };
+        assert_eq!(participant, None);
+        owner.output_until(TEST_PROMPT).await;
+        let join = || {
+            let options = SessionOptions { join: Some((id.clone(), share_token.clone().unwrap())), ..Default::default() };
+            TestClient::attach_with(&state, options)
+        };
+        let joined = |id: &str, color: &str| json!({ "type": "peer_joined", "participant": id, "color": color });
+
+        let mut first = join();
+        let ServerMessage::Hello { participant, .. } = first.message().await else { panic!("expected hello") };
+        assert_eq!(participant.map(|p| p.id), Some("p2".to_string()));
+        assert_eq!(first.peer_event().await.to_value(), joined("p1", PARTICIPANT_COLORS[0]));
+        assert_eq!(owner.peer_event().await.to_value(), joined("p2", PARTICIPANT_COLORS[1]));
+
+        let mut second = join();
+        assert!(matches!(second.message().await, ServerMessage::Hello { participant: Some(Participant { ref id, .. }), .. } if id == "p3"));
+        assert_eq!(second.peer_event().await.to_value(), joined("p1", PARTICIPANT_COLORS[0]));
+        assert_eq!(second.peer_event().await.to_value(), joined("p2", PARTICIPANT_COLORS[1]));
+        assert_eq!(owner.peer_event().await.to_value(), joined("p3", PARTICIPANT_COLORS[2]));
+        assert_eq!(first.peer_event().await.to_value(), joined("p3", PARTICIPANT_COLORS[2]));
+
+        // Typing within half a second of a summary goes out together in the next one.
+        let typed = |bytes: usize| json!({ "type": "input_attribution", "participant": "p2", "bytes": bytes });
+        first.input("echo hi\r");
+        assert_eq!(owner.peer_event().await.to_value(), typed(8));
+        assert_eq!(second.peer_event().await.to_value(), typed(8));
+        first.input("true");
+        first.input("\r");
+        assert_eq!(owner.peer_event().await.to_value(), typed(5));
+
+        second.peer.tx.send(ClientFrame::Close).unwrap();
+        second.session.await.unwrap();
+        assert_eq!(owner.peer_event().await.to_value(), json!({ "type": "peer_left", "participant": "p3" }));
+
+        owner.input("exit\r");
+        first.session.await.unwrap();
+        owner.session.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn resuming_an_attached_session_supersedes_its_client() {
+        let (state, _shutdown) = lingering_state();
+        let (mut client, id, token) = attach_resumable(&state).await;
+
+        let mut resumed = resume(&state, &id, &token);
+        assert!(matches!(resumed.message().await, ServerMessage::Hello { .. }));
+        loop {
+            if let ServerFrame::Close(reason) = client.recv().await {
+                assert_eq!(reason, Some(CloseReason::Superseded));
+                break;
+            }
+        }
+        resumed.input("echo taken-over\r");
+        resumed.output_until("taken-over\r\n").await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sessions_are_not_resumed_without_their_token() {
+        let (state, _shutdown) = lingering_state();
+        let (_client, id, _token) = attach_resumable(&state).await;
+        let mut wrong = resume(&state, &id, "not-the-token");
+        let ServerMessage::Error(error) = wrong.message().await else { panic!("expected an error") };
+        assert_eq!(error.code, "resume_rejected");
+        assert!(matches!(wrong.recv().await, ServerFrame::Close(Some(CloseReason::PolicyViolation))));
+
+        let mut unknown = resume(&state, "no-such-session", "not-the-token");
+        let ServerMessage::Error(error) = unknown.message().await else { panic!("expected an error") };
+        assert_eq!(error.code, "session_not_found");
+
+        // Sessions that do not linger hand out no token to resume with.
+        let (plain, _shutdown) = test_state();
+        let mut client = TestClient::attach_raw(&plain);
+        let ServerMessage::Hello { resume_token, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(resume_token, None);
+        assert_eq!(SessionOptions::from_query(Some("session_id=abc"), &plain.defaults).unwrap_err().code(), "invalid_resume");
+        assert_eq!(SessionOptions::from_query(Some("replay_bytes=lots"), &plain.defaults).unwrap_err().code(), "invalid_replay_bytes");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shells_linger_after_their_client_leaves_and_are_then_hung_up() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            disconnect: DisconnectConfig { linger_secs: 1, kill_grace_secs: 1 },
+            ..Default::default()
+        });
+        let mut client = TestClient::attach(&state).await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        let id = metadata.id.clone();
+        let pid = {
+            let [session] = &state.sessions.all().await[..] else { panic!("expected one session") };
+            let pid = session.lock().unwrap().pty.pid().unwrap();
+            pid as i32
+        };
+
+        client.peer.tx.send(ClientFrame::Close).unwrap();
+        assert!(matches!(client.recv().await, ServerFrame::Close(None)));
+        let lingering = async {
+            while state.sessions.get_metadata(&id).await.unwrap().state != SessionState::Lingering {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), lingering).await.expect("session never reported lingering");
+        assert!(!state.sessions.get_metadata(&id).await.unwrap().attached);
+        assert_eq!(unsafe { libc::kill(pid, 0) }, 0, "the shell should still run while the session lingers");
+
+        tokio::time::timeout(Duration::from_secs(3), client.session).await.expect("linger never ended").unwrap();
+        assert_eq!(state.sessions.count().await, 0);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while unsafe { libc::kill(pid, 0) } == 0 {
+            assert!(Instant::now() < deadline, "the shell survived being hung up");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn outputs_keep_order_and_notices_reach_idle_clients() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        for i in 0..100 {
+            client.input(&format!("printf 'out-%s\\n' {}\n", i));
+        }
+        let output = client.output_until("out-99\r\n").await;
+        let positions: Vec<usize> = (0..100).map(|i| output.find(&format!("out-{}\r\n", i)).unwrap()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        state.notices.broadcast(Notice::new(NoticeLevel::Info, "hello", None).unwrap());
+        let ServerMessage::Notice(notice) = client.event().await else { panic!("expected a notice") };
+        assert_eq!(notice.message, "hello");
+    }
+
+    #[tokio::test]
+    async fn killed_session_closes_its_client() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        assert!(metadata.attached);
+
+        state.sessions.kill(&metadata.id, CloseReason::AdminDisconnect).await.unwrap();
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::AdminDisconnect))));
+        client.session.await.unwrap();
+        assert_eq!(state.sessions.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn sessions_end_at_their_maximum_lifetime_even_when_busy() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions { max_lifetime: Some(Duration::from_millis(300)), ..Default::default() };
+        let mut client = TestClient::attach_with(&state, options);
+        client.message().await;
+        client.output().await;
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        assert!(metadata.expires_at.is_some_and(|at| at > metadata.created_at));
+
+        let mut outputs = 0;
+        loop {
+            // The session may already be gone by the time this is sent.
+            let _ = client.peer.tx.send(ClientFrame::Text(json!({ "type": "input", "data": "still busy" }).to_string()));
+            match client.message().await {
+                ServerMessage::Output { .. } => outputs += 1,
+                ServerMessage::Exit { reason, code } => {
+                    assert_eq!((reason, code), (CloseReason::MaxLifetime, None));
+                    break;
+                }
+                msg => panic!("unexpected {:?}", msg),
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(outputs > 0);
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::MaxLifetime))));
+        client.session.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chaos_settings_drop_and_close_a_sessions_messages() {
+        let (mut state, _shutdown) = test_state();
+        state.chaos = Chaos::enabled();
+        state.chaos.set(None, ChaosSettings { drop_every: 2, ..Default::default() }).unwrap();
+        // A program that prints nothing, so only replies are counted.
+        let options = SessionOptions { launch: Launch::Command(vec!["sleep".to_string(), "60".to_string()]), ..Default::default() };
+        let mut client = TestClient::attach_with(&state, options);
+        assert!(matches!(client.message().await, ServerMessage::Hello { .. }));
+        // The banner was the 2nd message, so this reply is the 3rd and the next one the 4th.
+        client.send(json!({ "type": "diagnostics" }));
+        client.send(json!({ "type": "diagnostics" }));
+        client.send(json!({ "type": "timings" }));
+        assert!(matches!(client.message().await, ServerMessage::Diagnostics { .. }));
+        assert!(matches!(client.message().await, ServerMessage::Timings(_)));
+
+        let [metadata] = &state.sessions.list().await[..] else { panic!("expected one session") };
+        state.chaos.set(Some(&metadata.id), ChaosSettings { close_code: Some(4999), ..Default::default() }).unwrap();
+        client.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::Chaos(4999)))));
+    }
+
+    #[tokio::test]
+    async fn banner_template_and_motd() {
+        let motd = std::env::temp_dir().join(format!("forge-motd-{}", Uuid::new_v4()));
+        std::fs::write(&motd, "maintenance at noon").unwrap();
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            banner: BannerConfig::Template("{{v{server_version}}} {peer_addr}\n".to_string()),
+            motd_file: Some(motd.clone()),
+            ..Default::default()
+        });
+
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { session_id, server_version, .. } = client.message().await else { panic!("expected hello") };
+        assert_eq!(server_version, SERVER_VERSION);
+        assert!(!session_id.is_empty());
+        assert_eq!(client.output().await, format!("{{v{}}} memory\nmaintenance at noon\n", SERVER_VERSION));
+
+        std::fs::write(&motd, "all clear\n").unwrap();
+        state.banner.reload_motd().unwrap();
+        assert!(state.banner.render("id", "peer").unwrap().ends_with("all clear\n"));
+        std::fs::remove_file(motd).unwrap();
+    }
+
+    #[tokio::test]
+    async fn disabled_banner_sends_only_hello() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            banner: BannerConfig::Enabled(false),
+            ..Default::default()
+        });
+        let mut client = TestClient::attach_raw(&state);
+        assert!(matches!(client.message().await, ServerMessage::Hello { .. }));
+        client.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(client.event().await, ServerMessage::Diagnostics { .. }));
+    }
+
+    #[test]
+    fn unknown_banner_variable_fails_at_load() {
+        for template in ["hi {user}", "hi {session_id", "hi }"] {
+            let config = TerminalConfig {
+                banner: BannerConfig::Template(template.to_string()),
+                ..Default::default()
+            };
+            assert!(Banner::from_config(&config).is_err(), "{}", template);
+        }
+    }
+
+    #[tokio::test]
+    async fn osc_133_markers_become_command_boundaries() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("printf 'done\\033]133;D;2\\007'\n");
+        let output = client.output_until("\ndone").await;
+        assert!(!output.contains("\x1b]133"));
+        let ServerMessage::CommandBoundary { phase, exit_code } = client.event().await else { panic!("expected a boundary") };
+        assert_eq!((phase.as_str(), exit_code), ("command_end", Some(2)));
+    }
+
+    #[tokio::test]
+    async fn prompt_event_follows_quiet_output() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig::default());
+        let mut client = TestClient::attach(&state).await;
+        assert!(matches!(client.event().await, ServerMessage::Prompt));
+
+        // OSC 133 markers take over from the heuristic for the rest of the session.
+        client.input("printf '\\033]133;A\\007'\n");
+        assert!(matches!(client.event().await, ServerMessage::CommandBoundary { .. }));
+        client.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(client.event().await, ServerMessage::Diagnostics { .. }));
+    }
+
+    #[tokio::test]
+    async fn long_commands_report_when_they_finish() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            long_commands: LongCommandConfig { threshold_secs: 0, ..Default::default() },
+            ..Default::default()
+        });
+        let mut client = TestClient::attach(&state).await;
+        assert!(matches!(client.event().await, ServerMessage::Prompt));
+        client.input("sleep 0.1\n");
+        assert!(matches!(client.event().await, ServerMessage::Prompt));
+        let ServerMessage::CommandFinished { command, exit_code, .. } = client.event().await else {
+            panic!("expected command_finished")
+        };
+        assert_eq!((command.as_str(), exit_code), ("sleep 0.1", None));
+    }
+
+    #[tokio::test]
+    async fn timings_table_is_cleared_with_the_scrollback() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("echo marker-$((1 + 1))\n");
+        client.output_until("marker-2").await;
+
+        client.send(json!({ "type": "timings" }));
+        let ServerMessage::Timings(timings) = client.event().await else { panic!("expected timings") };
+        assert_eq!(timings.commands.len(), 1);
+        assert_eq!((timings.commands[0].command.as_str(), timings.commands[0].duration_ms), ("echo marker-$((1 + 1))", None));
+
+        client.send(json!({ "type": "clear_scrollback" }));
+        client.send(json!({ "type": "search", "query": "marker-2" }));
+        let ServerMessage::SearchResults { results, .. } = client.event().await else { panic!("expected results") };
+        assert!(results.matches.is_empty());
+        client.send(json!({ "type": "timings" }));
+        let ServerMessage::Timings(timings) = client.event().await else { panic!("expected timings") };
+        assert!(timings.commands.is_empty());
+    }
+
+    #[tokio::test]
+    async fn output_streams_while_a_command_runs_without_more_input() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("for i in 1 2 3; do echo tick$i; sleep 0.3; done\n");
+        let first = client.output_until("tick1\r\n").await;
+        assert!(!first.contains("tick3"), "{:?}", first);
+        let started = Instant::now();
+        client.output_until("tick3\r\n").await;
+        assert!(started.elapsed() >= Duration::from_millis(400), "later ticks arrive as they are printed");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn sigint_stops_the_foreground_command_and_not_the_shell() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("sleep 30\n");
+        loop {
+            client.send(json!({ "type": "ps" }));
+            let ServerMessage::Processes(processes) = client.event().await else { panic!("expected ps") };
+            if processes.iter().any(|process| process.command.starts_with("sleep")) {
+                break;
+            }
+        }
+
+        client.send(json!({ "type": "signal", "signal": "SIGINT" }));
+        client.output_until(TEST_PROMPT).await;
+        client.input("echo alive-$((1 + 1))\n");
+        client.output_until("alive-2").await;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn background_jobs_are_listed_and_can_be_signalled() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.send(json!({ "type": "signal", "signal": "SIGTERM", "job": 1 }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "job_not_found");
+
+        client.input("sleep 1000 &\n");
+        let job = loop {
+            client.send(json!({ "type": "jobs" }));
+            let ServerMessage::Jobs(jobs) = client.event().await else { panic!("expected jobs") };
+            assert!(jobs.iter().all(|job| !job.command.starts_with(TEST_SHELL)), "{:?}", jobs);
+            if let Some(job) = jobs.into_iter().find(|job| job.command == "sleep 1000") {
+                break job;
+            }
+        };
+        assert!(!job.foreground);
+
+        client.send(json!({ "type": "signal", "signal": "SIGKILL", "job": job.pgid }));
+        loop {
+            tokio::time::sleep(process_tree::CACHE_TTL).await;
+            client.send(json!({ "type": "jobs" }));
+            let ServerMessage::Jobs(jobs) = client.event().await else { panic!("expected jobs") };
+            if jobs.iter().all(|listed| listed.pgid != job.pgid || listed.processes.iter().all(|process| process.state == "Z")) {
+                break;
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn ps_lists_the_sessions_shell() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.send(json!({ "type": "ps" }));
+        let ServerMessage::Processes(processes) = client.event().await else { panic!("expected ps") };
+        assert!(processes.iter().any(|process| process.command.starts_with(TEST_SHELL)), "{:?}", processes);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn sessions_over_their_resource_limits_are_warned_then_killed() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            resources: ResourceConfig {
+                interval_ms: Some(20),
+                warn_rss_bytes: Some(1),
+                kill_rss_bytes: Some(1),
+                kill_after_samples: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let mut client = TestClient::attach(&state).await;
+        // Stand in for the shell with this test process, which certainly uses memory.
+        let [session] = &state.sessions.all().await[..] else { panic!("expected one session") };
+        session.lock().unwrap().processes = Some(Arc::new(Mutex::new(ProcessSampler::new(std::process::id()))));
+        tokio::spawn(sample_resources(state.sessions.clone(), Duration::from_millis(20)));
+
+        let ServerMessage::ResourceWarning { limit_bytes, .. } = client.event().await else { panic!("expected resource_warning") };
+        assert_eq!(limit_bytes, 1);
+        let ServerMessage::Exit { reason, .. } = client.event().await else { panic!("expected exit") };
+        assert_eq!(reason, CloseReason::ResourceLimit);
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::ResourceLimit))));
+        client.session.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_are_swept_away() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        // Any quiet moment counts, so the shell's prompt can't keep the session alive.
+        let policy = IdlePolicy { timeout: Duration::ZERO, interval: Duration::from_millis(20) };
+        tokio::spawn(sweep_idle(state.sessions.clone(), policy));
+
+        let ServerMessage::Exit { reason, code } = client.event().await else { panic!("expected exit") };
+        assert_eq!((reason, code), (CloseReason::IdleTimeout, None));
+        assert!(matches!(client.event().await, ServerMessage::ReconnectHint { resumable: false, .. }));
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::IdleTimeout))));
+        client.session.await.unwrap();
+        assert!(state.sessions.all().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_tmpdirs_warn_over_their_cap_and_are_removed_at_the_end() {
+        let root = std::env::temp_dir().join(format!("forge-tmpdirs-{}", Uuid::new_v4()));
+        let (mut state, _shutdown) = test_state();
+        let config = TmpDirConfig { root: Some(root.clone()), max_bytes: Some(4), ..Default::default() };
+        state.defaults = state.defaults.clone().with_tmpdirs(TmpDirs::from_config(&config).unwrap());
+        let mut client = TestClient::attach(&state).await;
+        let dir = match &state.sessions.all().await[..] {
+            [session] => session.lock().unwrap().tmpdir.as_ref().unwrap().path().to_path_buf(),
+            _ => panic!("expected one session"),
+        };
+        assert_eq!(dir.parent(), Some(root.as_path()));
+        client.input("printf 'more than four bytes' > \"$TMPDIR/scratch\"; echo written\n");
+        client.output_until("\nwritten").await;
+
+        let checker = tokio::spawn(check_tmpdirs(state.sessions.clone(), Duration::from_millis(20)));
+        let ServerMessage::TmpdirWarning { bytes, limit_bytes } = client.event().await else { panic!("expected tmpdir_warning") };
+        assert_eq!((bytes, limit_bytes), (20, 4));
+        checker.abort();
+        let _ = checker.await;
+
+        client.detach().await;
+        assert!(!dir.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn captured_sessions_replay_to_the_same_message_types() {
+        let dir = std::env::temp_dir().join(format!("forge-capture-{}", Uuid::new_v4()));
+        let (mut state, _shutdown) = test_state();
+        state.capture_dir = Some(Arc::new(dir.clone()));
+        let script = [
+            json!({ "type": "input", "data": "export API_TOKEN=hunter2" }),
+            json!({ "type": "resize", "cols": 100, "rows": 30 }),
+            json!({ "type": "diagnostics" }),
+            json!({ "type": "timings" }),
+        ];
+        let baseline = TestClient::attach_raw(&state).play(&script).await;
+
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let capture = Capture::read(&file).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(capture.header.version, CAPTURE_VERSION);
+        assert_eq!(capture.messages.len(), script.len());
+        assert_eq!(capture.messages[0].message["data"], "export API_TOKEN=***REDACTED***");
+
+        state.capture_dir = None;
+        let messages: Vec<serde_json::Value> = capture.messages.iter().map(|captured| captured.message.clone()).collect();
+        let replayed = TestClient::attach_raw(&state).play(&messages).await;
+        protocol_capture::compare_baseline(&baseline, &replayed).unwrap();
+
+        let (addr, _server_shutdown) = start_server().await;
+        let over_ws = protocol_capture::replay(&format!("ws://{}/", addr), &capture, 0.0).await.unwrap();
+        protocol_capture::compare_baseline(&baseline, &over_ws).unwrap();
+    }
+
+    #[tokio::test]
+    async fn screen_snapshot_tracks_output_and_resize() {
+        let (state, _shutdown) = state_with_terminal(TerminalConfig {
+            banner: BannerConfig::Template("hello {peer_addr}\r\n".to_string()),
+            prompt_detection: PromptDetectionConfig { enabled: false, ..Default::default() },
+            screen_model: true,
+            ..Default::default()
+        });
+        let mut client = TestClient::attach_raw(&state);
+        client.message().await;
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        client.resize(40, 10);
+        client.send(json!({ "type": "screen_snapshot" }));
+        let ServerMessage::ScreenSnapshot(snapshot) = client.event().await else { panic!("expected a snapshot") };
+        assert_eq!((snapshot.rows, snapshot.cols), (10, 40));
+        assert_eq!(snapshot.lines.len(), 10);
+        assert_eq!(snapshot.lines[..2], ["hello memory".to_string(), TEST_PROMPT.to_string()]);
+        assert_eq!((snapshot.cursor.row, snapshot.cursor.col), (1, TEST_PROMPT.len() as u16));
+    }
+
+    #[tokio::test]
+    async fn screen_snapshot_without_model_is_an_error() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.send(json!({ "type": "screen_snapshot" }));
+        assert!(matches!(client.event().await, ServerMessage::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn search_finds_scrollback_matches() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("printf '\\033[31mERROR\\033[0m: disk full\\n'\n");
+        client.output_until(&format!("disk full\r\n{}", TEST_PROMPT)).await;
+
+        client.send(json!({ "type": "search", "query": "ERROR: disk" }));
+        let ServerMessage::SearchResults { results, .. } = client.event().await else { panic!("expected search results") };
+        let [found] = &results.matches[..] else { panic!("expected one match, got {:?}", results.matches) };
+        assert_eq!(&found.text[found.start..found.end], "ERROR: disk");
+        assert!(found.context_after[0].starts_with(TEST_PROMPT.trim_end()));
+        assert!(!results.truncated);
+
+        client.send(json!({ "type": "search", "query": "disk|forge", "regex": true, "max_results": 2 }));
+        let ServerMessage::SearchResults { results, .. } = client.event().await else { panic!("expected search results") };
+        assert_eq!(results.matches.len(), 2);
+        assert!(results.truncated);
+
+        client.send(json!({ "type": "search", "query": "(", "regex": true }));
+        assert!(matches!(client.event().await, ServerMessage::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn export_returns_txt_and_html_transcripts() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        client.input("printf '\\033[1;32m<b>green</b>\\033[0m\\n'\n");
+        client.output_until("</b>\x1b[0m").await;
+
+        client.send(json!({ "type": "export", "format": "txt" }));
+        let ServerMessage::Export { filename, content, .. } = client.event().await else { panic!("expected an export") };
+        assert!(filename.ends_with(".txt"));
+        assert!(content.starts_with("# Session: "));
+        assert!(content.contains(&format!("# Shell: {}\n", TEST_SHELL)));
+        assert!(content.contains("<b>green</b>"));
+
+        client.send(json!({ "type": "export", "format": "html" }));
+        let ServerMessage::Export { content, .. } = client.event().await else { panic!("expected an export") };
+        assert!(content.contains("<span style=\"color:#0dbc79;font-weight:bold\">&lt;b&gt;green&lt;/b&gt;</span>"));
+        assert!(!content.contains("<b>"));
+
+        client.send(json!({ "type": "export", "format": "pdf" }));
+        let ServerMessage::Error(error) = client.event().await else { panic!("expected an error") };
+        assert_eq!(error.code, "invalid_fields");
+        client.send(json!({ "type": "diagnostics" }));
+        let ServerMessage::Diagnostics { counters, .. } = client.event().await else { panic!("expected diagnostics") };
+        assert_eq!(counters.malformed_messages, 1);
+    }
+
+    #[tokio::test]
+    async fn announced_ports_are_reported_once_when_requested() {
+        let (state, _shutdown) = test_state();
+        let options = SessionOptions::from_query(Some("detect_ports=true"), &session_defaults(Templates::default())).unwrap();
+        let mut client = TestClient::attach_with(&state, options);
+        client.message().await;
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        // printf keeps the port out of the echoed command line.
+        client.input("printf 'Server listening on port %s\\n' 4321\n");
+        client.output_until("port 4321").await;
+        let ServerMessage::PortOpened { port, pid, hint } = client.event().await else { panic!("expected port_opened") };
+        assert_eq!((port, pid, hint), (4321, None, None));
+
+        client.input("printf 'Server listening on port %s\\n' 4321\n");
+        client.output_until("port 4321").await;
+        client.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(client.event().await, ServerMessage::Diagnostics { .. }));
+    }
+
+    #[tokio::test]
+    async fn links_are_reported_only_when_requested() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach_with(&state, SessionOptions::from_query(Some("detect_links=true"), &session_defaults(Templates::default())).unwrap());
+        client.message().await;
+        client.output().await;
+        client.output_until(TEST_PROMPT).await;
+
+        // The echoed command line carries the links first; the output may share its batch.
+        client.input("echo see https://example.com/docs, and src/main.rs:12:5\n");
+        let ServerMessage::Links(batch) = client.event().await else { panic!("expected links") };
+        let items = serde_json::to_value(&batch.items[..2]).unwrap();
+        assert_eq!(items, json!([
+            { "kind": "url", "text": "https://example.com/docs", "href": "https://example.com/docs" },
+            { "kind": "file", "text": "src/main.rs:12:5", "path": "src/main.rs", "line": 12, "col": 5 },
+        ]));
+
+        let mut plain = TestClient::attach(&state).await;
+        plain.input("echo https://example.com\n");
+        plain.output_until("\nhttps://example.com").await;
+        plain.send(json!({ "type": "diagnostics" }));
+        assert!(matches!(plain.event().await, ServerMessage::Diagnostics { .. }));
+    }
+
+    #[tokio::test]
+    async fn dangerous_input_waits_for_confirmation() {
+        let (state, _shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        // Typed in pieces; the guard sees the whole line when Enter arrives.
+        client.input("echo mk");
+        client.input("fs.ext4 /dev/sdz\n");
+        let ServerMessage::ConfirmRequired { pattern, token, .. } = client.event().await else { panic!("expected confirm_required") };
+        assert_eq!(pattern, "mkfs");
+        client.send(json!({ "type": "confirm", "token": token, "proceed": true }));
+        client.output_until("\nmkfs.ext4 /dev/sdz").await;
+
+        client.input("echo mkfs again\n");
+        let ServerMessage::ConfirmRequired { token, .. } = client.event().await else { panic!("expected confirm_required") };
+        client.send(json!({ "type": "confirm", "token": token, "proceed": false }));
+        client.input("echo after\n");
+        let output = client.output_until("\nafter").await;
+        assert!(!output.contains("\nmkfs again"), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_in_process_session() {
+        let (state, shutdown) = test_state();
+        let mut client = TestClient::attach(&state).await;
+        shutdown.send(true).unwrap();
+        let ServerFrame::Message(ServerMessage::ReconnectHint { retry_after_ms, resumable, resume_token }) = client.recv().await else {
+            panic!("expected reconnect_hint before the close")
+        };
+        assert!((1_000..=6_000).contains(&retry_after_ms));
+        assert!(!resumable && resume_token.is_none());
+        assert!(matches!(client.recv().await, ServerFrame::Close(Some(CloseReason::ServerShutdown))));
+    }
+
+    #[tokio::test]
+    async fn shutdown_hints_persisted_sessions_to_resume() {
+        let (state, shutdown) = state_with_terminal(TerminalConfig {
+            persistence: PersistenceConfig { path: Some(PathBuf::from("unused")) },
+            ..Default::default()
+        });
+        let mut client = TestClient::attach_raw(&state);
+        let ServerMessage::Hello { resume_token: issued, .. } = client.message().await else { panic!("expected hello") };
+        client.output_until(TEST_PROMPT).await;
+        shutdown.send(true).unwrap();
+        let ServerMessage::ReconnectHint { resumable, resume_token, .. } = client.event().await else { panic!("expected reconnect_hint") };
+        assert!(resumable);
+        assert_eq!(resume_token, issued);
+        assert!(resume_token.is_some());
+        let close = loop {
+            if let ServerFrame::Close(reason) = client.recv().await {
+                break reason;
+            }
+        };
+        assert_eq!(close, Some(CloseReason::ServerShutdown));
+    }
+
+    #[test]
+    fn only_transient_closes_suggest_a_retry() {
+        assert!(reconnect_hint(CloseReason::ServerFull, None).is_some());
+        assert!(reconnect_hint(CloseReason::IdleTimeout, None).is_some());
+        for reason in [CloseReason::PolicyViolation, CloseReason::AdminDisconnect, CloseReason::MaxLifetime, CloseReason::Normal] {
+            assert!(reconnect_hint(reason, None).is_none(), "{:?} should not be retried", reason);
+        }
+        // Only a restart brings a saved session back.
+        let Some(ServerMessage::ReconnectHint { resumable, .. }) = reconnect_hint(CloseReason::IdleTimeout, Some("token")) else { panic!("expected a hint") };
+        assert!(!resumable);
+        let delays: std::collections::BTreeSet<u64> = (0..20)
+            .filter_map(|_| CloseReason::ServerShutdown.retry_after())
+            .map(|delay| delay.as_millis() as u64)
+            .collect();
+        assert!(delays.len() > 1, "restart retries are not staggered");
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_with_1001() {
+        let (addr, shutdown) = start_server().await;
+        let mut client = connect(addr).await;
+        shutdown.send(true).unwrap();
+        assert_eq!(close_frame(&mut client).await, (1001, "server_shutdown".to_string()));
+    }
+}
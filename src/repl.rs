@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use uuid::Uuid;
+
+use crate::config::{InitScript, ReplConfig};
+use crate::session_tmp::{SessionTmpDir, TmpDirs};
+use crate::shell_env::ShellEnv;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplError {
+    #[error("REPL {0} not found")]
+    NotFound(String),
+    #[error("too many REPLs open (limit {0})")]
+    LimitReached(usize),
+    #[error("failed to start shell: {0}")]
+    Spawn(std::io::Error),
+    #[error("REPL shell exited")]
+    Exited,
+    #[error("command timed out after {0:?}; the REPL was closed")]
+    Timeout(Duration),
+    #[error("REPL I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to read init script {path}: {source}")]
+    InitScript {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("client-supplied init scripts are disabled (repl.allow_client_init)")]
+    ClientInitDisabled,
+}
+
+impl ReplError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ReplError::NotFound(_) => "repl_not_found",
+            ReplError::LimitReached(_) => "repl_limit_reached",
+            ReplError::Spawn(_) => "repl_spawn_failed",
+            ReplError::Exited => "repl_exited",
+            ReplError::Timeout(_) => "repl_timeout",
+            ReplError::Io(_) => "repl_io_failed",
+            ReplError::InitScript { .. } => "repl_init_script",
+            ReplError::ClientInitDisabled => "repl_client_init_disabled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplInfo {
+    pub id: String,
+    pub shell: String,
+    pub created_at: DateTime<Utc>,
+    /// What the init script printed, when there was one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_output: Option<ReplOutput>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplOutput {
+    pub output: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+struct ReplIo {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+}
+
+/// The init script as written for the shell to source; deleted with the REPL.
+struct InitFile(PathBuf);
+
+impl InitFile {
+    fn write(script: &str) -> Result<Self, ReplError> {
+        let path = std::env::temp_dir().join(format!("forge-init-{}.sh", Uuid::new_v4().simple()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&path)?, script.as_bytes())?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for InitFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+struct Repl {
+    info: ReplInfo,
+    _init_file: Option<InitFile>,
+    last_used: Mutex<Instant>,
+    /// Held for the whole exec so concurrent calls run one after another.
+    io: tokio::sync::Mutex<ReplIo>,
+    /// Last, so the shell is killed before its directory is removed.
+    _tmpdir: Option<SessionTmpDir>,
+}
+
+/// Persistent non-TTY shells addressed by id, so consecutive HTTP calls share
+/// shell state (cwd, exported variables).
+#[derive(Clone)]
+pub struct ReplManager {
+    config: Arc<ReplConfig>,
+    shell_env: Arc<ShellEnv>,
+    tmpdirs: Option<Arc<TmpDirs>>,
+    repls: Arc<Mutex<HashMap<String, Arc<Repl>>>>,
+}
+
+impl ReplManager {
+    pub fn new(config: ReplConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            shell_env: Arc::new(ShellEnv::default()),
+            tmpdirs: None,
+            repls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Replaces the default `[shell_env]` allowlist shells are started with.
+    pub fn with_shell_env(mut self, shell_env: ShellEnv) -> Self {
+        self.shell_env = Arc::new(shell_env);
+        self
+    }
+
+    /// Gives each shell its own `TMPDIR`; without this they share the system one.
+    pub fn with_tmpdirs(mut self, tmpdirs: Option<TmpDirs>) -> Self {
+        self.tmpdirs = tmpdirs.map(Arc::new);
+        self
+    }
+
+    /// Starts a shell and sources the configured init script, then `init` when the
+    /// config allows client-supplied scripts.
+    pub async fn create(&self, init: Option<&str>) -> Result<ReplInfo, ReplError> {
+        if self.repls.lock().unwrap().len() >= self.config.max_repls {
+            return Err(ReplError::LimitReached(self.config.max_repls));
+        }
+        if init.is_some() && !self.config.allow_client_init {
+            return Err(ReplError::ClientInitDisabled);
+        }
+        let init_file = match self.init_script(init)? {
+            Some(script) => Some(InitFile::write(&script)?),
+            None => None,
+        };
+
+        let id = Uuid::new_v4().to_string();
+        let tmpdir = match &self.tmpdirs {
+            Some(tmpdirs) => Some(tmpdirs.create(&id).map_err(ReplError::Spawn)?),
+            None => None,
+        };
+
+        let mut command = Command::new(&self.config.shell);
+        self.shell_env.apply(&mut command);
+        if let Some(tmpdir) = &tmpdir {
+            let (name, path) = tmpdir.env();
+            command.env(name, path);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(ReplError::Spawn)?;
+        let mut io = ReplIo {
+            stdin: child.stdin.take().ok_or(ReplError::Exited)?,
+            stdout: child.stdout.take().ok_or(ReplError::Exited)?,
+            stderr: child.stderr.take().ok_or(ReplError::Exited)?,
+            child,
+        };
+
+        let init_output = match &init_file {
+            Some(file) => {
+                let timeout = Duration::from_secs(self.config.exec_timeout_secs);
+                let source = source_command(&self.config.shell, &file.0);
+                match tokio::time::timeout(timeout, run_with_sentinel(&mut io, &source)).await {
+                    Ok(output) => Some(output?),
+                    Err(_) => return Err(ReplError::Timeout(timeout)),
+                }
+            }
+            None => None,
+        };
+
+        let info = ReplInfo {
+            id,
+            shell: self.config.shell.clone(),
+            created_at: Utc::now(),
+            init_output,
+        };
+        let repl = Arc::new(Repl {
+            info: info.clone(),
+            _init_file: init_file,
+            last_used: Mutex::new(Instant::now()),
+            io: tokio::sync::Mutex::new(io),
+            _tmpdir: tmpdir,
+        });
+        self.repls.lock().unwrap().insert(info.id.clone(), repl);
+        info!("🐚 REPL {} started ({})", info.id, info.shell);
+        Ok(info)
+    }
+
+    /// Runs `command` in the REPL's shell and collects everything it printed up to
+    /// an injected sentinel line carrying `$?`.
+    pub async fn exec(&self, id: &str, command: &str) -> Result<ReplOutput, ReplError> {
+        let repl = self.get(id)?;
+        let mut io = repl.io.lock().await;
+        *repl.last_used.lock().unwrap() = Instant::now();
+
+        let timeout = Duration::from_secs(self.config.exec_timeout_secs);
+        let result = tokio::time::timeout(timeout, run_with_sentinel(&mut io, command)).await;
+        *repl.last_used.lock().unwrap() = Instant::now();
+
+        match result {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(e)) => {
+                self.remove(id);
+                Err(e)
+            }
+            Err(_) => {
+                warn!("⏰ REPL {} command timed out, closing it", id);
+                let _ = io.child.start_kill();
+                self.remove(id);
+                Err(ReplError::Timeout(timeout))
+            }
+        }
+    }
+
+    pub fn close(&self, id: &str) -> Result<(), ReplError> {
+        self.remove(id).map(|_| ()).ok_or_else(|| ReplError::NotFound(id.to_string()))
+    }
+
+    /// Closes REPLs idle for longer than the configured timeout.
+    pub fn reap_idle(&self) -> usize {
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let mut repls = self.repls.lock().unwrap();
+        let before = repls.len();
+        repls.retain(|id, repl| {
+            let keep = repl.last_used.lock().unwrap().elapsed() < idle_timeout;
+            if !keep {
+                info!("🧹 Reaping idle REPL {}", id);
+            }
+            keep
+        });
+        before - repls.len()
+    }
+
+    /// Background task calling `reap_idle` periodically.
+    pub fn spawn_reaper(&self, interval: Duration) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                manager.reap_idle();
+            }
+        });
+    }
+
+    /// The configured script followed by the client's, or `None` when neither is set.
+    fn init_script(&self, init: Option<&str>) -> Result<Option<String>, ReplError> {
+        let configured = match &self.config.init_script {
+            Some(InitScript::Path(path)) => Some(std::fs::read_to_string(path).map_err(|source| ReplError::InitScript {
+                path: path.clone(),
+                source,
+            })?),
+            Some(InitScript::Inline(script)) => Some(script.clone()),
+            None => None,
+        };
+        Ok(match (configured, init) {
+            (Some(configured), Some(init)) => Some(format!("{}\n{}\n", configured, init)),
+            (configured, init) => configured.or(init.map(str::to_string)),
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Repl>, ReplError> {
+        self.repls
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ReplError::NotFound(id.to_string()))
+    }
+
+    fn remove(&self, id: &str) -> Option<ReplInfo> {
+        let removed = self.repls.lock().unwrap().remove(id);
+        removed.map(|repl| {
+            info!("🐚 REPL {} closed", id);
+            repl.info.clone()
+        })
+    }
+}
+
+/// How `shell` sources a file into the running session.
+fn source_command(shell: &str, path: &Path) -> String {
+    let quoted = format!("'{}'", path.display().to_string().replace('\'', r"'\''"));
+    match Path::new(shell).file_name().and_then(|name| name.to_str()) {
+        Some("fish") => format!("source {}", quoted),
+        _ => format!(". {}", quoted),
+    }
+}
+
+async fn run_with_sentinel(io: &mut ReplIo, command: &str) -> Result<ReplOutput, ReplError> {
+    let sentinel = format!("__FORGE_REPL_{}__", Uuid::new_v4().simple());
+    // The leading newline keeps the sentinel on its own line even when the
+    // command's output does not end with one; it is stripped again below.
+    let script = format!(
+        "{command}\n__forge_rc=$?; printf '\\n{sentinel} %d\\n' \"$__forge_rc\"; printf '\\n{sentinel}\\n' >&2\n"
+    );
+    io.stdin.write_all(script.as_bytes()).await?;
+    io.stdin.flush().await?;
+
+    let (stdout, stderr) = tokio::try_join!(
+        read_until_sentinel(&mut io.stdout, &sentinel),
+        read_until_sentinel(&mut io.stderr, &sentinel),
+    )?;
+
+    let exit_code = stdout.1.trim().parse().unwrap_or(-1);
+    Ok(ReplOutput {
+        output: stdout.0,
+        stderr: stderr.0,
+        exit_code,
+    })
+}
+
+/// Reads until `sentinel` appears, returning the text before it (minus the injected
+/// newline) and the remainder of the sentinel line.
+async fn read_until_sentinel<R: AsyncRead + Unpin>(reader: &mut R, sentinel: &str) -> Result<(String, String), ReplError> {
+    let marker = format!("\n{}", sentinel);
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(ReplError::Exited);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        let text = String::from_utf8_lossy(&buf);
+        if let Some(pos) = text.find(&marker) {
+            let rest = &text[pos + marker.len()..];
+            if let Some(end) = rest.find('\n') {
+                return Ok((text[..pos].to_string(), rest[..end].to_string()));
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+
+/// The last `max_bytes` of output a session sent its client, replayed to whoever resumes
+/// it so the screen is not blank. Older output is dropped a whole line at a time when a
+/// line break is close enough, and never in the middle of a UTF-8 character.
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer {
+    buf: VecDeque<u8>,
+    max_bytes: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { buf: VecDeque::new(), max_bytes }
+    }
+
+    pub fn push(&mut self, output: &[u8]) {
+        if self.max_bytes == 0 {
+            return;
+        }
+        self.buf.extend(output);
+        if self.buf.len() <= self.max_bytes {
+            return;
+        }
+        let excess = self.buf.len() - self.max_bytes;
+        let rest = self.buf.range(excess..);
+        let cut = match rest.clone().position(|&byte| byte == b'\n') {
+            Some(newline) => excess + newline + 1,
+            None => excess + rest.take_while(|&&byte| byte & 0xC0 == 0x80).count(),
+        };
+        self.buf.drain(..cut);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Everything retained, oldest first.
+    pub fn contents(&self) -> Vec<u8> {
+        self.buf.iter().copied().collect()
+    }
+}
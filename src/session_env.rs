@@ -0,0 +1,177 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ConfigError, EnvironmentConfig};
+
+/// The `color` hint; only `truecolor` sets `COLORTERM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSupport {
+    #[serde(rename = "truecolor")]
+    Truecolor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+impl ColorSupport {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "truecolor" | "24bit" => Some(ColorSupport::Truecolor),
+            "256" => Some(ColorSupport::Ansi256),
+            "16" => Some(ColorSupport::Ansi16),
+            _ => None,
+        }
+    }
+}
+
+/// Terminal-related environment a session's shell is started with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionEnvironment {
+    pub term: String,
+    pub lang: String,
+    pub lc_all: Option<String>,
+    pub color: ColorSupport,
+}
+
+impl SessionEnvironment {
+    /// Variables to set on the shell before exec.
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("TERM", self.term.clone()), ("LANG", self.lang.clone())];
+        if let Some(lc_all) = &self.lc_all {
+            vars.push(("LC_ALL", lc_all.clone()));
+        }
+        if self.color == ColorSupport::Truecolor {
+            vars.push(("COLORTERM", "truecolor".to_string()));
+        }
+        vars
+    }
+}
+
+impl Default for SessionEnvironment {
+    fn default() -> Self {
+        Self {
+            term: "xterm-256color".to_string(),
+            lang: "C.UTF-8".to_string(),
+            lc_all: None,
+            color: ColorSupport::Truecolor,
+        }
+    }
+}
+
+/// Per-session overrides as the client sent them, before validation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvironmentRequest {
+    pub term: Option<String>,
+    pub lang: Option<String>,
+    pub lc_all: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EnvironmentError {
+    #[error("term '{0}' is not in the allowed list")]
+    InvalidTerm(String),
+    #[error("'{0}' is not a valid locale")]
+    InvalidLocale(String),
+    #[error("color must be truecolor, 256 or 16, not '{0}'")]
+    InvalidColor(String),
+}
+
+impl EnvironmentError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EnvironmentError::InvalidTerm(_) => "invalid_term",
+            EnvironmentError::InvalidLocale(_) => "invalid_locale",
+            EnvironmentError::InvalidColor(_) => "invalid_color",
+        }
+    }
+}
+
+/// Defaults from `[terminal.environment]` plus the rules client overrides must pass.
+#[derive(Debug, Clone)]
+pub struct EnvironmentPolicy {
+    defaults: SessionEnvironment,
+    allowed_terms: Vec<String>,
+}
+
+impl EnvironmentPolicy {
+    pub fn from_config(config: &EnvironmentConfig) -> Result<Self, ConfigError> {
+        let policy = Self {
+            defaults: SessionEnvironment::default(),
+            allowed_terms: config.allowed_terms.clone(),
+        };
+        let defaults = policy
+            .resolve(&EnvironmentRequest {
+                term: Some(config.term.clone()),
+                lang: Some(config.lang.clone()),
+                lc_all: config.lc_all.clone(),
+                color: None,
+            })
+            .map_err(|e| ConfigError::Invalid(format!("terminal.environment: {}", e)))?;
+        Ok(Self {
+            defaults: SessionEnvironment { color: config.color, ..defaults },
+            ..policy
+        })
+    }
+
+    pub fn defaults(&self) -> &SessionEnvironment {
+        &self.defaults
+    }
+
+    pub fn allows_term(&self, term: &str) -> bool {
+        self.allowed_terms.iter().any(|allowed| allowed == term)
+    }
+
+    /// The defaults with `request` applied; anything invalid is an error, never a fallback.
+    pub fn resolve(&self, request: &EnvironmentRequest) -> Result<SessionEnvironment, EnvironmentError> {
+        self.update(&self.defaults, request)
+    }
+
+    /// `current` with `request` applied, by the same rules as `resolve`.
+    pub fn update(&self, current: &SessionEnvironment, request: &EnvironmentRequest) -> Result<SessionEnvironment, EnvironmentError> {
+        let mut env = current.clone();
+        if let Some(term) = &request.term {
+            if !self.allowed_terms.contains(term) {
+                return Err(EnvironmentError::InvalidTerm(term.clone()));
+            }
+            env.term = term.clone();
+            // A dumb terminal cannot show colors, so it does not get to claim truecolor.
+            if term == "dumb" && request.color.is_none() {
+                env.color = ColorSupport::Ansi16;
+            }
+        }
+        if let Some(lang) = &request.lang {
+            env.lang = valid_locale(lang)?;
+        }
+        if let Some(lc_all) = &request.lc_all {
+            env.lc_all = Some(valid_locale(lc_all)?);
+        }
+        if let Some(color) = &request.color {
+            env.color = ColorSupport::parse(color).ok_or_else(|| EnvironmentError::InvalidColor(color.clone()))?;
+        }
+        Ok(env)
+    }
+}
+
+impl Default for EnvironmentPolicy {
+    fn default() -> Self {
+        Self::from_config(&EnvironmentConfig::default()).expect("default terminal environment is valid")
+    }
+}
+
+/// `C`, `POSIX` or `language[_TERRITORY][.codeset][@modifier]`.
+fn valid_locale(locale: &str) -> Result<String, EnvironmentError> {
+    static LOCALE: OnceLock<Regex> = OnceLock::new();
+    let pattern = LOCALE.get_or_init(|| {
+        Regex::new(r"^(?:C|POSIX|C\.UTF-8|[a-z]{2,3}(?:_[A-Z]{2})?(?:\.[A-Za-z0-9-]{1,16})?(?:@[A-Za-z0-9]{1,16})?)$").unwrap()
+    });
+    if pattern.is_match(locale) {
+        Ok(locale.to_string())
+    } else {
+        Err(EnvironmentError::InvalidLocale(locale.to_string()))
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, watch};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use crate::chaos::{Chaos, ChaosSettings};
+use crate::error_catalog::ClientError;
+use crate::notices::{Notice, NoticeBus, NoticeLevel};
+use crate::protocol::{CloseReason, ServerMessage};
+use crate::scrollback::{ScrollbackPage, DEFAULT_FETCH_LINES};
+
+/// How many events the admin fan-out buffers before slow subscribers start losing them.
+pub const EVENT_BUFFER: usize = 256;
+
+/// Session activity as seen by the admin monitoring channel.
+/// Never carries terminal input or output, only metadata.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionEvent {
+    pub session_id: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: SessionEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum SessionEventKind {
+    #[serde(rename = "session_created")]
+    Created { peer_addr: String },
+    #[serde(rename = "session_attached")]
+    Attached { peer_addr: String },
+    #[serde(rename = "session_detached")]
+    Detached,
+    #[serde(rename = "session_exited")]
+    Exited,
+    #[serde(rename = "throughput")]
+    Throughput {
+        bytes_in: u64,
+        bytes_out: u64,
+        interval_ms: u64,
+    },
+    #[serde(rename = "protocol_error")]
+    ProtocolError { error: String },
+}
+
+/// Bounded broadcast of session events. Publishing never waits on subscribers;
+/// a subscriber that falls more than `EVENT_BUFFER` events behind skips ahead.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<SessionEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    pub fn publish(&self, session_id: &str, kind: SessionEventKind) {
+        if !self.has_subscribers() {
+            return;
+        }
+        let _ = self.tx.send(SessionEvent {
+            session_id: session_id.to_string(),
+            timestamp: Utc::now(),
+            kind,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.tx.subscribe()
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUFFER)
+    }
+}
+
+/// What the admin channel may do to live sessions, provided by the server running them.
+#[async_trait]
+pub trait AdminSessions: Send + Sync {
+    /// Closes the client attached to `session_id` with `admin_disconnect` and leaves the
+    /// session detached for its user to attach again; `Ok(false)` when no client was
+    /// attached. `admin` names who asked, for the audit log.
+    async fn disconnect(&self, session_id: &str, admin: &str) -> Result<bool, ClientError>;
+
+    /// The last `replay_lines` of `session_id`'s scrollback and its output from here on,
+    /// for `admin` to watch without being able to type into it.
+    async fn observe(&self, session_id: &str, replay_lines: usize, admin: &str) -> Result<Observation, ClientError>;
+}
+
+/// A session being watched over the admin channel.
+pub struct Observation {
+    pub replay: ScrollbackPage,
+    /// Output as the session's client gets it, ending with `exit`.
+    pub output: broadcast::Receiver<ServerMessage>,
+    /// Whether the session's client was told with `observer_joined`.
+    pub announced: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AdminCommand {
+    /// `{"subscribe":{"session_id":"..."}}` narrows the stream, `{"subscribe":{}}` widens it again.
+    Subscribe { session_id: Option<String> },
+    /// `{"broadcast":{"message":"...","level":"warning","countdown_secs":300}}`
+    Broadcast {
+        message: String,
+        level: Option<NoticeLevel>,
+        countdown_secs: Option<u64>,
+    },
+    /// `{"chaos":{"session_id":"...","settings":{"drop_every":3}}}`; only with `--chaos`.
+    /// Without `session_id` the settings apply to every session without its own.
+    Chaos {
+        session_id: Option<String>,
+        #[serde(default)]
+        settings: ChaosSettings,
+    },
+    /// `{"disconnect":{"session_id":"..."}}` closes the session's client but keeps the session.
+    Disconnect { session_id: String },
+    /// `{"observe":{"session_id":"...","replay_lines":200}}` streams the session's output,
+    /// in place of any session observed before; `{"unobserve":{}}` stops.
+    Observe {
+        session_id: String,
+        #[serde(default = "default_replay_lines")]
+        replay_lines: usize,
+    },
+    Unobserve {},
+}
+
+fn default_replay_lines() -> usize {
+    DEFAULT_FETCH_LINES
+}
+
+/// Streams session events to an authenticated admin WebSocket until it disconnects.
+/// Dropping the receiver on return is all the cleanup a subscription needs.
+pub async fn run_admin_channel<S>(
+    ws_stream: WebSocketStream<S>,
+    bus: EventBus,
+    notices: NoticeBus,
+    chaos: Chaos,
+    sessions: Arc<dyn AdminSessions>,
+    mut shutdown: watch::Receiver<bool>,
+    peer: String,
+)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let mut events = bus.subscribe();
+    let mut filter: Option<String> = None;
+    let mut observing: Option<(String, broadcast::Receiver<ServerMessage>)> = None;
+    let mut close_reason = None;
+
+    info!("🛰️ Admin monitor {} subscribed to session events", peer);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => {
+                close_reason = Some(CloseReason::ServerShutdown);
+                break;
+            }
+            event = events.recv() => {
+                let payload = match event {
+                    Ok(event) => {
+                        if filter.as_deref().is_some_and(|id| id != event.session_id) {
+                            continue;
+                        }
+                        serde_json::to_string(&event).unwrap_or_default()
+                    }
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("🐌 Admin monitor {} lagged, dropped {} events", peer, count);
+                        json!({ "type": "events_dropped", "count": count }).to_string()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if ws_sender.send(Message::Text(payload)).await.is_err() {
+                    break;
+                }
+            }
+            output = observed(&mut observing), if observing.is_some() => {
+                let Some((session_id, _)) = &observing else { continue };
+                let payload = match output {
+                    Ok(msg) => {
+                        let mut payload = msg.to_value();
+                        payload["session_id"] = session_id.as_str().into();
+                        if let ServerMessage::Exit { .. } = msg {
+                            info!("👁️ Session {} observed by {} ended", session_id, peer);
+                            observing = None;
+                        }
+                        payload
+                    }
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("🐌 Admin observer {} lagged, dropped {} messages of session {}", peer, count, session_id);
+                        json!({ "type": "output_dropped", "session_id": session_id, "count": count })
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        observing = None;
+                        continue;
+                    }
+                };
+                if ws_sender.send(Message::Text(payload.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            msg = ws_receiver.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = match serde_json::from_str::<AdminCommand>(&text) {
+                            Ok(AdminCommand::Subscribe { session_id }) => {
+                                info!("🛰️ Admin monitor {} filter: {:?}", peer, session_id);
+                                filter = session_id;
+                                json!({ "type": "subscribed", "session_id": filter })
+                            }
+                            Ok(AdminCommand::Broadcast { message, level, countdown_secs }) => {
+                                let level = level.unwrap_or(NoticeLevel::Info);
+                                match Notice::new(level, &message, countdown_secs) {
+                                    Some(notice) => {
+                                        let delivered = notices.broadcast(notice);
+                                        info!("📢 Admin {} broadcast a {:?} notice to {} connections", peer, level, delivered);
+                                        json!({ "type": "broadcast_sent", "delivered": delivered })
+                                    }
+                                    None => error_message(ClientError::new("notice_empty")),
+                                }
+                            }
+                            Ok(AdminCommand::Chaos { session_id, settings }) => match chaos.set(session_id.as_deref(), settings.clone()) {
+                                Ok(()) => json!({ "type": "chaos_set", "session_id": session_id, "settings": settings }),
+                                Err(e) => error_message(ClientError::from(&e)),
+                            },
+                            Ok(AdminCommand::Disconnect { session_id }) => match sessions.disconnect(&session_id, &format!("admin {}", peer)).await {
+                                Ok(was_attached) => json!({ "type": "disconnected", "session_id": session_id, "was_attached": was_attached }),
+                                Err(error) => error_message(error),
+                            },
+                            Ok(AdminCommand::Observe { session_id, replay_lines }) => match sessions.observe(&session_id, replay_lines, &format!("admin {}", peer)).await {
+                                Ok(observation) => {
+                                    let reply = json!({
+                                        "type": "observing",
+                                        "session_id": session_id,
+                                        "announced": observation.announced,
+                                        "from": observation.replay.from,
+                                        "lines": observation.replay.lines
+                                    });
+                                    observing = Some((session_id, observation.output));
+                                    reply
+                                }
+                                Err(error) => error_message(error),
+                            },
+                            Ok(AdminCommand::Unobserve {}) => {
+                                let session_id = observing.take().map(|(session_id, _)| session_id);
+                                if let Some(session_id) = &session_id {
+                                    info!("👁️ Admin {} stopped observing session {}", peer, session_id);
+                                }
+                                json!({ "type": "unobserved", "session_id": session_id })
+                            }
+                            Err(e) => {
+                                warn!("⚠️ Bad admin command from {}: {}", peer, e);
+                                error_message(ClientError::new("invalid_admin_command").with("error", e))
+                            }
+                        };
+                        if ws_sender.send(Message::Text(reply.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => debug!("🔧 Ignoring non-text admin frame from {}", peer),
+                    Some(Err(e)) => {
+                        warn!("❌ Admin monitor {} socket error: {}", peer, e);
+                        close_reason = CloseReason::for_error(&e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(reason) = close_reason {
+        let _ = ws_sender.send(Message::Close(Some(reason.frame()))).await;
+    }
+    let _ = ws_sender.close().await;
+    info!("🛰️ Admin monitor {} disconnected, subscription dropped", peer);
+}
+
+/// The next message of the observed session; only polled while there is one.
+async fn observed(observing: &mut Option<(String, broadcast::Receiver<ServerMessage>)>) -> Result<ServerMessage, broadcast::error::RecvError> {
+    match observing {
+        Some((_, output)) => output.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn error_message(error: ClientError) -> serde_json::Value {
+    json!({ "type": "error", "code": error.code, "message": error.message, "params": error.params })
+}
@@ -0,0 +1,24 @@
+pub const MAX_NAME_LEN: usize = 64;
+
+/// What a session is called in a tab bar, like `build` or `logs`; its ID stays the UUID.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("session name '{0}' must be 1-{MAX_NAME_LEN} letters, digits, spaces or any of _-.:/@+#()")]
+pub struct NameError(pub String);
+
+impl NameError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        "invalid_session_name"
+    }
+}
+
+/// `name` without surrounding whitespace, if it is a valid session name.
+pub fn parse(name: &str) -> Result<String, NameError> {
+    let trimmed = name.trim();
+    let ok = (1..=MAX_NAME_LEN).contains(&trimmed.chars().count())
+        && trimmed.chars().all(|c| c.is_alphanumeric() || c == ' ' || "_-.:/@+#()".contains(c));
+    if !ok {
+        return Err(NameError(name.to_string()));
+    }
+    Ok(trimmed.to_string())
+}
@@ -0,0 +1,365 @@
+use std::collections::{hash_map, BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::protocol::CloseReason;
+use crate::screen::TerminalSize;
+use crate::session_tags::{self, SessionTags};
+
+/// Requests the registry actor may queue before callers start waiting.
+const COMMAND_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RegistryError {
+    #[error("session {0} not found")]
+    NotFound(String),
+    #[error("session {0} already exists")]
+    AlreadyExists(String),
+    #[error("session {0} already has a client attached")]
+    AlreadyAttached(String),
+    #[error("the server already has its maximum of {0} sessions")]
+    Full(usize),
+}
+
+impl RegistryError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RegistryError::NotFound(_) => "session_not_found",
+            RegistryError::AlreadyExists(_) => "session_exists",
+            RegistryError::AlreadyAttached(_) => "session_attached",
+            RegistryError::Full(_) => "server_full",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    Running,
+    /// The shell exited, but a process it left behind still holds the PTY open.
+    OrphanedIo,
+    /// The client disconnected; the shell runs on until `[terminal.disconnect]` linger ends.
+    Lingering,
+    /// The client detached; the session runs on until a client attaches to it again.
+    Detached,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionMetadata {
+    pub id: String,
+    /// A label for tab bars, from the connect URL's `name=` or a later `rename`.
+    pub name: Option<String>,
+    /// The workspace the session was opened in, to be listed and closed with it.
+    pub workspace: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub attached: bool,
+    pub peer_addr: Option<String>,
+    /// Set once at creation from `[terminal.max_lifetime]`; attaching never moves it.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub tags: SessionTags,
+    pub state: SessionState,
+    /// The PTY's size as of the last resize, for clients joining later.
+    pub size: TerminalSize,
+    /// Variables the session set on its shell beyond the server's allowlist, as last
+    /// updated by the client.
+    pub env: BTreeMap<String, String>,
+    /// The shell's working directory as last seen.
+    pub cwd: Option<String>,
+    /// Set once the shell exits; the session lives on while its leftovers hold the PTY.
+    pub exit_code: Option<u32>,
+}
+
+impl SessionMetadata {
+    /// A running, detached session created now, with nothing else known about it yet.
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            name: None,
+            workspace: None,
+            created_at: Utc::now(),
+            attached: false,
+            peer_addr: None,
+            expires_at: None,
+            tags: SessionTags::new(),
+            state: SessionState::Running,
+            size: TerminalSize::default(),
+            env: BTreeMap::new(),
+            cwd: None,
+            exit_code: None,
+        }
+    }
+}
+
+/// Fires once when the session is killed through the registry.
+pub type KillSignal = oneshot::Receiver<CloseReason>;
+
+struct Entry<S> {
+    metadata: SessionMetadata,
+    session: S,
+    kill: Option<oneshot::Sender<CloseReason>>,
+}
+
+/// A slot held against `max_sessions` while a session's shell starts, so a full server
+/// refuses before spawning anything. Dropping it unused gives the slot back.
+#[derive(Debug)]
+pub struct Reservation {
+    reserved: Arc<AtomicUsize>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.reserved.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+type Reply<T> = oneshot::Sender<T>;
+type Update = Box<dyn FnOnce(&mut SessionMetadata) + Send>;
+
+enum Command<S> {
+    Reserve { reply: Reply<Result<Reservation, RegistryError>> },
+    Create { metadata: SessionMetadata, session: S, reservation: Option<Reservation>, reply: Reply<Result<KillSignal, RegistryError>> },
+    Attach { id: String, peer_addr: String, reply: Reply<Result<SessionMetadata, RegistryError>> },
+    Detach { id: String, reply: Reply<Result<SessionMetadata, RegistryError>> },
+    Remove { id: String, reply: Reply<Result<SessionMetadata, RegistryError>> },
+    Kill { id: String, reason: CloseReason, reply: Reply<Result<(), RegistryError>> },
+    GetMetadata { id: String, reply: Reply<Result<SessionMetadata, RegistryError>> },
+    Update { id: String, update: Update, reply: Reply<Result<SessionMetadata, RegistryError>> },
+    Get { id: String, reply: Reply<Result<S, RegistryError>> },
+    List { filter: Vec<(String, String)>, reply: Reply<Vec<SessionMetadata>> },
+    All { reply: Reply<Vec<S>> },
+    Count { reply: Reply<usize> },
+}
+
+/// Handle to the task that owns every live session. The map is only ever touched
+/// by that task, so no lock is held across an `.await` anywhere else.
+pub struct SessionRegistry<S> {
+    tx: mpsc::Sender<Command<S>>,
+}
+
+impl<S> Clone for SessionRegistry<S> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone() }
+    }
+}
+
+impl<S: Clone + Send + 'static> SessionRegistry<S> {
+    /// Spawns the registry task; it stops once every handle is dropped.
+    pub fn new() -> Self {
+        Self::with_limit(None)
+    }
+
+    /// Like `new`, refusing to create sessions beyond `max_sessions` with `Full`.
+    pub fn with_limit(max_sessions: Option<usize>) -> Self {
+        let (tx, rx) = mpsc::channel(COMMAND_BUFFER);
+        tokio::spawn(run(rx, max_sessions));
+        Self { tx }
+    }
+
+    /// Holds a slot for a session about to start; `Full` when none is left.
+    pub async fn reserve(&self) -> Result<Reservation, RegistryError> {
+        self.call(|reply| Command::Reserve { reply }).await
+    }
+
+    /// Registers a detached session under `id`.
+    pub async fn create(&self, id: String, session: S) -> Result<KillSignal, RegistryError> {
+        self.create_with(SessionMetadata::new(id), session).await
+    }
+
+    /// Like `create`, registering the session with everything `metadata` says about it at once.
+    pub async fn create_with(&self, metadata: SessionMetadata, session: S) -> Result<KillSignal, RegistryError> {
+        self.call(|reply| Command::Create { metadata, session, reservation: None, reply }).await
+    }
+
+    /// Like `create_with`, filling the slot `reservation` holds instead of taking a new one.
+    pub async fn create_reserved(&self, reservation: Reservation, metadata: SessionMetadata, session: S) -> Result<KillSignal, RegistryError> {
+        self.call(|reply| Command::Create { metadata, session, reservation: Some(reservation), reply }).await
+    }
+
+    pub async fn attach(&self, id: &str, peer_addr: String) -> Result<SessionMetadata, RegistryError> {
+        self.call(|reply| Command::Attach { id: id.to_string(), peer_addr, reply }).await
+    }
+
+    pub async fn detach(&self, id: &str) -> Result<SessionMetadata, RegistryError> {
+        self.call(|reply| Command::Detach { id: id.to_string(), reply }).await
+    }
+
+    pub async fn remove(&self, id: &str) -> Result<SessionMetadata, RegistryError> {
+        self.call(|reply| Command::Remove { id: id.to_string(), reply }).await
+    }
+
+    /// Asks the session to close its client with `reason`; the session removes itself.
+    pub async fn kill(&self, id: &str, reason: CloseReason) -> Result<(), RegistryError> {
+        self.call(|reply| Command::Kill { id: id.to_string(), reason, reply }).await
+    }
+
+    pub async fn get_metadata(&self, id: &str) -> Result<SessionMetadata, RegistryError> {
+        self.call(|reply| Command::GetMetadata { id: id.to_string(), reply }).await
+    }
+
+    /// Applies `update` to the session's metadata. Callers validate names and tags with
+    /// `session_names` and `session_tags` first.
+    pub async fn update(&self, id: &str, update: impl FnOnce(&mut SessionMetadata) + Send + 'static) -> Result<SessionMetadata, RegistryError> {
+        self.call(|reply| Command::Update { id: id.to_string(), update: Box::new(update), reply }).await
+    }
+
+    pub async fn get(&self, id: &str) -> Result<S, RegistryError> {
+        self.call(|reply| Command::Get { id: id.to_string(), reply }).await
+    }
+
+    pub async fn list(&self) -> Vec<SessionMetadata> {
+        self.list_tagged(Vec::new()).await
+    }
+
+    /// Sessions carrying every `key:value` pair in `filter`.
+    pub async fn list_tagged(&self, filter: Vec<(String, String)>) -> Vec<SessionMetadata> {
+        self.call(|reply| Command::List { filter, reply }).await
+    }
+
+    pub async fn all(&self) -> Vec<S> {
+        self.call(|reply| Command::All { reply }).await
+    }
+
+    pub async fn count(&self) -> usize {
+        self.call(|reply| Command::Count { reply }).await
+    }
+
+    async fn call<T>(&self, command: impl FnOnce(Reply<T>) -> Command<S>) -> T {
+        let (reply, rx) = oneshot::channel();
+        // The task only exits once every handle, including this one, is gone.
+        self.tx
+            .send(command(reply))
+            .await
+            .unwrap_or_else(|_| panic!("session registry task stopped"));
+        rx.await.expect("session registry task dropped a reply")
+    }
+}
+
+impl<S: Clone + Send + 'static> Default for SessionRegistry<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn run<S: Clone>(mut rx: mpsc::Receiver<Command<S>>, max_sessions: Option<usize>) {
+    let mut sessions: HashMap<String, Entry<S>> = HashMap::new();
+    let reserved = Arc::new(AtomicUsize::new(0));
+    while let Some(command) = rx.recv().await {
+        match command {
+            Command::Reserve { reply } => {
+                let count = sessions.len() + reserved.load(Ordering::SeqCst);
+                let result = match max_sessions {
+                    Some(max) if count >= max => Err(RegistryError::Full(count)),
+                    _ => {
+                        reserved.fetch_add(1, Ordering::SeqCst);
+                        Ok(Reservation { reserved: reserved.clone() })
+                    }
+                };
+                let _ = reply.send(result);
+            }
+            Command::Create { metadata, session, reservation, reply } => {
+                let count = sessions.len() + reserved.load(Ordering::SeqCst);
+                let full = reservation.is_none() && max_sessions.is_some_and(|max| count >= max);
+                let result = match sessions.entry(metadata.id.clone()) {
+                    hash_map::Entry::Occupied(occupied) => Err(RegistryError::AlreadyExists(occupied.key().clone())),
+                    hash_map::Entry::Vacant(_) if full => Err(RegistryError::Full(count)),
+                    hash_map::Entry::Vacant(vacant) => {
+                        let (kill, signal) = oneshot::channel();
+                        vacant.insert(Entry { metadata, session, kill: Some(kill) });
+                        Ok(signal)
+                    }
+                };
+                let _ = reply.send(result);
+            }
+            Command::Attach { id, peer_addr, reply } => {
+                let result = match sessions.get_mut(&id) {
+                    Some(entry) if entry.metadata.attached => Err(RegistryError::AlreadyAttached(id)),
+                    Some(entry) => {
+                        entry.metadata.attached = true;
+                        entry.metadata.peer_addr = Some(peer_addr);
+                        Ok(entry.metadata.clone())
+                    }
+                    None => Err(RegistryError::NotFound(id)),
+                };
+                let _ = reply.send(result);
+            }
+            Command::Detach { id, reply } => {
+                let result = match sessions.get_mut(&id) {
+                    Some(entry) => {
+                        entry.metadata.attached = false;
+                        entry.metadata.peer_addr = None;
+                        Ok(entry.metadata.clone())
+                    }
+                    None => Err(RegistryError::NotFound(id)),
+                };
+                let _ = reply.send(result);
+            }
+            Command::Remove { id, reply } => {
+                let result = sessions
+                    .remove(&id)
+                    .map(|entry| entry.metadata)
+                    .ok_or(RegistryError::NotFound(id));
+                let _ = reply.send(result);
+            }
+            Command::Kill { id, reason, reply } => {
+                let result = match sessions.get_mut(&id) {
+                    Some(entry) => {
+                        match entry.kill.take() {
+                            Some(kill) => {
+                                info!("🔪 Killing session {} ({})", id, reason.reason());
+                                let _ = kill.send(reason);
+                            }
+                            None => debug!("🔧 Session {} is already being killed", id),
+                        }
+                        Ok(())
+                    }
+                    None => Err(RegistryError::NotFound(id)),
+                };
+                let _ = reply.send(result);
+            }
+            Command::GetMetadata { id, reply } => {
+                let result = sessions
+                    .get(&id)
+                    .map(|entry| entry.metadata.clone())
+                    .ok_or(RegistryError::NotFound(id));
+                let _ = reply.send(result);
+            }
+            Command::Update { id, update, reply } => {
+                let result = match sessions.get_mut(&id) {
+                    Some(entry) => {
+                        update(&mut entry.metadata);
+                        Ok(entry.metadata.clone())
+                    }
+                    None => Err(RegistryError::NotFound(id)),
+                };
+                let _ = reply.send(result);
+            }
+            Command::Get { id, reply } => {
+                let result = sessions
+                    .get(&id)
+                    .map(|entry| entry.session.clone())
+                    .ok_or(RegistryError::NotFound(id));
+                let _ = reply.send(result);
+            }
+            Command::List { filter, reply } => {
+                let listed = sessions
+                    .values()
+                    .filter(|entry| session_tags::matches(&entry.metadata.tags, &filter))
+                    .map(|entry| entry.metadata.clone())
+                    .collect();
+                let _ = reply.send(listed);
+            }
+            Command::All { reply } => {
+                let _ = reply.send(sessions.values().map(|entry| entry.session.clone()).collect());
+            }
+            Command::Count { reply } => {
+                let _ = reply.send(sessions.len());
+            }
+        }
+    }
+}
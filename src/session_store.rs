@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::admin::constant_time_eq;
+
+/// Bumped whenever the file format changes; `SessionStore::take` refuses other versions.
+pub const STORE_VERSION: u32 = 1;
+
+/// What outlives a pty-server restart of one session. Its processes do not: they are hung
+/// up with the server, and the restored session starts a fresh shell where it left off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub id: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub cwd: Option<String>,
+    pub env: BTreeMap<String, String>,
+    pub scrollback: String,
+    /// The session's resume token, which its client reconnects with.
+    pub resume_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    saved_at: DateTime<Utc>,
+    sessions: Vec<SavedSession>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("failed to read session store {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("failed to write session store {path}: {source}")]
+    Write { path: PathBuf, source: std::io::Error },
+    #[error("session store {path}: {error}")]
+    Parse { path: PathBuf, error: String },
+    #[error("session store {path} is version {version}; this build reads version {STORE_VERSION}")]
+    UnsupportedVersion { path: PathBuf, version: u32 },
+}
+
+/// `[terminal.persistence] path`: sessions saved at shutdown, as one JSON file.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    path: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replaces the file with `sessions`, readable only by the server's user since it holds
+    /// their resume tokens and output.
+    pub fn save(&self, sessions: Vec<SavedSession>) -> Result<(), StoreError> {
+        let write_error = |source| StoreError::Write { path: self.path.clone(), source };
+        let file = StoreFile { version: STORE_VERSION, saved_at: Utc::now(), sessions };
+        let json = serde_json::to_vec(&file).map_err(|e| write_error(e.into()))?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(write_error)?;
+        }
+        // Written aside and renamed over, so a crash mid-write leaves the old file whole.
+        let partial = self.path.with_extension("partial");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut out = options.open(&partial).map_err(write_error)?;
+        out.write_all(&json).and_then(|()| out.sync_all()).map_err(write_error)?;
+        std::fs::rename(&partial, &self.path).map_err(write_error)
+    }
+
+    /// The saved sessions, removing the file so that they are restored once at most. A
+    /// missing file means nothing was saved.
+    pub fn take(&self) -> Result<Vec<SavedSession>, StoreError> {
+        let json = match std::fs::read(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(StoreError::Read { path: self.path.clone(), source }),
+        };
+        let file: StoreFile = serde_json::from_slice(&json).map_err(|e| StoreError::Parse { path: self.path.clone(), error: e.to_string() })?;
+        if file.version != STORE_VERSION {
+            return Err(StoreError::UnsupportedVersion { path: self.path.clone(), version: file.version });
+        }
+        std::fs::remove_file(&self.path).map_err(|source| StoreError::Write { path: self.path.clone(), source })?;
+        Ok(file.sessions)
+    }
+}
+
+/// Sessions restored at startup, waiting for their clients to come back for them.
+#[derive(Debug, Clone, Default)]
+pub struct RestoredSessions {
+    waiting: Arc<Mutex<HashMap<String, SavedSession>>>,
+}
+
+impl RestoredSessions {
+    pub fn new(sessions: Vec<SavedSession>) -> Self {
+        let waiting = sessions.into_iter().map(|session| (session.id.clone(), session)).collect();
+        Self { waiting: Arc::new(Mutex::new(waiting)) }
+    }
+
+    /// Hands over session `id` to the client presenting its resume token.
+    pub fn claim(&self, id: &str, token: &str) -> Option<SavedSession> {
+        let mut waiting = self.waiting.lock().unwrap();
+        let expected = &waiting.get(id)?.resume_token;
+        constant_time_eq(token.as_bytes(), expected.as_bytes()).then(|| waiting.remove(id)).flatten()
+    }
+
+    /// Sessions nobody came back for, to be saved again.
+    pub fn unclaimed(&self) -> Vec<SavedSession> {
+        self.waiting.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
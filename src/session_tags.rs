@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+pub const MAX_TAGS: usize = 16;
+pub const MAX_KEY_LEN: usize = 32;
+pub const MAX_VALUE_LEN: usize = 128;
+
+/// Free-form labels automation puts on sessions to find them again, like `purpose: build`.
+pub type SessionTags = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TagError {
+    #[error("tag '{0}' is not key:value")]
+    Malformed(String),
+    #[error("tag key '{0}' must be 1-{MAX_KEY_LEN} letters, digits, '_', '-' or '.'")]
+    InvalidKey(String),
+    #[error("value of tag '{0}' must be 1-{MAX_VALUE_LEN} letters, digits or any of _-.:/@+")]
+    InvalidValue(String),
+    #[error("at most {MAX_TAGS} tags per session")]
+    TooMany,
+}
+
+impl TagError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TagError::TooMany => "too_many_tags",
+            _ => "invalid_tag",
+        }
+    }
+}
+
+/// Splits `key:value` at the first colon and checks both halves.
+pub fn parse(tag: &str) -> Result<(String, String), TagError> {
+    let (key, value) = tag.split_once(':').ok_or_else(|| TagError::Malformed(tag.to_string()))?;
+    check(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Adds `key:value`, replacing an earlier value for the same key.
+pub fn insert(tags: &mut SessionTags, tag: &str) -> Result<(), TagError> {
+    let (key, value) = parse(tag)?;
+    if !tags.contains_key(&key) && tags.len() >= MAX_TAGS {
+        return Err(TagError::TooMany);
+    }
+    tags.insert(key, value);
+    Ok(())
+}
+
+/// Whether `tags` carries every `key:value` pair in `filter`.
+pub fn matches(tags: &SessionTags, filter: &[(String, String)]) -> bool {
+    filter.iter().all(|(key, value)| tags.get(key) == Some(value))
+}
+
+fn check(key: &str, value: &str) -> Result<(), TagError> {
+    let key_ok = (1..=MAX_KEY_LEN).contains(&key.len())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !key_ok {
+        return Err(TagError::InvalidKey(key.to_string()));
+    }
+    let value_ok = (1..=MAX_VALUE_LEN).contains(&value.len())
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "_-.:/@+".contains(c));
+    if !value_ok {
+        return Err(TagError::InvalidValue(key.to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::warn;
+
+use crate::config::{ConfigError, TmpDirConfig};
+
+/// Removal attempts before a session directory is left behind; processes that outlived
+/// the shell may still be writing into it.
+const REMOVE_ATTEMPTS: usize = 3;
+
+/// `[tmpdir]`, validated; `None` from `from_config` when sessions share the system TMPDIR.
+#[derive(Debug, Clone)]
+pub struct TmpDirs {
+    root: PathBuf,
+    max_bytes: Option<u64>,
+    /// How often sizes are checked; `None` without a `max_bytes`.
+    pub check_interval: Option<Duration>,
+}
+
+impl TmpDirs {
+    pub fn from_config(config: &TmpDirConfig) -> Result<Option<Self>, ConfigError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        if config.max_bytes.is_some() && config.check_interval_secs == 0 {
+            return Err(ConfigError::Invalid("tmpdir.check_interval_secs must be above zero".to_string()));
+        }
+        Ok(Some(Self {
+            root: config.root.clone().unwrap_or_else(default_root),
+            max_bytes: config.max_bytes,
+            check_interval: config.max_bytes.map(|_| Duration::from_secs(config.check_interval_secs)),
+        }))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Creates `<root>/<id>`, readable only by the server's user.
+    pub fn create(&self, id: &str) -> io::Result<SessionTmpDir> {
+        private_root(&self.root)?;
+        let path = self.root.join(id);
+        std::fs::create_dir(&path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(SessionTmpDir { path, max_bytes: self.max_bytes, over: false })
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/forge`, which only this user can reach, or else a per-user
+/// `forge-<uid>` in the shared temp directory.
+fn default_root() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("forge"),
+        #[cfg(unix)]
+        _ => std::env::temp_dir().join(format!("forge-{}", unsafe { libc::geteuid() })),
+        #[cfg(not(unix))]
+        _ => std::env::temp_dir().join("forge"),
+    }
+}
+
+/// Creates `root` with mode 0700, or makes sure the one already there is a real directory
+/// owned by this user and closed to everyone else. In a shared temp directory, anything
+/// else could have been put there by another user to read or redirect session files.
+#[cfg(unix)]
+fn private_root(root: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(root)?;
+    let metadata = std::fs::symlink_metadata(root)?;
+    // SAFETY: geteuid cannot fail and touches no memory.
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid {
+        let reason = format!("{} is not a directory owned by uid {}", root.display(), uid);
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(root, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn private_root(root: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(root)
+}
+
+/// One session's `TMPDIR`; removed with everything in it when dropped.
+#[derive(Debug)]
+pub struct SessionTmpDir {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    over: bool,
+}
+
+impl SessionTmpDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The variable to set on the session's child processes.
+    pub fn env(&self) -> (&'static str, &Path) {
+        ("TMPDIR", &self.path)
+    }
+
+    /// Records a size from `dir_size` and returns the cap when it was just crossed, so
+    /// each crossing warns once.
+    pub fn record_size(&mut self, bytes: u64) -> Option<u64> {
+        let limit_bytes = self.max_bytes?;
+        let over = bytes > limit_bytes;
+        let crossed = over && !self.over;
+        self.over = over;
+        crossed.then_some(limit_bytes)
+    }
+}
+
+impl Drop for SessionTmpDir {
+    fn drop(&mut self) {
+        remove(&self.path);
+    }
+}
+
+/// Total size of the regular files below `path`, without following symlinks.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else { return 0 };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Removes `path` recursively. Leftover subprocesses may have made directories read-only
+/// or still be adding files, so failures are retried after making everything writable.
+pub fn remove(path: &Path) {
+    let mut last_error = None;
+    for _ in 0..REMOVE_ATTEMPTS {
+        match std::fs::remove_dir_all(path) {
+            Ok(()) => return,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => last_error = Some(e),
+        }
+        make_writable(path);
+    }
+    if let Some(e) = last_error {
+        warn!("🗑️ Could not remove session temp dir {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(unix)]
+fn make_writable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(metadata) = std::fs::symlink_metadata(path) else { return };
+    if !metadata.is_dir() {
+        return;
+    }
+    let mode = metadata.permissions().mode();
+    if mode & 0o700 != 0o700 {
+        let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode | 0o700));
+    }
+    for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+        make_writable(&entry.path());
+    }
+}
+
+#[cfg(not(unix))]
+fn make_writable(path: &Path) {
+    for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+        let Ok(metadata) = entry.metadata() else { continue };
+        let mut permissions = metadata.permissions();
+        if permissions.readonly() {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            let _ = std::fs::set_permissions(entry.path(), permissions);
+        }
+        if metadata.is_dir() {
+            make_writable(&entry.path());
+        }
+    }
+}
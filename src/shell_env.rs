@@ -0,0 +1,115 @@
+use log::warn;
+use tokio::process::Command;
+
+use crate::config::ShellEnvConfig;
+
+/// Passed to every spawned shell when set on the server.
+#[cfg(not(windows))]
+pub const BASE_ALLOWLIST: &[&str] = &["PATH", "HOME", "USER", "TERM", "LANG"];
+/// cmd and PowerShell do not start without `SystemRoot` and friends.
+#[cfg(windows)]
+pub const BASE_ALLOWLIST: &[&str] = &[
+    "PATH", "PATHEXT", "SystemRoot", "windir", "ComSpec", "USERPROFILE", "USERNAME", "APPDATA", "LOCALAPPDATA",
+    "ProgramData", "ProgramFiles", "TEMP", "TMP", "TERM", "LANG",
+];
+
+/// Name fragments that mark a variable as probably secret, for the startup check.
+const SENSITIVE_MARKERS: &[&str] = &[
+    "SECRET", "TOKEN", "PASSWORD", "PASSWD", "API_KEY", "APIKEY", "ACCESS_KEY", "PRIVATE_KEY", "CREDENTIAL",
+    "DATABASE_URL", "DSN", "AUTH",
+];
+
+/// Builds the environment for spawned shells from an allowlist instead of inheriting
+/// the server's, so API keys and database URLs stay in the server process.
+#[derive(Debug, Clone)]
+pub struct ShellEnv {
+    allow: Vec<String>,
+    pass_env: Vec<String>,
+}
+
+impl ShellEnv {
+    pub fn from_config(config: &ShellEnvConfig) -> Self {
+        Self {
+            allow: BASE_ALLOWLIST.iter().map(|name| name.to_string()).chain(config.allow.iter().cloned()).collect(),
+            pass_env: config.pass_env.clone(),
+        }
+    }
+
+    pub fn allows(&self, name: &str) -> bool {
+        self.allow.iter().any(|allowed| same_name(allowed, name)) || self.pass_env.iter().any(|glob| glob_match(glob, name))
+    }
+
+    /// The variables from `source` a shell gets to see.
+    pub fn vars_from<I: IntoIterator<Item = (String, String)>>(&self, source: I) -> Vec<(String, String)> {
+        source.into_iter().filter(|(name, _)| self.allows(name)).collect()
+    }
+
+    /// The variables from the server's own environment a shell gets to see.
+    pub fn vars(&self) -> Vec<(String, String)> {
+        self.vars_from(server_vars())
+    }
+
+    /// Clears `command`'s inherited environment and sets only the allowed variables.
+    pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command.env_clear().envs(self.vars())
+    }
+
+    /// Secret-looking variable names in `source`: those no longer inherited, and those
+    /// the config still passes on.
+    pub fn sensitive_names<I: IntoIterator<Item = (String, String)>>(&self, source: I) -> (Vec<String>, Vec<String>) {
+        source
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| looks_sensitive(name))
+            .partition(|name| !self.allows(name))
+    }
+
+    /// Startup check: warns about secrets that spawned shells used to inherit, and louder
+    /// about any the config still lets through.
+    pub fn self_check(&self) {
+        let (withheld, passed) = self.sensitive_names(server_vars());
+        if !withheld.is_empty() {
+            warn!("🧼 Shells no longer inherit {} (they used to); add them to [shell_env] allow if a shell needs one", withheld.join(", "));
+        }
+        for name in passed {
+            warn!("⚠️ [shell_env] passes {} to every spawned shell", name);
+        }
+    }
+}
+
+impl Default for ShellEnv {
+    fn default() -> Self {
+        Self::from_config(&ShellEnvConfig::default())
+    }
+}
+
+/// Windows variable names ignore case: `Path` is `PATH`.
+fn same_name(a: &str, b: &str) -> bool {
+    if cfg!(windows) { a.eq_ignore_ascii_case(b) } else { a == b }
+}
+
+/// Non-UTF-8 variables are never passed on.
+fn server_vars() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+}
+
+fn looks_sensitive(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    SENSITIVE_MARKERS.iter().any(|marker| upper.contains(marker))
+}
+
+/// `*` matches any run of characters; everything else is literal.
+fn glob_match(glob: &str, name: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
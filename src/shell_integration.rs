@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::config::{ConfigError, PromptDetectionConfig};
+
+/// Start of an OSC 133 shell-integration marker; the payload ends at BEL or ST.
+const OSC_133: &str = "\x1b]133;";
+/// Start of an OSC 7 working-directory report, `file://host/path`.
+const OSC_7: &str = "\x1b]7;";
+const MARKERS: [&str; 2] = [OSC_133, OSC_7];
+/// Longest marker we wait for before giving up and passing the bytes through.
+const MAX_MARKER_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPhase {
+    /// `A`: the prompt is about to be printed.
+    PromptStart,
+    /// `B`: the prompt ended, the user is typing a command.
+    CommandStart,
+    /// `C`: the command was submitted and its output follows.
+    OutputStart,
+    /// `D[;exit]`: the command finished.
+    CommandEnd,
+}
+
+impl CommandPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommandPhase::PromptStart => "prompt_start",
+            CommandPhase::CommandStart => "command_start",
+            CommandPhase::OutputStart => "output_start",
+            CommandPhase::CommandEnd => "command_end",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    Text(String),
+    Boundary {
+        phase: CommandPhase,
+        exit_code: Option<i32>,
+    },
+    /// The shell reported its working directory (OSC 7).
+    WorkingDirectory(String),
+}
+
+/// Splits terminal output into text, OSC 133 command boundaries and OSC 7 working
+/// directories. Markers may be
+/// split across chunks; output without markers comes back unchanged, except that a
+/// trailing partial escape sequence is held until the next chunk decides it.
+#[derive(Debug, Default)]
+pub struct ShellIntegrationParser {
+    pending: String,
+}
+
+impl ShellIntegrationParser {
+    pub fn feed(&mut self, chunk: &str) -> Vec<OutputEvent> {
+        let buf = std::mem::take(&mut self.pending) + chunk;
+        let mut events = Vec::new();
+        let mut text = String::new();
+        let mut rest = buf.as_str();
+
+        while let Some(esc) = rest.find('\x1b') {
+            text.push_str(&rest[..esc]);
+            rest = &rest[esc..];
+
+            if MARKERS.iter().any(|marker| rest.len() < marker.len() && marker.starts_with(rest)) {
+                self.pending = rest.to_string();
+                rest = "";
+                break;
+            }
+            let Some((marker, body)) = MARKERS.iter().find_map(|marker| Some((*marker, rest.strip_prefix(marker)?))) else {
+                text.push('\x1b');
+                rest = &rest[1..];
+                continue;
+            };
+
+            let terminator = [("\x07", body.find('\x07')), ("\x1b\\", body.find("\x1b\\"))]
+                .into_iter()
+                .filter_map(|(t, pos)| pos.map(|pos| (pos, t.len())))
+                .min();
+            match terminator {
+                Some((end, len)) => match parse_marker(marker, &body[..end]) {
+                    Some(boundary) => {
+                        if !text.is_empty() {
+                            events.push(OutputEvent::Text(std::mem::take(&mut text)));
+                        }
+                        events.push(boundary);
+                        rest = &body[end + len..];
+                    }
+                    None => {
+                        text.push('\x1b');
+                        rest = &rest[1..];
+                    }
+                },
+                None if rest.len() <= MAX_MARKER_LEN => {
+                    self.pending = rest.to_string();
+                    rest = "";
+                    break;
+                }
+                None => {
+                    text.push('\x1b');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        text.push_str(rest);
+        if !text.is_empty() {
+            events.push(OutputEvent::Text(text));
+        }
+        events
+    }
+}
+
+fn parse_marker(marker: &str, payload: &str) -> Option<OutputEvent> {
+    if marker == OSC_7 {
+        return parse_working_directory(payload);
+    }
+    let mut fields = payload.split(';');
+    let phase = match fields.next()? {
+        "A" => CommandPhase::PromptStart,
+        "B" => CommandPhase::CommandStart,
+        "C" => CommandPhase::OutputStart,
+        "D" => CommandPhase::CommandEnd,
+        _ => return None,
+    };
+    let exit_code = match phase {
+        CommandPhase::CommandEnd => fields.next().and_then(|code| code.parse().ok()),
+        _ => None,
+    };
+    Some(OutputEvent::Boundary { phase, exit_code })
+}
+
+/// The path of a `file://host/path` URL; the host is dropped, so a shell reporting from
+/// over ssh names a directory on the remote machine.
+fn parse_working_directory(payload: &str) -> Option<OutputEvent> {
+    let url = payload.strip_prefix("file://")?;
+    let path = &url[url.find('/')?..];
+    Some(OutputEvent::WorkingDirectory(crate::client_env::percent_decode(path)))
+}
+
+/// Longest tail of the current output line kept for prompt matching.
+const MAX_PROMPT_LINE: usize = 512;
+
+/// Guesses "the prompt is back" from the last output line once output has been quiet
+/// for the debounce period. Switches itself off for good once OSC 133 markers show up.
+#[derive(Debug, Clone)]
+pub struct PromptDetector {
+    pattern: Regex,
+    debounce: Duration,
+    last_line: String,
+    matched_at: Option<Instant>,
+    disabled: bool,
+}
+
+impl PromptDetector {
+    /// `None` when prompt detection is switched off.
+    pub fn from_config(config: &PromptDetectionConfig) -> Result<Option<Self>, ConfigError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let pattern = Regex::new(&config.pattern)
+            .map_err(|e| ConfigError::Invalid(format!("terminal.prompt_detection.pattern: {}", e)))?;
+        Ok(Some(Self {
+            pattern,
+            debounce: Duration::from_millis(config.debounce_ms),
+            last_line: String::new(),
+            matched_at: None,
+            disabled: false,
+        }))
+    }
+
+    pub fn observe(&mut self, events: &[OutputEvent]) {
+        let mut saw_text = false;
+        for event in events {
+            match event {
+                OutputEvent::Text(text) => {
+                    saw_text = true;
+                    match text.rfind('\n') {
+                        Some(newline) => self.last_line = text[newline + 1..].to_string(),
+                        None => self.last_line.push_str(text),
+                    }
+                }
+                OutputEvent::Boundary { .. } => self.disabled = true,
+                OutputEvent::WorkingDirectory(_) => {}
+            }
+        }
+        if self.last_line.len() > MAX_PROMPT_LINE {
+            let mut cut = self.last_line.len() - MAX_PROMPT_LINE;
+            while !self.last_line.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.last_line.drain(..cut);
+        }
+        if self.disabled {
+            self.matched_at = None;
+        } else if saw_text {
+            self.matched_at = self.pattern.is_match(&self.last_line).then(Instant::now);
+        }
+    }
+
+    /// When a `prompt` event is due, if the output currently ends in a prompt.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.matched_at.map(|at| at + self.debounce)
+    }
+
+    /// Consumes the pending match; `true` when a `prompt` event should be sent.
+    pub fn fire(&mut self) -> bool {
+        self.matched_at.take().is_some() && !self.disabled
+    }
+}
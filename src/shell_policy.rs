@@ -0,0 +1,51 @@
+use crate::config::TerminalConfig;
+
+/// `[terminal] shell` and `allowed_shells`: what a client may ask for with `?shell=`.
+#[derive(Debug, Clone)]
+pub struct ShellPolicy {
+    default: String,
+    allowed: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ShellError {
+    #[error("shell '{shell}' is not allowed; choose one of {allowed}")]
+    NotAllowed { shell: String, allowed: String },
+}
+
+impl ShellError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShellError::NotAllowed { .. } => "shell_not_allowed",
+        }
+    }
+}
+
+impl ShellPolicy {
+    pub fn from_config(config: &TerminalConfig) -> Self {
+        Self { default: config.shell.clone(), allowed: config.allowed_shells.clone() }
+    }
+
+    /// What sessions run unless their template or client picks something else.
+    pub fn default_shell(&self) -> &str {
+        &self.default
+    }
+
+    /// Every shell a client may ask for, the default first.
+    pub fn allowed(&self) -> Vec<&str> {
+        let mut allowed = vec![self.default.as_str()];
+        allowed.extend(self.allowed.iter().map(String::as_str).filter(|shell| *shell != self.default));
+        allowed
+    }
+
+    /// `requested` if it is on the list, compared as written: `zsh` and `/bin/zsh` are
+    /// different entries.
+    pub fn resolve(&self, requested: &str) -> Result<String, ShellError> {
+        let allowed = self.allowed();
+        if allowed.contains(&requested) {
+            Ok(requested.to_string())
+        } else {
+            Err(ShellError::NotAllowed { shell: requested.to_string(), allowed: allowed.join(", ") })
+        }
+    }
+}
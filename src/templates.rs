@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::warn;
+use serde::Serialize;
+
+use crate::config::{ConfigError, TemplateConfig};
+
+/// Connect-time fields a template's `overridable` list may name.
+pub const OVERRIDABLE_FIELDS: &[&str] = &["term", "lang", "lc_all", "color", "detect_links", "shell", "env"];
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("no template named '{0}'")]
+    Unknown(String),
+    #[error("template '{template}' does not let clients set '{field}'")]
+    NotOverridable { template: String, field: String },
+}
+
+impl TemplateError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TemplateError::Unknown(_) => "unknown_template",
+            TemplateError::NotOverridable { .. } => "not_overridable",
+        }
+    }
+}
+
+/// What a session runs, from its template or the server's default shell.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Launch {
+    #[default]
+    DefaultShell,
+    Shell(String),
+    Command(Vec<String>),
+}
+
+/// A validated `[[templates]]` entry.
+#[derive(Debug, Clone)]
+pub struct SessionTemplate {
+    config: TemplateConfig,
+}
+
+impl SessionTemplate {
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn launch(&self) -> Launch {
+        match (&self.config.shell, self.config.command.is_empty()) {
+            (Some(shell), _) => Launch::Shell(shell.clone()),
+            (None, false) => Launch::Command(self.config.command.clone()),
+            (None, true) => Launch::DefaultShell,
+        }
+    }
+
+    pub fn cwd(&self) -> Option<&PathBuf> {
+        self.config.cwd.as_ref()
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.config.env
+    }
+
+    pub fn term(&self) -> Option<&str> {
+        self.config.term.as_deref()
+    }
+
+    pub fn lang(&self) -> Option<&str> {
+        self.config.lang.as_deref()
+    }
+
+    pub fn lc_all(&self) -> Option<&str> {
+        self.config.lc_all.as_deref()
+    }
+
+    pub fn color(&self) -> Option<&str> {
+        self.config.color.as_deref()
+    }
+
+    pub fn detect_links(&self) -> Option<bool> {
+        self.config.detect_links
+    }
+
+    pub fn scrollback_bytes(&self) -> Option<usize> {
+        self.config.scrollback_bytes
+    }
+
+    pub fn confirm_dangerous(&self) -> bool {
+        self.config.policy.confirm_dangerous
+    }
+
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.config.policy.max_lifetime_secs.map(Duration::from_secs)
+    }
+
+    /// Fails unless the client may set `field` on sessions from this template.
+    pub fn check_override(&self, field: &str) -> Result<(), TemplateError> {
+        if self.config.overridable.iter().any(|allowed| allowed == field) {
+            Ok(())
+        } else {
+            Err(TemplateError::NotOverridable {
+                template: self.config.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+
+    pub fn summary(&self) -> TemplateSummary {
+        TemplateSummary {
+            name: self.config.name.clone(),
+            description: self.config.description.clone(),
+            launch: self.launch(),
+            cwd: self.config.cwd.clone(),
+            env: self.config.env.keys().cloned().collect(),
+            record: self.config.record,
+            overridable: self.config.overridable.clone(),
+        }
+    }
+}
+
+/// A template as `GET /api/templates` lists it. Environment values are left out, names only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TemplateSummary {
+    pub name: String,
+    pub description: String,
+    pub launch: Launch,
+    pub cwd: Option<PathBuf>,
+    pub env: Vec<String>,
+    pub record: bool,
+    pub overridable: Vec<String>,
+}
+
+/// All configured templates, in config order.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    templates: Arc<Vec<SessionTemplate>>,
+}
+
+impl Templates {
+    pub fn from_config(configs: &[TemplateConfig]) -> Result<Self, ConfigError> {
+        let mut templates: Vec<SessionTemplate> = Vec::new();
+        for config in configs {
+            let invalid = |why: &str| ConfigError::Invalid(format!("template '{}': {}", config.name, why));
+            if config.name.is_empty() {
+                return Err(ConfigError::Invalid("template without a name".to_string()));
+            }
+            if templates.iter().any(|t| t.name() == config.name) {
+                return Err(invalid("defined twice"));
+            }
+            if config.shell.is_some() && !config.command.is_empty() {
+                return Err(invalid("set shell or command, not both"));
+            }
+            if let Some(field) = config.overridable.iter().find(|f| !OVERRIDABLE_FIELDS.contains(&f.as_str())) {
+                return Err(invalid(&format!("'{}' cannot be overridable", field)));
+            }
+            if config.record {
+                warn!("📼 Template '{}' asks for recording, which is not supported yet", config.name);
+            }
+            templates.push(SessionTemplate { config: config.clone() });
+        }
+        Ok(Self { templates: Arc::new(templates) })
+    }
+
+    pub fn get(&self, name: &str) -> Result<&SessionTemplate, TemplateError> {
+        self.templates
+            .iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| TemplateError::Unknown(name.to_string()))
+    }
+
+    pub fn summaries(&self) -> Vec<TemplateSummary> {
+        self.templates.iter().map(SessionTemplate::summary).collect()
+    }
+}
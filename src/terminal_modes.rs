@@ -0,0 +1,97 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+/// xterm mouse tracking (1000, 1002, 1003) and SGR encoding (1006).
+pub const MOUSE_MODES: &[u16] = &[1000, 1002, 1003, 1006];
+/// The old (47, 1047) and save-cursor (1049) ways into the alternate screen.
+pub const ALTERNATE_SCREEN_MODES: &[u16] = &[47, 1047, 1049];
+pub const BRACKETED_PASTE_MODE: u16 = 2004;
+
+/// Longest partial sequence carried over to the next chunk.
+const MAX_PENDING: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeChange {
+    pub mode: u16,
+    pub enabled: bool,
+}
+
+/// The tracked modes currently on, for hello and attach replies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TerminalModes {
+    pub mouse: Vec<u16>,
+    /// A full-screen app like `less` or `vim` is running; scrollback says little about the screen.
+    pub alternate_screen: bool,
+    pub bracketed_paste: bool,
+}
+
+/// Follows DECSET/DECRST (`ESC [ ? Pm h` / `l`) and full resets (`ESC c`) in PTY output.
+#[derive(Debug, Default)]
+pub struct ModeTracker {
+    enabled: BTreeSet<u16>,
+    pending: String,
+}
+
+impl ModeTracker {
+    /// Changes to tracked modes in `output`, in order; repeats of the current state are skipped.
+    pub fn feed(&mut self, output: &str) -> Vec<ModeChange> {
+        let buf = std::mem::take(&mut self.pending) + output;
+        let mut changes = Vec::new();
+        let mut rest = buf.as_str();
+        while let Some(esc) = rest.find('\x1b') {
+            rest = &rest[esc..];
+            if rest.starts_with("\x1bc") {
+                changes.extend(std::mem::take(&mut self.enabled).into_iter().map(|mode| ModeChange { mode, enabled: false }));
+                rest = &rest[2..];
+                continue;
+            }
+            if let Some(body) = rest.strip_prefix("\x1b[?") {
+                let params_len = body.find(|c: char| !(c.is_ascii_digit() || c == ';')).unwrap_or(body.len());
+                match body[params_len..].chars().next() {
+                    None if rest.len() <= MAX_PENDING => {
+                        self.pending = rest.to_string();
+                        break;
+                    }
+                    Some(final_byte @ ('h' | 'l')) => {
+                        let enabled = final_byte == 'h';
+                        for mode in body[..params_len].split(';').filter_map(|mode| mode.parse().ok()) {
+                            if self.set(mode, enabled) {
+                                changes.push(ModeChange { mode, enabled });
+                            }
+                        }
+                        rest = &body[params_len + 1..];
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            if rest == "\x1b" || rest == "\x1b[" {
+                self.pending = rest.to_string();
+                break;
+            }
+            rest = &rest[1..];
+        }
+        changes
+    }
+
+    pub fn modes(&self) -> TerminalModes {
+        TerminalModes {
+            mouse: self.enabled.iter().copied().filter(|mode| MOUSE_MODES.contains(mode)).collect(),
+            alternate_screen: ALTERNATE_SCREEN_MODES.iter().any(|mode| self.enabled.contains(mode)),
+            bracketed_paste: self.enabled.contains(&BRACKETED_PASTE_MODE),
+        }
+    }
+
+    /// Whether `mode` is tracked and actually changed.
+    fn set(&mut self, mode: u16, enabled: bool) -> bool {
+        if !(MOUSE_MODES.contains(&mode) || ALTERNATE_SCREEN_MODES.contains(&mode) || mode == BRACKETED_PASTE_MODE) {
+            return false;
+        }
+        if enabled {
+            self.enabled.insert(mode)
+        } else {
+            self.enabled.remove(&mode)
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ansi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    Txt,
+    Html,
+}
+
+impl TranscriptFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TranscriptFormat::Txt => "txt",
+            TranscriptFormat::Html => "html",
+        }
+    }
+}
+
+/// Session details printed above the output.
+#[derive(Debug, Clone)]
+pub struct TranscriptHeader {
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    pub exported_at: DateTime<Utc>,
+    pub shell: Option<String>,
+    /// Lines that had already left the scrollback buffer.
+    pub dropped_lines: u64,
+}
+
+impl TranscriptHeader {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Session", self.session_id.clone()),
+            ("Started", self.created_at.to_rfc3339()),
+            ("Exported", self.exported_at.to_rfc3339()),
+        ];
+        if let Some(shell) = &self.shell {
+            fields.push(("Shell", shell.clone()));
+        }
+        if self.dropped_lines > 0 {
+            fields.push(("Omitted", format!("{} earlier lines no longer retained", self.dropped_lines)));
+        }
+        fields
+    }
+
+    pub fn filename(&self, format: TranscriptFormat) -> String {
+        format!("session-{}.{}", self.session_id, format.as_str())
+    }
+}
+
+pub fn render(format: TranscriptFormat, header: &TranscriptHeader, output: &str) -> String {
+    match format {
+        TranscriptFormat::Txt => {
+            let mut text: String = header
+                .fields()
+                .into_iter()
+                .map(|(name, value)| format!("# {}: {}\n", name, value))
+                .collect();
+            text.push('\n');
+            text.push_str(&ansi::strip(output));
+            text
+        }
+        TranscriptFormat::Html => {
+            let fields: String = header
+                .fields()
+                .into_iter()
+                .map(|(name, value)| format!("<dt>{}</dt><dd>{}</dd>", name, ansi::escape_html(&value)))
+                .collect();
+            format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Session {id}</title>\
+                 <style>body{{background:#1e1e1e;color:#e5e5e5;font-family:monospace}}dt{{font-weight:bold}}pre{{white-space:pre-wrap}}</style>\
+                 </head><body><dl>{fields}</dl><pre>{body}</pre></body></html>\n",
+                id = ansi::escape_html(&header.session_id),
+                fields = fields,
+                body = ansi::to_html(output),
+            )
+        }
+    }
+}
@@ -0,0 +1,551 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Interval;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::bandwidth::SessionShaper;
+use crate::chaos::{Chaos, OutputFault};
+use crate::config::HeartbeatConfig;
+use crate::protocol::{self, ClientMessage, CloseReason, DecodeError, OutputEncoding, ServerMessage, WireFormat, TERMINAL_CHANNEL};
+use crate::redaction;
+
+/// What a session reads from its transport.
+#[derive(Debug)]
+pub enum Inbound {
+    Message(ClientMessage),
+    /// A frame that did not decode; the connection stays open.
+    Invalid(DecodeError),
+    /// The client went away or closed the connection.
+    Closed,
+    /// The connection broke; `close` is what to tell the client, if it can still hear it.
+    Failed {
+        error: String,
+        close: Option<CloseReason>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error(transparent)]
+    WebSocket(#[from] WsError),
+    #[error("peer disconnected")]
+    Disconnected,
+}
+
+/// How many frames may queue between a session and its reader or writer task.
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// Server-initiated pings on a connection: one every `interval`, with the connection
+/// failing once `missed` of them in a row go unanswered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub missed: u32,
+}
+
+impl Heartbeat {
+    /// `None` when `[terminal.heartbeat]` sends no pings.
+    pub fn from_config(config: &HeartbeatConfig) -> Option<Self> {
+        (config.interval_secs > 0).then(|| Self { interval: Duration::from_secs(config.interval_secs), missed: config.missed_pongs.max(1) })
+    }
+}
+
+/// Frame-level connection to one terminal client, split into halves so reading
+/// and writing run as separate tasks. Session logic only ever sees decoded
+/// `ClientMessage`s and hands back `ServerMessage`s.
+pub trait Transport: Send + 'static {
+    type Reader: TransportReader;
+    type Writer: TransportWriter;
+
+    fn split(self) -> (Self::Reader, Self::Writer);
+
+    /// Pings the connection sends to notice a client that vanished without closing it.
+    fn heartbeat(&self) -> Option<Heartbeat> {
+        None
+    }
+}
+
+#[async_trait]
+pub trait TransportReader: Send + 'static {
+    /// Next message from the client.
+    async fn recv(&mut self) -> Inbound;
+
+    /// Whether the client answered a ping, or sent one, since the last call.
+    fn take_pong(&mut self) -> bool;
+}
+
+#[async_trait]
+pub trait TransportWriter: Send + 'static {
+    async fn send(&mut self, msg: &ServerMessage) -> Result<(), TransportError>;
+    async fn ping(&mut self) -> Result<(), TransportError>;
+    /// Sends the close frame for `reason` (if any) and shuts the connection down.
+    async fn close(&mut self, reason: Option<CloseReason>);
+}
+
+/// Queued work for a connection's writer task.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum Outbound {
+    Message(ServerMessage),
+    Close(Option<CloseReason>),
+}
+
+/// A client connection driven by a reader task and a writer task. Outgoing
+/// messages go through one queue, so they reach the client in send order.
+pub struct Connection {
+    inbound: mpsc::Receiver<Inbound>,
+    outbound: mpsc::Sender<Outbound>,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+    window: Option<AckWindow>,
+}
+
+/// How far a client that acks its output may fall behind, for `?ack_window=`. Output
+/// messages are stamped with `seq`, the output bytes sent so far, and the client acks
+/// the `seq` it has processed.
+#[derive(Debug)]
+struct AckWindow {
+    limit: u64,
+    sent: AtomicU64,
+    acked: AtomicU64,
+}
+
+impl Connection {
+    pub fn spawn<T: Transport>(transport: T) -> Self {
+        Self::spawn_with_chaos(transport, Chaos::default(), String::new())
+    }
+
+    /// Like `spawn`, with outgoing messages subject to `session_id`'s chaos settings.
+    pub fn spawn_with_chaos<T: Transport>(transport: T, chaos: Chaos, session_id: String) -> Self {
+        Self::spawn_shaped(transport, chaos, session_id, None)
+    }
+
+    /// Like `spawn_with_chaos`, with output paced by `shaper`. A transport with a
+    /// heartbeat is pinged, and reads as `Inbound::Failed` once it stops answering.
+    pub fn spawn_shaped<T: Transport>(transport: T, chaos: Chaos, session_id: String, mut shaper: Option<SessionShaper>) -> Self {
+        let heartbeat = transport.heartbeat();
+        let (mut reader, mut writer) = transport.split();
+        let (inbound_tx, inbound) = mpsc::channel(CHANNEL_CAPACITY);
+        let (outbound, mut outbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (pings, mut pings_rx) = mpsc::channel(1);
+
+        let writer = tokio::spawn(async move {
+            let mut sent = 0;
+            loop {
+                let next = tokio::select! {
+                    next = outbound_rx.recv() => next,
+                    Some(()) = pings_rx.recv() => {
+                        if let Err(e) = writer.ping().await {
+                            debug!("🔧 Writer stopping: {}", e);
+                            writer.close(None).await;
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                let Some(next) = next else { break };
+                match next {
+                    Outbound::Message(msg) => {
+                        sent += 1;
+                        match chaos.output_fault(&session_id, sent) {
+                            OutputFault::Deliver => {}
+                            OutputFault::Delay(delay) => tokio::time::sleep(delay).await,
+                            OutputFault::Drop => continue,
+                            OutputFault::Close(code) => {
+                                writer.close(Some(CloseReason::Chaos(code))).await;
+                                return;
+                            }
+                        }
+                        if let Some(shaper) = shaper.as_mut() {
+                            match &msg {
+                                ServerMessage::Output { data, .. } => shaper.throttle(data.len()).await,
+                                ServerMessage::OutputBytes { data, .. } => shaper.throttle(data.len()).await,
+                                _ => {}
+                            }
+                        }
+                        if let Err(e) = writer.send(&msg).await {
+                            debug!("🔧 Writer stopping: {}", e);
+                            // Sends fail once the client has closed; the reply to its close
+                            // frame is still queued and goes out with this.
+                            writer.close(None).await;
+                            return;
+                        }
+                    }
+                    Outbound::Close(reason) => {
+                        writer.close(reason).await;
+                        return;
+                    }
+                }
+            }
+            writer.close(None).await;
+        });
+
+        let stuck = writer.abort_handle();
+
+        let reader = tokio::spawn(async move {
+            let mut beats = heartbeat.map(|heartbeat| tokio::time::interval_at(tokio::time::Instant::now() + heartbeat.interval, heartbeat.interval));
+            let mut unanswered = 0;
+            loop {
+                let msg = tokio::select! {
+                    msg = reader.recv() => msg,
+                    _ = next_beat(&mut beats) => {
+                        if reader.take_pong() {
+                            unanswered = 0;
+                        }
+                        if unanswered < heartbeat.map_or(0, |heartbeat| heartbeat.missed) {
+                            unanswered += 1;
+                            let _ = pings.try_send(());
+                            continue;
+                        }
+                        // A writer stuck on a dead socket would otherwise hold up the session.
+                        stuck.abort();
+                        Inbound::Failed { error: format!("no pong to the last {} pings", unanswered), close: None }
+                    }
+                };
+                unanswered = 0;
+                let last = matches!(msg, Inbound::Closed | Inbound::Failed { .. });
+                if inbound_tx.send(msg).await.is_err() || last {
+                    break;
+                }
+            }
+        });
+
+        Self { inbound, outbound, reader, writer, window: None }
+    }
+
+    /// Stamps output with `seq` and keeps count of how much of it the client has acked.
+    pub fn with_ack_window(mut self, limit: Option<u64>) -> Self {
+        self.window = limit.map(|limit| AckWindow { limit, sent: AtomicU64::new(0), acked: AtomicU64::new(0) });
+        self
+    }
+
+    /// Whether the client is within its ack window, so more output may be read for it.
+    /// Always true without one.
+    pub fn window_open(&self) -> bool {
+        self.window.as_ref().is_none_or(|window| {
+            window.sent.load(Ordering::Relaxed) - window.acked.load(Ordering::Relaxed) <= window.limit
+        })
+    }
+
+    /// Records that the client has processed output up to `seq`; acks for output not yet
+    /// sent count as acking everything sent.
+    pub fn ack(&self, seq: u64) {
+        if let Some(window) = &self.window {
+            window.acked.fetch_max(seq.min(window.sent.load(Ordering::Relaxed)), Ordering::Relaxed);
+        }
+    }
+
+    /// Next client message; `None` once either task has stopped, e.g. after a failed send.
+    pub async fn recv(&mut self) -> Option<Inbound> {
+        tokio::select! {
+            msg = self.inbound.recv() => msg,
+            _ = self.outbound.closed() => None,
+        }
+    }
+
+    /// Whether the writer task has stopped, which it does once the client cannot be written to.
+    pub fn writer_stopped(&self) -> bool {
+        self.outbound.is_closed()
+    }
+
+    /// Queues `msg` for the writer, waiting while the queue is full.
+    pub async fn send(&self, mut msg: ServerMessage) -> Result<(), TransportError> {
+        if let Some(window) = &self.window {
+            let (len, seq) = match &mut msg {
+                ServerMessage::Output { data, seq } => (data.len(), Some(seq)),
+                ServerMessage::OutputBytes { data, seq, .. } => (data.len(), Some(seq)),
+                _ => (0, None),
+            };
+            if let Some(seq) = seq {
+                *seq = Some(window.sent.fetch_add(len as u64, Ordering::Relaxed) + len as u64);
+            }
+        }
+        self.outbound
+            .send(Outbound::Message(msg))
+            .await
+            .map_err(|_| TransportError::Disconnected)
+    }
+
+    /// Flushes queued messages, closes with `reason` and tears both tasks down.
+    pub async fn shutdown(self, reason: Option<CloseReason>) {
+        let _ = self.outbound.send(Outbound::Close(reason)).await;
+        drop(self.outbound);
+        let _ = self.writer.await;
+        self.reader.abort();
+    }
+}
+
+/// Waits for the next heartbeat, or forever on a connection without them.
+async fn next_beat(beats: &mut Option<Interval>) {
+    match beats {
+        Some(beats) => {
+            beats.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Production transport over a tungstenite WebSocket.
+pub struct WsTransport<S> {
+    ws: WebSocketStream<S>,
+    peer: String,
+    format: WireFormat,
+    heartbeat: Option<Heartbeat>,
+}
+
+impl<S> WsTransport<S> {
+    pub fn new(ws: WebSocketStream<S>, peer: String) -> Self {
+        Self { ws, peer, format: WireFormat::Json, heartbeat: None }
+    }
+
+    /// Sends protocol messages in `format` instead of JSON.
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Option<Heartbeat>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+}
+
+impl<S> Transport for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Reader = WsReader<S>;
+    type Writer = WsWriter<S>;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (sink, stream) = self.ws.split();
+        (
+            WsReader { stream, peer: self.peer.clone(), pong: false },
+            WsWriter { sink, peer: self.peer, format: self.format },
+        )
+    }
+
+    fn heartbeat(&self) -> Option<Heartbeat> {
+        self.heartbeat
+    }
+}
+
+pub struct WsReader<S> {
+    stream: SplitStream<WebSocketStream<S>>,
+    peer: String,
+    pong: bool,
+}
+
+pub struct WsWriter<S> {
+    sink: SplitSink<WebSocketStream<S>, Message>,
+    peer: String,
+    format: WireFormat,
+}
+
+#[async_trait]
+impl<S> TransportReader for WsReader<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    async fn recv(&mut self) -> Inbound {
+        loop {
+            match self.stream.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    debug!("📋 Message content from {}: {}", self.peer, redaction::redact(&text));
+                    return match ClientMessage::decode(&text) {
+                        Ok(msg) => Inbound::Message(msg),
+                        Err(e) => Inbound::Invalid(e),
+                    };
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    info!("🔚 WebSocket connection closed by {} - Frame: {:?}", self.peer, frame);
+                    return Inbound::Closed;
+                }
+                None => return Inbound::Closed,
+                Some(Ok(Message::Binary(data))) => {
+                    debug!("📦 Binary message received from {} ({} bytes)", self.peer, data.len());
+                    return match ClientMessage::decode_binary(&data) {
+                        Ok(msg) => Inbound::Message(msg),
+                        Err(e) => Inbound::Invalid(e),
+                    };
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    debug!("🏓 Ping received from {} ({} bytes)", self.peer, data.len());
+                    self.pong = true;
+                }
+                Some(Ok(Message::Pong(data))) => {
+                    debug!("🏓 Pong received from {} ({} bytes)", self.peer, data.len());
+                    self.pong = true;
+                }
+                Some(Ok(Message::Frame(_))) => debug!("🔧 Raw frame message received from {}", self.peer),
+                Some(Err(e)) => {
+                    return Inbound::Failed {
+                        close: CloseReason::for_error(&e),
+                        error: e.to_string(),
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_pong(&mut self) -> bool {
+        std::mem::take(&mut self.pong)
+    }
+}
+
+#[async_trait]
+impl<S> TransportWriter for WsWriter<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    async fn send(&mut self, msg: &ServerMessage) -> Result<(), TransportError> {
+        let frame = match msg {
+            ServerMessage::OutputBytes { data, encoding: OutputEncoding::Binary, .. } => Message::Binary(protocol::binary_frame(TERMINAL_CHANNEL, data)),
+            msg if self.format == WireFormat::MessagePack => Message::Binary(msg.encode_msgpack()),
+            msg => Message::Text(msg.encode()),
+        };
+        Ok(self.sink.send(frame).await?)
+    }
+
+    async fn ping(&mut self) -> Result<(), TransportError> {
+        Ok(self.sink.send(Message::Ping(Vec::new())).await?)
+    }
+
+    async fn close(&mut self, reason: Option<CloseReason>) {
+        if let Some(reason) = reason {
+            if let Err(e) = self.sink.send(Message::Close(Some(reason.frame()))).await {
+                debug!("🔧 Close frame for {} not delivered: {}", self.peer, e);
+            }
+        }
+        // Also flushes the automatic reply when the client initiated the close.
+        let _ = self.sink.close().await;
+    }
+}
+
+/// Raw client-side frames for `MemoryTransport`.
+#[derive(Debug, Clone)]
+pub enum ClientFrame {
+    Text(String),
+    Binary(Vec<u8>),
+    Pong,
+    Close,
+}
+
+/// Server-side frames as seen by a `MemoryPeer`.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum ServerFrame {
+    Message(ServerMessage),
+    Ping,
+    Close(Option<CloseReason>),
+}
+
+/// In-process transport over channels: one channel of a multiplexed connection, or a test
+/// driving a session without sockets.
+pub struct MemoryTransport {
+    reader: MemoryReader,
+    writer: MemoryWriter,
+    heartbeat: Option<Heartbeat>,
+}
+
+impl MemoryTransport {
+    /// Pings the peer, which answers with `ClientFrame::Pong` if it wants to stay.
+    pub fn with_heartbeat(mut self, heartbeat: Option<Heartbeat>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+}
+
+pub struct MemoryReader {
+    inbound: mpsc::UnboundedReceiver<ClientFrame>,
+    pong: bool,
+}
+
+pub struct MemoryWriter {
+    outbound: mpsc::UnboundedSender<ServerFrame>,
+}
+
+/// The client end of a `MemoryTransport`.
+pub struct MemoryPeer {
+    pub tx: mpsc::UnboundedSender<ClientFrame>,
+    pub rx: mpsc::UnboundedReceiver<ServerFrame>,
+}
+
+pub fn memory_pair() -> (MemoryTransport, MemoryPeer) {
+    let (client_tx, inbound) = mpsc::unbounded_channel();
+    let (outbound, client_rx) = mpsc::unbounded_channel();
+    (
+        MemoryTransport {
+            reader: MemoryReader { inbound, pong: false },
+            writer: MemoryWriter { outbound },
+            heartbeat: None,
+        },
+        MemoryPeer { tx: client_tx, rx: client_rx },
+    )
+}
+
+impl Transport for MemoryTransport {
+    type Reader = MemoryReader;
+    type Writer = MemoryWriter;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        (self.reader, self.writer)
+    }
+
+    fn heartbeat(&self) -> Option<Heartbeat> {
+        self.heartbeat
+    }
+}
+
+#[async_trait]
+impl TransportReader for MemoryReader {
+    async fn recv(&mut self) -> Inbound {
+        loop {
+            return match self.inbound.recv().await {
+                Some(ClientFrame::Text(text)) => match ClientMessage::decode(&text) {
+                    Ok(msg) => Inbound::Message(msg),
+                    Err(e) => Inbound::Invalid(e),
+                },
+                Some(ClientFrame::Binary(data)) => match ClientMessage::decode_binary(&data) {
+                    Ok(msg) => Inbound::Message(msg),
+                    Err(e) => Inbound::Invalid(e),
+                },
+                Some(ClientFrame::Pong) => {
+                    self.pong = true;
+                    continue;
+                }
+                Some(ClientFrame::Close) | None => Inbound::Closed,
+            };
+        }
+    }
+
+    fn take_pong(&mut self) -> bool {
+        std::mem::take(&mut self.pong)
+    }
+}
+
+#[async_trait]
+impl TransportWriter for MemoryWriter {
+    async fn send(&mut self, msg: &ServerMessage) -> Result<(), TransportError> {
+        self.outbound
+            .send(ServerFrame::Message(msg.clone()))
+            .map_err(|_| TransportError::Disconnected)
+    }
+
+    async fn ping(&mut self) -> Result<(), TransportError> {
+        self.outbound.send(ServerFrame::Ping).map_err(|_| TransportError::Disconnected)
+    }
+
+    async fn close(&mut self, reason: Option<CloseReason>) {
+        let _ = self.outbound.send(ServerFrame::Close(reason));
+    }
+}
@@ -0,0 +1,281 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Uri};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::sync::mpsc;
+
+use crate::config::{ConfigError, WebhookConfig};
+use crate::redaction;
+use crate::session_tags::SessionTags;
+
+/// `sha1=<hex HMAC of the body>`, present when the webhook has a secret.
+pub const SIGNATURE_HEADER: &str = "x-forge-signature";
+/// The event name, so receivers can route without parsing the body.
+pub const EVENT_HEADER: &str = "x-forge-event";
+/// Deliveries each endpoint may have queued before new ones are dropped.
+pub const QUEUE_CAPACITY: usize = 256;
+/// Attempts per delivery, including the first.
+pub const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    SessionCreated,
+    SessionEnded,
+    AuthFailed,
+    AdminKill,
+    ExecuteSlow,
+    DangerousConfirmed,
+    CommandFinished,
+}
+
+/// Something ops tooling may want to hear about. Never carries terminal output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    SessionCreated { session_id: String, peer_addr: String, tags: SessionTags },
+    /// `exit_code` is the shell's, when the shell exiting ended the session.
+    SessionEnded { session_id: String, reason: String, exit_code: Option<u32>, tags: SessionTags },
+    /// A rejected admin token, on `endpoint`.
+    AuthFailed { endpoint: String, peer_addr: Option<String> },
+    AdminKill { session_id: String },
+    ExecuteSlow { command: String, duration_ms: u64, exit_code: i32 },
+    DangerousConfirmed { session_id: Option<String>, pattern: String, command: String },
+    /// A long-running terminal command completed; see `[terminal.long_commands]`.
+    CommandFinished { session_id: String, command: String, duration_ms: u64, exit_code: Option<i32> },
+}
+
+impl WebhookEvent {
+    /// Masks secrets in any command text the event carries.
+    fn redacted(mut self) -> Self {
+        match &mut self {
+            WebhookEvent::ExecuteSlow { command, .. }
+            | WebhookEvent::DangerousConfirmed { command, .. }
+            | WebhookEvent::CommandFinished { command, .. } => *command = redaction::redact(command),
+            _ => {}
+        }
+        self
+    }
+
+    pub fn kind(&self) -> WebhookEventKind {
+        match self {
+            WebhookEvent::SessionCreated { .. } => WebhookEventKind::SessionCreated,
+            WebhookEvent::SessionEnded { .. } => WebhookEventKind::SessionEnded,
+            WebhookEvent::AuthFailed { .. } => WebhookEventKind::AuthFailed,
+            WebhookEvent::AdminKill { .. } => WebhookEventKind::AdminKill,
+            WebhookEvent::ExecuteSlow { .. } => WebhookEventKind::ExecuteSlow,
+            WebhookEvent::DangerousConfirmed { .. } => WebhookEventKind::DangerousConfirmed,
+            WebhookEvent::CommandFinished { .. } => WebhookEventKind::CommandFinished,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    timestamp: DateTime<Utc>,
+}
+
+/// Delivery outcomes across every configured webhook.
+#[derive(Debug, Default)]
+pub struct WebhookMetrics {
+    delivered: AtomicU64,
+    retried: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct WebhookMetricsSnapshot {
+    pub delivered: u64,
+    /// Attempts after the first one.
+    pub retried: u64,
+    /// Deliveries that ran out of attempts.
+    pub failed: u64,
+    /// Events discarded because the endpoint's queue was full.
+    pub dropped: u64,
+}
+
+impl WebhookMetrics {
+    pub fn snapshot(&self) -> WebhookMetricsSnapshot {
+        WebhookMetricsSnapshot {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    events: Vec<WebhookEventKind>,
+    slow_execute_ms: u64,
+    queue: mpsc::Sender<Delivery>,
+}
+
+impl Endpoint {
+    fn wants(&self, event: &WebhookEvent) -> bool {
+        if !self.events.is_empty() && !self.events.contains(&event.kind()) {
+            return false;
+        }
+        match event {
+            WebhookEvent::ExecuteSlow { duration_ms, .. } => *duration_ms >= self.slow_execute_ms,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Delivery {
+    event: WebhookEventKind,
+    body: Bytes,
+}
+
+/// Fans events out to the configured webhooks. Each endpoint gets its own bounded
+/// queue and delivery task, so `emit` never waits and a dead endpoint only ever
+/// holds up its own deliveries.
+#[derive(Clone, Default)]
+pub struct Webhooks {
+    endpoints: Arc<Vec<Endpoint>>,
+    metrics: Arc<WebhookMetrics>,
+}
+
+impl Webhooks {
+    /// Validates every entry and spawns its delivery task.
+    pub fn from_config(configs: &[WebhookConfig]) -> Result<Self, ConfigError> {
+        let metrics = Arc::new(WebhookMetrics::default());
+        let client = Client::new();
+        let mut endpoints = Vec::with_capacity(configs.len());
+        for config in configs {
+            let uri: Uri = config
+                .url
+                .parse()
+                .map_err(|e| ConfigError::Invalid(format!("webhooks.url '{}': {}", config.url, e)))?;
+            if uri.scheme_str() != Some("http") {
+                return Err(ConfigError::Invalid(format!("webhooks.url '{}': only http:// is supported", config.url)));
+            }
+            let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+            tokio::spawn(deliver_all(rx, client.clone(), uri, config.secret.clone(), metrics.clone()));
+            info!("🪝 Webhook registered: {} ({:?})", config.url, config.events);
+            endpoints.push(Endpoint {
+                url: config.url.clone(),
+                events: config.events.clone(),
+                slow_execute_ms: config.slow_execute_ms,
+                queue,
+            });
+        }
+        Ok(Self { endpoints: Arc::new(endpoints), metrics })
+    }
+
+    /// Queues `event` for every webhook that wants it, dropping it for those whose queue is full.
+    pub fn emit(&self, event: WebhookEvent) {
+        let targets: Vec<&Endpoint> = self.endpoints.iter().filter(|endpoint| endpoint.wants(&event)).collect();
+        if targets.is_empty() {
+            return;
+        }
+        let event = event.redacted();
+        let payload = Payload { event: &event, timestamp: Utc::now() };
+        let delivery = Delivery {
+            event: event.kind(),
+            body: Bytes::from(serde_json::to_vec(&payload).unwrap_or_default()),
+        };
+        for endpoint in targets {
+            if endpoint.queue.try_send(delivery.clone()).is_err() {
+                self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("🐌 Webhook {} is backed up, dropped {:?} event", endpoint.url, delivery.event);
+            }
+        }
+    }
+
+    pub fn metrics(&self) -> WebhookMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+}
+
+async fn deliver_all(
+    mut rx: mpsc::Receiver<Delivery>,
+    client: Client<HttpConnector>,
+    uri: Uri,
+    secret: Option<String>,
+    metrics: Arc<WebhookMetrics>,
+) {
+    while let Some(delivery) = rx.recv().await {
+        let signature = secret.as_deref().map(|secret| sign(secret.as_bytes(), &delivery.body));
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match post(&client, &uri, &delivery, signature.as_deref()).await {
+                Ok(()) => {
+                    debug!("🪝 Delivered {:?} to {} (attempt {})", delivery.event, uri, attempt);
+                    metrics.delivered.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(e) if attempt == MAX_ATTEMPTS => {
+                    warn!("❌ Giving up on {:?} webhook to {} after {} attempts: {}", delivery.event, uri, attempt, e);
+                    metrics.failed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    debug!("🔁 Webhook to {} failed ({}), retrying in {:?}", uri, e, backoff);
+                    metrics.retried.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        }
+    }
+}
+
+async fn post(client: &Client<HttpConnector>, uri: &Uri, delivery: &Delivery, signature: Option<&str>) -> Result<(), String> {
+    let event = serde_json::to_value(delivery.event).unwrap_or_default();
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(uri.clone())
+        .header("content-type", "application/json")
+        .header(EVENT_HEADER, event.as_str().unwrap_or_default());
+    if let Some(signature) = signature {
+        request = request.header(SIGNATURE_HEADER, signature);
+    }
+    let request = request.body(Body::from(delivery.body.clone())).map_err(|e| e.to_string())?;
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("status {}", response.status()))
+    }
+}
+
+/// HMAC-SHA1 of `body` under `secret`, formatted as `sha1=<hex>`.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    const BLOCK: usize = 64;
+    let mut key = [0u8; BLOCK];
+    if secret.len() > BLOCK {
+        key[..20].copy_from_slice(&Sha1::digest(secret));
+    } else {
+        key[..secret.len()].copy_from_slice(secret);
+    }
+    let inner = Sha1::new().chain_update(key.map(|b| b ^ 0x36)).chain_update(body).finalize();
+    let outer = Sha1::new().chain_update(key.map(|b| b ^ 0x5c)).chain_update(inner).finalize();
+    let hex: String = outer.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha1={}", hex)
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub const MAX_WORKSPACE_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum WorkspaceError {
+    #[error("workspace name '{0}' must be 1-{MAX_WORKSPACE_LEN} letters, digits, '_', '-' or '.'")]
+    InvalidName(String),
+    #[error("workspace '{0}' not found")]
+    NotFound(String),
+}
+
+impl WorkspaceError {
+    /// Stable code for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WorkspaceError::InvalidName(_) => "invalid_workspace",
+            WorkspaceError::NotFound(_) => "workspace_not_found",
+        }
+    }
+}
+
+/// `name` if it is a valid workspace name. Names go into URL paths as they are, so they
+/// keep to characters that need no escaping there.
+pub fn parse(name: &str) -> Result<String, WorkspaceError> {
+    let ok = (1..=MAX_WORKSPACE_LEN).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !ok {
+        return Err(WorkspaceError::InvalidName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// A named group of sessions, like `project-A`, that a multi-pane client opens and closes
+/// together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Workspace {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Every workspace, whether or not it has sessions in it. Which sessions belong to one is
+/// kept with the sessions.
+#[derive(Debug, Clone, Default)]
+pub struct Workspaces {
+    created: Arc<Mutex<BTreeMap<String, DateTime<Utc>>>>,
+}
+
+impl Workspaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Workspace `name`, created now unless it exists, and whether it was created.
+    pub fn create(&self, name: &str) -> (Workspace, bool) {
+        let mut created = self.created.lock().unwrap();
+        let new = !created.contains_key(name);
+        let created_at = *created.entry(name.to_string()).or_insert_with(Utc::now);
+        (Workspace { name: name.to_string(), created_at }, new)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Workspace, WorkspaceError> {
+        let created_at = self.created.lock().unwrap().get(name).copied();
+        created_at.map(|created_at| Workspace { name: name.to_string(), created_at }).ok_or_else(|| WorkspaceError::NotFound(name.to_string()))
+    }
+
+    pub fn remove(&self, name: &str) -> Result<Workspace, WorkspaceError> {
+        let created_at = self.created.lock().unwrap().remove(name);
+        created_at.map(|created_at| Workspace { name: name.to_string(), created_at }).ok_or_else(|| WorkspaceError::NotFound(name.to_string()))
+    }
+
+    /// By name.
+    pub fn list(&self) -> Vec<Workspace> {
+        let created = self.created.lock().unwrap();
+        created.iter().map(|(name, created_at)| Workspace { name: name.clone(), created_at: *created_at }).collect()
+    }
+}
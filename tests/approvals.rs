@@ -0,0 +1,46 @@
+use rust_terminal_forge::approvals::{ApprovalError, ApprovalStatus, Approvals};
+use rust_terminal_forge::command_guard::CommandGuard;
+use rust_terminal_forge::config::{ApprovalConfig, DangerousCommandConfig, DangerousPattern};
+use rust_terminal_forge::executor::Capture;
+use rust_terminal_forge::policy::{self, CommandSpec};
+
+fn approvals(ttl_secs: u64) -> Approvals {
+    Approvals::from_config(&ApprovalConfig {
+        ttl_secs,
+        patterns: vec![DangerousPattern { name: "prod_db".to_string(), regex: r"psql\b.*\bprod\b".to_string() }],
+    })
+    .unwrap()
+}
+
+#[test]
+fn approval_supersedes_confirmation_and_keeps_the_validated_argv() {
+    let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+    let approvals = approvals(900);
+    let spec = CommandSpec::Argv { program: "psql".to_string(), args: vec!["prod".to_string(), "-c".to_string(), "select 1".to_string()] };
+
+    let verdict = policy::evaluate_with_approvals(&guard, &approvals, &spec);
+    assert!(verdict.requires_approval && !verdict.requires_confirmation);
+    assert_eq!(verdict.approval_pattern(), Some("prod_db"));
+    assert!(!policy::evaluate(&guard, &spec).requires_approval);
+
+    let approval = approvals.request(&spec, Capture::Text, &verdict, "junior");
+    let approved = approvals.decide(&approval.id, true, "senior").unwrap();
+    assert_eq!(approved.status, ApprovalStatus::Approved);
+    assert_eq!(approved.spec, spec);
+    assert_eq!(approved.argv, ["psql", "prod", "-c", "select 1"]);
+    assert!(approvals.pending().is_empty());
+}
+
+#[test]
+fn undecided_requests_expire() {
+    let guard = CommandGuard::from_config(&DangerousCommandConfig::default()).unwrap();
+    let approvals = approvals(0);
+    let spec = CommandSpec::Shell("psql prod".to_string());
+    let approval = approvals.request(&spec, Capture::Text, &policy::evaluate_with_approvals(&guard, &approvals, &spec), "junior");
+
+    assert_eq!(approvals.get(&approval.id).unwrap().status, ApprovalStatus::Expired);
+    assert!(approvals.pending().is_empty());
+    let err = approvals.decide(&approval.id, true, "senior").unwrap_err();
+    assert_eq!(err.code(), "approval_expired");
+    assert_eq!(approvals.get("nope").unwrap_err(), ApprovalError::NotFound("nope".to_string()));
+}
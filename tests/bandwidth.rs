@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use rust_terminal_forge::bandwidth::Bandwidth;
+use rust_terminal_forge::chaos::Chaos;
+use rust_terminal_forge::config::BandwidthConfig;
+use rust_terminal_forge::protocol::ServerMessage;
+use rust_terminal_forge::transport::{memory_pair, Connection, MemoryPeer, ServerFrame};
+
+fn limits(global: Option<u64>, session: Option<u64>) -> BandwidthConfig {
+    BandwidthConfig {
+        global_bytes_per_sec: global,
+        session_bytes_per_sec: session,
+        burst_bytes: 1000,
+        interactive_bytes: 16,
+    }
+}
+
+fn received(peer: &mut MemoryPeer) -> usize {
+    let mut count = 0;
+    while let Ok(frame) = peer.rx.try_recv() {
+        if matches!(frame, ServerFrame::Message(ServerMessage::Output { .. })) {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[tokio::test(start_paused = true)]
+async fn session_rate_holds_bulk_output_but_not_small_chunks() {
+    let bandwidth = Bandwidth::from_config(&limits(None, Some(1000))).unwrap();
+    let mut shaper = bandwidth.session();
+
+    assert_eq!(shaper.charge(1000), Duration::ZERO, "a quiet session may burst");
+    assert_eq!(shaper.charge(500), Duration::from_millis(500));
+    assert_eq!(shaper.charge(10), Duration::ZERO, "echo-sized chunks are never held");
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(shaper.charge(1000), Duration::ZERO);
+
+    bandwidth.apply(&limits(None, None)).unwrap();
+    assert_eq!(shaper.charge(100_000), Duration::ZERO, "reloaded limits apply to live sessions");
+    assert!(bandwidth.apply(&limits(Some(0), None)).is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn sessions_under_a_global_cap_all_make_progress() {
+    let bandwidth = Bandwidth::from_config(&limits(Some(10_000), None)).unwrap();
+    let mut peers = Vec::new();
+    let mut stats = Vec::new();
+    for id in ["a", "b"] {
+        let (transport, peer) = memory_pair();
+        let shaper = bandwidth.session();
+        stats.push(shaper.stats());
+        let conn = Connection::spawn_shaped(transport, Chaos::default(), id.to_string(), Some(shaper));
+        tokio::spawn(async move {
+            for _ in 0..40 {
+                if conn.send(ServerMessage::Output { data: "x".repeat(1000), seq: None }).await.is_err() {
+                    break;
+                }
+            }
+            std::future::pending::<()>().await;
+        });
+        peers.push(peer);
+    }
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let counts: Vec<usize> = peers.iter_mut().map(received).collect();
+    assert!(counts.iter().all(|count| *count >= 3), "a session starved: {:?}", counts);
+    assert!(counts.iter().sum::<usize>() <= 12, "the global cap was exceeded: {:?}", counts);
+    assert!(stats.iter().all(|stats| stats.snapshot().delayed_messages > 0));
+}
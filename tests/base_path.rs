@@ -0,0 +1,54 @@
+use rust_terminal_forge::base_path::{BasePath, BasePathError};
+use warp::Filter;
+
+#[test]
+fn paths_are_normalized_and_validated() {
+    assert_eq!(BasePath::parse("/terminal/").unwrap().as_str(), "/terminal");
+    assert_eq!(BasePath::parse("/tools/terminal").unwrap().as_str(), "/tools/terminal");
+    assert!(BasePath::parse("/").unwrap().is_root());
+    for invalid in ["terminal", "/a//b", "/../etc", "/a b", "/a?b"] {
+        assert!(matches!(BasePath::parse(invalid), Err(BasePathError::Invalid(_))), "{}", invalid);
+    }
+
+    let args = |args: &[&str]| BasePath::from_args(args.iter().map(|arg| arg.to_string()));
+    assert!(args(&["server"]).unwrap().is_root());
+    assert_eq!(args(&["server", "--base-path", "/terminal"]).unwrap().as_str(), "/terminal");
+    assert_eq!(args(&["server", "--base-path=/terminal"]).unwrap().as_str(), "/terminal");
+    assert_eq!(args(&["server", "--base-path"]), Err(BasePathError::Missing));
+}
+
+#[test]
+fn urls_are_built_and_stripped_under_the_prefix() {
+    let base = BasePath::parse("/terminal").unwrap();
+    assert_eq!(base.url("/api/approvals/1"), "/terminal/api/approvals/1");
+    assert_eq!(base.strip("/terminal"), Some("/"));
+    assert_eq!(base.strip("/terminal/admin/ws"), Some("/admin/ws"));
+    assert_eq!(base.strip("/terminalx"), None);
+    assert_eq!(base.strip("/admin/ws"), None);
+
+    let root = BasePath::default();
+    assert_eq!(root.url("/api/health"), "/api/health");
+    assert_eq!(root.strip("/admin/ws"), Some("/admin/ws"));
+}
+
+#[test]
+fn pages_get_a_base_and_prefixed_asset_links() {
+    let html = r#"<html><head><script src="/assets/app.js"></script><link href="//cdn.example.com/x.css"></head></html>"#;
+    let injected = BasePath::parse("/terminal").unwrap().inject(html);
+    assert!(injected.starts_with(r#"<html><head><base href="/terminal/"><script>window.__FORGE_BASE_PATH__ = "/terminal";</script>"#));
+    assert!(injected.contains(r#"src="/terminal/assets/app.js""#));
+    assert!(injected.contains(r#"href="//cdn.example.com/x.css""#));
+
+    let unchanged = BasePath::default().inject(html);
+    assert!(unchanged.contains(r#"<base href="/">"#));
+    assert!(unchanged.contains(r#"src="/assets/app.js""#));
+}
+
+#[tokio::test]
+async fn routes_only_match_under_the_prefix() {
+    let routes = BasePath::parse("/tools/terminal").unwrap().filter().and(warp::path!("api" / "health")).map(|| "ok");
+    let reply = |path: &'static str| warp::test::request().path(path).reply(&routes);
+    assert_eq!(reply("/tools/terminal/api/health").await.status(), 200);
+    assert_eq!(reply("/api/health").await.status(), 404);
+    assert_eq!(reply("/tools/api/health").await.status(), 404);
+}
@@ -0,0 +1,37 @@
+use rust_terminal_forge::capabilities::{Capabilities, Capability, OutputFilter};
+
+#[test]
+fn undeclared_clients_get_everything() {
+    let mut filter = OutputFilter::default();
+    let output = "\x1b]52;c;aGVsbG8=\x07\x1b[?1000h\x1b[?2004hok";
+    assert_eq!(filter.filter(output), output);
+}
+
+#[test]
+fn unsupported_sequences_are_dropped_even_when_split() {
+    let mut filter = OutputFilter::new(Capabilities::from_names(&["truecolor"]));
+    let chunks = ["a\x1b]5", "2;c;aGVs", "bG8=\x1b", "\\b\x1b[?10", "06;1h", "c\x1b[?2004lc\x1b[31m!"];
+    let filtered: String = chunks.iter().map(|chunk| filter.filter(chunk)).collect();
+    assert_eq!(filtered, "ab\x1b[?1hcc\x1b[31m!");
+    assert!(!filter.capabilities().supports(Capability::Mouse));
+}
+
+#[test]
+fn capabilities_choose_term_and_color() {
+    let caps = Capabilities::from_names(&["truecolor", "nonsense", "truecolor"]);
+    assert_eq!((caps.term(), caps.color()), ("xterm-256color", "truecolor"));
+    let caps = Capabilities::from_names(&["mouse"]);
+    assert_eq!((caps.term(), caps.color()), ("xterm", "16"));
+}
+
+#[test]
+fn inline_images_need_image_passthrough() {
+    let mut filter = OutputFilter::new(Capabilities::from_names(&["osc52"]));
+    let chunks = ["a\x1bPq#0;2;0;0;0#0~~", "-\x1b\\b\x1b]1337;File=inline=1:aGk=\x07c\x1b_Gf=100;aGk=\x1b", "\\d\x1bP1$r\x1b\\"];
+    let filtered: String = chunks.iter().map(|chunk| filter.filter(chunk)).collect();
+    assert_eq!(filtered, "abcd\x1bP1$r\x1b\\");
+
+    let mut filter = OutputFilter::new(Capabilities::from_names(&["image_passthrough", "binary_frames", "compression"]));
+    assert_eq!(filter.filter("\x1bPq#0~-\x1b\\\x1b_Ga=T;aGk=\x1b\\"), "\x1bPq#0~-\x1b\\\x1b_Ga=T;aGk=\x1b\\");
+    assert!(filter.capabilities().supports(Capability::Compression));
+}
@@ -0,0 +1,47 @@
+use rust_terminal_forge::chaos::{Chaos, ChaosError, ChaosSettings, OutputFault};
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn chaos_is_off_unless_asked_for() {
+    let chaos = Chaos::from_args(args(&["pty-server"])).unwrap();
+    assert!(!chaos.is_enabled());
+    assert_eq!(chaos.set(None, ChaosSettings { drop_every: 1, ..Default::default() }), Err(ChaosError::Disabled));
+    assert_eq!(chaos.output_fault("s", 1), OutputFault::Deliver);
+    assert!(!chaos.fail_api_call("/api/execute"));
+
+    // Debug builds (which tests are) take the flag without confirmation.
+    assert!(Chaos::from_args(args(&["pty-server", "--chaos"])).unwrap().is_enabled());
+}
+
+#[test]
+fn session_settings_override_the_global_ones() {
+    let chaos = Chaos::enabled();
+    chaos.set(None, ChaosSettings { drop_every: 3, ..Default::default() }).unwrap();
+    chaos.set(Some("loud"), ChaosSettings { close_code: Some(4999), ..Default::default() }).unwrap();
+
+    let faults: Vec<OutputFault> = (1..=6).map(|sent| chaos.output_fault("quiet", sent)).collect();
+    assert_eq!(faults.iter().filter(|fault| **fault == OutputFault::Drop).count(), 2);
+    assert_eq!(faults[2], OutputFault::Drop);
+    assert_eq!(chaos.output_fault("loud", 1), OutputFault::Close(4999));
+
+    chaos.forget("loud");
+    assert_eq!(chaos.output_fault("loud", 1), OutputFault::Deliver);
+}
+
+#[test]
+fn delays_and_api_failures_follow_the_settings() {
+    let chaos = Chaos::enabled();
+    chaos.set(None, ChaosSettings { delay_output_ms: 50, api_error_percent: 100, ..Default::default() }).unwrap();
+    for sent in 1..20 {
+        match chaos.output_fault("s", sent) {
+            OutputFault::Delay(delay) => assert!(delay.as_millis() <= 50),
+            fault => panic!("expected a delay, got {:?}", fault),
+        }
+    }
+    assert!(chaos.fail_api_call("/api/health"));
+    chaos.set(None, ChaosSettings::default()).unwrap();
+    assert!(!chaos.fail_api_call("/api/health"));
+}
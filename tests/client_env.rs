@@ -0,0 +1,48 @@
+use rust_terminal_forge::client_env::{self, ClientEnv, ClientEnvError, MAX_VALUE_BYTES, MAX_VARS};
+
+#[test]
+fn variables_are_validated_and_later_values_win() {
+    let mut env = ClientEnv::new();
+    client_env::insert(&mut env, "NODE_ENV=development").unwrap();
+    client_env::insert(&mut env, "OPTS=a=b").unwrap();
+    client_env::insert(&mut env, "NODE_ENV=test").unwrap();
+    assert_eq!(env.len(), 2);
+    assert_eq!(env["NODE_ENV"], "test");
+    assert_eq!(env["OPTS"], "a=b");
+
+    assert_eq!(client_env::insert(&mut env, "NODE_ENV").unwrap_err(), ClientEnvError::Malformed("NODE_ENV".to_string()));
+    for name in ["", "1X", "A-B", "A B", "É"] {
+        assert_eq!(client_env::set(&mut env, name, "x").unwrap_err().code(), "invalid_env", "{:?}", name);
+    }
+    assert_eq!(client_env::set(&mut env, "TMPDIR", "/tmp").unwrap_err().code(), "reserved_env");
+    assert_eq!(client_env::set(&mut env, "NUL", "a\0b").unwrap_err().code(), "invalid_env");
+    assert_eq!(client_env::set(&mut env, "BIG", &"x".repeat(MAX_VALUE_BYTES + 1)).unwrap_err().code(), "invalid_env");
+
+    for i in env.len()..MAX_VARS {
+        client_env::set(&mut env, &format!("V{}", i), "x").unwrap();
+    }
+    assert_eq!(client_env::set(&mut env, "ONE_MORE", "x").unwrap_err().code(), "too_many_env_vars");
+    client_env::set(&mut env, "NODE_ENV", "production").unwrap();
+}
+
+#[test]
+fn terminal_variables_become_an_environment_request() {
+    let mut env = ClientEnv::new();
+    for pair in ["TERM=screen-256color", "COLORTERM=truecolor", "LANG=C", "APP=1"] {
+        client_env::insert(&mut env, pair).unwrap();
+    }
+    let request = client_env::take_terminal(&mut env);
+    assert_eq!(request.term.as_deref(), Some("screen-256color"));
+    assert_eq!(request.lang.as_deref(), Some("C"));
+    assert_eq!(request.lc_all, None);
+    assert_eq!(request.color.as_deref(), Some("truecolor"));
+    assert_eq!(env.keys().collect::<Vec<_>>(), ["APP"]);
+}
+
+#[test]
+fn query_values_are_percent_decoded() {
+    assert_eq!(client_env::percent_decode("GREETING%3Dhello%20w%C3%B6rld"), "GREETING=hello wörld");
+    assert_eq!(client_env::percent_decode("100%25"), "100%");
+    assert_eq!(client_env::percent_decode("50%"), "50%");
+    assert_eq!(client_env::percent_decode("%zz%4"), "%zz%4");
+}
@@ -0,0 +1,81 @@
+use rust_terminal_forge::command_timing::{CommandTimer, MAX_COMMAND_CHARS, TIMING_HISTORY};
+use rust_terminal_forge::shell_integration::{CommandPhase, OutputEvent};
+
+fn boundary(phase: CommandPhase, exit_code: Option<i32>) -> OutputEvent {
+    OutputEvent::Boundary { phase, exit_code }
+}
+
+#[test]
+fn heuristic_prompt_ends_the_submitted_command() {
+    let mut timer = CommandTimer::new(true);
+    assert_eq!(timer.prompt(), None);
+
+    timer.submit("sleep 1\n");
+    // Input typed into the running command does not restart the clock.
+    timer.submit("y");
+    let finished = timer.prompt().unwrap();
+    assert_eq!((finished.command.as_str(), finished.exit_code), ("sleep 1", None));
+    assert_eq!(timer.prompt(), None);
+}
+
+#[test]
+fn markers_time_from_output_start_to_command_end() {
+    let mut timer = CommandTimer::new(true);
+    timer.submit("make");
+    assert!(timer.observe(&[boundary(CommandPhase::PromptStart, None)]).is_empty());
+
+    timer.submit("make test");
+    assert!(timer.observe(&[boundary(CommandPhase::OutputStart, None)]).is_empty());
+    assert_eq!(timer.prompt(), None);
+    let finished = timer.observe(&[OutputEvent::Text("ok\n".to_string()), boundary(CommandPhase::CommandEnd, Some(2))]);
+    assert_eq!(finished.len(), 1);
+    assert_eq!((finished[0].command.as_str(), finished[0].exit_code), ("make test", Some(2)));
+
+    // A stray end marker without a start is not a command.
+    assert!(timer.observe(&[boundary(CommandPhase::CommandEnd, Some(0))]).is_empty());
+}
+
+#[test]
+fn long_command_text_is_truncated() {
+    let mut timer = CommandTimer::new(true);
+    timer.submit(&"é".repeat(MAX_COMMAND_CHARS + 50));
+    let command = timer.prompt().unwrap().command;
+    assert_eq!(command.chars().count(), MAX_COMMAND_CHARS + 1);
+    assert!(command.ends_with('…'));
+}
+
+#[test]
+fn table_records_unknown_durations_as_null() {
+    // No markers and no prompt heuristic: the end of a command is never visible.
+    let mut timer = CommandTimer::new(false);
+    timer.submit("ls");
+    assert_eq!(timer.prompt(), None);
+
+    // With markers, a command that never got its C marker is not guessed either.
+    timer.observe(&[boundary(CommandPhase::PromptStart, None)]);
+    timer.submit("vim");
+    timer.submit("make");
+    timer.observe(&[boundary(CommandPhase::OutputStart, None), boundary(CommandPhase::CommandEnd, Some(0))]);
+
+    let timings = timer.timings();
+    let rows: Vec<_> = timings.commands.iter().map(|r| (r.command.as_str(), r.duration_ms.is_some(), r.exit_code)).collect();
+    assert_eq!(rows, vec![("ls", false, None), ("vim", false, None), ("make", true, Some(0))]);
+    assert_eq!(timings.slowest.unwrap().command, "make");
+}
+
+#[test]
+fn table_is_bounded_and_clearable() {
+    let mut timer = CommandTimer::new(true);
+    for i in 0..TIMING_HISTORY + 5 {
+        timer.submit(&format!("echo {}", i));
+        timer.prompt();
+    }
+    let timings = timer.timings();
+    assert_eq!(timings.commands.len(), TIMING_HISTORY);
+    assert_eq!(timings.commands[0].command, "echo 5");
+
+    timer.clear();
+    let timings = timer.timings();
+    assert!(timings.commands.is_empty());
+    assert_eq!((timings.slowest, timings.busy_ms), (None, 0));
+}
@@ -0,0 +1,29 @@
+use std::net::IpAddr;
+
+use rust_terminal_forge::connection_limits::{ConnectionLimiter, TooManyConnections};
+
+const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(203, 0, 113, 7));
+const OTHER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(198, 51, 100, 1));
+
+#[test]
+fn each_address_gets_its_own_limit() {
+    let limiter = ConnectionLimiter::new(Some(2));
+    let first = limiter.acquire(CLIENT).unwrap();
+    let _second = limiter.acquire(CLIENT).unwrap();
+    assert_eq!(limiter.acquire(CLIENT).unwrap_err(), TooManyConnections { ip: CLIENT, limit: 2 });
+    let _other = limiter.acquire(OTHER).unwrap();
+    assert_eq!(limiter.open(CLIENT), 2);
+
+    drop(first);
+    assert_eq!(limiter.open(CLIENT), 1);
+    let _third = limiter.acquire(CLIENT).unwrap();
+}
+
+#[test]
+fn unlimited_limiters_still_count() {
+    let limiter = ConnectionLimiter::new(None);
+    let permits: Vec<_> = (0..100).map(|_| limiter.acquire(CLIENT).unwrap()).collect();
+    assert_eq!(limiter.open(CLIENT), 100);
+    drop(permits);
+    assert_eq!(limiter.open(CLIENT), 0);
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use rust_terminal_forge::selftest::{self, Outcome, Targets};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+
+/// Both servers on ephemeral loopback ports, with sessions in plain `sh` so the
+/// scenarios do not wait on whatever the user's bash startup files do.
+const CONFIG: &str = r#"
+[listen]
+api = "127.0.0.1:0"
+terminal = "127.0.0.1:0"
+
+[terminal]
+shell = "sh"
+"#;
+
+const E2E_API_TOKEN: &str = "e2e-api-token";
+
+/// Starts a server binary and waits for the address it prints among its logs.
+async fn spawn(binary: &str, config: &PathBuf, args: &[&str]) -> (Child, String) {
+    let mut child = Command::new(binary)
+        .args(args)
+        .env("FORGE_CONFIG", config)
+        .env_remove("FORGE_ADMIN_TOKEN")
+        .env("FORGE_API_TOKEN", E2E_API_TOKEN)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let addr = tokio::time::timeout(Duration::from_secs(10), async {
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if let Some(addr) = line.strip_prefix("listening on ") {
+                return addr.to_string();
+            }
+        }
+        panic!("{} exited before listening", binary);
+    })
+    .await
+    .expect("server did not report its address");
+    // Keep draining so the server never blocks on a full pipe.
+    tokio::spawn(async move { while let Ok(Some(_)) = lines.next_line().await {} });
+    (child, addr)
+}
+
+/// Runs every selftest scenario against both servers started with `args`, reaching them
+/// under `prefix`, and returns their addresses.
+async fn run_suite(args: &[&str], prefix: &str) -> (Child, String, Child, String) {
+    let config = std::env::temp_dir().join(format!("forge-e2e-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&config, CONFIG).unwrap();
+
+    let (api, api_addr) = spawn(env!("CARGO_BIN_EXE_server"), &config, args).await;
+    let (terminal, terminal_addr) = spawn(env!("CARGO_BIN_EXE_pty-server"), &config, args).await;
+    let targets = Targets::new(&format!("http://{}{}", api_addr, prefix), &format!("ws://{}{}", terminal_addr, prefix))
+        .with_api_token(Some(E2E_API_TOKEN.to_string()));
+
+    let results = tokio::time::timeout(Duration::from_secs(60), selftest::run_all(&targets)).await.unwrap();
+    std::fs::remove_file(&config).unwrap();
+
+    let failures: Vec<String> = results.iter().filter(|result| result.failed()).map(ToString::to_string).collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    assert!(results.iter().filter(|result| result.outcome == Outcome::Passed).count() >= 8);
+    (api, api_addr, terminal, terminal_addr)
+}
+
+#[tokio::test]
+async fn selftest_scenarios_pass_against_both_servers() {
+    run_suite(&[], "").await;
+}
+
+#[tokio::test]
+async fn selftest_scenarios_pass_behind_a_base_path_and_unprefixed_paths_404() {
+    let (_api, api_addr, _terminal, terminal_addr) = run_suite(&["--base-path", "/terminal/"], "/terminal").await;
+
+    let uri = format!("http://{}/api/health", api_addr).parse().unwrap();
+    let response = hyper::Client::new().get(uri).await.unwrap();
+    assert_eq!(response.status(), 404);
+    match tokio_tungstenite::connect_async(format!("ws://{}/", terminal_addr)).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 404),
+        other => panic!("expected a 404 handshake, got {:?}", other.map(|(_, response)| response.status())),
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rust_terminal_forge::admin::AdminRejection;
+use rust_terminal_forge::approvals::{ApprovalError, ApprovalStatus};
+use rust_terminal_forge::chaos::ChaosError;
+use rust_terminal_forge::client_env::ClientEnvError;
+use rust_terminal_forge::connection_limits::TooManyConnections;
+use rust_terminal_forge::error_catalog::{self, ClientError, CATALOG};
+use rust_terminal_forge::executor::ExecError;
+use rust_terminal_forge::log_control::LogLevelError;
+use rust_terminal_forge::multiplex::MuxError;
+use rust_terminal_forge::protocol::{CloseReason, DecodeError};
+use rust_terminal_forge::repl::ReplError;
+use rust_terminal_forge::scrollback::SearchError;
+use rust_terminal_forge::session_env::EnvironmentError;
+use rust_terminal_forge::session_names::NameError;
+use rust_terminal_forge::session_registry::RegistryError;
+use rust_terminal_forge::session_tags::TagError;
+use rust_terminal_forge::templates::TemplateError;
+use rust_terminal_forge::workspaces::WorkspaceError;
+
+fn io_error() -> std::io::Error {
+    std::io::Error::other("disk on fire")
+}
+
+/// One of every client-visible error variant; add new variants here.
+fn every_error() -> Vec<ClientError> {
+    let mut errors: Vec<ClientError> = vec![
+        (&AdminRejection::Disabled).into(),
+        (&AdminRejection::ApiDisabled).into(),
+        (&AdminRejection::Unauthorized).into(),
+        (&ApprovalError::NotFound("a1".into())).into(),
+        (&ApprovalError::Expired("a1".into())).into(),
+        (&ApprovalError::AlreadyDecided { id: "a1".into(), status: ApprovalStatus::Denied }).into(),
+        (&ChaosError::Disabled).into(),
+        (&ChaosError::NotConfirmed).into(),
+        (&LogLevelError::InvalidDirective("nope=loud".into())).into(),
+        (&LogLevelError::Reload("gone".into())).into(),
+        (&ExecError::Spawn { program: "sh".into(), source: io_error() }).into(),
+        (&ExecError::Timeout(Duration::from_secs(30))).into(),
+        (&ExecError::QueueFull { running: 4, queued: 32, retry_after: Duration::from_secs(5) }).into(),
+        (&ReplError::NotFound("r1".into())).into(),
+        (&ReplError::LimitReached(4)).into(),
+        (&ReplError::Spawn(io_error())).into(),
+        (&ReplError::Exited).into(),
+        (&ReplError::Timeout(Duration::from_secs(30))).into(),
+        (&ReplError::Io(io_error())).into(),
+        (&ReplError::InitScript { path: PathBuf::from("/etc/init.sh"), source: io_error() }).into(),
+        (&ReplError::ClientInitDisabled).into(),
+        (&EnvironmentError::InvalidTerm("vt52".into())).into(),
+        (&EnvironmentError::InvalidLocale("xx".into())).into(),
+        (&EnvironmentError::InvalidColor("8".into())).into(),
+        (&TemplateError::Unknown("psql".into())).into(),
+        (&TemplateError::NotOverridable { template: "psql".into(), field: "shell".into() }).into(),
+        (&TagError::Malformed("x".into())).into(),
+        (&TagError::InvalidKey("x".into())).into(),
+        (&TagError::InvalidValue("x".into())).into(),
+        (&TagError::TooMany).into(),
+        (&NameError("\x07".into())).into(),
+        (&WorkspaceError::InvalidName("a b".into())).into(),
+        (&WorkspaceError::NotFound("project-a".into())).into(),
+        (&ClientEnvError::Malformed("NODE_ENV".into())).into(),
+        (&ClientEnvError::InvalidName("1X".into())).into(),
+        (&ClientEnvError::InvalidValue("BIG".into())).into(),
+        (&ClientEnvError::Reserved("TMPDIR".into())).into(),
+        (&ClientEnvError::TooMany).into(),
+        (&DecodeError::InvalidJson("eof".into())).into(),
+        (&DecodeError::MissingType).into(),
+        (&DecodeError::UnknownType("teleport".into())).into(),
+        (&DecodeError::InvalidFields { msg_type: "resize".into(), error: "cols".into() }).into(),
+        (&DecodeError::InvalidBinary("unknown channel 7".into())).into(),
+        (&MuxError::Decode(DecodeError::MissingType)).into(),
+        (&MuxError::MissingChannel).into(),
+        (&MuxError::ChannelInUse(1)).into(),
+        (&MuxError::UnknownChannel(9)).into(),
+        (&SearchError::InvalidPattern("(".into())).into(),
+        (&RegistryError::NotFound("s1".into())).into(),
+        (&RegistryError::AlreadyExists("s1".into())).into(),
+        (&RegistryError::AlreadyAttached("s1".into())).into(),
+        (&RegistryError::Full(100)).into(),
+        (&TooManyConnections { ip: [203, 0, 113, 7].into(), limit: 4 }).into(),
+        ClientError::new("origin_not_allowed"),
+        ClientError::new("observe_disabled"),
+        ClientError::new("execute_job_not_found").with("id", "j1"),
+    ];
+    errors.extend(
+        [
+            CloseReason::Normal,
+            CloseReason::ServerShutdown,
+            CloseReason::ProtocolError,
+            CloseReason::PolicyViolation,
+            CloseReason::MessageTooBig,
+            CloseReason::ServerFull,
+            CloseReason::AdminDisconnect,
+            CloseReason::IdleTimeout,
+            CloseReason::Superseded,
+            CloseReason::MaxLifetime,
+            CloseReason::ResourceLimit,
+            CloseReason::Chaos(4999),
+        ]
+        .map(ClientError::from),
+    );
+    errors
+}
+
+#[test]
+fn every_client_visible_error_has_a_catalog_entry() {
+    for error in every_error() {
+        assert!(error_catalog::lookup(error.code).is_some(), "'{}' is not in the catalog", error.code);
+        assert!(!error.message.contains('{'), "'{}' left a placeholder unfilled: {}", error.code, error.message);
+    }
+}
+
+#[test]
+fn catalog_codes_are_unique_snake_case() {
+    let mut seen = HashSet::new();
+    for entry in CATALOG {
+        assert!(seen.insert(entry.code), "'{}' is in the catalog twice", entry.code);
+        assert!(entry.code.chars().all(|c| c.is_ascii_lowercase() || c == '_'), "'{}' is not snake_case", entry.code);
+    }
+}
+
+#[test]
+fn parameters_fill_the_message_and_travel_alongside() {
+    let error = ClientError::new("repl_limit_reached").with("limit", 4);
+    assert_eq!(error.message, "too many REPLs open (limit 4)");
+    assert_eq!(error.params.get("limit").map(String::as_str), Some("4"));
+    assert_eq!(error_catalog::placeholders("template '{template}' does not let clients set '{field}'"), ["template", "field"]);
+    assert_eq!(
+        serde_json::to_value(ClientError::new("repl_exited")).unwrap(),
+        serde_json::json!({ "code": "repl_exited", "message": "REPL shell exited" })
+    );
+}
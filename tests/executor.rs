@@ -0,0 +1,130 @@
+#![cfg(unix)]
+
+use std::time::{Duration, Instant};
+
+use rust_terminal_forge::config::ExecuteConfig;
+use rust_terminal_forge::executor::{Capture, ExecError, Executor, OutputStream};
+use rust_terminal_forge::policy::CommandSpec;
+use rust_terminal_forge::shell_env::ShellEnv;
+
+fn executor(timeout_secs: u64) -> Executor {
+    Executor::from_config(&ExecuteConfig { timeout_secs, ..ExecuteConfig::default() })
+}
+
+fn shell(command: &str) -> CommandSpec {
+    CommandSpec::Shell(command.to_string())
+}
+
+fn argv(program: &str, args: &[&str]) -> CommandSpec {
+    CommandSpec::Argv { program: program.to_string(), args: args.iter().map(|arg| arg.to_string()).collect() }
+}
+
+#[tokio::test]
+async fn streams_are_kept_apart_and_the_exit_code_is_real() {
+    let output = executor(5).run(&shell("echo out; echo err >&2; exit 7")).await.unwrap();
+    assert_eq!((output.stdout.as_str(), output.stderr.as_str(), output.exit_code), ("out\n", "err\n", 7));
+}
+
+#[tokio::test]
+async fn signals_are_reported_like_the_shell_does() {
+    let output = executor(5).run(&shell("kill -9 $$")).await.unwrap();
+    assert_eq!(output.exit_code, 128 + 9);
+}
+
+#[tokio::test]
+async fn argv_mode_runs_no_shell() {
+    let output = executor(5).run(&argv("printf", &["%s|", "$(id)", "; echo no", "*"])).await.unwrap();
+    assert_eq!(output.stdout, "$(id)|; echo no|*|");
+    match executor(5).run(&argv("forge-no-such-program", &[])).await {
+        Err(ExecError::Spawn { program, .. }) => assert_eq!(program, "forge-no-such-program"),
+        other => panic!("expected a spawn error, got {:?}", other),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn commands_past_the_timeout_are_killed_with_what_they_started() {
+    let marker = std::env::temp_dir().join(format!("forge-executor-{}", std::process::id()));
+    let command = format!("(sleep 2; touch {}) & sleep 30", marker.display());
+    let started = Instant::now();
+    assert!(matches!(executor(1).run(&shell(&command)).await, Err(ExecError::Timeout(timeout)) if timeout == Duration::from_secs(1)));
+    assert!(started.elapsed() < Duration::from_secs(2), "the request should end at the timeout");
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert!(!marker.exists(), "the background job outlived the timeout");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn background_jobs_do_not_hold_the_request_open() {
+    let started = Instant::now();
+    let output = executor(30).run(&shell("sleep 100 & echo started")).await.unwrap();
+    assert_eq!((output.stdout.as_str(), output.exit_code), ("started\n", 0));
+    assert!(started.elapsed() < Duration::from_secs(5), "the request waited on the background job");
+}
+
+#[tokio::test]
+async fn output_past_the_limit_is_cut_and_flagged() {
+    let executor = Executor::from_config(&ExecuteConfig { max_output_bytes: 1000, ..ExecuteConfig::default() });
+    let output = executor.run(&shell("yes | head -c 100000; echo err >&2")).await.unwrap();
+    assert_eq!((output.stdout.len(), output.stderr.as_str(), output.truncated), (1000, "err\n", true));
+    assert!(!executor.run(&shell("echo short")).await.unwrap().truncated);
+}
+
+#[tokio::test]
+async fn events_keep_both_streams_in_the_order_they_were_written() {
+    let executor = executor(5);
+    let output = executor.run_with(executor.enqueue().unwrap(), &shell("echo one; sleep 0.2; echo two >&2; sleep 0.2; echo three"), Capture::Events).await.unwrap();
+    let events = output.events.unwrap();
+    let timeline: Vec<(OutputStream, &str)> = events.iter().map(|event| (event.stream, event.data.as_str())).collect();
+    assert_eq!(timeline, [(OutputStream::Stdout, "one\n"), (OutputStream::Stderr, "two\n"), (OutputStream::Stdout, "three\n")]);
+    assert!(events[1].t_ms >= 150 && events[2].t_ms >= events[1].t_ms + 150, "timestamps {:?}", events);
+    assert_eq!((output.stdout.as_str(), output.stderr.as_str(), output.dropped_events, output.truncated), ("", "", 0, false));
+}
+
+#[tokio::test]
+async fn events_past_the_limit_drop_the_oldest() {
+    let executor = Executor::from_config(&ExecuteConfig { max_output_bytes: 12, ..ExecuteConfig::default() });
+    let command = shell("for i in 1 2 3 4 5; do echo line$i; sleep 0.05; done");
+    let output = executor.run_with(executor.enqueue().unwrap(), &command, Capture::Events).await.unwrap();
+    let data: Vec<String> = output.events.unwrap().into_iter().map(|event| event.data).collect();
+    assert_eq!(data, ["line4\n", "line5\n"]);
+    assert_eq!((output.dropped_events, output.truncated), (3, true));
+}
+
+#[tokio::test]
+async fn commands_only_see_the_allowlisted_environment() {
+    std::env::set_var("FORGE_EXECUTOR_TEST_SECRET", "hunter2");
+    let shell_env = ShellEnv::default();
+    let output = executor(5).with_shell_env(shell_env.clone()).run(&argv("env", &[])).await.unwrap();
+    let names: Vec<&str> = output.stdout.lines().filter_map(|line| line.split_once('=').map(|(name, _)| name)).collect();
+    assert!(!names.is_empty());
+    assert!(names.iter().all(|name| shell_env.allows(name)), "unexpected variables in {:?}", names);
+    assert!(!output.stdout.contains("hunter2"));
+}
+
+#[tokio::test]
+async fn commands_past_the_slots_wait_in_line_and_leave_it_when_dropped() {
+    let executor = Executor::from_config(&ExecuteConfig { max_concurrent: 1, max_queued: 2, ..ExecuteConfig::default() });
+    let mut first = executor.enqueue().unwrap();
+    first.ready().await;
+    assert_eq!(first.position(), None);
+
+    let (second, third) = (executor.enqueue().unwrap(), executor.enqueue().unwrap());
+    assert_eq!((second.position(), third.position()), (Some(1), Some(2)));
+    assert!(matches!(executor.enqueue(), Err(ExecError::QueueFull { running: 1, queued: 2, .. })));
+
+    // A caller that goes away gives up its place without its command starting.
+    drop(second);
+    assert_eq!(third.position(), Some(1));
+    let waiting = tokio::spawn({
+        let executor = executor.clone();
+        async move { executor.run_with(third, &shell("echo ran"), Capture::Text).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    drop(first);
+    let output = waiting.await.unwrap();
+    assert_eq!(output.stdout, "ran\n");
+    assert!(output.queue_wait_ms >= 200, "waited only {}ms", output.queue_wait_ms);
+    assert_eq!(executor.run(&shell("true")).await.unwrap().queue_wait_ms, 0);
+}
@@ -0,0 +1,42 @@
+use rust_terminal_forge::input_line::InputLine;
+
+#[test]
+fn lines_end_at_the_first_enter() {
+    let mut line = InputLine::default();
+    assert_eq!(line.feed("ls -"), None);
+    assert_eq!(line.current(), "ls -");
+    assert_eq!(line.feed("la\rpwd\r"), Some((3, "ls -la".to_string())));
+    assert_eq!(line.feed("pwd\r"), Some((4, "pwd".to_string())));
+    assert_eq!(line.feed("\n"), Some((1, String::new())));
+}
+
+#[test]
+fn editing_keys_change_the_line() {
+    let mut line = InputLine::default();
+    line.feed("rm -rf /tmpx\x7f");
+    assert_eq!(line.current(), "rm -rf /tmp");
+    line.feed("\x17");
+    assert_eq!(line.current(), "rm -rf ");
+    line.feed("\x15echo hi\x03");
+    assert_eq!(line.current(), "");
+    line.feed("echo\x01 ok");
+    assert_eq!(line.current(), "echo ok");
+}
+
+#[test]
+fn escape_sequences_are_skipped_even_across_chunks() {
+    let mut line = InputLine::default();
+    line.feed("ab\x1b[");
+    line.feed("1;5Dc\x1bOAd\x1bxe");
+    assert_eq!(line.current(), "abcde");
+}
+
+#[test]
+fn restored_lines_continue_where_they_were() {
+    let mut line = InputLine::default();
+    let (_, finished) = line.feed("sudo reboot\r").unwrap();
+    line.restore(finished);
+    assert_eq!(line.current(), "sudo reboot");
+    line.clear();
+    assert_eq!(line.current(), "");
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use rust_terminal_forge::config::{IdleTimeoutConfig, LifetimeOverrideConfig, MaxLifetimeConfig};
+use rust_terminal_forge::lifetime::{IdlePolicy, Lifetime, LifetimeEvent, LifetimePolicy};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[tokio::test(start_paused = true)]
+async fn warnings_come_at_60_15_and_5_minutes_then_expiry() {
+    let mut lifetime = Lifetime::new(120 * MINUTE);
+    assert_eq!(lifetime.poll(), None);
+
+    let mut events = Vec::new();
+    while events.last() != Some(&LifetimeEvent::Expired) {
+        tokio::time::sleep_until(lifetime.next_deadline()).await;
+        events.push(lifetime.poll().unwrap());
+    }
+    assert_eq!(
+        events,
+        [60, 15, 5]
+            .map(|mins| LifetimeEvent::Warning { remaining: mins * MINUTE })
+            .into_iter()
+            .chain([LifetimeEvent::Expired])
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn short_lifetimes_skip_warnings_already_past() {
+    let mut lifetime = Lifetime::new(10 * MINUTE);
+    tokio::time::sleep_until(lifetime.next_deadline()).await;
+    assert_eq!(lifetime.poll(), Some(LifetimeEvent::Warning { remaining: 5 * MINUTE }));
+    tokio::time::sleep_until(lifetime.next_deadline()).await;
+    assert_eq!(lifetime.poll(), Some(LifetimeEvent::Expired));
+}
+
+#[test]
+fn tokens_select_overrides_and_everyone_else_gets_the_default() {
+    std::env::set_var("FORGE_TEST_LIFETIME_TOKEN", "svc-secret");
+    let policy = LifetimePolicy::from_config(&MaxLifetimeConfig {
+        secs: Some(8 * 3600),
+        overrides: vec![
+            LifetimeOverrideConfig { token_env: "FORGE_TEST_LIFETIME_TOKEN".to_string(), secs: 24 * 3600 },
+            LifetimeOverrideConfig { token_env: "FORGE_TEST_LIFETIME_UNSET".to_string(), secs: 1 },
+        ],
+    });
+    assert_eq!(policy.for_token(Some("svc-secret")), Some(Duration::from_secs(24 * 3600)));
+    assert_eq!(policy.for_token(Some("guess")), Some(Duration::from_secs(8 * 3600)));
+    assert_eq!(policy.for_token(Some("")), Some(Duration::from_secs(8 * 3600)));
+    assert_eq!(policy.for_token(None), Some(Duration::from_secs(8 * 3600)));
+    assert_eq!(LifetimePolicy::default().for_token(Some("svc-secret")), None);
+}
+
+#[test]
+fn idle_sessions_expire_after_the_timeout() {
+    assert_eq!(IdlePolicy::from_config(&IdleTimeoutConfig::default()), None);
+    let policy = IdlePolicy::from_config(&IdleTimeoutConfig { secs: Some(10 * 60), check_interval_secs: 30 }).unwrap();
+    assert_eq!(policy.interval, Duration::from_secs(30));
+
+    let now = Utc::now();
+    assert!(!policy.expired(now - chrono::Duration::minutes(9), now));
+    assert!(policy.expired(now - chrono::Duration::minutes(10), now));
+    assert!(!policy.expired(now + chrono::Duration::minutes(1), now), "activity after the check is not idle");
+}
+
+#[test]
+fn idle_checks_run_at_least_once_per_timeout() {
+    let policy = IdlePolicy::from_config(&IdleTimeoutConfig { secs: Some(5), check_interval_secs: 30 }).unwrap();
+    assert_eq!(policy.interval, Duration::from_secs(5));
+    let policy = IdlePolicy::from_config(&IdleTimeoutConfig { secs: Some(0), check_interval_secs: 0 }).unwrap();
+    assert_eq!(policy.interval, Duration::from_secs(1));
+}
@@ -0,0 +1,34 @@
+use rust_terminal_forge::links::{Link, LinkScanner, MAX_LINKS_PER_FLUSH};
+
+fn url(s: &str) -> Link {
+    Link::Url { text: s.to_string(), href: s.to_string() }
+}
+
+#[test]
+fn detects_urls_and_paths_through_ansi() {
+    let mut scanner = LinkScanner::default();
+    let batch = scanner.scan("(\x1b[4mhttps://x.dev/a?b=1\x1b[0m) error at ./lib/app.ts:3 and e.g. v1.2 README.md\n");
+    assert_eq!(
+        batch.items,
+        vec![
+            url("https://x.dev/a?b=1"),
+            Link::File { text: "./lib/app.ts:3".to_string(), path: "./lib/app.ts".to_string(), line: Some(3), col: None },
+        ]
+    );
+}
+
+#[test]
+fn tokens_split_across_chunks_are_held_back() {
+    let mut scanner = LinkScanner::default();
+    assert!(scanner.scan("go to https://exa").items.is_empty());
+    assert_eq!(scanner.scan("mple.com/x now\n").items, vec![url("https://example.com/x")]);
+}
+
+#[test]
+fn flushes_are_capped() {
+    let mut scanner = LinkScanner::default();
+    let flood: String = (0..100).map(|i| format!("https://h{}.example\n", i)).collect();
+    let batch = scanner.scan(&flood);
+    assert_eq!(batch.items.len(), MAX_LINKS_PER_FLUSH);
+    assert!(batch.truncated);
+}
@@ -0,0 +1,32 @@
+use rust_terminal_forge::multiplex::{self, MuxError, MuxFrame};
+use rust_terminal_forge::protocol::{CloseReason, DecodeError, ServerMessage};
+use serde_json::json;
+
+#[test]
+fn frames_name_their_channel() {
+    assert_eq!(
+        MuxFrame::decode(r#"{"type":"open_channel","channel":1,"query":"cols=120&rows=40"}"#).unwrap(),
+        MuxFrame::Open { channel: 1, query: "cols=120&rows=40".to_string() }
+    );
+    assert_eq!(MuxFrame::decode(r#"{"type":"open_channel","channel":2}"#).unwrap(), MuxFrame::Open { channel: 2, query: String::new() });
+    assert_eq!(MuxFrame::decode(r#"{"type":"close_channel","channel":2}"#).unwrap(), MuxFrame::Close { channel: 2 });
+
+    let MuxFrame::Message { channel, text } = MuxFrame::decode(r#"{"type":"input","channel":3,"data":"ls\r"}"#).unwrap() else { panic!("expected a message") };
+    assert_eq!(channel, 3);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap(), json!({ "type": "input", "data": "ls\r" }));
+
+    assert_eq!(MuxFrame::decode(r#"{"type":"input","data":"ls\r"}"#).unwrap_err(), MuxError::MissingChannel);
+    assert_eq!(MuxFrame::decode(r#"{"type":"input","channel":-1}"#).unwrap_err().code(), "missing_channel");
+    assert!(matches!(MuxFrame::decode("{nope").unwrap_err(), MuxError::Decode(DecodeError::InvalidJson(_))));
+    assert_eq!(MuxFrame::decode(r#"{"type":"open_channel","channel":1,"query":7}"#).unwrap_err().code(), "invalid_fields");
+}
+
+#[test]
+fn server_messages_carry_their_channel() {
+    let output: serde_json::Value = serde_json::from_str(&multiplex::encode(4, &ServerMessage::Output { data: "hi".to_string(), seq: None })).unwrap();
+    assert_eq!(output, json!({ "type": "output", "data": "hi", "channel": 4 }));
+
+    let closed: serde_json::Value = serde_json::from_str(&multiplex::channel_closed(4, Some(CloseReason::IdleTimeout))).unwrap();
+    assert_eq!(closed, json!({ "type": "channel_closed", "channel": 4, "code": 4001, "reason": "idle_timeout" }));
+    assert_eq!(MuxError::UnknownChannel(4).channel(), Some(4));
+}
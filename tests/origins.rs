@@ -0,0 +1,23 @@
+use rust_terminal_forge::config::ListenConfig;
+use rust_terminal_forge::origins::{AllowedOrigins, OriginError};
+
+#[test]
+fn only_listed_origins_pass_and_requests_without_one_are_left_alone() {
+    let origins = AllowedOrigins::from_config(&ListenConfig::default().allowed_origins).unwrap();
+    assert!(origins.allows(None));
+    assert!(origins.allows(Some("http://localhost:8080")));
+    assert!(origins.allows(Some("HTTP://LOCALHOST:8080")));
+    for origin in ["http://localhost:8081", "https://localhost:8080", "https://evil.example", "null", ""] {
+        assert!(!origins.allows(Some(origin)), "{:?} passed", origin);
+    }
+}
+
+#[test]
+fn origins_must_be_scheme_host_and_port() {
+    let parse = |origin: &str| AllowedOrigins::from_config(&[origin.to_string()]);
+    assert!(parse("https://forge.example.com").is_ok());
+    assert!(parse("http://[::1]:3001").is_ok());
+    for origin in ["forge.example.com", "https://forge.example.com/app", "http://host:99999", "*"] {
+        assert_eq!(parse(origin).unwrap_err(), OriginError::Invalid(origin.to_string()));
+    }
+}
@@ -0,0 +1,44 @@
+use rust_terminal_forge::ports::{PortAnnouncementScanner, PortEvent, ProcPortScanner};
+
+#[test]
+fn announcements_are_found_across_chunks_once_each() {
+    let mut scanner = PortAnnouncementScanner::default();
+    assert!(scanner.scan("\x1b[32m  VITE v5.0.0\x1b[0m  ready in 300 ms\n\n  ➜  Local:   http://local").is_empty());
+    assert_eq!(
+        scanner.scan("host:5173/\n  ➜  Network: use --host to expose\n"),
+        vec![PortEvent::Opened { port: 5173, pid: None, hint: Some("vite".to_string()) }]
+    );
+    assert!(scanner.scan("  ➜  Local:   http://localhost:5173/\n").is_empty());
+
+    let events = scanner.scan("Puma starting\n* Listening on http://127.0.0.1:3000\nServer listening on port 8080\n");
+    assert_eq!(
+        events,
+        vec![
+            PortEvent::Opened { port: 3000, pid: None, hint: Some("rails".to_string()) },
+            PortEvent::Opened { port: 8080, pid: None, hint: Some("rails".to_string()) },
+        ]
+    );
+    assert!(scanner.scan("compiled 12 modules in 300 ms on port-forwarded host\n").is_empty());
+}
+
+#[test]
+fn proc_scan_only_sees_the_session_process_tree() {
+    if !ProcPortScanner::supported() {
+        return;
+    }
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let me = std::process::id();
+
+    let mut own = ProcPortScanner::new(me);
+    assert!(own.poll().iter().any(|event| matches!(event, PortEvent::Opened { port: p, pid: Some(pid), .. } if *p == port && *pid == me)));
+
+    let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+    let mut other = ProcPortScanner::new(child.id());
+    assert!(!other.poll().iter().any(|event| matches!(event, PortEvent::Opened { port: p, .. } if *p == port)));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    drop(listener);
+    assert!(own.poll().contains(&PortEvent::Closed { port, pid: Some(me) }));
+}
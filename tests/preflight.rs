@@ -0,0 +1,57 @@
+use std::net::TcpListener;
+
+use rust_terminal_forge::config::ForgeConfig;
+use rust_terminal_forge::preflight::{self, Check, CheckOutcome, Preflight};
+
+fn config(toml: &str) -> ForgeConfig {
+    toml::from_str(toml).unwrap()
+}
+
+fn outcome(check: Check, config: &ForgeConfig) -> CheckOutcome {
+    Preflight::new(&[check]).run(config).remove(0).outcome
+}
+
+#[test]
+fn invalid_sections_and_missing_programs_fail() {
+    let broken = config(
+        r#"
+        [[templates]]
+        name = "psql"
+        command = ["no-such-program-anywhere", "app"]
+        cwd = "/no/such/dir"
+        overridable = ["cwd"]
+        "#,
+    );
+    assert!(matches!(outcome(preflight::CONFIG, &broken), CheckOutcome::Failed(why) if why.contains("'cwd'")));
+    assert!(matches!(outcome(preflight::SHELLS, &broken), CheckOutcome::Failed(why) if why.contains("no-such-program-anywhere")));
+    assert!(matches!(outcome(preflight::TEMPLATE_DIRS, &broken), CheckOutcome::Failed(why) if why.contains("/no/such/dir")));
+
+    let fine = ForgeConfig::default();
+    assert!(matches!(outcome(preflight::CONFIG, &fine), CheckOutcome::Passed(_)));
+    assert!(matches!(outcome(preflight::SHELLS, &fine), CheckOutcome::Passed(_)));
+    assert!(matches!(outcome(preflight::TEMPLATE_DIRS, &fine), CheckOutcome::Skipped(_)));
+}
+
+#[test]
+fn listen_fails_while_an_address_is_taken() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = taken.local_addr().unwrap().port();
+    let busy = config(&format!("[listen]\napi = \"127.0.0.1:0\"\nterminal = \"127.0.0.1:{}\"\n", port));
+    assert!(matches!(outcome(preflight::LISTEN, &busy), CheckOutcome::Failed(why) if why.starts_with("terminal")));
+
+    drop(taken);
+    assert!(matches!(outcome(preflight::LISTEN, &busy), CheckOutcome::Passed(_)));
+}
+
+#[test]
+fn registered_checks_run_after_the_built_in_ones() {
+    let mut checks = Preflight::default();
+    checks.register(Check { name: "custom", run: |_| CheckOutcome::Failed("always".to_string()) });
+    let results = checks.run(&ForgeConfig::default());
+
+    let last = results.last().unwrap();
+    assert_eq!(last.name, "custom");
+    assert!(last.failed());
+    assert_eq!(last.to_string(), "FAIL  custom: always");
+    assert!(results.iter().any(|result| result.name == "sqlite" && !result.failed()));
+}
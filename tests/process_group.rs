@@ -0,0 +1,98 @@
+#[cfg(target_os = "linux")]
+use std::io::Read;
+#[cfg(target_os = "linux")]
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rust_terminal_forge::config::{DisconnectConfig, OrphanedIoConfig, OrphanedIoMode};
+#[cfg(target_os = "linux")]
+use rust_terminal_forge::process_group;
+use rust_terminal_forge::process_group::{DisconnectPolicy, OrphanedIoPolicy};
+
+/// Runs `script` as a session leader, waits for the shell itself to exit and returns its
+/// pid (the session id) with the still-open stdout standing in for the PTY.
+#[cfg(target_os = "linux")]
+fn orphan(script: &str) -> (u32, std::process::ChildStdout) {
+    let mut command = Command::new("bash");
+    command.args(["-c", script]).stdout(Stdio::piped());
+    let mut shell = process_group::new_session(&mut command).spawn().unwrap();
+    let stdout = shell.stdout.take().unwrap();
+    assert!(shell.wait().unwrap().success());
+    (shell.id(), stdout)
+}
+
+#[test]
+fn the_policy_follows_the_configured_mode() {
+    let since = Instant::now();
+    let keep = OrphanedIoConfig { mode: OrphanedIoMode::KeepStreaming, ..Default::default() };
+    assert_eq!(OrphanedIoPolicy::from_config(&keep).close_at(since), None);
+    let close = OrphanedIoConfig { mode: OrphanedIoMode::Close, grace_secs: 5 };
+    assert_eq!(OrphanedIoPolicy::from_config(&close).close_at(since), Some(since + Duration::from_secs(5)));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn keep_streaming_reads_the_leftovers_output_after_the_shell_exits() {
+    let (sid, mut stdout) = orphan("(sleep 0.3; echo late) &");
+    assert!(!process_group::leftovers(sid).is_empty(), "the background job should outlive the shell");
+    let keep = OrphanedIoConfig { mode: OrphanedIoMode::KeepStreaming, ..Default::default() };
+    assert_eq!(OrphanedIoPolicy::from_config(&keep).close_at(Instant::now()), None);
+
+    let mut output = String::new();
+    stdout.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "late\n");
+    // The pipe closes as the job exits, a moment before /proc stops listing it.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !process_group::leftovers(sid).is_empty() {
+        assert!(Instant::now() < deadline, "the background job never exited");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn close_kills_every_leftover_after_the_grace_period() {
+    let (sid, mut stdout) = orphan("sleep 1000 &");
+    let orphaned_at = Instant::now();
+    assert_eq!(process_group::leftovers(sid).len(), 1);
+
+    let close = OrphanedIoConfig { mode: OrphanedIoMode::Close, grace_secs: 0 };
+    let close_at = OrphanedIoPolicy::from_config(&close).close_at(orphaned_at).unwrap();
+    std::thread::sleep(close_at.saturating_duration_since(Instant::now()));
+    assert_eq!(process_group::signal_session(sid, libc::SIGKILL), 1);
+
+    let mut output = String::new();
+    stdout.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "", "the pipe closes once the leftover is gone");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !process_group::leftovers(sid).is_empty() {
+        assert!(Instant::now() < deadline, "leftovers survived SIGKILL");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn sessions_hang_up_at_once_unless_they_linger() {
+    let since = Instant::now();
+    let policy = DisconnectPolicy::from_config(&DisconnectConfig::default());
+    assert_eq!(policy.hang_up_at(since), None);
+    assert_eq!(policy.kill_grace, Duration::from_secs(2));
+    let linger = DisconnectPolicy::from_config(&DisconnectConfig { linger_secs: 30, kill_grace_secs: 5 });
+    assert_eq!(linger.hang_up_at(since), Some(since + Duration::from_secs(30)));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn killed_leftovers_are_adopted_and_reaped() {
+    process_group::become_subreaper().unwrap();
+    let (sid, _stdout) = orphan("sleep 1000 &");
+    let [leftover] = process_group::leftovers(sid)[..] else { panic!("expected one leftover") };
+    assert_eq!(process_group::signal_session(sid, libc::SIGKILL), 1);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while std::path::Path::new(&format!("/proc/{}", leftover)).exists() {
+        assert!(Instant::now() < deadline, "the killed leftover was never reaped");
+        process_group::reap_orphans();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
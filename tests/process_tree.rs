@@ -0,0 +1,56 @@
+#[cfg(target_os = "linux")]
+use std::process::Command;
+
+use rust_terminal_forge::process_tree::{self, ProcessInfo, ProcessSampler};
+
+#[cfg(target_os = "linux")]
+#[test]
+fn children_show_up_below_their_parent_and_samples_are_cached() {
+    let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+    let mut sampler = ProcessSampler::new(std::process::id());
+
+    let processes = sampler.sample();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(processes[0].pid, std::process::id());
+    let sleep = processes.iter().find(|process| process.pid == child.id()).expect("child missing from the tree");
+    assert_eq!(sleep.ppid, std::process::id());
+    assert_eq!(sleep.command, "sleep 30");
+    assert!(sleep.rss_bytes > 0);
+    assert_eq!(sampler.sample(), processes, "a second sample within the TTL is the cached one");
+}
+
+#[test]
+fn a_missing_root_has_no_tree() {
+    assert!(ProcessSampler::new(u32::MAX).sample().is_empty());
+}
+
+fn process(pid: u32, pgid: u32, command: &str, foreground: bool) -> ProcessInfo {
+    ProcessInfo {
+        pid,
+        ppid: 10,
+        pgid,
+        command: command.to_string(),
+        state: "S".to_string(),
+        cpu_percent: 1.5,
+        rss_bytes: 1000,
+        foreground,
+    }
+}
+
+#[test]
+fn jobs_group_processes_by_process_group_without_the_shell() {
+    let processes = [
+        process(10, 10, "bash", false),
+        process(31, 30, "grep error", false),
+        process(30, 30, "tail -f app.log", true),
+        process(20, 20, "sleep 1000", false),
+    ];
+    let jobs = process_tree::jobs(&processes, 10);
+    assert_eq!(jobs.iter().map(|job| job.pgid).collect::<Vec<_>>(), [20, 30]);
+    let pipeline = &jobs[1];
+    assert_eq!(pipeline.command, "tail -f app.log");
+    assert!(pipeline.foreground && !jobs[0].foreground);
+    assert_eq!((pipeline.cpu_percent, pipeline.rss_bytes, pipeline.processes.len()), (3.0, 2000, 2));
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use rust_terminal_forge::protocol::ClientMessage;
+use rust_terminal_forge::protocol_capture::{self, Capture, CaptureError, CaptureWriter, CapturedMessage, CAPTURE_VERSION};
+use serde_json::json;
+
+fn temp_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("forge-capture-{}", uuid::Uuid::new_v4()))
+}
+
+#[test]
+fn captures_round_trip_with_secrets_redacted() {
+    let dir = temp_dir();
+    let mut writer = CaptureWriter::create(&dir, "abc").unwrap();
+    writer.record(&ClientMessage::Input { data: "mysql -u root -p'hunter2' prod".to_string() }).unwrap();
+    writer.record(&ClientMessage::Resize { cols: 100, rows: 30 }).unwrap();
+    drop(writer);
+
+    let capture = Capture::read(&dir.join("abc.jsonl")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!((capture.header.version, capture.header.session_id.as_str()), (CAPTURE_VERSION, "abc"));
+    let messages: Vec<_> = capture.messages.iter().map(|captured| captured.message.clone()).collect();
+    assert_eq!(
+        messages,
+        [
+            json!({ "type": "input", "data": "mysql -u root -p***REDACTED*** prod" }),
+            json!({ "type": "resize", "cols": 100, "rows": 30 }),
+        ]
+    );
+    assert!(capture.messages.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+}
+
+#[test]
+fn other_capture_versions_are_refused() {
+    let dir = temp_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("future.jsonl");
+    std::fs::write(&path, "{\"version\":2,\"session_id\":\"x\",\"started_at\":\"2026-01-01T00:00:00Z\"}\n").unwrap();
+    let result = Capture::read(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(result, Err(CaptureError::UnsupportedVersion { version: 2, .. })));
+}
+
+#[test]
+fn pacing_flags_and_baselines() {
+    let captured = CapturedMessage { at_ms: 2000, message: json!({ "type": "ps" }) };
+    assert_eq!(captured.due(1.0), Duration::from_secs(2));
+    assert_eq!(captured.due(4.0), Duration::from_millis(500));
+    assert_eq!(captured.due(0.0), Duration::ZERO);
+
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    assert_eq!(protocol_capture::dir_from_args(args(&["pty-server", "--capture-protocol-dir", "/tmp/c"])), Some("/tmp/c".into()));
+    assert_eq!(protocol_capture::dir_from_args(args(&["pty-server", "--capture-protocol-dir=/tmp/c"])), Some("/tmp/c".into()));
+    assert_eq!(protocol_capture::dir_from_args(args(&["pty-server", "--chaos"])), None);
+
+    let types = |types: &[&str]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+    let baseline = types(&["hello", "output", "diagnostics"]);
+    assert!(protocol_capture::compare_baseline(&baseline, &baseline).is_ok());
+    let rechunked = types(&["hello", "output", "output", "diagnostics", "output"]);
+    assert!(protocol_capture::compare_baseline(&baseline, &rechunked).is_ok());
+    let diverged = protocol_capture::compare_baseline(&baseline, &types(&["hello", "error", "diagnostics"])).unwrap_err();
+    assert!(diverged.contains("message 2 is 'error'"), "{}", diverged);
+    assert!(protocol_capture::compare_baseline(&baseline, &types(&["hello", "output"])).is_err());
+}
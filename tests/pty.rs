@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use rust_terminal_forge::protocol::ClientMessage;
+use rust_terminal_forge::pty::{Pty, PtyEvent, PtySignal, SpawnSpec, Utf8Decoder};
+#[cfg(unix)]
+use rust_terminal_forge::run_as::RunAs;
+#[cfg(unix)]
+use rust_terminal_forge::screen::TerminalSize;
+use tokio::sync::mpsc;
+
+#[cfg(unix)]
+fn sh(size: TerminalSize) -> SpawnSpec {
+    SpawnSpec {
+        program: "sh".to_string(),
+        env: vec![("PS1".to_string(), "pty$ ".to_string()), ("PATH".to_string(), std::env::var("PATH").unwrap_or_default())],
+        size,
+        ..Default::default()
+    }
+}
+
+/// Output up to the first chunk containing `needle`.
+async fn output_until(events: &mut mpsc::Receiver<PtyEvent>, needle: &str) -> String {
+    let mut output = String::new();
+    while !output.contains(needle) {
+        match tokio::time::timeout(Duration::from_secs(5), events.recv()).await.expect("timed out waiting for the PTY") {
+            Some(PtyEvent::Output(bytes)) => output.push_str(&String::from_utf8_lossy(&bytes)),
+            other => panic!("expected output, got {:?}", other),
+        }
+    }
+    output
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn shells_run_what_they_are_sent_at_their_size() {
+    let (pty, mut events) = Pty::spawn(&sh(TerminalSize { cols: 100, rows: 30 })).unwrap();
+    assert!(pty.pid().is_some());
+    output_until(&mut events, "pty$ ").await;
+
+    pty.write(b"stty size; echo \"home=$HOME\"\n");
+    let output = output_until(&mut events, "home=").await + &output_until(&mut events, "pty$ ").await;
+    assert!(output.contains("30 100"), "{:?}", output);
+    assert!(output.contains("home=\r\n"), "nothing is inherited: {:?}", output);
+
+    pty.resize(TerminalSize { cols: 60, rows: 20 });
+    pty.write(b"stty size\n");
+    assert!(output_until(&mut events, "20 60").await.contains("20 60"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn exits_are_reported_with_their_code_and_a_close() {
+    let (pty, mut events) = Pty::spawn(&sh(TerminalSize::default())).unwrap();
+    pty.write(b"exit 7\n");
+    let (mut code, mut closed) = (None, false);
+    while let Some(event) = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.expect("timed out waiting for the exit") {
+        match event {
+            PtyEvent::Output(_) => {}
+            PtyEvent::Exited { code: exited } => code = Some(exited),
+            PtyEvent::Closed => closed = true,
+        }
+    }
+    assert_eq!(code, Some(7));
+    assert!(closed);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn signals_reach_the_foreground_process_group() {
+    let spec = SpawnSpec { program: "sleep".to_string(), args: vec!["30".to_string()], ..sh(TerminalSize::default()) };
+    let (pty, mut events) = Pty::spawn(&spec).unwrap();
+    assert_eq!(pty.signal_foreground(PtySignal::Sigterm).unwrap(), pty.pid().unwrap());
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), events.recv()).await.expect("timed out waiting for the exit") {
+            Some(PtyEvent::Exited { code }) => break assert_ne!(code, 0),
+            Some(_) => {}
+            None => panic!("no exit event"),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn cmd() -> SpawnSpec {
+    let vars = ["SystemRoot", "ComSpec", "PATH"].into_iter().filter_map(|name| Some((name.to_string(), std::env::var(name).ok()?)));
+    SpawnSpec { program: "cmd.exe".to_string(), args: vec!["/Q".to_string()], env: vars.collect(), ..Default::default() }
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn cmd_runs_in_a_console_and_reports_its_exit_code() {
+    let (pty, mut events) = Pty::spawn(&cmd()).unwrap();
+    pty.write(b"echo forge-%SystemRoot:~0,1%-ok\r\n");
+    output_until(&mut events, "-ok").await;
+    assert!(pty.signal_foreground(PtySignal::Sigtstp).is_err(), "consoles have no job control");
+
+    pty.write(b"exit 7\r\n");
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), events.recv()).await.expect("timed out waiting for the exit") {
+            Some(PtyEvent::Exited { code }) => break assert_eq!(code, 7),
+            Some(_) => {}
+            None => panic!("no exit event"),
+        }
+    }
+}
+
+#[test]
+fn signal_messages_name_the_signal() {
+    let msg = ClientMessage::decode(r#"{"type":"signal","signal":"SIGTSTP"}"#).unwrap();
+    assert_eq!(msg, ClientMessage::Signal { signal: PtySignal::Sigtstp, job: None });
+    let msg = ClientMessage::decode(r#"{"type":"signal","signal":"SIGTERM","job":4242}"#).unwrap();
+    assert_eq!(msg, ClientMessage::Signal { signal: PtySignal::Sigterm, job: Some(4242) });
+    assert_eq!(ClientMessage::decode(r#"{"type":"signal","signal":"SIGSEGV"}"#).unwrap_err().code(), "invalid_fields");
+}
+
+#[test]
+fn missing_programs_fail_to_spawn() {
+    let spec = SpawnSpec { program: "/nonexistent/forge-shell".to_string(), ..Default::default() };
+    let Err(error) = Pty::spawn(&spec) else { panic!("expected a spawn error") };
+    assert_eq!(error.code(), "shell_spawn_failed");
+    assert!(error.to_string().contains("/nonexistent/forge-shell"));
+}
+
+#[test]
+fn characters_split_across_reads_are_decoded_whole() {
+    let mut decoder = Utf8Decoder::default();
+    let bytes = "héllo ✓".as_bytes();
+    let (first, rest) = bytes.split_at(2);
+    let (middle, last) = rest.split_at(rest.len() - 1);
+    assert_eq!(decoder.decode(first), "h");
+    assert_eq!(decoder.decode(middle), "éllo ");
+    assert_eq!(decoder.decode(last), "✓");
+    assert_eq!(decoder.decode(&[0xff, b'a']), "\u{fffd}a");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn shells_can_run_as_another_user() {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let Ok(nobody) = RunAs::lookup("nobody") else { return };
+    let spec = nobody.clone().with_launcher(env!("CARGO_BIN_EXE_pty-server")).wrap(&sh(TerminalSize::default()));
+    let (pty, mut events) = Pty::spawn(&spec).unwrap();
+    output_until(&mut events, "pty$ ").await;
+
+    pty.write(b"echo \"uid=$(id -u) user=$USER\"\n");
+    let output = output_until(&mut events, "user=").await + &output_until(&mut events, "pty$ ").await;
+    assert!(output.contains(&format!("uid={} user=nobody", nobody.uid)), "{:?}", output);
+}
@@ -0,0 +1,149 @@
+use rust_terminal_forge::config::{InitScript, ReplConfig, ShellEnvConfig, TmpDirConfig};
+use rust_terminal_forge::repl::{ReplError, ReplManager};
+use rust_terminal_forge::session_tmp::TmpDirs;
+use rust_terminal_forge::shell_env::{ShellEnv, BASE_ALLOWLIST};
+
+fn manager() -> ReplManager {
+    ReplManager::new(ReplConfig {
+        exec_timeout_secs: 5,
+        ..ReplConfig::default()
+    })
+}
+
+#[tokio::test]
+async fn shell_state_persists_between_execs() {
+    let repls = manager();
+    let repl = repls.create(None).await.unwrap();
+
+    let cd = repls.exec(&repl.id, "cd /tmp && export FORGE_TEST=persisted").await.unwrap();
+    assert_eq!(cd.exit_code, 0);
+
+    let pwd = repls.exec(&repl.id, "pwd; echo $FORGE_TEST").await.unwrap();
+    assert_eq!(pwd.output, "/tmp\npersisted\n");
+    assert_eq!(pwd.exit_code, 0);
+
+    repls.close(&repl.id).unwrap();
+    assert!(matches!(repls.exec(&repl.id, "pwd").await, Err(ReplError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn captures_stderr_exit_code_and_unterminated_output() {
+    let repls = manager();
+    let repl = repls.create(None).await.unwrap();
+
+    let out = repls.exec(&repl.id, "printf partial; echo oops >&2; false").await.unwrap();
+    assert_eq!(out.output, "partial");
+    assert_eq!(out.stderr, "oops\n");
+    assert_eq!(out.exit_code, 1);
+}
+
+#[tokio::test]
+async fn concurrent_execs_are_serialized() {
+    let repls = manager();
+    let repl = repls.create(None).await.unwrap();
+
+    let runs = (0..8).map(|i| {
+        let repls = repls.clone();
+        let id = repl.id.clone();
+        tokio::spawn(async move {
+            let command = format!("for n in 1 2 3; do echo {i}-$n; sleep 0.01; done");
+            (i, repls.exec(&id, &command).await.unwrap())
+        })
+    });
+
+    for run in runs {
+        let (i, out) = run.await.unwrap();
+        assert_eq!(out.output, format!("{i}-1\n{i}-2\n{i}-3\n"));
+    }
+}
+
+#[tokio::test]
+async fn exiting_the_shell_closes_the_repl() {
+    let repls = manager();
+    let repl = repls.create(None).await.unwrap();
+
+    assert!(matches!(repls.exec(&repl.id, "exit 3").await, Err(ReplError::Exited)));
+    assert!(matches!(repls.close(&repl.id), Err(ReplError::NotFound(_))));
+}
+
+/// Init files still on disk whose script contains `marker`.
+fn init_files_with(marker: &str) -> usize {
+    std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("forge-init-"))
+        .filter(|entry| std::fs::read_to_string(entry.path()).is_ok_and(|script| script.contains(marker)))
+        .count()
+}
+
+#[tokio::test]
+async fn init_scripts_are_sourced_and_cleaned_up() {
+    let marker = format!("forge-init-test-{}", std::process::id());
+    let repls = ReplManager::new(ReplConfig {
+        exec_timeout_secs: 5,
+        init_script: Some(InitScript::Inline(format!("echo {marker}; FORGE_TEAM=core"))),
+        allow_client_init: true,
+        ..ReplConfig::default()
+    });
+
+    let repl = repls.create(Some("greet() { echo hi $FORGE_TEAM; }")).await.unwrap();
+    assert_eq!(repl.init_output.unwrap().output, format!("{marker}\n"));
+    assert_eq!(init_files_with(&marker), 1);
+
+    let out = repls.exec(&repl.id, "greet").await.unwrap();
+    assert_eq!(out.output, "hi core\n");
+
+    repls.close(&repl.id).unwrap();
+    assert_eq!(init_files_with(&marker), 0);
+}
+
+#[tokio::test]
+async fn init_script_problems_fail_creation() {
+    let missing = ReplManager::new(ReplConfig {
+        init_script: Some(InitScript::Path("/nonexistent/forge-init.sh".into())),
+        ..ReplConfig::default()
+    });
+    let err = missing.create(None).await.unwrap_err();
+    assert!(err.to_string().contains("/nonexistent/forge-init.sh"), "{}", err);
+
+    let repls = manager();
+    assert!(matches!(repls.create(Some("echo hi")).await, Err(ReplError::ClientInitDisabled)));
+    assert!(repls.create(None).await.unwrap().init_output.is_none());
+}
+
+#[tokio::test]
+async fn shells_see_only_the_allowlisted_environment() {
+    std::env::set_var("FORGE_TEST_API_TOKEN", "leaked");
+    std::env::set_var("CI_FORGE_TEST", "passed");
+    let repls = manager().with_shell_env(ShellEnv::from_config(&ShellEnvConfig {
+        allow: Vec::new(),
+        pass_env: vec!["CI_FORGE_*".to_string()],
+    }));
+    let repl = repls.create(None).await.unwrap();
+
+    let env = repls.exec(&repl.id, "env").await.unwrap();
+    let names: Vec<&str> = env.output.lines().filter_map(|line| line.split_once('=')).map(|(name, _)| name).collect();
+    assert!(names.contains(&"CI_FORGE_TEST"));
+    // Whatever the shell sets for itself is fine; nothing else may come from the server.
+    let shell_own = ["PWD", "OLDPWD", "SHLVL", "_"];
+    for name in names {
+        assert!(BASE_ALLOWLIST.contains(&name) || shell_own.contains(&name) || name == "CI_FORGE_TEST", "{} leaked", name);
+    }
+}
+
+#[tokio::test]
+async fn shells_get_a_private_tmpdir_removed_on_close() {
+    let root = std::env::temp_dir().join(format!("forge-repl-tmpdirs-{}", uuid::Uuid::new_v4()));
+    let tmpdirs = TmpDirs::from_config(&TmpDirConfig { root: Some(root.clone()), ..Default::default() }).unwrap();
+    let repls = manager().with_tmpdirs(tmpdirs);
+    let repl = repls.create(None).await.unwrap();
+
+    let out = repls.exec(&repl.id, "echo $TMPDIR; touch \"$TMPDIR/scratch\"").await.unwrap();
+    let tmpdir = root.join(&repl.id);
+    assert_eq!(out.output.trim_end(), tmpdir.to_str().unwrap());
+    assert!(tmpdir.join("scratch").exists());
+
+    repls.close(&repl.id).unwrap();
+    assert!(!tmpdir.exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
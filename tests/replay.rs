@@ -0,0 +1,24 @@
+use rust_terminal_forge::replay::ReplayBuffer;
+
+#[test]
+fn replay_keeps_the_most_recent_whole_lines() {
+    let mut replay = ReplayBuffer::new(10);
+    replay.push(b"one\ntwo\nthree\n");
+    assert_eq!(replay.contents(), b"three\n");
+    replay.push(b"four");
+    assert_eq!(replay.contents(), b"three\nfour");
+}
+
+#[test]
+fn replay_never_starts_inside_a_character() {
+    let mut replay = ReplayBuffer::new(4);
+    replay.push("aé€".as_bytes());
+    assert_eq!(String::from_utf8(replay.contents()).unwrap(), "€");
+}
+
+#[test]
+fn zero_sized_replay_keeps_nothing() {
+    let mut replay = ReplayBuffer::new(0);
+    replay.push(b"output\n");
+    assert!(replay.is_empty());
+}
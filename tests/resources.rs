@@ -0,0 +1,44 @@
+use rust_terminal_forge::config::ResourceConfig;
+use rust_terminal_forge::resources::{ResourceEvent, ResourceLimits, ResourceMonitor, ResourceUsage};
+
+fn usage(rss_bytes: u64, cpu_percent: f32) -> ResourceUsage {
+    ResourceUsage { at: chrono::Utc::now(), cpu_percent, rss_bytes }
+}
+
+#[test]
+fn sampling_is_off_unless_an_interval_is_set() {
+    assert!(ResourceLimits::from_config(&ResourceConfig::default()).unwrap().is_none());
+    for config in [
+        ResourceConfig { interval_ms: Some(0), ..Default::default() },
+        ResourceConfig { interval_ms: Some(1000), warn_rss_bytes: Some(2), kill_rss_bytes: Some(1), ..Default::default() },
+    ] {
+        assert!(ResourceLimits::from_config(&config).is_err());
+    }
+}
+
+#[test]
+fn warnings_fire_once_per_crossing_and_kills_need_consecutive_samples() {
+    let config = ResourceConfig {
+        interval_ms: Some(1000),
+        history: 3,
+        warn_rss_bytes: Some(100),
+        kill_rss_bytes: Some(1000),
+        kill_cpu_percent: Some(90.0),
+        kill_after_samples: 2,
+    };
+    let mut monitor = ResourceMonitor::new(ResourceLimits::from_config(&config).unwrap().unwrap());
+
+    assert_eq!(monitor.record(usage(150, 0.0)), Some(ResourceEvent::Warning { rss_bytes: 150, limit_bytes: 100 }));
+    assert_eq!(monitor.record(usage(200, 0.0)), None, "still over: no repeat");
+    assert_eq!(monitor.record(usage(50, 0.0)), None);
+    assert!(matches!(monitor.record(usage(150, 0.0)), Some(ResourceEvent::Warning { .. })), "crossed again");
+
+    assert_eq!(monitor.record(usage(50, 95.0)), None);
+    assert_eq!(monitor.record(usage(50, 0.0)), None, "the streak resets");
+    assert_eq!(monitor.record(usage(50, 95.0)), None);
+    assert_eq!(monitor.record(usage(5000, 0.0)), Some(ResourceEvent::LimitExceeded));
+
+    let history: Vec<u64> = monitor.history().map(|usage| usage.rss_bytes).collect();
+    assert_eq!(history, [50, 50, 5000]);
+    assert_eq!(monitor.current().map(|usage| usage.rss_bytes), Some(5000));
+}
@@ -0,0 +1,49 @@
+#[cfg(unix)]
+use rust_terminal_forge::pty::SpawnSpec;
+use rust_terminal_forge::run_as;
+#[cfg(unix)]
+use rust_terminal_forge::run_as::{RunAs, RunAsError};
+
+#[cfg(unix)]
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn users_are_named_on_the_command_line() {
+    assert_eq!(RunAs::from_args(args(&["pty-server"])), Ok(None));
+    assert_eq!(RunAs::from_args(args(&["pty-server", "--run-as-user"])), Err(RunAsError::Missing));
+    assert_eq!(
+        RunAs::from_args(args(&["pty-server", "--run-as-user=no-such-forge-user"])),
+        Err(RunAsError::UnknownUser("no-such-forge-user".to_string()))
+    );
+    assert_eq!(RunAs::from_args(args(&["pty-server", "--run-as-user", "root"])), Err(RunAsError::Root("root".to_string())));
+}
+
+#[cfg(unix)]
+#[test]
+fn wrapped_shells_start_through_the_launcher_as_the_user() {
+    let Ok(nobody) = RunAs::lookup("nobody") else { return };
+    let spec = SpawnSpec {
+        program: "sh".to_string(),
+        args: args(&["-l"]),
+        env: vec![("HOME".to_string(), "/root".to_string()), ("TERM".to_string(), "xterm".to_string())],
+        ..Default::default()
+    };
+    let wrapped = nobody.clone().with_launcher("/usr/bin/pty-server").wrap(&spec);
+    assert_eq!(wrapped.program, "/usr/bin/pty-server");
+    let uid = nobody.uid.to_string();
+    let gid = nobody.gid.to_string();
+    assert_eq!(wrapped.args, args(&["--exec-as", "nobody", &uid, &gid, "--", "sh", "-l"]));
+    assert!(wrapped.env.contains(&("TERM".to_string(), "xterm".to_string())));
+    assert!(wrapped.env.contains(&("HOME".to_string(), nobody.home.display().to_string())));
+    assert!(!wrapped.env.contains(&("HOME".to_string(), "/root".to_string())));
+}
+
+#[test]
+fn only_the_exec_as_command_line_is_taken_over() {
+    assert!(run_as::exec_if_requested(["pty-server", "--base-path", "/t"].map(Into::into)).is_none());
+    let error = run_as::exec_if_requested(["pty-server", "--exec-as", "nobody", "x", "1", "--", "sh"].map(Into::into));
+    assert_eq!(error.map(|e| e.kind()), Some(std::io::ErrorKind::InvalidInput));
+}
@@ -0,0 +1,57 @@
+use rust_terminal_forge::ansi;
+use rust_terminal_forge::scrollback::{compile_query, search, Scrollback};
+
+#[test]
+fn strip_removes_escape_sequences() {
+    assert_eq!(ansi::strip("\x1b[1;31mred\x1b[0m\r\n\x1b]0;title\x07ok\x1b]133;A\x1b\\\t$ \x1b=x"), "red\nok\t$ x");
+}
+
+#[test]
+fn scrollback_drops_whole_lines_and_counts_them() {
+    let mut scrollback = Scrollback::new(10);
+    scrollback.push("one\ntwo\nthree\n");
+    assert_eq!(scrollback.contents(), "three\n");
+    assert_eq!(scrollback.dropped_lines(), 2);
+
+    let pattern = compile_query("three", false).unwrap();
+    let results = search(scrollback.contents(), scrollback.dropped_lines(), &pattern, 10);
+    assert_eq!(results.matches[0].line, 3);
+}
+
+#[test]
+fn fetch_pages_through_retained_lines() {
+    let mut scrollback = Scrollback::new(16);
+    scrollback.push("one\r\ntwo\r\nthree\r\nfour\r\n$ ");
+    assert_eq!((scrollback.dropped_lines(), scrollback.contents()), (2, "three\r\nfour\r\n$ "));
+
+    let newest = scrollback.fetch(None, 2);
+    assert_eq!((newest.from, newest.first_line, newest.last_line), (4, 3, 5));
+    assert_eq!(newest.lines, ["four\r", "$ "]);
+    let older = scrollback.fetch(Some(1), 1);
+    assert_eq!((older.from, older.lines), (3, vec!["three\r".to_string()]));
+    assert!(scrollback.fetch(Some(9), 10).lines.is_empty());
+    assert_eq!(scrollback.fetch(None, 0).lines.len(), 1);
+    assert_eq!(scrollback.fetch(None, usize::MAX).lines.len(), 3);
+}
+
+#[test]
+fn literal_queries_are_escaped() {
+    let pattern = compile_query("a.b", false).unwrap();
+    let results = search("axb\na.b\n", 0, &pattern, 10);
+    assert_eq!(results.matches.len(), 1);
+    assert_eq!(results.matches[0].line, 2);
+    assert_eq!(results.matches[0].context_before, vec!["axb".to_string()]);
+}
+
+#[test]
+fn oversized_regex_is_rejected() {
+    assert!(compile_query("(?:a{1000}){1000}", true).is_err());
+}
+
+#[test]
+fn to_html_escapes_and_colors() {
+    assert_eq!(
+        ansi::to_html("a\x1b[38;5;196m<x>\x1b[48;2;1;2;3m&\x1b[0m\"'\x1b]0;t\x07"),
+        "a<span style=\"color:#ff0000\">&lt;x&gt;</span><span style=\"color:#ff0000;background-color:#010203\">&amp;</span>&quot;&#39;"
+    );
+}
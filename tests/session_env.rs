@@ -0,0 +1,68 @@
+use rust_terminal_forge::config::EnvironmentConfig;
+use rust_terminal_forge::session_env::{ColorSupport, EnvironmentError, EnvironmentPolicy, EnvironmentRequest};
+
+fn request(term: Option<&str>, lang: Option<&str>, lc_all: Option<&str>, color: Option<&str>) -> EnvironmentRequest {
+    EnvironmentRequest {
+        term: term.map(str::to_string),
+        lang: lang.map(str::to_string),
+        lc_all: lc_all.map(str::to_string),
+        color: color.map(str::to_string),
+    }
+}
+
+#[test]
+fn overrides_apply_on_top_of_config_defaults() {
+    let policy = EnvironmentPolicy::from_config(&EnvironmentConfig {
+        lang: "de_DE.UTF-8".to_string(),
+        color: ColorSupport::Ansi256,
+        ..Default::default()
+    })
+    .unwrap();
+    let defaults = policy.resolve(&EnvironmentRequest::default()).unwrap();
+    assert_eq!(defaults.vars(), vec![("TERM", "xterm-256color".to_string()), ("LANG", "de_DE.UTF-8".to_string())]);
+
+    let env = policy.resolve(&request(Some("screen-256color"), None, Some("C"), Some("truecolor"))).unwrap();
+    assert_eq!(
+        env.vars(),
+        vec![
+            ("TERM", "screen-256color".to_string()),
+            ("LANG", "de_DE.UTF-8".to_string()),
+            ("LC_ALL", "C".to_string()),
+            ("COLORTERM", "truecolor".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn dumb_terminals_are_not_told_about_colors() {
+    let policy = EnvironmentPolicy::default();
+    let dumb = policy.resolve(&request(Some("dumb"), None, Some("C"), None)).unwrap();
+    assert_eq!(dumb.vars(), vec![("TERM", "dumb".to_string()), ("LANG", "C.UTF-8".to_string()), ("LC_ALL", "C".to_string())]);
+    let asked = policy.resolve(&request(Some("dumb"), None, None, Some("truecolor"))).unwrap();
+    assert_eq!(asked.color, ColorSupport::Truecolor);
+    assert_eq!(policy.resolve(&request(Some("screen"), None, None, None)).unwrap().term, "screen");
+}
+
+#[test]
+fn invalid_values_are_errors_not_defaults() {
+    let policy = EnvironmentPolicy::default();
+    let cases = [
+        (request(Some("xterm-kitty"), None, None, None), "invalid_term"),
+        (request(None, Some("en_US.UTF-8; rm -rf /"), None, None), "invalid_locale"),
+        (request(None, None, Some("../../etc"), None), "invalid_locale"),
+        (request(None, None, None, Some("88")), "invalid_color"),
+    ];
+    for (req, code) in cases {
+        assert_eq!(policy.resolve(&req).map_err(|e| e.code()), Err(code), "{:?}", req);
+    }
+    assert_eq!(
+        policy.resolve(&request(Some("vt52"), None, None, None)),
+        Err(EnvironmentError::InvalidTerm("vt52".to_string()))
+    );
+}
+
+#[test]
+fn config_defaults_must_pass_the_same_rules() {
+    let config = EnvironmentConfig { term: "vt52".to_string(), ..Default::default() };
+    assert!(EnvironmentPolicy::from_config(&config).is_err());
+}
@@ -0,0 +1,13 @@
+use rust_terminal_forge::session_names::{self, NameError, MAX_NAME_LEN};
+
+#[test]
+fn names_are_trimmed_and_validated() {
+    assert_eq!(session_names::parse("build").unwrap(), "build");
+    assert_eq!(session_names::parse("  dev server (api) ").unwrap(), "dev server (api)");
+    assert_eq!(session_names::parse("журнал").unwrap(), "журнал");
+
+    assert_eq!(session_names::parse("   ").unwrap_err(), NameError("   ".to_string()));
+    assert_eq!(session_names::parse("tab\x1b[31m").unwrap_err().code(), "invalid_session_name");
+    assert!(session_names::parse(&"n".repeat(MAX_NAME_LEN)).is_ok());
+    assert!(session_names::parse(&"n".repeat(MAX_NAME_LEN + 1)).is_err());
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use rust_terminal_forge::protocol::CloseReason;
+use rust_terminal_forge::screen::TerminalSize;
+use rust_terminal_forge::session_registry::{RegistryError, SessionMetadata, SessionRegistry, SessionState};
+
+#[tokio::test]
+async fn attach_detach_and_kill() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let mut kill = registry.create("a".to_string(), 7).await.unwrap();
+    assert_eq!(registry.create("a".to_string(), 8).await.unwrap_err(), RegistryError::AlreadyExists("a".to_string()));
+
+    let metadata = registry.attach("a", "127.0.0.1:1".to_string()).await.unwrap();
+    assert!(metadata.attached);
+    assert_eq!(registry.attach("a", "127.0.0.1:2".to_string()).await.unwrap_err(), RegistryError::AlreadyAttached("a".to_string()));
+    assert!(!registry.detach("a").await.unwrap().attached);
+    assert_eq!(registry.get("a").await.unwrap(), 7);
+
+    registry.kill("a", CloseReason::AdminDisconnect).await.unwrap();
+    assert_eq!(kill.try_recv().unwrap(), CloseReason::AdminDisconnect);
+    assert_eq!(registry.remove("a").await.unwrap().id, "a");
+    assert_eq!(registry.get_metadata("a").await.unwrap_err(), RegistryError::NotFound("a".to_string()));
+    assert_eq!(registry.kill("a", CloseReason::AdminDisconnect).await.unwrap_err(), RegistryError::NotFound("a".to_string()));
+}
+
+#[tokio::test]
+async fn reattaching_keeps_the_expiry() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(8);
+    let metadata = SessionMetadata { expires_at: Some(expires_at), ..SessionMetadata::new("a".to_string()) };
+    let _kill = registry.create_with(metadata, 1).await.unwrap();
+    registry.attach("a", "127.0.0.1:1".to_string()).await.unwrap();
+    registry.detach("a").await.unwrap();
+    assert_eq!(registry.attach("a", "127.0.0.1:2".to_string()).await.unwrap().expires_at, Some(expires_at));
+    let _kill = registry.create("b".to_string(), 2).await.unwrap();
+    assert_eq!(registry.get_metadata("b").await.unwrap().expires_at, None);
+}
+
+#[tokio::test]
+async fn listings_filter_on_tags() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let _a = registry.create("a".to_string(), 1).await.unwrap();
+    let _b = registry.create("b".to_string(), 2).await.unwrap();
+    let tags = [("purpose".to_string(), "build".to_string())].into();
+    assert_eq!(registry.update("a", |metadata| metadata.tags = tags).await.unwrap().tags["purpose"], "build");
+    assert_eq!(registry.update("c", |metadata| metadata.tags.clear()).await.unwrap_err(), RegistryError::NotFound("c".to_string()));
+
+    let build = registry.list_tagged(vec![("purpose".to_string(), "build".to_string())]).await;
+    assert_eq!(build.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["a"]);
+    assert!(registry.list_tagged(vec![("purpose".to_string(), "preview".to_string())]).await.is_empty());
+    assert_eq!(registry.list().await.len(), 2);
+    assert_eq!(registry.update("b", |metadata| metadata.workspace = Some("project-a".to_string())).await.unwrap().workspace.as_deref(), Some("project-a"));
+    assert_eq!(registry.get_metadata("a").await.unwrap().workspace, None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_create_list_remove_loses_nothing() {
+    let registry: SessionRegistry<usize> = SessionRegistry::new();
+    let workers: Vec<_> = (0..32)
+        .map(|worker| {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                for i in 0..200 {
+                    let id = format!("{}-{}", worker, i);
+                    let _kill = registry.create(id.clone(), i).await.unwrap();
+                    assert!(registry.list().await.iter().any(|m| m.id == id));
+                    // Keep every other session to check nothing is lost.
+                    if i % 2 == 0 {
+                        registry.remove(&id).await.unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let all = tokio::time::timeout(Duration::from_secs(30), futures_util::future::join_all(workers))
+        .await
+        .expect("registry deadlocked");
+    assert!(all.into_iter().all(|r| r.is_ok()));
+    assert_eq!(registry.count().await, 32 * 100);
+    assert_eq!(registry.all().await.iter().filter(|&&i| i % 2 == 1).count(), 32 * 100);
+}
+
+#[tokio::test]
+async fn orphaned_sessions_say_so_in_their_metadata() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let _kill = registry.create("a".to_string(), 1).await.unwrap();
+    assert_eq!(registry.get_metadata("a").await.unwrap().state, SessionState::Running);
+
+    let metadata = registry.update("a", |metadata| metadata.state = SessionState::OrphanedIo).await.unwrap();
+    assert_eq!(serde_json::to_value(&metadata).unwrap()["state"], "orphaned_io");
+    assert_eq!(registry.update("b", |metadata| metadata.state = SessionState::Running).await.unwrap_err(), RegistryError::NotFound("b".to_string()));
+}
+
+#[tokio::test]
+async fn sizes_are_kept_for_later_clients() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let _kill = registry.create("a".to_string(), 1).await.unwrap();
+    assert_eq!(registry.get_metadata("a").await.unwrap().size, TerminalSize::default());
+
+    let metadata = registry.update("a", |metadata| metadata.size = TerminalSize { cols: 120, rows: 40 }).await.unwrap();
+    assert_eq!(serde_json::to_value(&metadata).unwrap()["size"], serde_json::json!({ "cols": 120, "rows": 40 }));
+    assert_eq!(registry.update("b", |metadata| metadata.size = TerminalSize::default()).await.unwrap_err(), RegistryError::NotFound("b".to_string()));
+}
+
+#[tokio::test]
+async fn full_registries_refuse_new_sessions() {
+    let registry: SessionRegistry<u32> = SessionRegistry::with_limit(Some(1));
+    let _kill = registry.create("a".to_string(), 1).await.unwrap();
+    assert_eq!(registry.create("b".to_string(), 2).await.unwrap_err(), RegistryError::Full(1));
+    assert_eq!(registry.create("a".to_string(), 3).await.unwrap_err(), RegistryError::AlreadyExists("a".to_string()));
+    registry.remove("a").await.unwrap();
+    let _kill = registry.create("b".to_string(), 2).await.unwrap();
+}
+
+#[tokio::test]
+async fn reservations_hold_a_slot_until_dropped_or_filled() {
+    let registry: SessionRegistry<u32> = SessionRegistry::with_limit(Some(1));
+    let reservation = registry.reserve().await.unwrap();
+    assert_eq!(registry.reserve().await.unwrap_err(), RegistryError::Full(1));
+    assert_eq!(registry.create("a".to_string(), 1).await.unwrap_err(), RegistryError::Full(1));
+    drop(reservation);
+
+    let reservation = registry.reserve().await.unwrap();
+    let _kill = registry.create_reserved(reservation, SessionMetadata::new("a".to_string()), 1).await.unwrap();
+    assert_eq!(registry.count().await, 1);
+    assert_eq!(registry.reserve().await.unwrap_err(), RegistryError::Full(1));
+    registry.remove("a").await.unwrap();
+    registry.reserve().await.unwrap();
+}
+
+#[tokio::test]
+async fn sessions_are_registered_with_their_metadata_at_once() {
+    let registry: SessionRegistry<u32> = SessionRegistry::new();
+    let metadata = SessionMetadata {
+        name: Some("build".to_string()),
+        attached: true,
+        peer_addr: Some("127.0.0.1:1".to_string()),
+        ..SessionMetadata::new("a".to_string())
+    };
+    let _kill = registry.create_with(metadata.clone(), 1).await.unwrap();
+    assert_eq!(registry.get_metadata("a").await.unwrap(), metadata);
+    assert_eq!(registry.attach("a", "127.0.0.1:2".to_string()).await.unwrap_err(), RegistryError::AlreadyAttached("a".to_string()));
+}
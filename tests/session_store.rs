@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use rust_terminal_forge::session_store::{RestoredSessions, SavedSession, SessionStore, StoreError};
+
+fn saved(id: &str) -> SavedSession {
+    SavedSession {
+        id: id.to_string(),
+        name: Some("build".to_string()),
+        workspace: Some("project-a".to_string()),
+        created_at: chrono::DateTime::from_timestamp(1_790_000_000, 0).unwrap(),
+        cwd: Some("/srv/app".to_string()),
+        env: BTreeMap::from([("NODE_ENV".to_string(), "test".to_string())]),
+        scrollback: "$ make\r\nok\r\n".to_string(),
+        resume_token: format!("{}-token", id),
+    }
+}
+
+fn store_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("forge-store-{}-{}", name, uuid::Uuid::new_v4())).join("sessions.json")
+}
+
+#[test]
+fn saved_sessions_are_taken_back_once() {
+    let store = SessionStore::new(store_path("roundtrip"));
+    assert_eq!(store.take().unwrap(), Vec::new(), "nothing saved yet");
+    store.save(vec![saved("s1"), saved("s2")]).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(store.path()).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    assert_eq!(store.take().unwrap(), vec![saved("s1"), saved("s2")]);
+    assert_eq!(store.take().unwrap(), Vec::new());
+}
+
+#[test]
+fn other_versions_are_refused() {
+    let store = SessionStore::new(store_path("version"));
+    std::fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+    std::fs::write(store.path(), r#"{"version":99,"saved_at":"2026-01-01T00:00:00Z","sessions":[]}"#).unwrap();
+    assert!(matches!(store.take(), Err(StoreError::UnsupportedVersion { version: 99, .. })));
+    std::fs::write(store.path(), "not json").unwrap();
+    assert!(matches!(store.take(), Err(StoreError::Parse { .. })));
+}
+
+#[test]
+fn restored_sessions_go_to_the_holder_of_their_token() {
+    let restored = RestoredSessions::new(vec![saved("s1"), saved("s2")]);
+    assert_eq!(restored.claim("s1", "s2-token"), None);
+    assert_eq!(restored.claim("s3", "s1-token"), None);
+    assert_eq!(restored.claim("s1", "s1-token"), Some(saved("s1")));
+    assert_eq!(restored.claim("s1", "s1-token"), None, "claimed once");
+    assert_eq!(restored.unclaimed(), vec![saved("s2")]);
+}
@@ -0,0 +1,27 @@
+use rust_terminal_forge::session_tags::{self, SessionTags, TagError, MAX_TAGS};
+
+#[test]
+fn tags_are_validated_and_later_values_win() {
+    let mut tags = SessionTags::new();
+    session_tags::insert(&mut tags, "purpose:build").unwrap();
+    session_tags::insert(&mut tags, "repo:github.com/forge/app").unwrap();
+    session_tags::insert(&mut tags, "purpose:preview").unwrap();
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags["purpose"], "preview");
+
+    assert_eq!(session_tags::insert(&mut tags, "purpose").unwrap_err(), TagError::Malformed("purpose".to_string()));
+    assert_eq!(session_tags::insert(&mut tags, "bad key:x").unwrap_err().code(), "invalid_tag");
+    assert_eq!(session_tags::insert(&mut tags, "note:has space").unwrap_err().code(), "invalid_tag");
+    assert_eq!(session_tags::insert(&mut tags, &format!("long:{}", "v".repeat(129))).unwrap_err().code(), "invalid_tag");
+
+    for i in tags.len()..MAX_TAGS {
+        session_tags::insert(&mut tags, &format!("k{}:v", i)).unwrap();
+    }
+    assert_eq!(session_tags::insert(&mut tags, "one:more").unwrap_err().code(), "too_many_tags");
+    session_tags::insert(&mut tags, "purpose:build").unwrap();
+
+    let filter = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>();
+    assert!(session_tags::matches(&tags, &filter(&[("purpose", "build")])));
+    assert!(!session_tags::matches(&tags, &filter(&[("purpose", "build"), ("team", "ci")])));
+    assert!(session_tags::matches(&tags, &[]));
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use rust_terminal_forge::config::TmpDirConfig;
+use rust_terminal_forge::session_tmp::{self, TmpDirs};
+use uuid::Uuid;
+
+fn tmpdirs_under_fresh_root(max_bytes: Option<u64>) -> (TmpDirs, PathBuf) {
+    let root = std::env::temp_dir().join(format!("forge-tmpdirs-{}", Uuid::new_v4()));
+    let config = TmpDirConfig { root: Some(root.clone()), max_bytes, ..Default::default() };
+    (TmpDirs::from_config(&config).unwrap().unwrap(), root)
+}
+
+#[test]
+fn opting_out_shares_the_system_tmpdir() {
+    assert!(TmpDirs::from_config(&TmpDirConfig { enabled: false, ..Default::default() }).unwrap().is_none());
+    let zero_interval = TmpDirConfig { max_bytes: Some(1), check_interval_secs: 0, ..Default::default() };
+    assert!(TmpDirs::from_config(&zero_interval).is_err());
+}
+
+#[test]
+fn directories_are_private_and_removed_with_leftovers_on_drop() {
+    let (tmpdirs, root) = tmpdirs_under_fresh_root(None);
+    let tmpdir = tmpdirs.create("session-1").unwrap();
+    let path = tmpdir.path().to_path_buf();
+    assert_eq!(path, root.join("session-1"));
+    assert_eq!(tmpdir.env(), ("TMPDIR", path.as_path()));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+
+    // What a subprocess that outlived the shell might leave: nested, read-only directories.
+    let nested = path.join("build/cache");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(nested.join("object.o"), "1234").unwrap();
+    std::fs::write(path.join("notes"), "12").unwrap();
+    assert_eq!(session_tmp::dir_size(&path), 6);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&nested, std::fs::Permissions::from_mode(0o500)).unwrap();
+    }
+
+    drop(tmpdir);
+    assert!(!path.exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn size_warnings_fire_once_per_crossing() {
+    let (tmpdirs, root) = tmpdirs_under_fresh_root(Some(10));
+    let mut tmpdir = tmpdirs.create("session-2").unwrap();
+    assert_eq!(tmpdir.record_size(5), None);
+    assert_eq!(tmpdir.record_size(11), Some(10));
+    assert_eq!(tmpdir.record_size(12), None);
+    assert_eq!(tmpdir.record_size(3), None);
+    assert_eq!(tmpdir.record_size(20), Some(10));
+
+    let (uncapped, uncapped_root) = tmpdirs_under_fresh_root(None);
+    assert_eq!(uncapped.create("session-3").unwrap().record_size(u64::MAX), None);
+    drop(tmpdir);
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_dir_all(&uncapped_root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn roots_are_private_and_never_followed_through_symlinks() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (tmpdirs, root) = tmpdirs_under_fresh_root(None);
+    let tmpdir = tmpdirs.create("session-4").unwrap();
+    assert_eq!(std::fs::metadata(&root).unwrap().permissions().mode() & 0o777, 0o700);
+    drop(tmpdir);
+    std::fs::set_permissions(&root, std::fs::Permissions::from_mode(0o755)).unwrap();
+    drop(tmpdirs.create("session-5").unwrap());
+    assert_eq!(std::fs::metadata(&root).unwrap().permissions().mode() & 0o777, 0o700);
+
+    let link = root.with_extension("link");
+    std::os::unix::fs::symlink(&root, &link).unwrap();
+    let config = TmpDirConfig { root: Some(link.clone()), ..Default::default() };
+    let err = TmpDirs::from_config(&config).unwrap().unwrap().create("session-6").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(!root.join("session-6").exists());
+    std::fs::remove_file(&link).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,46 @@
+use rust_terminal_forge::config::ShellEnvConfig;
+use rust_terminal_forge::shell_env::ShellEnv;
+
+fn source() -> Vec<(String, String)> {
+    [
+        ("PATH", "/usr/bin"),
+        ("HOME", "/home/forge"),
+        ("LANG", "C.UTF-8"),
+        ("AWS_SECRET_ACCESS_KEY", "wJalrXUtnFEMI"),
+        ("DATABASE_URL", "postgres://forge:pw@db/forge"),
+        ("CI_PIPELINE_ID", "42"),
+        ("CI", "true"),
+        ("EDITOR", "vim"),
+        ("GITHUB_TOKEN", "ghs_x"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+fn names(vars: Vec<(String, String)>) -> Vec<String> {
+    vars.into_iter().map(|(name, _)| name).collect()
+}
+
+#[test]
+fn only_the_allowlist_passes_by_default() {
+    assert_eq!(names(ShellEnv::default().vars_from(source())), ["PATH", "HOME", "LANG"]);
+}
+
+#[test]
+fn extras_and_globs_widen_the_allowlist() {
+    let env = ShellEnv::from_config(&ShellEnvConfig {
+        allow: vec!["EDITOR".to_string()],
+        pass_env: vec!["CI_*".to_string(), "*_TOKEN".to_string()],
+    });
+    assert_eq!(names(env.vars_from(source())), ["PATH", "HOME", "LANG", "CI_PIPELINE_ID", "EDITOR", "GITHUB_TOKEN"]);
+    assert!(!env.allows("CI"));
+}
+
+#[test]
+fn self_check_names_withheld_and_passed_secrets() {
+    let env = ShellEnv::from_config(&ShellEnvConfig { allow: vec!["GITHUB_TOKEN".to_string()], pass_env: Vec::new() });
+    let (withheld, passed) = env.sensitive_names(source());
+    assert_eq!(withheld, ["AWS_SECRET_ACCESS_KEY", "DATABASE_URL"]);
+    assert_eq!(passed, ["GITHUB_TOKEN"]);
+}
@@ -0,0 +1,111 @@
+use rust_terminal_forge::config::PromptDetectionConfig;
+use rust_terminal_forge::shell_integration::{CommandPhase, OutputEvent, PromptDetector, ShellIntegrationParser};
+
+fn text(s: &str) -> OutputEvent {
+    OutputEvent::Text(s.to_string())
+}
+
+fn boundary(phase: CommandPhase, exit_code: Option<i32>) -> OutputEvent {
+    OutputEvent::Boundary { phase, exit_code }
+}
+
+#[test]
+fn output_without_markers_is_unchanged() {
+    let mut parser = ShellIntegrationParser::default();
+    assert_eq!(parser.feed("ls\n\x1b[1;32mfile\x1b[0m\n"), vec![text("ls\n\x1b[1;32mfile\x1b[0m\n")]);
+    assert_eq!(parser.feed("\x1b]0;title\x07"), vec![text("\x1b]0;title\x07")]);
+}
+
+#[test]
+fn parses_every_phase_with_bel_and_st() {
+    let mut parser = ShellIntegrationParser::default();
+    let events = parser.feed("\x1b]133;A\x07$ \x1b]133;B\x1b\\ls\n\x1b]133;C\x07out\n\x1b]133;D;127\x07\x1b]133;D\x07");
+    assert_eq!(
+        events,
+        vec![
+            boundary(CommandPhase::PromptStart, None),
+            text("$ "),
+            boundary(CommandPhase::CommandStart, None),
+            text("ls\n"),
+            boundary(CommandPhase::OutputStart, None),
+            text("out\n"),
+            boundary(CommandPhase::CommandEnd, Some(127)),
+            boundary(CommandPhase::CommandEnd, None),
+        ]
+    );
+}
+
+#[test]
+fn markers_split_across_chunks() {
+    let input = "before\x1b]133;D;1\x07after";
+    let expected = vec![text("before"), boundary(CommandPhase::CommandEnd, Some(1)), text("after")];
+    for split in 0..=input.len() {
+        let mut parser = ShellIntegrationParser::default();
+        let mut events = parser.feed(&input[..split]);
+        events.extend(parser.feed(&input[split..]));
+
+        let mut merged: Vec<OutputEvent> = Vec::new();
+        for event in events {
+            match (merged.last_mut(), event) {
+                (Some(OutputEvent::Text(prev)), OutputEvent::Text(next)) => prev.push_str(&next),
+                (_, event) => merged.push(event),
+            }
+        }
+        assert_eq!(merged, expected, "split at {}", split);
+    }
+}
+
+#[test]
+fn unknown_or_runaway_markers_pass_through() {
+    let mut parser = ShellIntegrationParser::default();
+    assert_eq!(parser.feed("\x1b]133;Z\x07"), vec![text("\x1b]133;Z\x07")]);
+
+    let runaway = format!("\x1b]133;A{}", "x".repeat(300));
+    assert_eq!(parser.feed(&runaway), vec![text(&runaway)]);
+}
+
+#[test]
+fn osc_7_reports_the_working_directory() {
+    let mut parser = ShellIntegrationParser::default();
+    let cwd = |path: &str| OutputEvent::WorkingDirectory(path.to_string());
+    assert_eq!(parser.feed("\x1b]7;file://devbox/home/rick/my%20project\x07$ "), vec![cwd("/home/rick/my project"), text("$ ")]);
+    assert_eq!(parser.feed("\x1b]7;fi"), vec![]);
+    assert_eq!(parser.feed("le:///srv\x1b\\"), vec![cwd("/srv")]);
+    assert_eq!(parser.feed("\x1b]7;http://x/y\x07"), vec![text("\x1b]7;http://x/y\x07")]);
+}
+
+fn detector() -> PromptDetector {
+    PromptDetector::from_config(&PromptDetectionConfig::default()).unwrap().unwrap()
+}
+
+#[test]
+fn prompt_detector_tracks_the_last_line() {
+    let mut prompt = detector();
+    prompt.observe(&[text("building...\n50%")]);
+    assert_eq!(prompt.deadline(), None);
+
+    prompt.observe(&[text(" done\nuser@host:~"), text("$ ")]);
+    assert!(prompt.deadline().is_some());
+    assert!(prompt.fire());
+    assert!(!prompt.fire(), "one event per prompt");
+
+    prompt.observe(&[text("$ ls\nfile")]);
+    assert_eq!(prompt.deadline(), None);
+}
+
+#[test]
+fn prompt_detector_yields_to_osc_133() {
+    let mut prompt = detector();
+    prompt.observe(&[boundary(CommandPhase::PromptStart, None), text("$ ")]);
+    assert_eq!(prompt.deadline(), None);
+    prompt.observe(&[text("\n# ")]);
+    assert!(!prompt.fire());
+}
+
+#[test]
+fn prompt_pattern_is_validated_and_can_be_disabled() {
+    let invalid = PromptDetectionConfig { pattern: "[".to_string(), ..Default::default() };
+    assert!(PromptDetector::from_config(&invalid).is_err());
+    let disabled = PromptDetectionConfig { enabled: false, ..Default::default() };
+    assert!(PromptDetector::from_config(&disabled).unwrap().is_none());
+}
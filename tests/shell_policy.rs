@@ -0,0 +1,22 @@
+use rust_terminal_forge::config::TerminalConfig;
+use rust_terminal_forge::shell_policy::{ShellError, ShellPolicy};
+
+#[test]
+fn only_the_default_and_listed_shells_are_allowed() {
+    let config = TerminalConfig { allowed_shells: vec!["zsh".to_string(), "bash".to_string()], ..TerminalConfig::default() };
+    let policy = ShellPolicy::from_config(&config);
+    assert_eq!(policy.default_shell(), "bash");
+    assert_eq!(policy.allowed(), ["bash", "zsh"]);
+    assert_eq!(policy.resolve("zsh").unwrap(), "zsh");
+
+    let error = policy.resolve("/bin/zsh").unwrap_err();
+    assert_eq!(error, ShellError::NotAllowed { shell: "/bin/zsh".to_string(), allowed: "bash, zsh".to_string() });
+    assert_eq!(error.code(), "shell_not_allowed");
+}
+
+#[test]
+fn without_a_list_clients_can_only_name_the_default() {
+    let policy = ShellPolicy::from_config(&TerminalConfig::default());
+    assert!(policy.resolve("bash").is_ok());
+    assert!(policy.resolve("fish").is_err());
+}
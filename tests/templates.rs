@@ -0,0 +1,53 @@
+use rust_terminal_forge::config::ForgeConfig;
+use rust_terminal_forge::templates::{Launch, TemplateError, Templates};
+
+fn templates(toml: &str) -> Result<Templates, String> {
+    let config: ForgeConfig = toml::from_str(toml).unwrap();
+    Templates::from_config(&config.templates).map_err(|e| e.to_string())
+}
+
+#[test]
+fn listing_shows_env_names_but_not_values() {
+    let templates = templates(r#"
+        [[templates]]
+        name = "db-console"
+        description = "psql on the app database"
+        command = ["psql", "app"]
+        env = { PGPASSWORD = "hunter2", PGHOST = "db" }
+
+        [[templates]]
+        name = "rust-dev"
+        cwd = "/srv/forge"
+    "#).unwrap();
+
+    let summaries = templates.summaries();
+    assert_eq!(summaries.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["db-console", "rust-dev"]);
+    assert_eq!(summaries[0].launch, Launch::Command(vec!["psql".to_string(), "app".to_string()]));
+    assert_eq!(summaries[0].env, ["PGHOST", "PGPASSWORD"]);
+    assert!(!serde_json::to_string(&summaries).unwrap().contains("hunter2"));
+    assert_eq!(summaries[1].launch, Launch::DefaultShell);
+}
+
+#[test]
+fn unknown_names_and_locked_fields_are_specific_errors() {
+    let templates = templates(r#"
+        [[templates]]
+        name = "rust-dev"
+        overridable = ["term"]
+    "#).unwrap();
+
+    assert_eq!(templates.get("nope").unwrap_err(), TemplateError::Unknown("nope".to_string()));
+    let template = templates.get("rust-dev").unwrap();
+    assert!(template.check_override("term").is_ok());
+    assert_eq!(template.check_override("lang").unwrap_err().code(), "not_overridable");
+}
+
+#[test]
+fn invalid_templates_are_config_errors() {
+    let duplicate = "[[templates]]\nname = \"a\"\n[[templates]]\nname = \"a\"\n";
+    assert!(templates(duplicate).unwrap_err().contains("defined twice"));
+    let both = "[[templates]]\nname = \"a\"\nshell = \"/bin/zsh\"\ncommand = [\"psql\"]\n";
+    assert!(templates(both).unwrap_err().contains("not both"));
+    let locked = "[[templates]]\nname = \"a\"\noverridable = [\"cwd\"]\n";
+    assert!(templates(locked).unwrap_err().contains("'cwd' cannot be overridable"));
+}
@@ -0,0 +1,51 @@
+use rust_terminal_forge::terminal_modes::{ModeChange, ModeTracker};
+
+fn change(mode: u16, enabled: bool) -> ModeChange {
+    ModeChange { mode, enabled }
+}
+
+#[test]
+fn mouse_modes_are_tracked_across_split_reads() {
+    let mut tracker = ModeTracker::default();
+    let changes: Vec<ModeChange> = ["\x1b[?10", "00;25;1006h", "text\x1b", "[?1000h\x1b[?1002h"]
+        .iter()
+        .flat_map(|chunk| tracker.feed(chunk))
+        .collect();
+    assert_eq!(changes, [change(1000, true), change(1006, true), change(1002, true)]);
+    assert_eq!(tracker.modes().mouse, [1000, 1002, 1006]);
+
+    assert_eq!(tracker.feed("\x1b[?1002l\x1b[?1003l"), [change(1002, false)]);
+    assert_eq!(tracker.modes().mouse, [1000, 1006]);
+}
+
+#[test]
+fn full_reset_turns_everything_off() {
+    let mut tracker = ModeTracker::default();
+    tracker.feed("\x1b[?1003;1006h");
+    assert_eq!(tracker.feed("bye\x1b"), []);
+    assert_eq!(tracker.feed("c"), [change(1003, false), change(1006, false)]);
+    assert!(tracker.modes().mouse.is_empty());
+}
+
+#[test]
+fn alternate_screen_and_bracketed_paste_follow_the_app() {
+    let mut tracker = ModeTracker::default();
+    let modes = tracker.modes();
+    assert!(!modes.alternate_screen && !modes.bracketed_paste);
+
+    for chunk in ["\x1b[?2004h$ less README\r\n\x1b[?104", "9h\x1b[?1h\x1b=", "page 1"] {
+        tracker.feed(chunk);
+    }
+    let modes = tracker.modes();
+    assert!(modes.alternate_screen && modes.bracketed_paste);
+    assert!(modes.mouse.is_empty());
+
+    tracker.feed("\x1b[?1049l\r$ ");
+    let modes = tracker.modes();
+    assert!(!modes.alternate_screen && modes.bracketed_paste);
+
+    tracker.feed("\x1b[?47h");
+    assert!(tracker.modes().alternate_screen);
+    tracker.feed("\x1bc");
+    assert_eq!(tracker.modes(), Default::default());
+}
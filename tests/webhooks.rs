@@ -0,0 +1,93 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use rust_terminal_forge::config::WebhookConfig;
+use rust_terminal_forge::webhooks::{self, WebhookEvent, WebhookEventKind, Webhooks};
+use tokio::sync::mpsc;
+use warp::http::{HeaderMap, StatusCode};
+use warp::Filter;
+
+/// Records every POST; the first `failures` answer 500.
+fn receiver(failures: usize) -> (SocketAddr, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let seen = Arc::new(AtomicUsize::new(0));
+    let route = warp::post()
+        .and(warp::header::headers_cloned())
+        .and(warp::body::bytes())
+        .map(move |headers: HeaderMap, body: Bytes| {
+            let _ = tx.send((headers, body));
+            let status = if seen.fetch_add(1, Ordering::SeqCst) < failures {
+                StatusCode::INTERNAL_SERVER_ERROR
+            } else {
+                StatusCode::NO_CONTENT
+            };
+            warp::reply::with_status("", status)
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (addr, rx)
+}
+
+fn hook(addr: SocketAddr, events: Vec<WebhookEventKind>, secret: Option<&str>) -> WebhookConfig {
+    WebhookConfig {
+        url: format!("http://{}/hook", addr),
+        events,
+        secret: secret.map(str::to_string),
+        slow_execute_ms: 1_000,
+    }
+}
+
+#[test]
+fn signature_is_hmac_sha1() {
+    // RFC 2202, test case 1
+    assert_eq!(webhooks::sign(&[0x0b; 20], b"Hi There"), "sha1=b617318655057264e28bc0b6fb378c8ef146be00");
+}
+
+#[tokio::test]
+async fn deliveries_are_signed_and_retried() {
+    let (addr, mut posts) = receiver(1);
+    let hooks = Webhooks::from_config(&[hook(addr, Vec::new(), Some("s3cret"))]).unwrap();
+
+    hooks.emit(WebhookEvent::SessionCreated { session_id: "abc".to_string(), peer_addr: "10.0.0.1:5000".to_string(), tags: [("purpose".to_string(), "build".to_string())].into() });
+
+    let (_, first) = posts.recv().await.unwrap();
+    let (headers, body) = posts.recv().await.unwrap();
+    assert_eq!(first, body);
+    assert_eq!(headers[webhooks::EVENT_HEADER], "session_created");
+    assert_eq!(headers[webhooks::SIGNATURE_HEADER], webhooks::sign(b"s3cret", &body).as_str());
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(payload["event"], "session_created");
+    assert_eq!(payload["session_id"], "abc");
+    assert_eq!(payload["tags"]["purpose"], "build");
+    assert!(payload["timestamp"].is_string());
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let metrics = hooks.metrics();
+    assert_eq!((metrics.delivered, metrics.retried, metrics.failed), (1, 1, 0));
+}
+
+#[tokio::test]
+async fn endpoints_only_get_the_events_they_asked_for() {
+    let (addr, mut posts) = receiver(0);
+    let hooks = Webhooks::from_config(&[hook(addr, vec![WebhookEventKind::AdminKill, WebhookEventKind::ExecuteSlow], None)]).unwrap();
+
+    hooks.emit(WebhookEvent::SessionCreated { session_id: "abc".to_string(), peer_addr: "peer".to_string(), tags: Default::default() });
+    hooks.emit(WebhookEvent::ExecuteSlow { command: "ls".to_string(), duration_ms: 10, exit_code: 0 });
+    hooks.emit(WebhookEvent::AdminKill { session_id: "abc".to_string() });
+
+    let (headers, body) = posts.recv().await.unwrap();
+    assert_eq!(headers[webhooks::EVENT_HEADER], "admin_kill");
+    assert!(!headers.contains_key(webhooks::SIGNATURE_HEADER));
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(payload["session_id"], "abc");
+}
+
+#[tokio::test]
+async fn only_plain_http_urls_are_accepted() {
+    let mut config = hook(([127, 0, 0, 1], 9).into(), Vec::new(), None);
+    config.url = "https://ops.example.com/hook".to_string();
+    assert!(Webhooks::from_config(&[config]).is_err());
+}
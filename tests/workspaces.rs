@@ -0,0 +1,31 @@
+use rust_terminal_forge::workspaces::{self, WorkspaceError, Workspaces, MAX_WORKSPACE_LEN};
+
+#[test]
+fn names_stay_within_url_safe_characters() {
+    assert_eq!(workspaces::parse("project-A").unwrap(), "project-A");
+    assert_eq!(workspaces::parse("v1.2_beta").unwrap(), "v1.2_beta");
+    assert!(workspaces::parse(&"w".repeat(MAX_WORKSPACE_LEN)).is_ok());
+
+    assert_eq!(workspaces::parse("").unwrap_err(), WorkspaceError::InvalidName(String::new()));
+    assert_eq!(workspaces::parse("project A").unwrap_err().code(), "invalid_workspace");
+    assert!(workspaces::parse("a/b").is_err());
+    assert!(workspaces::parse(&"w".repeat(MAX_WORKSPACE_LEN + 1)).is_err());
+}
+
+#[test]
+fn workspaces_are_created_once_and_removed() {
+    let workspaces = Workspaces::new();
+    let (created, new) = workspaces.create("project-b");
+    assert!(new);
+    let (again, new) = workspaces.create("project-b");
+    assert!(!new);
+    assert_eq!(again, created);
+    workspaces.create("project-a");
+    let names: Vec<_> = workspaces.list().into_iter().map(|workspace| workspace.name).collect();
+    assert_eq!(names, ["project-a", "project-b"]);
+
+    assert_eq!(workspaces.remove("project-b").unwrap(), created);
+    assert_eq!(workspaces.get("project-b").unwrap_err(), WorkspaceError::NotFound("project-b".to_string()));
+    assert_eq!(workspaces.remove("project-b").unwrap_err().code(), "workspace_not_found");
+    assert_eq!(workspaces.list().len(), 1);
+}